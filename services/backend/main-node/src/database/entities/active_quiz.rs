@@ -0,0 +1,204 @@
+use crate::database::models::game::GameResults;
+use crate::database::DbResult;
+use chrono::Utc;
+use sea_orm::entity::prelude::*;
+use sea_orm::{ActiveValue::Set, ConnectionTrait, IntoActiveModel, QueryOrder, UpdateResult};
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+
+use super::quiz::{Quiz, QuizId};
+use super::user::{User, UserId};
+
+pub type ActiveQuizId = i32;
+pub type ActiveQuiz = Model;
+pub type ActiveQuizEntity = Entity;
+pub type ActiveQuizActiveModel = ActiveModel;
+
+/// Database structure for a game session hosted for a quiz
+#[derive(Debug, Clone, PartialEq, DeriveEntityModel, Serialize)]
+#[sea_orm(table_name = "active_quiz")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: ActiveQuizId,
+    /// The quiz being played
+    pub quiz_id: QuizId,
+    /// The user hosting the game
+    pub host_id: UserId,
+    /// Code players use to join the game
+    pub code: String,
+    /// The current state of the game
+    pub state: ActiveQuizState,
+    /// The final results, present once the game is finished
+    #[sea_orm(column_type = "Json", nullable)]
+    pub results: Option<GameResults>,
+    /// When the game was created
+    pub created_at: DateTime,
+    /// When the game was started
+    pub started_at: Option<DateTime>,
+    /// When the game was finished or cancelled
+    pub finished_at: Option<DateTime>,
+}
+
+#[derive(Debug, Clone, Default, EnumIter, PartialEq, DeriveActiveEnum, Serialize, Deserialize)]
+#[sea_orm(rs_type = "i32", db_type = "Integer")]
+pub enum ActiveQuizState {
+    /// Waiting for players to join
+    #[default]
+    #[sea_orm(num_value = 0)]
+    Lobby,
+    /// Game is being played
+    #[sea_orm(num_value = 1)]
+    Running,
+    /// Game was played to completion
+    #[sea_orm(num_value = 2)]
+    Finished,
+    /// Game was ended early
+    #[sea_orm(num_value = 3)]
+    Cancelled,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::quiz::Entity",
+        from = "Column::QuizId",
+        to = "super::quiz::Column::Id"
+    )]
+    Quiz,
+    #[sea_orm(
+        belongs_to = "super::user::Entity",
+        from = "Column::HostId",
+        to = "super::user::Column::Id"
+    )]
+    User,
+}
+
+#[async_trait::async_trait]
+impl ActiveModelBehavior for ActiveModel {
+    /// Handles setting the `created_at` field when the model is inserted
+    async fn before_save<C>(mut self, _db: &C, insert: bool) -> Result<Self, DbErr>
+    where
+        C: ConnectionTrait,
+    {
+        if insert {
+            self.created_at = Set(Utc::now().naive_utc());
+        }
+
+        Ok(self)
+    }
+}
+
+impl Model {
+    /// Create a new game session for the provided `quiz`
+    pub fn create<'db, C>(
+        db: &'db C,
+        quiz: &Quiz,
+        host: &User,
+        code: String,
+    ) -> impl Future<Output = DbResult<ActiveQuiz>> + 'db
+    where
+        C: ConnectionTrait,
+    {
+        ActiveModel {
+            quiz_id: Set(quiz.id),
+            host_id: Set(host.id),
+            code: Set(code),
+            state: Set(ActiveQuizState::Lobby),
+            results: Set(None),
+            started_at: Set(None),
+            finished_at: Set(None),
+            ..Default::default()
+        }
+        .insert(db)
+    }
+
+    /// Finds a game session by its ID
+    pub fn find_by_id<C>(
+        db: &C,
+        id: ActiveQuizId,
+    ) -> impl Future<Output = DbResult<Option<ActiveQuiz>>> + '_
+    where
+        C: ConnectionTrait,
+    {
+        Entity::find_by_id(id).one(db)
+    }
+
+    /// Finds all the finished games of the quiz with the provided `quiz_id`
+    /// with the most recent first
+    pub fn find_finished_by_quiz<C>(
+        db: &C,
+        quiz_id: QuizId,
+    ) -> impl Future<Output = DbResult<Vec<ActiveQuiz>>> + '_
+    where
+        C: ConnectionTrait,
+    {
+        Entity::find()
+            .filter(Column::QuizId.eq(quiz_id))
+            .filter(Column::State.eq(ActiveQuizState::Finished))
+            .order_by_desc(Column::FinishedAt)
+            .all(db)
+    }
+
+    /// Marks the game as started at the current time
+    pub fn set_started<C>(self, db: &C) -> impl Future<Output = DbResult<ActiveQuiz>> + '_
+    where
+        C: ConnectionTrait,
+    {
+        let mut model = self.into_active_model();
+        model.state = Set(ActiveQuizState::Running);
+        model.started_at = Set(Some(Utc::now().naive_utc()));
+        model.update(db)
+    }
+
+    /// Marks the game as finished storing the provided `results`
+    pub fn set_finished<C>(
+        self,
+        db: &C,
+        results: GameResults,
+    ) -> impl Future<Output = DbResult<ActiveQuiz>> + '_
+    where
+        C: ConnectionTrait,
+    {
+        let mut model = self.into_active_model();
+        model.state = Set(ActiveQuizState::Finished);
+        model.results = Set(Some(results));
+        model.finished_at = Set(Some(Utc::now().naive_utc()));
+        model.update(db)
+    }
+
+    /// Marks the game as cancelled
+    pub fn set_cancelled<C>(self, db: &C) -> impl Future<Output = DbResult<ActiveQuiz>> + '_
+    where
+        C: ConnectionTrait,
+    {
+        let mut model = self.into_active_model();
+        model.state = Set(ActiveQuizState::Cancelled);
+        model.finished_at = Set(Some(Utc::now().naive_utc()));
+        model.update(db)
+    }
+
+    /// Marks every game that is still in the lobby or running as
+    /// cancelled, used for games left behind when the server stopped
+    pub fn cancel_unfinished<C>(db: &C) -> impl Future<Output = DbResult<UpdateResult>> + '_
+    where
+        C: ConnectionTrait,
+    {
+        Entity::update_many()
+            .col_expr(Column::State, Expr::value(ActiveQuizState::Cancelled))
+            .col_expr(Column::FinishedAt, Expr::value(Utc::now().naive_utc()))
+            .filter(Column::State.is_in([ActiveQuizState::Lobby, ActiveQuizState::Running]))
+            .exec(db)
+    }
+}
+
+impl Related<super::quiz::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Quiz.def()
+    }
+}
+
+impl Related<super::user::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::User.def()
+    }
+}
@@ -0,0 +1,198 @@
+use crate::database::DbResult;
+use sea_orm::entity::prelude::*;
+use sea_orm::sea_query::Expr;
+use sea_orm::{ActiveValue::Set, ConnectionTrait, FromQueryResult, QueryOrder, QuerySelect};
+use serde::Serialize;
+use std::future::Future;
+
+use super::active_quiz::ActiveQuizId;
+use super::quiz::QuizId;
+
+pub type AnswerEvent = Model;
+pub type AnswerEventEntity = Entity;
+pub type AnswerEventActiveModel = ActiveModel;
+
+/// Time-series record of a player answering a question within a game
+#[derive(Debug, Clone, PartialEq, DeriveEntityModel, Serialize)]
+#[sea_orm(table_name = "analytics")]
+pub struct Model {
+    /// The game session the answer is from
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub session_id: ActiveQuizId,
+    /// ID of the question within the quiz
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub question_id: i32,
+    /// ID of the player within the game
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub player_id: i32,
+    /// When the answer was recorded
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub time: DateTime,
+    /// The quiz that was played
+    pub quiz_id: QuizId,
+    /// Display name of the player
+    pub player_name: String,
+    /// Whether the answer was correct
+    pub correct: bool,
+    /// Points awarded for the answer
+    pub points: i32,
+    /// Time in milliseconds taken to answer, [None] if the
+    /// player didn't answer
+    pub response_time: Option<i32>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::active_quiz::Entity",
+        from = "Column::SessionId",
+        to = "super::active_quiz::Column::Id"
+    )]
+    ActiveQuiz,
+    #[sea_orm(
+        belongs_to = "super::quiz::Entity",
+        from = "Column::QuizId",
+        to = "super::quiz::Column::Id"
+    )]
+    Quiz,
+}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+/// Aggregated answer statistics for a question
+#[derive(Debug, FromQueryResult, Serialize)]
+pub struct QuestionStats {
+    pub question_id: i32,
+    /// Number of players that were asked the question
+    pub total: i64,
+    /// Number of players that answered the question
+    pub answered: i64,
+    /// Number of correct answers
+    pub correct: i64,
+    /// Average time in milliseconds taken to answer
+    pub average_response_time: Option<f64>,
+}
+
+/// Aggregated answer statistics for a player within a game
+#[derive(Debug, FromQueryResult, Serialize)]
+pub struct PlayerStats {
+    pub player_id: i32,
+    pub player_name: String,
+    /// Number of questions answered
+    pub answered: i64,
+    /// Number of correct answers
+    pub correct: i64,
+    /// Total points awarded
+    pub score: i64,
+    /// Average time in milliseconds taken to answer
+    pub average_response_time: Option<f64>,
+}
+
+impl Model {
+    /// Stores the provided answer `events`
+    pub async fn create_many<C>(db: &C, events: Vec<AnswerEvent>) -> DbResult<()>
+    where
+        C: ConnectionTrait,
+    {
+        if events.is_empty() {
+            return Ok(());
+        }
+
+        let models = events.into_iter().map(|event| ActiveModel {
+            session_id: Set(event.session_id),
+            question_id: Set(event.question_id),
+            player_id: Set(event.player_id),
+            time: Set(event.time),
+            quiz_id: Set(event.quiz_id),
+            player_name: Set(event.player_name),
+            correct: Set(event.correct),
+            points: Set(event.points),
+            response_time: Set(event.response_time),
+        });
+
+        Entity::insert_many(models)
+            .exec_without_returning(db)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Provides the per-question statistics for the quiz with the provided
+    /// `quiz_id`, optionally limited to a single game `session_id`
+    pub fn question_stats<C>(
+        db: &C,
+        quiz_id: QuizId,
+        session_id: Option<ActiveQuizId>,
+    ) -> impl Future<Output = DbResult<Vec<QuestionStats>>> + '_
+    where
+        C: ConnectionTrait,
+    {
+        let mut select = Entity::find()
+            .select_only()
+            .column(Column::QuestionId)
+            .column_as(Expr::cust("COUNT(*)"), "total")
+            .column_as(Expr::col(Column::ResponseTime).count(), "answered")
+            .column_as(
+                Expr::cust(r#"COUNT(*) FILTER (WHERE "correct")"#),
+                "correct",
+            )
+            .column_as(
+                Expr::cust(r#"AVG("response_time")::float8"#),
+                "average_response_time",
+            )
+            .filter(Column::QuizId.eq(quiz_id));
+
+        if let Some(session_id) = session_id {
+            select = select.filter(Column::SessionId.eq(session_id));
+        }
+
+        select
+            .group_by(Column::QuestionId)
+            .order_by_asc(Column::QuestionId)
+            .into_model::<QuestionStats>()
+            .all(db)
+    }
+
+    /// Provides the per-player statistics for the game with the
+    /// provided `session_id` ordered by score
+    pub fn player_stats<C>(
+        db: &C,
+        session_id: ActiveQuizId,
+    ) -> impl Future<Output = DbResult<Vec<PlayerStats>>> + '_
+    where
+        C: ConnectionTrait,
+    {
+        Entity::find()
+            .select_only()
+            .column(Column::PlayerId)
+            .column(Column::PlayerName)
+            .column_as(Expr::col(Column::ResponseTime).count(), "answered")
+            .column_as(
+                Expr::cust(r#"COUNT(*) FILTER (WHERE "correct")"#),
+                "correct",
+            )
+            .column_as(Expr::cust(r#"SUM("points")::int8"#), "score")
+            .column_as(
+                Expr::cust(r#"AVG("response_time")::float8"#),
+                "average_response_time",
+            )
+            .filter(Column::SessionId.eq(session_id))
+            .group_by(Column::PlayerId)
+            .group_by(Column::PlayerName)
+            .order_by_desc(Expr::cust(r#"SUM("points")"#))
+            .into_model::<PlayerStats>()
+            .all(db)
+    }
+}
+
+impl Related<super::active_quiz::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ActiveQuiz.def()
+    }
+}
+
+impl Related<super::quiz::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Quiz.def()
+    }
+}
@@ -1,5 +1,19 @@
+pub mod active_quiz;
+pub mod analytics;
+pub mod organization;
+pub mod organization_domain;
+pub mod organization_member;
 pub mod quiz;
+pub mod quiz_permission;
 pub mod resource;
+pub mod revoked_token;
+pub mod scim_group;
+pub mod scim_group_member;
+pub mod scim_token;
 pub mod user;
 pub mod user_link;
+pub mod user_passkey;
+pub mod user_recovery_code;
 pub mod user_refresh_token;
+pub mod user_rotated_refresh_token;
+pub mod user_totp;
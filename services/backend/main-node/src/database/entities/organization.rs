@@ -0,0 +1,145 @@
+use crate::database::DbResult;
+use chrono::Utc;
+use sea_orm::entity::prelude::*;
+use sea_orm::{ActiveValue::Set, ConnectionTrait, FromQueryResult, IntoActiveModel};
+use sea_orm::{JoinType, QueryOrder, QuerySelect};
+use serde::Serialize;
+use std::future::Future;
+
+use super::organization_member::{self, OrgRole};
+use super::user::User;
+
+pub type OrganizationId = i32;
+pub type Organization = Model;
+pub type OrganizationEntity = Entity;
+pub type OrganizationActiveModel = ActiveModel;
+
+/// Database structure for an organization that owns quizzes and
+/// resources on behalf of its members
+#[derive(Debug, Clone, PartialEq, DeriveEntityModel, Serialize)]
+#[sea_orm(table_name = "organizations")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: OrganizationId,
+    /// Name of the organization
+    pub name: String,
+    /// When the organization was created
+    pub created_at: DateTime,
+    /// When the organization was last updated
+    pub updated_at: DateTime,
+}
+
+/// Organization a user is a member of along with their role
+#[derive(Debug, Clone, FromQueryResult, Serialize)]
+pub struct Membership {
+    /// ID of the organization
+    pub id: OrganizationId,
+    /// Name of the organization
+    pub name: String,
+    /// The role of the user within the organization
+    pub role: OrgRole,
+    /// When the user joined the organization
+    pub joined_at: DateTime,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(has_many = "super::organization_member::Entity")]
+    Members,
+    #[sea_orm(has_many = "super::organization_domain::Entity")]
+    Domains,
+}
+
+#[async_trait::async_trait]
+impl ActiveModelBehavior for ActiveModel {
+    /// Handles updating the `updated_at` field before the model is saved, using
+    /// the current date time.
+    ///
+    /// If the save is an insertion the `created_at` field will also be updated
+    async fn before_save<C>(mut self, _db: &C, insert: bool) -> Result<Self, DbErr>
+    where
+        C: ConnectionTrait,
+    {
+        let now = Utc::now().naive_utc();
+        self.updated_at = Set(now);
+
+        if insert {
+            self.created_at = Set(now);
+        }
+
+        Ok(self)
+    }
+}
+
+impl Model {
+    /// Creates a new organization with the provided `name`
+    pub fn create<C>(db: &C, name: String) -> impl Future<Output = DbResult<Organization>> + '_
+    where
+        C: ConnectionTrait,
+    {
+        ActiveModel {
+            name: Set(name),
+            ..Default::default()
+        }
+        .insert(db)
+    }
+
+    /// Finds an organization by its ID
+    pub fn find_by_id<C>(
+        db: &C,
+        id: OrganizationId,
+    ) -> impl Future<Output = DbResult<Option<Organization>>> + '_
+    where
+        C: ConnectionTrait,
+    {
+        Entity::find_by_id(id).one(db)
+    }
+
+    /// Finds all the organizations the provided `user` is a member
+    /// of in the order they were joined
+    pub fn find_by_member<'db, C>(
+        db: &'db C,
+        user: &User,
+    ) -> impl Future<Output = DbResult<Vec<Membership>>> + 'db
+    where
+        C: ConnectionTrait,
+    {
+        Entity::find()
+            .select_only()
+            .column(Column::Id)
+            .column(Column::Name)
+            .column(organization_member::Column::Role)
+            .column_as(organization_member::Column::CreatedAt, "joined_at")
+            .join(JoinType::InnerJoin, Relation::Members.def())
+            .filter(organization_member::Column::UserId.eq(user.id))
+            .order_by_asc(organization_member::Column::CreatedAt)
+            .into_model::<Membership>()
+            .all(db)
+    }
+
+    /// Changes the name of the organization
+    pub fn set_name<C>(
+        self,
+        db: &C,
+        name: String,
+    ) -> impl Future<Output = DbResult<Organization>> + '_
+    where
+        C: ConnectionTrait,
+    {
+        let mut model = self.into_active_model();
+        model.name = Set(name);
+        model.update(db)
+    }
+}
+
+impl Related<super::organization_member::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Members.def()
+    }
+}
+
+impl Related<super::organization_domain::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Domains.def()
+    }
+}
@@ -0,0 +1,250 @@
+use crate::database::DbResult;
+use chrono::Utc;
+use sea_orm::entity::prelude::*;
+use sea_orm::{ActiveValue::Set, ConnectionTrait, DeleteResult, IntoActiveModel, QueryOrder};
+use serde::Serialize;
+use std::future::Future;
+
+use super::organization::OrganizationId;
+
+pub type OrganizationDomain = Model;
+pub type OrganizationDomainEntity = Entity;
+pub type OrganizationDomainActiveModel = ActiveModel;
+
+pub type DomainId = i32;
+
+/// Database structure for an email domain claimed by an organization,
+/// once verified users that verify an email on the domain automatically
+/// join the organization
+#[derive(Debug, Clone, PartialEq, DeriveEntityModel, Serialize)]
+#[sea_orm(table_name = "organization_domains")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: DomainId,
+    /// The organization claiming the domain
+    #[serde(skip)]
+    pub organization_id: OrganizationId,
+    /// The claimed email domain
+    pub domain: String,
+    /// Token that must be published in a DNS TXT record to verify the claim
+    pub verification_token: String,
+    /// When the claim was verified
+    pub verified_at: Option<DateTime>,
+    /// When the domain was claimed
+    pub created_at: DateTime,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::organization::Entity",
+        from = "Column::OrganizationId",
+        to = "super::organization::Column::Id"
+    )]
+    Organization,
+}
+
+#[async_trait::async_trait]
+impl ActiveModelBehavior for ActiveModel {
+    /// Handles setting the `created_at` field when the model is inserted
+    async fn before_save<C>(mut self, _db: &C, insert: bool) -> Result<Self, DbErr>
+    where
+        C: ConnectionTrait,
+    {
+        if insert {
+            self.created_at = Set(Utc::now().naive_utc());
+        }
+
+        Ok(self)
+    }
+}
+
+impl Model {
+    /// Creates an unverified claim on the `domain` for the organization
+    pub fn create<C>(
+        db: &C,
+        organization_id: OrganizationId,
+        domain: String,
+        verification_token: String,
+    ) -> impl Future<Output = DbResult<OrganizationDomain>> + '_
+    where
+        C: ConnectionTrait,
+    {
+        ActiveModel {
+            organization_id: Set(organization_id),
+            domain: Set(domain),
+            verification_token: Set(verification_token),
+            verified_at: Set(None),
+            ..Default::default()
+        }
+        .insert(db)
+    }
+
+    /// Finds the domain claim with the provided `id` made by the organization
+    pub fn find_by_id<C>(
+        db: &C,
+        organization_id: OrganizationId,
+        id: DomainId,
+    ) -> impl Future<Output = DbResult<Option<OrganizationDomain>>> + '_
+    where
+        C: ConnectionTrait,
+    {
+        Entity::find_by_id(id)
+            .filter(Column::OrganizationId.eq(organization_id))
+            .one(db)
+    }
+
+    /// Finds the claim the organization has made on the `domain`
+    pub fn find_by_domain<'db, C>(
+        db: &'db C,
+        organization_id: OrganizationId,
+        domain: &str,
+    ) -> impl Future<Output = DbResult<Option<OrganizationDomain>>> + 'db
+    where
+        C: ConnectionTrait,
+    {
+        Entity::find()
+            .filter(Column::OrganizationId.eq(organization_id))
+            .filter(Column::Domain.eq(domain))
+            .one(db)
+    }
+
+    /// Finds the verified claim on the `domain`, only one organization
+    /// can have a verified claim on each domain
+    pub fn find_verified<'db, C>(
+        db: &'db C,
+        domain: &str,
+    ) -> impl Future<Output = DbResult<Option<OrganizationDomain>>> + 'db
+    where
+        C: ConnectionTrait,
+    {
+        Entity::find()
+            .filter(Column::Domain.eq(domain))
+            .filter(Column::VerifiedAt.is_not_null())
+            .one(db)
+    }
+
+    /// Finds the verified claim on the domain of the `email` address
+    pub async fn find_verified_for_email<C>(db: &C, email: &str) -> DbResult<Option<Self>>
+    where
+        C: ConnectionTrait,
+    {
+        let Some(domain) = email_domain(email) else {
+            return Ok(None);
+        };
+
+        Self::find_verified(db, &domain).await
+    }
+
+    /// Checks whether the organization has a verified claim on the domain
+    /// of the `email` address
+    pub async fn is_email_verified<C>(
+        db: &C,
+        organization_id: OrganizationId,
+        email: &str,
+    ) -> DbResult<bool>
+    where
+        C: ConnectionTrait,
+    {
+        Ok(Self::find_verified_for_email(db, email)
+            .await?
+            .is_some_and(|claim| claim.is_verified_for(organization_id)))
+    }
+
+    /// Whether this is a verified claim made by the organization with
+    /// the provided `organization_id`
+    pub fn is_verified_for(&self, organization_id: OrganizationId) -> bool {
+        self.organization_id == organization_id && self.verified_at.is_some()
+    }
+
+    /// Finds all the domains claimed by the organization
+    pub fn find_by_organization<C>(
+        db: &C,
+        organization_id: OrganizationId,
+    ) -> impl Future<Output = DbResult<Vec<OrganizationDomain>>> + '_
+    where
+        C: ConnectionTrait,
+    {
+        Entity::find()
+            .filter(Column::OrganizationId.eq(organization_id))
+            .order_by_asc(Column::Domain)
+            .all(db)
+    }
+
+    /// Marks the claim as verified at the current time
+    pub fn set_verified<C>(self, db: &C) -> impl Future<Output = DbResult<OrganizationDomain>> + '_
+    where
+        C: ConnectionTrait,
+    {
+        let mut model = self.into_active_model();
+        model.verified_at = Set(Some(Utc::now().naive_utc()));
+        model.update(db)
+    }
+
+    /// Removes the claim on the domain
+    pub fn remove<C>(self, db: &C) -> impl Future<Output = DbResult<DeleteResult>> + '_
+    where
+        C: ConnectionTrait,
+    {
+        Entity::delete_by_id(self.id).exec(db)
+    }
+}
+
+impl Related<super::organization::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Organization.def()
+    }
+}
+
+/// Provides the lowercase domain of the `email` address, [None] when
+/// the address doesn't have a domain
+fn email_domain(email: &str) -> Option<String> {
+    let (_, domain) = email.rsplit_once('@')?;
+
+    if domain.is_empty() {
+        return None;
+    }
+
+    Some(domain.to_lowercase())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{email_domain, OrganizationDomain, OrganizationId};
+    use chrono::Utc;
+
+    fn claim(organization_id: OrganizationId, verified: bool) -> OrganizationDomain {
+        let now = Utc::now().naive_utc();
+        OrganizationDomain {
+            id: 1,
+            organization_id,
+            domain: "example.com".to_string(),
+            verification_token: "token".to_string(),
+            verified_at: verified.then_some(now),
+            created_at: now,
+        }
+    }
+
+    /// Tests that the domain is taken from after the last @ and lowercased
+    #[test]
+    fn test_email_domain() {
+        assert_eq!(
+            email_domain("user@Example.COM").as_deref(),
+            Some("example.com")
+        );
+        assert_eq!(
+            email_domain("\"user@evil.com\"@example.com").as_deref(),
+            Some("example.com")
+        );
+        assert_eq!(email_domain("user"), None);
+        assert_eq!(email_domain("user@"), None);
+    }
+
+    /// Tests that only verified claims by the same organization verify emails
+    #[test]
+    fn test_verified_for() {
+        assert!(claim(1, true).is_verified_for(1));
+        assert!(!claim(1, false).is_verified_for(1));
+        assert!(!claim(2, true).is_verified_for(1));
+    }
+}
@@ -0,0 +1,377 @@
+use crate::database::DbResult;
+use chrono::Utc;
+use sea_orm::entity::prelude::*;
+use sea_orm::{ActiveValue::Set, ConnectionTrait, DeleteResult, FromQueryResult, IntoActiveModel};
+use sea_orm::{JoinType, PaginatorTrait, QueryOrder, QuerySelect};
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+
+use super::organization::OrganizationId;
+use super::organization_domain::OrganizationDomain;
+use super::user::{self, User, UserId};
+
+pub type OrganizationMember = Model;
+pub type OrganizationMemberEntity = Entity;
+pub type OrganizationMemberActiveModel = ActiveModel;
+
+/// Database structure for a user that is a member of an organization
+#[derive(Debug, Clone, PartialEq, DeriveEntityModel, Serialize)]
+#[sea_orm(table_name = "organization_members")]
+pub struct Model {
+    /// The organization the user is a member of
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub organization_id: OrganizationId,
+    /// The member user
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub user_id: UserId,
+    /// The role of the member within the organization
+    pub role: OrgRole,
+    /// When the user joined the organization
+    pub created_at: DateTime,
+    /// When the role was last changed
+    pub updated_at: DateTime,
+}
+
+/// Roles members can have within an organization
+#[derive(Debug, Clone, Copy, EnumIter, PartialEq, Eq, DeriveActiveEnum, Serialize, Deserialize)]
+#[sea_orm(rs_type = "i32", db_type = "Integer")]
+pub enum OrgRole {
+    /// Can view and create content within the organization
+    #[sea_orm(num_value = 0)]
+    Member,
+    /// Can manage the content, members, domains and provisioning of
+    /// the organization
+    #[sea_orm(num_value = 1)]
+    Admin,
+    /// Can do anything including deleting the organization
+    #[sea_orm(num_value = 2)]
+    Owner,
+}
+
+/// Actions that can be performed on an organization
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OrgAction {
+    /// Viewing the organization, its members and its content
+    View,
+    /// Creating quizzes and resources owned by the organization
+    CreateContent,
+    /// Making changes to and deleting any content owned by the organization
+    ManageContent,
+    /// Adding, changing and removing members
+    ManageMembers,
+    /// Claiming, verifying and removing email domains
+    ManageDomains,
+    /// Creating and revoking the tokens used for SCIM provisioning
+    ManageScim,
+    /// Changing the details of the organization
+    Update,
+    /// Deleting the organization
+    Delete,
+}
+
+impl OrgRole {
+    /// Checks whether this role allows the provided `action`
+    pub fn allows(&self, action: OrgAction) -> bool {
+        match self {
+            OrgRole::Member => matches!(action, OrgAction::View | OrgAction::CreateContent),
+            OrgRole::Admin => !matches!(action, OrgAction::Delete),
+            OrgRole::Owner => true,
+        }
+    }
+}
+
+/// Member of an organization along with the user details
+#[derive(Debug, Clone, FromQueryResult, Serialize)]
+pub struct Member {
+    /// ID of the member user
+    pub user_id: UserId,
+    /// Username of the member user
+    pub username: String,
+    /// Display name of the member user
+    pub name: Option<String>,
+    /// The role of the member
+    pub role: OrgRole,
+    /// When the user joined the organization
+    pub created_at: DateTime,
+}
+
+/// Membership a user receives when joining an organization through
+/// a verified claim on their email domain
+#[derive(Debug, PartialEq)]
+struct DomainJoin {
+    /// The organization being joined
+    organization_id: OrganizationId,
+    /// The role the user joins with
+    role: OrgRole,
+    /// Whether the user becomes managed by the organization
+    managed: bool,
+}
+
+impl DomainJoin {
+    /// Determines the membership from the verified `claim`, users from the
+    /// identity provider of the `provider_organization` can only join that
+    /// organization and are managed by it
+    fn for_claim(
+        claim: &OrganizationDomain,
+        provider_organization: Option<OrganizationId>,
+    ) -> Option<DomainJoin> {
+        let verified = match provider_organization {
+            Some(organization_id) => claim.is_verified_for(organization_id),
+            None => claim.verified_at.is_some(),
+        };
+
+        if !verified {
+            return None;
+        }
+
+        Some(DomainJoin {
+            organization_id: claim.organization_id,
+            role: OrgRole::Member,
+            managed: provider_organization.is_some(),
+        })
+    }
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::organization::Entity",
+        from = "Column::OrganizationId",
+        to = "super::organization::Column::Id"
+    )]
+    Organization,
+    #[sea_orm(
+        belongs_to = "super::user::Entity",
+        from = "Column::UserId",
+        to = "super::user::Column::Id"
+    )]
+    User,
+}
+
+#[async_trait::async_trait]
+impl ActiveModelBehavior for ActiveModel {
+    /// Handles updating the `updated_at` field before the model is saved, using
+    /// the current date time.
+    ///
+    /// If the save is an insertion the `created_at` field will also be updated
+    async fn before_save<C>(mut self, _db: &C, insert: bool) -> Result<Self, DbErr>
+    where
+        C: ConnectionTrait,
+    {
+        let now = Utc::now().naive_utc();
+        self.updated_at = Set(now);
+
+        if insert {
+            self.created_at = Set(now);
+        }
+
+        Ok(self)
+    }
+}
+
+impl Model {
+    /// Adds the user to the organization with the provided `role`
+    pub fn create<C>(
+        db: &C,
+        organization_id: OrganizationId,
+        user_id: UserId,
+        role: OrgRole,
+    ) -> impl Future<Output = DbResult<OrganizationMember>> + '_
+    where
+        C: ConnectionTrait,
+    {
+        ActiveModel {
+            organization_id: Set(organization_id),
+            user_id: Set(user_id),
+            role: Set(role),
+            ..Default::default()
+        }
+        .insert(db)
+    }
+
+    /// Finds the membership of the user within the organization if
+    /// they are a member
+    pub fn find<C>(
+        db: &C,
+        organization_id: OrganizationId,
+        user_id: UserId,
+    ) -> impl Future<Output = DbResult<Option<OrganizationMember>>> + '_
+    where
+        C: ConnectionTrait,
+    {
+        Entity::find_by_id((organization_id, user_id)).one(db)
+    }
+
+    /// Finds all the members of the organization in the order
+    /// they joined
+    pub fn find_members<C>(
+        db: &C,
+        organization_id: OrganizationId,
+    ) -> impl Future<Output = DbResult<Vec<Member>>> + '_
+    where
+        C: ConnectionTrait,
+    {
+        Entity::find()
+            .select_only()
+            .column(Column::UserId)
+            .column(user::Column::Username)
+            .column(user::Column::Name)
+            .column(Column::Role)
+            .column(Column::CreatedAt)
+            .join(JoinType::InnerJoin, Relation::User.def())
+            .filter(Column::OrganizationId.eq(organization_id))
+            .order_by_asc(Column::CreatedAt)
+            .into_model::<Member>()
+            .all(db)
+    }
+
+    /// Counts the number of owners the organization has
+    pub fn count_owners<C>(
+        db: &C,
+        organization_id: OrganizationId,
+    ) -> impl Future<Output = DbResult<u64>> + '_
+    where
+        C: ConnectionTrait,
+    {
+        Entity::find()
+            .filter(Column::OrganizationId.eq(organization_id))
+            .filter(Column::Role.eq(OrgRole::Owner))
+            .count(db)
+    }
+
+    /// Adds the `user` as a member of the organization that has verified
+    /// the domain of their email address, responds with the membership
+    /// when the user was added
+    ///
+    /// Users created through an identity provider belonging to the
+    /// `provider_organization` only join that organization, and become
+    /// managed by it when they aren't already managed. Users joining
+    /// without a provider organization are never made managed
+    ///
+    /// Must only be used once the email address of the user is verified
+    pub async fn join_by_email_domain<C>(
+        db: &C,
+        user: &User,
+        provider_organization: Option<OrganizationId>,
+    ) -> DbResult<Option<Self>>
+    where
+        C: ConnectionTrait,
+    {
+        let Some(claim) = OrganizationDomain::find_verified_for_email(db, &user.email).await?
+        else {
+            return Ok(None);
+        };
+
+        let Some(join) = DomainJoin::for_claim(&claim, provider_organization) else {
+            return Ok(None);
+        };
+
+        if join.managed && user.managed_by.is_none() {
+            user.clone()
+                .set_managed_by(db, Some(join.organization_id))
+                .await?;
+        }
+
+        if Self::find(db, join.organization_id, user.id)
+            .await?
+            .is_some()
+        {
+            return Ok(None);
+        }
+
+        Self::create(db, join.organization_id, user.id, join.role)
+            .await
+            .map(Some)
+    }
+
+    /// Changes the role of the member to the provided `role`
+    pub fn set_role<C>(
+        self,
+        db: &C,
+        role: OrgRole,
+    ) -> impl Future<Output = DbResult<OrganizationMember>> + '_
+    where
+        C: ConnectionTrait,
+    {
+        let mut model = self.into_active_model();
+        model.role = Set(role);
+        model.update(db)
+    }
+
+    /// Removes the member from the organization
+    pub fn remove<C>(self, db: &C) -> impl Future<Output = DbResult<DeleteResult>> + '_
+    where
+        C: ConnectionTrait,
+    {
+        Entity::delete_by_id((self.organization_id, self.user_id)).exec(db)
+    }
+}
+
+impl Related<super::organization::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Organization.def()
+    }
+}
+
+impl Related<super::user::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::User.def()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{DomainJoin, OrgRole};
+    use crate::database::entities::organization::OrganizationId;
+    use crate::database::entities::organization_domain::OrganizationDomain;
+    use chrono::Utc;
+
+    fn claim(organization_id: OrganizationId, verified: bool) -> OrganizationDomain {
+        let now = Utc::now().naive_utc();
+        OrganizationDomain {
+            id: 1,
+            organization_id,
+            domain: "example.com".to_string(),
+            verification_token: "token".to_string(),
+            verified_at: verified.then_some(now),
+            created_at: now,
+        }
+    }
+
+    /// Tests that a verified email on a claimed domain produces a membership
+    /// without making the user managed by the organization
+    #[test]
+    fn test_domain_join_verified_email() {
+        assert_eq!(
+            DomainJoin::for_claim(&claim(1, true), None),
+            Some(DomainJoin {
+                organization_id: 1,
+                role: OrgRole::Member,
+                managed: false
+            })
+        );
+    }
+
+    /// Tests that users from the identity provider of the organization
+    /// join as managed members
+    #[test]
+    fn test_domain_join_provider() {
+        assert_eq!(
+            DomainJoin::for_claim(&claim(1, true), Some(1)),
+            Some(DomainJoin {
+                organization_id: 1,
+                role: OrgRole::Member,
+                managed: true
+            })
+        );
+    }
+
+    /// Tests that unverified claims and claims made by an organization other
+    /// than the provider organization never produce a membership
+    #[test]
+    fn test_domain_join_rejected() {
+        assert_eq!(DomainJoin::for_claim(&claim(1, false), None), None);
+        assert_eq!(DomainJoin::for_claim(&claim(1, false), Some(1)), None);
+        assert_eq!(DomainJoin::for_claim(&claim(2, true), Some(1)), None);
+    }
+}
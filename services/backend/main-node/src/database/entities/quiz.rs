@@ -1,11 +1,17 @@
+use crate::database::models::quiz::QuizData;
 use crate::database::DbResult;
 use chrono::Utc;
+use garde::Validate;
 use sea_orm::entity::prelude::*;
+use sea_orm::sea_query::Expr;
 use sea_orm::{ActiveValue::Set, ConnectionTrait};
+use sea_orm::{Condition, FromQueryResult, JoinType, QuerySelect, UpdateResult};
+use sea_orm::{IntoActiveModel, Order, Paginator, QueryOrder, SelectModel};
 use serde::{Deserialize, Serialize};
-use serde_json::Map;
 use std::future::Future;
 
+use super::organization::OrganizationId;
+use super::resource::ResourceId;
 use super::user::{User, UserId};
 
 pub type QuizId = i32;
@@ -23,9 +29,21 @@ pub struct Model {
     pub description: String,
     pub state: QuizState,
     pub visibility: QuizVisibility,
-    pub cover_image: Option<String>,
-    pub data: serde_json::Value,
+    pub cover_image: Option<ResourceId>,
+    /// The questions and other quiz data
+    #[sea_orm(column_type = "Json")]
+    pub data: QuizData,
+    /// Tags used for discovering the quiz
+    pub tags: Vec<String>,
+    /// Number of times the quiz has been played
+    pub play_count: i64,
     pub owner: UserId,
+    /// The organization that owns the quiz, organization members
+    /// are given access based on their role
+    pub organization_id: Option<OrganizationId>,
+    /// When the quiz was hidden by a moderator, hidden quizzes can't
+    /// be made public by their owner
+    pub hidden_at: Option<DateTime>,
     /// When this quiz was created
     pub created_at: DateTime,
     /// When this quiz was updated
@@ -52,6 +70,96 @@ pub enum QuizVisibility {
     Public,
 }
 
+/// Changes to apply to a quiz, fields that are [None] are left unchanged
+#[derive(Default)]
+pub struct UpdateQuiz {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub cover_image: Option<Option<ResourceId>>,
+    pub visibility: Option<QuizVisibility>,
+    pub tags: Option<Vec<String>>,
+    pub data: Option<QuizData>,
+}
+
+/// SQL expression for the number of questions within a quiz
+const QUESTION_COUNT_EXPR: &str = r#"COALESCE(json_array_length("quiz"."data" -> 'questions'), 0)"#;
+
+/// Ordering used when browsing public quizzes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BrowseSort {
+    /// Most recently created quizzes first
+    #[default]
+    Recent,
+    /// Most played quizzes first
+    Popular,
+}
+
+/// Position within a browse listing, contains the sort value and
+/// ID of the last quiz that was seen
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BrowseCursor {
+    /// Creation timestamp (in microseconds) or play count depending
+    /// on the sort that was used
+    pub value: i64,
+    /// The ID of the last quiz
+    pub id: QuizId,
+}
+
+impl BrowseCursor {
+    /// Value of the sort column at the cursor position for the provided
+    /// `sort`, [None] when the value isn't valid for the sort
+    pub fn sort_value(&self, sort: BrowseSort) -> Option<Value> {
+        match sort {
+            BrowseSort::Recent => chrono::DateTime::from_timestamp_micros(self.value)
+                .map(|value| value.naive_utc().into()),
+            BrowseSort::Popular => Some(self.value.into()),
+        }
+    }
+}
+
+/// Filters for browsing public quizzes
+#[derive(Default)]
+pub struct BrowseFilter {
+    /// Full-text search query
+    pub query: Option<String>,
+    /// Tag the quiz must have
+    pub tag: Option<String>,
+    /// Username of the quiz owner
+    pub owner: Option<String>,
+    /// Minimum number of questions
+    pub min_questions: Option<u32>,
+    /// Maximum number of questions
+    pub max_questions: Option<u32>,
+}
+
+/// Public summary of a quiz shown when browsing
+#[derive(Debug, Clone, FromQueryResult, Serialize)]
+pub struct QuizSummary {
+    pub id: QuizId,
+    pub title: String,
+    pub description: String,
+    pub cover_image: Option<ResourceId>,
+    pub tags: Vec<String>,
+    pub play_count: i64,
+    pub question_count: i32,
+    /// Username of the user who owns the quiz
+    pub owner_username: String,
+    pub created_at: DateTime,
+}
+
+impl QuizSummary {
+    /// Creates a cursor positioned after this quiz for the provided `sort`
+    pub fn cursor(&self, sort: BrowseSort) -> BrowseCursor {
+        let value = match sort {
+            BrowseSort::Recent => self.created_at.and_utc().timestamp_micros(),
+            BrowseSort::Popular => self.play_count,
+        };
+
+        BrowseCursor { value, id: self.id }
+    }
+}
+
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {
     #[sea_orm(
@@ -60,6 +168,14 @@ pub enum Relation {
         to = "super::user::Column::Id"
     )]
     User,
+    #[sea_orm(has_many = "super::quiz_permission::Entity")]
+    Permissions,
+    #[sea_orm(
+        belongs_to = "super::organization::Entity",
+        from = "Column::OrganizationId",
+        to = "super::organization::Column::Id"
+    )]
+    Organization,
 }
 
 #[async_trait::async_trait]
@@ -68,10 +184,19 @@ impl ActiveModelBehavior for ActiveModel {
     /// the current date time.
     ///
     /// If the save is an insertion the `created_at` field will also be updated
+    ///
+    /// Requests are validated when they're extracted, any quiz data that is
+    /// being written is validated again here as a defensive check to ensure
+    /// invalid data never makes it into the database
     async fn before_save<C>(mut self, _db: &C, insert: bool) -> Result<Self, DbErr>
     where
         C: ConnectionTrait,
     {
+        if let Set(data) = &self.data {
+            data.validate(&())
+                .map_err(|err| DbErr::Custom(format!("Invalid quiz data: {err}")))?;
+        }
+
         let now = Utc::now().naive_utc();
         self.updated_at = Set(now);
 
@@ -84,10 +209,11 @@ impl ActiveModelBehavior for ActiveModel {
 }
 
 impl Model {
-    /// Create a new quiz
+    /// Create a new quiz, optionally owned by the `organization`
     pub fn create<'db, C>(
         db: &'db C,
         owner: &User,
+        organization: Option<OrganizationId>,
         title: String,
     ) -> impl Future<Output = DbResult<Quiz>> + 'db
     where
@@ -99,8 +225,11 @@ impl Model {
             state: Set(QuizState::Draft),
             visibility: Set(QuizVisibility::Private),
             cover_image: Set(None),
-            data: Set(serde_json::Value::Object(Map::new())),
+            data: Set(QuizData::default()),
+            tags: Set(Vec::new()),
+            play_count: Set(0),
             owner: Set(owner.id),
+            organization_id: Set(organization),
             ..Default::default()
         }
         .insert(db)
@@ -113,6 +242,246 @@ impl Model {
     {
         Entity::find_by_id(id).one(db)
     }
+
+    /// Creates a paginator over the quizzes owned by the provided `owner`
+    /// ordered by the provided `column` in the provided `order`
+    pub fn find_by_owner<'db, C>(
+        db: &'db C,
+        owner: &User,
+        column: Column,
+        order: Order,
+        page_size: u64,
+    ) -> Paginator<'db, C, SelectModel<Quiz>>
+    where
+        C: ConnectionTrait,
+    {
+        Entity::find()
+            .filter(Column::Owner.eq(owner.id))
+            .order_by(column, order)
+            // Secondary ordering to keep pages stable
+            .order_by_asc(Column::Id)
+            .paginate(db, page_size)
+    }
+
+    /// Finds the quizzes that the provided `user` has been granted a
+    /// role on by their owners, most recently updated first
+    pub fn find_shared_with<'db, C>(
+        db: &'db C,
+        user: &User,
+    ) -> impl Future<Output = DbResult<Vec<Quiz>>> + 'db
+    where
+        C: ConnectionTrait,
+    {
+        Entity::find()
+            .join(JoinType::InnerJoin, Relation::Permissions.def())
+            .filter(super::quiz_permission::Column::UserId.eq(user.id))
+            .order_by_desc(Column::UpdatedAt)
+            .all(db)
+    }
+
+    /// Finds the quizzes owned by the organization, most recently
+    /// updated first
+    pub fn find_by_organization<C>(
+        db: &C,
+        organization_id: OrganizationId,
+    ) -> impl Future<Output = DbResult<Vec<Quiz>>> + '_
+    where
+        C: ConnectionTrait,
+    {
+        Entity::find()
+            .filter(Column::OrganizationId.eq(organization_id))
+            .order_by_desc(Column::UpdatedAt)
+            .all(db)
+    }
+
+    /// Finds a page of published public quizzes matching the provided `filter`
+    /// starting after the provided `cursor`
+    pub fn browse<'db, C>(
+        db: &'db C,
+        filter: BrowseFilter,
+        sort: BrowseSort,
+        cursor: Option<BrowseCursor>,
+        limit: u64,
+    ) -> impl Future<Output = DbResult<Vec<QuizSummary>>> + 'db
+    where
+        C: ConnectionTrait,
+    {
+        let mut select = Entity::find()
+            .select_only()
+            .columns([
+                Column::Id,
+                Column::Title,
+                Column::Description,
+                Column::CoverImage,
+                Column::Tags,
+                Column::PlayCount,
+                Column::CreatedAt,
+            ])
+            .column_as(super::user::Column::Username, "owner_username")
+            .column_as(Expr::cust(QUESTION_COUNT_EXPR), "question_count")
+            .join(JoinType::InnerJoin, Relation::User.def())
+            .filter(Column::State.eq(QuizState::Published))
+            .filter(Column::Visibility.eq(QuizVisibility::Public));
+
+        if let Some(query) = filter.query {
+            select = select.filter(Expr::cust_with_values(
+                r#""quiz"."search_vector" @@ websearch_to_tsquery('english', $1)"#,
+                [query],
+            ));
+        }
+
+        if let Some(tag) = filter.tag {
+            // Containment can use the GIN index on the tags
+            select = select.filter(Expr::cust_with_values(
+                r#""quiz"."tags" @> ARRAY[$1]::varchar[]"#,
+                [tag],
+            ));
+        }
+
+        if let Some(owner) = filter.owner {
+            select = select.filter(super::user::Column::Username.eq(owner));
+        }
+
+        if let Some(min_questions) = filter.min_questions {
+            select = select.filter(Expr::cust_with_values(
+                format!("{QUESTION_COUNT_EXPR} >= $1"),
+                [min_questions],
+            ));
+        }
+
+        if let Some(max_questions) = filter.max_questions {
+            select = select.filter(Expr::cust_with_values(
+                format!("{QUESTION_COUNT_EXPR} <= $1"),
+                [max_questions],
+            ));
+        }
+
+        let sort_column = match sort {
+            BrowseSort::Recent => Column::CreatedAt,
+            BrowseSort::Popular => Column::PlayCount,
+        };
+
+        // Continue from after the cursor position, cursors are checked to
+        // have a valid value for the sort when they are decoded
+        if let Some((value, id)) =
+            cursor.and_then(|cursor| Some((cursor.sort_value(sort)?, cursor.id)))
+        {
+            select = select.filter(
+                Condition::any().add(sort_column.lt(value.clone())).add(
+                    Condition::all()
+                        .add(sort_column.eq(value))
+                        .add(Column::Id.lt(id)),
+                ),
+            );
+        }
+
+        select
+            .order_by(sort_column, Order::Desc)
+            .order_by(Column::Id, Order::Desc)
+            .limit(limit)
+            .into_model::<QuizSummary>()
+            .all(db)
+    }
+
+    /// Applies the provided `update` to the quiz
+    pub fn update<C>(self, db: &C, update: UpdateQuiz) -> impl Future<Output = DbResult<Quiz>> + '_
+    where
+        C: ConnectionTrait,
+    {
+        let mut model = self.into_active_model();
+
+        if let Some(title) = update.title {
+            model.title = Set(title);
+        }
+
+        if let Some(description) = update.description {
+            model.description = Set(description);
+        }
+
+        if let Some(cover_image) = update.cover_image {
+            model.cover_image = Set(cover_image);
+        }
+
+        if let Some(visibility) = update.visibility {
+            model.visibility = Set(visibility);
+        }
+
+        if let Some(tags) = update.tags {
+            model.tags = Set(tags);
+        }
+
+        if let Some(data) = update.data {
+            model.data = Set(data);
+        }
+
+        model.update(db)
+    }
+
+    /// Increments the number of times the quiz with the provided `id`
+    /// has been played
+    pub fn increment_play_count<C>(
+        db: &C,
+        id: QuizId,
+    ) -> impl Future<Output = DbResult<UpdateResult>> + '_
+    where
+        C: ConnectionTrait,
+    {
+        Entity::update_many()
+            .col_expr(Column::PlayCount, Expr::col(Column::PlayCount).add(1))
+            .filter(Column::Id.eq(id))
+            .exec(db)
+    }
+
+    /// Creates a paginator over the quizzes hidden by moderators, most
+    /// recently hidden first
+    pub fn find_hidden<C>(db: &C, page_size: u64) -> Paginator<'_, C, SelectModel<Quiz>>
+    where
+        C: ConnectionTrait,
+    {
+        Entity::find()
+            .filter(Column::HiddenAt.is_not_null())
+            .order_by_desc(Column::HiddenAt)
+            // Secondary ordering to keep pages stable
+            .order_by_asc(Column::Id)
+            .paginate(db, page_size)
+    }
+
+    /// Hides the quiz at the current time or allows it to be made public
+    /// again based on `hidden`, hiding a quiz also makes it private
+    pub fn set_hidden<C>(self, db: &C, hidden: bool) -> impl Future<Output = DbResult<Quiz>> + '_
+    where
+        C: ConnectionTrait,
+    {
+        self.into_hidden_model(hidden).update(db)
+    }
+
+    /// Creates the active model for changing whether the quiz is `hidden`
+    fn into_hidden_model(self, hidden: bool) -> ActiveModel {
+        let mut model = self.into_active_model();
+        model.hidden_at = Set(hidden.then(|| Utc::now().naive_utc()));
+
+        if hidden {
+            model.visibility = Set(QuizVisibility::Private);
+        }
+
+        model
+    }
+
+    /// Whether the quiz can be given the provided `visibility`, quizzes
+    /// hidden by moderators must stay private until they're unhidden
+    pub fn allows_visibility(&self, visibility: &QuizVisibility) -> bool {
+        *visibility == QuizVisibility::Private || self.hidden_at.is_none()
+    }
+
+    /// Sets the state of the quiz
+    pub fn set_state<C>(self, db: &C, state: QuizState) -> impl Future<Output = DbResult<Quiz>> + '_
+    where
+        C: ConnectionTrait,
+    {
+        let mut model = self.into_active_model();
+        model.state = Set(state);
+        model.update(db)
+    }
 }
 
 impl Related<super::user::Entity> for Entity {
@@ -120,3 +489,73 @@ impl Related<super::user::Entity> for Entity {
         Relation::User.def()
     }
 }
+
+impl Related<super::quiz_permission::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Permissions.def()
+    }
+}
+
+impl Related<super::organization::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Organization.def()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Quiz, QuizState, QuizVisibility};
+    use crate::database::models::quiz::QuizData;
+    use chrono::Utc;
+    use sea_orm::ActiveValue::{Set, Unchanged};
+
+    fn quiz(visibility: QuizVisibility, hidden: bool) -> Quiz {
+        let now = Utc::now().naive_utc();
+        Quiz {
+            id: 1,
+            title: "Quiz".to_string(),
+            description: String::new(),
+            state: QuizState::Published,
+            visibility,
+            cover_image: None,
+            data: QuizData::default(),
+            tags: Vec::new(),
+            play_count: 0,
+            owner: 1,
+            organization_id: None,
+            hidden_at: hidden.then_some(now),
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    /// Tests that hiding a quiz records when it was hidden and makes it private
+    #[test]
+    fn test_hide() {
+        let model = quiz(QuizVisibility::Public, false).into_hidden_model(true);
+
+        assert!(matches!(model.hidden_at, Set(Some(_))));
+        assert_eq!(model.visibility, Set(QuizVisibility::Private));
+    }
+
+    /// Tests that unhiding a quiz leaves it private for the owner to publish
+    #[test]
+    fn test_unhide() {
+        let model = quiz(QuizVisibility::Private, true).into_hidden_model(false);
+
+        assert_eq!(model.hidden_at, Set(None));
+        assert_eq!(model.visibility, Unchanged(QuizVisibility::Private));
+    }
+
+    /// Tests that hidden quizzes can't be made public
+    #[test]
+    fn test_hidden_visibility() {
+        let hidden = quiz(QuizVisibility::Private, true);
+        assert!(hidden.allows_visibility(&QuizVisibility::Private));
+        assert!(!hidden.allows_visibility(&QuizVisibility::Public));
+
+        let visible = quiz(QuizVisibility::Private, false);
+        assert!(visible.allows_visibility(&QuizVisibility::Private));
+        assert!(visible.allows_visibility(&QuizVisibility::Public));
+    }
+}
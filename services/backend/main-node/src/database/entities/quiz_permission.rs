@@ -0,0 +1,226 @@
+use crate::database::DbResult;
+use chrono::Utc;
+use sea_orm::entity::prelude::*;
+use sea_orm::{ActiveValue::Set, ConnectionTrait, DeleteResult, FromQueryResult, IntoActiveModel};
+use sea_orm::{JoinType, QueryOrder, QuerySelect};
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+
+use super::quiz::QuizId;
+use super::user::{self, UserId};
+
+pub type QuizPermission = Model;
+pub type QuizPermissionEntity = Entity;
+pub type QuizPermissionActiveModel = ActiveModel;
+
+/// Database structure for a role granted to a collaborator of a quiz
+#[derive(Debug, Clone, PartialEq, DeriveEntityModel, Serialize)]
+#[sea_orm(table_name = "permissions")]
+pub struct Model {
+    /// The quiz the role is granted for
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub quiz_id: QuizId,
+    /// The user the role is granted to
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub user_id: UserId,
+    /// The granted role
+    pub role: QuizRole,
+    /// When the role was granted
+    pub created_at: DateTime,
+    /// When the role was last changed
+    pub updated_at: DateTime,
+}
+
+/// Roles that can be granted to collaborators of a quiz
+#[derive(Debug, Clone, Copy, EnumIter, PartialEq, Eq, DeriveActiveEnum, Serialize, Deserialize)]
+#[sea_orm(rs_type = "i32", db_type = "Integer")]
+pub enum QuizRole {
+    /// Can view the quiz
+    #[sea_orm(num_value = 0)]
+    Viewer,
+    /// Can view and make changes to the quiz
+    #[sea_orm(num_value = 1)]
+    Editor,
+    /// Can view the quiz, host games and view their reports
+    #[sea_orm(num_value = 2)]
+    Host,
+    /// Can do anything other than delete the quiz
+    #[sea_orm(num_value = 3)]
+    Admin,
+}
+
+/// Actions that can be performed on a quiz
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuizAction {
+    /// Viewing the quiz details and data
+    View,
+    /// Making changes to the quiz details and data
+    Edit,
+    /// Hosting games of the quiz
+    Host,
+    /// Viewing reports for games of the quiz
+    ViewReports,
+    /// Publishing and unpublishing the quiz
+    Publish,
+    /// Granting, changing and revoking collaborator roles
+    ManageCollaborators,
+    /// Deleting the quiz
+    Delete,
+}
+
+impl QuizRole {
+    /// Checks whether this role allows the provided `action`
+    pub fn allows(&self, action: QuizAction) -> bool {
+        match self {
+            QuizRole::Viewer => matches!(action, QuizAction::View),
+            QuizRole::Editor => matches!(action, QuizAction::View | QuizAction::Edit),
+            QuizRole::Host => matches!(
+                action,
+                QuizAction::View | QuizAction::Host | QuizAction::ViewReports
+            ),
+            QuizRole::Admin => !matches!(action, QuizAction::Delete),
+        }
+    }
+}
+
+/// Collaborator of a quiz along with the user details
+#[derive(Debug, Clone, FromQueryResult, Serialize)]
+pub struct Collaborator {
+    /// ID of the collaborating user
+    pub user_id: UserId,
+    /// Username of the collaborating user
+    pub username: String,
+    /// Display name of the collaborating user
+    pub name: Option<String>,
+    /// The granted role
+    pub role: QuizRole,
+    /// When the role was granted
+    pub created_at: DateTime,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::quiz::Entity",
+        from = "Column::QuizId",
+        to = "super::quiz::Column::Id"
+    )]
+    Quiz,
+    #[sea_orm(
+        belongs_to = "super::user::Entity",
+        from = "Column::UserId",
+        to = "super::user::Column::Id"
+    )]
+    User,
+}
+
+#[async_trait::async_trait]
+impl ActiveModelBehavior for ActiveModel {
+    /// Handles updating the `updated_at` field before the model is saved, using
+    /// the current date time.
+    ///
+    /// If the save is an insertion the `created_at` field will also be updated
+    async fn before_save<C>(mut self, _db: &C, insert: bool) -> Result<Self, DbErr>
+    where
+        C: ConnectionTrait,
+    {
+        let now = Utc::now().naive_utc();
+        self.updated_at = Set(now);
+
+        if insert {
+            self.created_at = Set(now);
+        }
+
+        Ok(self)
+    }
+}
+
+impl Model {
+    /// Grants the provided `role` on the quiz to the user
+    pub fn create<C>(
+        db: &C,
+        quiz_id: QuizId,
+        user_id: UserId,
+        role: QuizRole,
+    ) -> impl Future<Output = DbResult<QuizPermission>> + '_
+    where
+        C: ConnectionTrait,
+    {
+        ActiveModel {
+            quiz_id: Set(quiz_id),
+            user_id: Set(user_id),
+            role: Set(role),
+            ..Default::default()
+        }
+        .insert(db)
+    }
+
+    /// Finds the role granted to the user on the quiz if one was granted
+    pub fn find<C>(
+        db: &C,
+        quiz_id: QuizId,
+        user_id: UserId,
+    ) -> impl Future<Output = DbResult<Option<QuizPermission>>> + '_
+    where
+        C: ConnectionTrait,
+    {
+        Entity::find_by_id((quiz_id, user_id)).one(db)
+    }
+
+    /// Finds all the collaborators of the quiz in the order they
+    /// were granted access
+    pub fn find_collaborators<C>(
+        db: &C,
+        quiz_id: QuizId,
+    ) -> impl Future<Output = DbResult<Vec<Collaborator>>> + '_
+    where
+        C: ConnectionTrait,
+    {
+        Entity::find()
+            .select_only()
+            .column(Column::UserId)
+            .column(user::Column::Username)
+            .column(user::Column::Name)
+            .column(Column::Role)
+            .column(Column::CreatedAt)
+            .join(JoinType::InnerJoin, Relation::User.def())
+            .filter(Column::QuizId.eq(quiz_id))
+            .order_by_asc(Column::CreatedAt)
+            .into_model::<Collaborator>()
+            .all(db)
+    }
+
+    /// Changes the granted role to the provided `role`
+    pub fn set_role<C>(
+        self,
+        db: &C,
+        role: QuizRole,
+    ) -> impl Future<Output = DbResult<QuizPermission>> + '_
+    where
+        C: ConnectionTrait,
+    {
+        let mut model = self.into_active_model();
+        model.role = Set(role);
+        model.update(db)
+    }
+
+    /// Revokes the granted role
+    pub fn revoke<C>(self, db: &C) -> impl Future<Output = DbResult<DeleteResult>> + '_
+    where
+        C: ConnectionTrait,
+    {
+        Entity::delete_by_id((self.quiz_id, self.user_id)).exec(db)
+    }
+}
+
+impl Related<super::quiz::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Quiz.def()
+    }
+}
+
+impl Related<super::user::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::User.def()
+    }
+}
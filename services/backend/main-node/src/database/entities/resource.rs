@@ -1,13 +1,12 @@
+use crate::database::models::resource::{variant_key, ImageVariant, ResourceVariants};
 use crate::database::DbResult;
-use crate::services::auth::AuthProvider;
 use chrono::Utc;
 use sea_orm::entity::prelude::*;
-use sea_orm::ActiveValue::NotSet;
-use sea_orm::{ActiveValue::Set, ConnectionTrait};
+use sea_orm::{ActiveValue::Set, ConnectionTrait, QueryOrder};
 use serde::{Deserialize, Serialize};
-use serde_json::Map;
 use std::future::Future;
 
+use super::organization::OrganizationId;
 use super::user::{User, UserId};
 
 pub type ResourceId = i32;
@@ -15,30 +14,64 @@ pub type Resource = Model;
 pub type ResourceEntity = Entity;
 pub type ResourceActiveModel = ActiveModel;
 
+/// Database structure for an uploaded resource such as an image
 #[derive(Debug, Clone, PartialEq, DeriveEntityModel, Serialize)]
-#[sea_orm(table_name = "quiz")]
+#[sea_orm(table_name = "resources")]
 pub struct Model {
     #[sea_orm(primary_key)]
     pub id: ResourceId,
+    /// The type of content
     pub mime_type: String,
+    /// The content name
     pub name: String,
-    pub description: String,
+    /// Optional description for the content
+    pub description: Option<String>,
+    /// Key the content is stored under
+    #[serde(skip)]
     pub path: String,
+    /// The user that the resource belongs to
     pub owner: UserId,
+    /// The organization that owns the resource, private resources
+    /// are visible to members of the organization
+    pub organization_id: Option<OrganizationId>,
+    /// Who the resource is visible to
     pub visibility: ResourceVisibility,
+    /// When the resource was created
     pub created_at: DateTime,
+    /// Width of the image in pixels
+    pub width: i32,
+    /// Height of the image in pixels
+    pub height: i32,
+    /// Processed variants of the image
+    #[sea_orm(column_type = "Json")]
+    pub variants: ResourceVariants,
 }
 
 #[derive(Debug, Clone, Default, EnumIter, PartialEq, DeriveActiveEnum, Serialize, Deserialize)]
 #[sea_orm(rs_type = "i32", db_type = "Integer")]
 pub enum ResourceVisibility {
+    /// Only visible to the owner
     #[default]
     #[sea_orm(num_value = 0)]
     Private,
+    /// Visible to anyone, including players in a game
     #[sea_orm(num_value = 1)]
     Public,
 }
 
+/// Details for creating a resource
+pub struct CreateResource {
+    pub mime_type: String,
+    pub name: String,
+    pub description: Option<String>,
+    pub path: String,
+    pub organization_id: Option<OrganizationId>,
+    pub visibility: ResourceVisibility,
+    pub width: i32,
+    pub height: i32,
+    pub variants: ResourceVariants,
+}
+
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {
     #[sea_orm(
@@ -47,22 +80,23 @@ pub enum Relation {
         to = "super::user::Column::Id"
     )]
     User,
+    #[sea_orm(
+        belongs_to = "super::organization::Entity",
+        from = "Column::OrganizationId",
+        to = "super::organization::Column::Id"
+    )]
+    Organization,
 }
 
 #[async_trait::async_trait]
 impl ActiveModelBehavior for ActiveModel {
-    /// Handles updating the `updated_at` field before the model is saved, using
-    /// the current date time.
-    ///
-    /// If the save is an insertion the `created_at` field will also be updated
+    /// Handles setting the `created_at` field when the model is inserted
     async fn before_save<C>(mut self, _db: &C, insert: bool) -> Result<Self, DbErr>
     where
         C: ConnectionTrait,
     {
-        let now = Utc::now().naive_utc();
-
         if insert {
-            self.created_at = Set(now);
+            self.created_at = Set(Utc::now().naive_utc());
         }
 
         Ok(self)
@@ -70,7 +104,32 @@ impl ActiveModelBehavior for ActiveModel {
 }
 
 impl Model {
-    /// Finds a quiz by its ID
+    /// Creates a new resource owned by the provided `owner`
+    pub fn create<'db, C>(
+        db: &'db C,
+        owner: &User,
+        create: CreateResource,
+    ) -> impl Future<Output = DbResult<Resource>> + 'db
+    where
+        C: ConnectionTrait,
+    {
+        ActiveModel {
+            mime_type: Set(create.mime_type),
+            name: Set(create.name),
+            description: Set(create.description),
+            path: Set(create.path),
+            owner: Set(owner.id),
+            organization_id: Set(create.organization_id),
+            visibility: Set(create.visibility),
+            width: Set(create.width),
+            height: Set(create.height),
+            variants: Set(create.variants),
+            ..Default::default()
+        }
+        .insert(db)
+    }
+
+    /// Finds a resource by its ID
     pub fn find_by_id<C>(
         db: &C,
         id: ResourceId,
@@ -80,6 +139,81 @@ impl Model {
     {
         Entity::find_by_id(id).one(db)
     }
+
+    /// Finds all the resources with the provided `ids`
+    pub fn find_by_ids<C>(
+        db: &C,
+        ids: Vec<ResourceId>,
+    ) -> impl Future<Output = DbResult<Vec<Resource>>> + '_
+    where
+        C: ConnectionTrait,
+    {
+        Entity::find().filter(Column::Id.is_in(ids)).all(db)
+    }
+
+    /// Finds all the resources owned by the provided `owner` with
+    /// the most recent first
+    pub fn find_by_owner<'db, C>(
+        db: &'db C,
+        owner: &User,
+    ) -> impl Future<Output = DbResult<Vec<Resource>>> + 'db
+    where
+        C: ConnectionTrait,
+    {
+        Entity::find()
+            .filter(Column::Owner.eq(owner.id))
+            .order_by_desc(Column::CreatedAt)
+            .all(db)
+    }
+
+    /// Finds all the resources owned by the organization with
+    /// the most recent first
+    pub fn find_by_organization<C>(
+        db: &C,
+        organization_id: OrganizationId,
+    ) -> impl Future<Output = DbResult<Vec<Resource>>> + '_
+    where
+        C: ConnectionTrait,
+    {
+        Entity::find()
+            .filter(Column::OrganizationId.eq(organization_id))
+            .order_by_desc(Column::CreatedAt)
+            .all(db)
+    }
+
+    /// Provides the storage key for the content of the provided `variant`,
+    /// the original is used when the variant doesn't exist
+    pub fn content_key(&self, variant: Option<ImageVariant>) -> String {
+        match variant {
+            Some(variant) if self.variants.get(variant).is_some() => {
+                variant_key(&self.path, variant)
+            }
+            _ => self.path.clone(),
+        }
+    }
+
+    /// Provides the storage keys for the original content and all
+    /// of its variants
+    pub fn storage_keys(&self) -> Vec<String> {
+        std::iter::once(self.path.clone())
+            .chain(
+                self.variants
+                    .0
+                    .iter()
+                    .map(|value| variant_key(&self.path, value.variant)),
+            )
+            .collect()
+    }
+
+    /// Checks whether the resource can be viewed by the provided `user`,
+    /// [None] for requests that aren't authenticated. Members of the
+    /// organization that owns the resource must be checked separately
+    pub fn is_visible_to(&self, user: Option<&User>) -> bool {
+        match self.visibility {
+            ResourceVisibility::Public => true,
+            ResourceVisibility::Private => user.is_some_and(|user| user.id == self.owner),
+        }
+    }
 }
 
 impl Related<super::user::Entity> for Entity {
@@ -87,3 +221,9 @@ impl Related<super::user::Entity> for Entity {
         Relation::User.def()
     }
 }
+
+impl Related<super::organization::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Organization.def()
+    }
+}
@@ -0,0 +1,70 @@
+use crate::database::DbResult;
+use chrono::Utc;
+use sea_orm::entity::prelude::*;
+use sea_orm::sea_query::OnConflict;
+use sea_orm::{ActiveValue::Set, ConnectionTrait, DeleteResult, PaginatorTrait};
+
+use std::future::Future;
+
+pub type RevokedToken = Model;
+pub type RevokedTokenEntity = Entity;
+pub type RevokedTokenActiveModel = ActiveModel;
+
+/// Database structure for an access token that was revoked before
+/// it expired
+#[derive(Debug, Clone, PartialEq, DeriveEntityModel)]
+#[sea_orm(table_name = "revoked_tokens")]
+pub struct Model {
+    /// Unique ID of the revoked token
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub jti: String,
+    /// When the token expires and no longer needs to be denied
+    pub expires_at: DateTime,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+impl Model {
+    /// Revokes the token with the provided `jti` until `expires_at`,
+    /// responds with whether the token wasn't already revoked
+    pub async fn create<C>(db: &C, jti: String, expires_at: DateTime) -> DbResult<bool>
+    where
+        C: ConnectionTrait,
+    {
+        let inserted = Entity::insert(ActiveModel {
+            jti: Set(jti),
+            expires_at: Set(expires_at),
+        })
+        // Token may already be revoked
+        .on_conflict(OnConflict::column(Column::Jti).do_nothing().to_owned())
+        .exec_without_returning(db)
+        .await?;
+
+        Ok(inserted == 1)
+    }
+
+    /// Checks whether the token with the provided `jti` has been revoked
+    pub async fn is_revoked<C>(db: &C, jti: &str) -> DbResult<bool>
+    where
+        C: ConnectionTrait,
+    {
+        Entity::find()
+            .filter(Column::Jti.eq(jti))
+            .count(db)
+            .await
+            .map(|value| value > 0)
+    }
+
+    /// Deletes revoked tokens that have expired
+    pub fn delete_expired<C>(db: &C) -> impl Future<Output = DbResult<DeleteResult>> + '_
+    where
+        C: ConnectionTrait,
+    {
+        Entity::delete_many()
+            .filter(Column::ExpiresAt.lt(Utc::now().naive_utc()))
+            .exec(db)
+    }
+}
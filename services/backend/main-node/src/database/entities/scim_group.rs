@@ -0,0 +1,185 @@
+use crate::database::DbResult;
+use chrono::Utc;
+use sea_orm::entity::prelude::*;
+use sea_orm::{ActiveValue::Set, ConnectionTrait, DeleteResult, IntoActiveModel};
+use sea_orm::{PaginatorTrait, QueryOrder, QuerySelect};
+use serde::Serialize;
+use std::future::Future;
+
+use super::organization::OrganizationId;
+
+pub type ScimGroup = Model;
+pub type ScimGroupEntity = Entity;
+pub type ScimGroupActiveModel = ActiveModel;
+
+pub type ScimGroupId = i32;
+
+/// Database structure for a group provisioned from the directory
+/// of an organization through SCIM
+#[derive(Debug, Clone, PartialEq, DeriveEntityModel, Serialize)]
+#[sea_orm(table_name = "scim_groups")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: ScimGroupId,
+    /// The organization the group belongs to
+    #[serde(skip)]
+    pub organization_id: OrganizationId,
+    /// Name of the group, unique within the organization
+    pub display_name: String,
+    /// ID of the group within the directory
+    pub external_id: Option<String>,
+    /// When the group was created
+    pub created_at: DateTime,
+    /// When the group was last updated
+    pub updated_at: DateTime,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::organization::Entity",
+        from = "Column::OrganizationId",
+        to = "super::organization::Column::Id"
+    )]
+    Organization,
+    #[sea_orm(has_many = "super::scim_group_member::Entity")]
+    Members,
+}
+
+#[async_trait::async_trait]
+impl ActiveModelBehavior for ActiveModel {
+    /// Handles updating the `updated_at` field before the model is saved, using
+    /// the current date time.
+    ///
+    /// If the save is an insertion the `created_at` field will also be updated
+    async fn before_save<C>(mut self, _db: &C, insert: bool) -> Result<Self, DbErr>
+    where
+        C: ConnectionTrait,
+    {
+        let now = Utc::now().naive_utc();
+        self.updated_at = Set(now);
+
+        if insert {
+            self.created_at = Set(now);
+        }
+
+        Ok(self)
+    }
+}
+
+impl Model {
+    /// Creates a new group within the organization
+    pub fn create<C>(
+        db: &C,
+        organization_id: OrganizationId,
+        display_name: String,
+        external_id: Option<String>,
+    ) -> impl Future<Output = DbResult<ScimGroup>> + '_
+    where
+        C: ConnectionTrait,
+    {
+        ActiveModel {
+            organization_id: Set(organization_id),
+            display_name: Set(display_name),
+            external_id: Set(external_id),
+            ..Default::default()
+        }
+        .insert(db)
+    }
+
+    /// Finds a group belonging to the organization by its ID
+    pub fn find_by_id<C>(
+        db: &C,
+        organization_id: OrganizationId,
+        id: ScimGroupId,
+    ) -> impl Future<Output = DbResult<Option<ScimGroup>>> + '_
+    where
+        C: ConnectionTrait,
+    {
+        Entity::find_by_id(id)
+            .filter(Column::OrganizationId.eq(organization_id))
+            .one(db)
+    }
+
+    /// Finds a group belonging to the organization by its name
+    pub fn find_by_name<'db, C>(
+        db: &'db C,
+        organization_id: OrganizationId,
+        display_name: &str,
+    ) -> impl Future<Output = DbResult<Option<ScimGroup>>> + 'db
+    where
+        C: ConnectionTrait,
+    {
+        Entity::find()
+            .filter(Column::OrganizationId.eq(organization_id))
+            .filter(Column::DisplayName.eq(display_name))
+            .one(db)
+    }
+
+    /// Finds the groups belonging to the organization in the order they
+    /// were created, optionally only the group named `display_name`. Skips
+    /// the first `offset` groups and responds with at most `limit` groups
+    /// along with the total number of matching groups
+    pub async fn find_by_organization<C>(
+        db: &C,
+        organization_id: OrganizationId,
+        display_name: Option<&str>,
+        offset: u64,
+        limit: u64,
+    ) -> DbResult<(Vec<ScimGroup>, u64)>
+    where
+        C: ConnectionTrait,
+    {
+        let mut query = Entity::find().filter(Column::OrganizationId.eq(organization_id));
+
+        if let Some(display_name) = display_name {
+            query = query.filter(Column::DisplayName.eq(display_name));
+        }
+
+        let total = query.clone().count(db).await?;
+        let groups = query
+            .order_by_asc(Column::Id)
+            .offset(offset)
+            .limit(limit)
+            .all(db)
+            .await?;
+
+        Ok((groups, total))
+    }
+
+    /// Replaces the name and directory ID of the group
+    pub fn set_details<C>(
+        self,
+        db: &C,
+        display_name: String,
+        external_id: Option<String>,
+    ) -> impl Future<Output = DbResult<ScimGroup>> + '_
+    where
+        C: ConnectionTrait,
+    {
+        let mut model = self.into_active_model();
+        model.display_name = Set(display_name);
+        model.external_id = Set(external_id);
+        model.update(db)
+    }
+
+    /// Removes the group along with its memberships
+    pub fn remove<C>(self, db: &C) -> impl Future<Output = DbResult<DeleteResult>> + '_
+    where
+        C: ConnectionTrait,
+    {
+        Entity::delete_by_id(self.id).exec(db)
+    }
+}
+
+impl Related<super::organization::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Organization.def()
+    }
+}
+
+impl Related<super::scim_group_member::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Members.def()
+    }
+}
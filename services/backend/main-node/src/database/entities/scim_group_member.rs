@@ -0,0 +1,159 @@
+use crate::database::DbResult;
+use sea_orm::entity::prelude::*;
+use sea_orm::sea_query::{OnConflict, Query};
+use sea_orm::{ActiveValue::Set, ConnectionTrait, DeleteResult, FromQueryResult};
+use sea_orm::{JoinType, QueryOrder, QuerySelect};
+use std::future::Future;
+
+use super::organization::OrganizationId;
+use super::scim_group::{self, ScimGroupId};
+use super::user::{self, UserId};
+
+pub type ScimGroupMember = Model;
+pub type ScimGroupMemberEntity = Entity;
+pub type ScimGroupMemberActiveModel = ActiveModel;
+
+/// Database structure for a user that is a member of a SCIM group
+#[derive(Debug, Clone, PartialEq, DeriveEntityModel)]
+#[sea_orm(table_name = "scim_group_members")]
+pub struct Model {
+    /// The group the user is a member of
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub group_id: ScimGroupId,
+    /// The member user
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub user_id: UserId,
+}
+
+/// Member of a group along with the username of the member
+#[derive(Debug, Clone, FromQueryResult)]
+pub struct GroupMember {
+    /// ID of the group
+    pub group_id: ScimGroupId,
+    /// ID of the member user
+    pub user_id: UserId,
+    /// Username of the member user
+    pub username: String,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::scim_group::Entity",
+        from = "Column::GroupId",
+        to = "super::scim_group::Column::Id"
+    )]
+    Group,
+    #[sea_orm(
+        belongs_to = "super::user::Entity",
+        from = "Column::UserId",
+        to = "super::user::Column::Id"
+    )]
+    User,
+}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+impl Model {
+    /// Adds the user to the group, nothing happens if the user
+    /// is already a member
+    pub async fn create<C>(db: &C, group_id: ScimGroupId, user_id: UserId) -> DbResult<()>
+    where
+        C: ConnectionTrait,
+    {
+        Entity::insert(ActiveModel {
+            group_id: Set(group_id),
+            user_id: Set(user_id),
+        })
+        // User may already be a member
+        .on_conflict(
+            OnConflict::columns([Column::GroupId, Column::UserId])
+                .do_nothing()
+                .to_owned(),
+        )
+        .exec_without_returning(db)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Finds the members of all the provided groups
+    pub fn find_by_groups<C>(
+        db: &C,
+        group_ids: Vec<ScimGroupId>,
+    ) -> impl Future<Output = DbResult<Vec<GroupMember>>> + '_
+    where
+        C: ConnectionTrait,
+    {
+        Entity::find()
+            .select_only()
+            .column(Column::GroupId)
+            .column(Column::UserId)
+            .column(user::Column::Username)
+            .join(JoinType::InnerJoin, Relation::User.def())
+            .filter(Column::GroupId.is_in(group_ids))
+            .order_by_asc(Column::UserId)
+            .into_model::<GroupMember>()
+            .all(db)
+    }
+
+    /// Removes the user from the group
+    pub fn remove<C>(
+        db: &C,
+        group_id: ScimGroupId,
+        user_id: UserId,
+    ) -> impl Future<Output = DbResult<DeleteResult>> + '_
+    where
+        C: ConnectionTrait,
+    {
+        Entity::delete_by_id((group_id, user_id)).exec(db)
+    }
+
+    /// Removes all the members of the group
+    pub fn remove_all<C>(
+        db: &C,
+        group_id: ScimGroupId,
+    ) -> impl Future<Output = DbResult<DeleteResult>> + '_
+    where
+        C: ConnectionTrait,
+    {
+        Entity::delete_many()
+            .filter(Column::GroupId.eq(group_id))
+            .exec(db)
+    }
+
+    /// Removes the user from every group belonging to the organization
+    pub fn remove_by_organization<C>(
+        db: &C,
+        organization_id: OrganizationId,
+        user_id: UserId,
+    ) -> impl Future<Output = DbResult<DeleteResult>> + '_
+    where
+        C: ConnectionTrait,
+    {
+        Entity::delete_many()
+            .filter(Column::UserId.eq(user_id))
+            .filter(
+                Column::GroupId.in_subquery(
+                    Query::select()
+                        .column(scim_group::Column::Id)
+                        .from(scim_group::Entity)
+                        .and_where(scim_group::Column::OrganizationId.eq(organization_id))
+                        .to_owned(),
+                ),
+            )
+            .exec(db)
+    }
+}
+
+impl Related<super::scim_group::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Group.def()
+    }
+}
+
+impl Related<super::user::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::User.def()
+    }
+}
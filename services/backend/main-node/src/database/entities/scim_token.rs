@@ -0,0 +1,149 @@
+use crate::database::DbResult;
+use chrono::Utc;
+use sea_orm::entity::prelude::*;
+use sea_orm::{ActiveValue::Set, ConnectionTrait, DeleteResult, IntoActiveModel, QueryOrder};
+use serde::Serialize;
+use std::future::Future;
+
+use super::organization::OrganizationId;
+
+pub type ScimToken = Model;
+pub type ScimTokenEntity = Entity;
+pub type ScimTokenActiveModel = ActiveModel;
+
+pub type ScimTokenId = i32;
+
+/// Database structure for a bearer token used by the directory of an
+/// organization to provision users through SCIM
+#[derive(Debug, Clone, PartialEq, DeriveEntityModel, Serialize)]
+#[sea_orm(table_name = "scim_tokens")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: ScimTokenId,
+    /// The organization the token provisions users for
+    #[serde(skip)]
+    pub organization_id: OrganizationId,
+    /// Name given to the token
+    pub name: String,
+    /// Keyed hash of the token
+    #[sea_orm(unique)]
+    #[serde(skip)]
+    pub token_hash: String,
+    /// When the token was created
+    pub created_at: DateTime,
+    /// When the token was last used
+    pub last_used_at: Option<DateTime>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::organization::Entity",
+        from = "Column::OrganizationId",
+        to = "super::organization::Column::Id"
+    )]
+    Organization,
+}
+
+#[async_trait::async_trait]
+impl ActiveModelBehavior for ActiveModel {
+    /// Handles setting the `created_at` field when the model is inserted
+    async fn before_save<C>(mut self, _db: &C, insert: bool) -> Result<Self, DbErr>
+    where
+        C: ConnectionTrait,
+    {
+        if insert {
+            self.created_at = Set(Utc::now().naive_utc());
+        }
+
+        Ok(self)
+    }
+}
+
+impl Model {
+    /// Creates a new token for the organization from the already
+    /// hashed `token_hash`
+    pub fn create<C>(
+        db: &C,
+        organization_id: OrganizationId,
+        name: String,
+        token_hash: String,
+    ) -> impl Future<Output = DbResult<ScimToken>> + '_
+    where
+        C: ConnectionTrait,
+    {
+        ActiveModel {
+            organization_id: Set(organization_id),
+            name: Set(name),
+            token_hash: Set(token_hash),
+            ..Default::default()
+        }
+        .insert(db)
+    }
+
+    /// Finds a token by the keyed hash of the token
+    pub fn find_by_hash<'db, C>(
+        db: &'db C,
+        token_hash: &str,
+    ) -> impl Future<Output = DbResult<Option<ScimToken>>> + 'db
+    where
+        C: ConnectionTrait,
+    {
+        Entity::find()
+            .filter(Column::TokenHash.eq(token_hash))
+            .one(db)
+    }
+
+    /// Finds a token belonging to the organization by its ID
+    pub fn find_by_id<C>(
+        db: &C,
+        organization_id: OrganizationId,
+        id: ScimTokenId,
+    ) -> impl Future<Output = DbResult<Option<ScimToken>>> + '_
+    where
+        C: ConnectionTrait,
+    {
+        Entity::find_by_id(id)
+            .filter(Column::OrganizationId.eq(organization_id))
+            .one(db)
+    }
+
+    /// Finds all the tokens belonging to the organization in the
+    /// order they were created
+    pub fn find_by_organization<C>(
+        db: &C,
+        organization_id: OrganizationId,
+    ) -> impl Future<Output = DbResult<Vec<ScimToken>>> + '_
+    where
+        C: ConnectionTrait,
+    {
+        Entity::find()
+            .filter(Column::OrganizationId.eq(organization_id))
+            .order_by_asc(Column::CreatedAt)
+            .all(db)
+    }
+
+    /// Updates when the token was last used to the current time
+    pub fn set_used<C>(self, db: &C) -> impl Future<Output = DbResult<ScimToken>> + '_
+    where
+        C: ConnectionTrait,
+    {
+        let mut model = self.into_active_model();
+        model.last_used_at = Set(Some(Utc::now().naive_utc()));
+        model.update(db)
+    }
+
+    /// Removes the token
+    pub fn remove<C>(self, db: &C) -> impl Future<Output = DbResult<DeleteResult>> + '_
+    where
+        C: ConnectionTrait,
+    {
+        Entity::delete_by_id(self.id).exec(db)
+    }
+}
+
+impl Related<super::organization::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Organization.def()
+    }
+}
@@ -1,12 +1,16 @@
 use crate::database::DbResult;
 use crate::utils::types::{EmailAddress, Username};
 use chrono::Utc;
+use sea_orm::sea_query::{Expr, Func, LikeExpr};
 use sea_orm::{entity::prelude::*, ActiveValue};
 use sea_orm::{ActiveModelTrait, ActiveValue::Set, ConnectionTrait};
+use sea_orm::{Condition, Paginator, PaginatorTrait, QueryOrder, SelectModel};
 use sea_orm::{IntoActiveModel, QuerySelect, SelectColumns};
 use serde::{Deserialize, Serialize};
 use std::future::Future;
 
+use super::organization::OrganizationId;
+
 pub type User = Model;
 pub type UserEntity = Entity;
 pub type UserActiveModel = ActiveModel;
@@ -33,15 +37,39 @@ pub struct Model {
     /// The password associated with this account
     #[serde(skip)]
     pub password: String,
+    /// Whether the password must be reset through the password reset flow
+    /// before it can be used to login again
+    pub password_reset_required: bool,
     /// The role for this user
     pub role: UserRole,
+    /// When the user was deactivated, deactivated users can't login
+    pub deactivated_at: Option<DateTime>,
+    /// The organization that provisions and manages this user
+    pub managed_by: Option<OrganizationId>,
+    /// When the user was suspended by an administrator, suspended
+    /// users can't login
+    pub suspended_at: Option<DateTime>,
     /// When this user was created
     pub created_at: DateTime,
     /// When the last change was made to this user
     pub updated_at: DateTime,
 }
 
-#[derive(Debug, Clone, Default, EnumIter, PartialEq, DeriveActiveEnum, Serialize, Deserialize)]
+/// Roles a user can have, each role includes the permissions of
+/// the roles before it
+#[derive(
+    Debug,
+    Clone,
+    Default,
+    EnumIter,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    DeriveActiveEnum,
+    Serialize,
+    Deserialize,
+)]
 #[sea_orm(rs_type = "i32", db_type = "Integer")]
 pub enum UserRole {
     #[default]
@@ -57,6 +85,7 @@ pub enum UserRole {
 pub struct CreateUser {
     pub email: String,
     pub username: String,
+    pub name: Option<String>,
     pub password: String,
 }
 
@@ -64,8 +93,20 @@ pub struct CreateUser {
 pub enum Relation {
     #[sea_orm(has_many = "super::user_link::Entity")]
     UserLinks,
-    #[sea_orm(has_one = "super::user_refresh_token::Entity")]
-    RefreshToken,
+    #[sea_orm(has_many = "super::user_refresh_token::Entity")]
+    RefreshTokens,
+    #[sea_orm(has_one = "super::user_totp::Entity")]
+    Totp,
+    #[sea_orm(has_many = "super::user_recovery_code::Entity")]
+    RecoveryCodes,
+    #[sea_orm(has_many = "super::user_passkey::Entity")]
+    Passkeys,
+    #[sea_orm(
+        belongs_to = "super::organization::Entity",
+        from = "Column::ManagedBy",
+        to = "super::organization::Column::Id"
+    )]
+    ManagedBy,
 }
 
 #[async_trait::async_trait]
@@ -98,6 +139,27 @@ impl Model {
         create.into_active_model().insert(db)
     }
 
+    /// Create a new user managed by the provided organization, the email
+    /// is only marked as verified when `email_verified` is set and the user
+    /// starts deactivated unless `active` is set
+    pub fn create_managed<C>(
+        db: &C,
+        create: CreateUser,
+        organization_id: OrganizationId,
+        email_verified: bool,
+        active: bool,
+    ) -> impl Future<Output = DbResult<User>> + '_
+    where
+        C: ConnectionTrait,
+    {
+        let now = Utc::now().naive_utc();
+        let mut model = create.into_active_model();
+        model.managed_by = Set(Some(organization_id));
+        model.email_verified_at = Set(email_verified.then_some(now));
+        model.deactivated_at = Set((!active).then_some(now));
+        model.insert(db)
+    }
+
     /// Finds a user by its ID
     pub fn find_by_id<C>(db: &C, id: UserId) -> impl Future<Output = DbResult<Option<User>>> + '_
     where
@@ -158,6 +220,183 @@ impl Model {
             .map(|value| value > 0)
     }
 
+    /// Finds a user managed by the provided organization by its ID
+    pub fn find_managed_by_id<C>(
+        db: &C,
+        organization_id: OrganizationId,
+        id: UserId,
+    ) -> impl Future<Output = DbResult<Option<User>>> + '_
+    where
+        C: ConnectionTrait,
+    {
+        Entity::find_by_id(id)
+            .filter(Column::ManagedBy.eq(organization_id))
+            .one(db)
+    }
+
+    /// Finds the users managed by the provided organization in the order
+    /// they were created, optionally only the user with the provided
+    /// `username`. Skips the first `offset` users and responds with at
+    /// most `limit` users along with the total number of matching users
+    pub async fn find_managed<C>(
+        db: &C,
+        organization_id: OrganizationId,
+        username: Option<&str>,
+        offset: u64,
+        limit: u64,
+    ) -> DbResult<(Vec<User>, u64)>
+    where
+        C: ConnectionTrait,
+    {
+        let mut query = Entity::find().filter(Column::ManagedBy.eq(organization_id));
+
+        if let Some(username) = username {
+            query = query.filter(Column::Username.eq(username));
+        }
+
+        let total = query.clone().count(db).await?;
+        let users = query
+            .order_by_asc(Column::Id)
+            .offset(offset)
+            .limit(limit)
+            .all(db)
+            .await?;
+
+        Ok((users, total))
+    }
+
+    /// Whether the user has been deactivated
+    pub fn is_deactivated(&self) -> bool {
+        self.deactivated_at.is_some()
+    }
+
+    /// Whether the user is allowed to login, deactivated and suspended
+    /// users can't login
+    pub fn can_login(&self) -> bool {
+        self.deactivated_at.is_none() && self.suspended_at.is_none()
+    }
+
+    /// Whether the user has at least the provided `role`
+    pub fn has_role(&self, role: &UserRole) -> bool {
+        &self.role >= role
+    }
+
+    /// Deactivates the user at the current time or reactivates the user
+    /// based on `active`
+    pub fn set_active<C>(self, db: &C, active: bool) -> impl Future<Output = DbResult<User>> + '_
+    where
+        C: ConnectionTrait,
+    {
+        let mut model = self.into_active_model();
+        model.deactivated_at = Set((!active).then(|| Utc::now().naive_utc()));
+        model.update(db)
+    }
+
+    /// Suspends the user at the current time or lifts the suspension
+    /// based on `suspended`
+    pub fn set_suspended<C>(
+        self,
+        db: &C,
+        suspended: bool,
+    ) -> impl Future<Output = DbResult<User>> + '_
+    where
+        C: ConnectionTrait,
+    {
+        let mut model = self.into_active_model();
+        model.suspended_at = Set(suspended.then(|| Utc::now().naive_utc()));
+        model.update(db)
+    }
+
+    /// Changes the role of the user
+    pub fn set_role<C>(self, db: &C, role: UserRole) -> impl Future<Output = DbResult<User>> + '_
+    where
+        C: ConnectionTrait,
+    {
+        let mut model = self.into_active_model();
+        model.role = Set(role);
+        model.update(db)
+    }
+
+    /// Creates a paginator over the users matching the provided `query`
+    /// against their username, email or name, optionally only users
+    /// with the provided `role`. Ordered by when the users were created
+    pub fn search<'db, C>(
+        db: &'db C,
+        query: Option<&str>,
+        role: Option<UserRole>,
+        page_size: u64,
+    ) -> Paginator<'db, C, SelectModel<User>>
+    where
+        C: ConnectionTrait,
+    {
+        let mut select = Entity::find();
+
+        if let Some(query) = query {
+            let pattern = like_pattern(query);
+            let matches = |column: Column| {
+                Expr::expr(Func::lower(Expr::col((Entity, column))))
+                    .like(LikeExpr::new(pattern.clone()).escape('\\'))
+            };
+
+            select = select.filter(
+                Condition::any()
+                    .add(matches(Column::Username))
+                    .add(matches(Column::Email))
+                    .add(matches(Column::Name)),
+            );
+        }
+
+        if let Some(role) = role {
+            select = select.filter(Column::Role.eq(role));
+        }
+
+        select
+            .order_by_desc(Column::CreatedAt)
+            // Secondary ordering to keep pages stable
+            .order_by_asc(Column::Id)
+            .paginate(db, page_size)
+    }
+
+    /// Sets the organization that manages the user
+    pub fn set_managed_by<C>(
+        self,
+        db: &C,
+        organization_id: Option<OrganizationId>,
+    ) -> impl Future<Output = DbResult<User>> + '_
+    where
+        C: ConnectionTrait,
+    {
+        let mut model = self.into_active_model();
+        model.managed_by = Set(organization_id);
+        model.update(db)
+    }
+
+    /// Replaces the profile details of a managed user, the email is
+    /// only marked as verified when `email_verified` is set
+    pub fn set_managed_details<C>(
+        self,
+        db: &C,
+        username: String,
+        name: Option<String>,
+        email: String,
+        email_verified: bool,
+    ) -> impl Future<Output = DbResult<User>> + '_
+    where
+        C: ConnectionTrait,
+    {
+        let email_changed = self.email != email;
+        let mut model = self.into_active_model();
+        model.username = Set(username);
+        model.name = Set(name);
+
+        if email_changed {
+            model.email = Set(email);
+            model.email_verified_at = Set(email_verified.then(|| Utc::now().naive_utc()));
+        }
+
+        model.update(db)
+    }
+
     /// Sets the email for the provided user to verified at the
     /// current time
     pub fn set_email_verified<C>(self, db: &C) -> impl Future<Output = DbResult<User>> + '_
@@ -168,6 +407,33 @@ impl Model {
         model.email_verified_at = Set(Some(Utc::now().naive_utc()));
         model.update(db)
     }
+
+    /// Replaces the password of the user with the provided already
+    /// hashed `password`, clearing any required password reset
+    pub fn set_password<C>(
+        self,
+        db: &C,
+        password: String,
+    ) -> impl Future<Output = DbResult<User>> + '_
+    where
+        C: ConnectionTrait,
+    {
+        let mut model = self.into_active_model();
+        model.password = Set(password);
+        model.password_reset_required = Set(false);
+        model.update(db)
+    }
+
+    /// Requires the user to reset their password before their password
+    /// can be used to login again
+    pub fn require_password_reset<C>(self, db: &C) -> impl Future<Output = DbResult<User>> + '_
+    where
+        C: ConnectionTrait,
+    {
+        let mut model = self.into_active_model();
+        model.password_reset_required = Set(true);
+        model.update(db)
+    }
 }
 
 impl Related<super::user_link::Entity> for Entity {
@@ -178,6 +444,86 @@ impl Related<super::user_link::Entity> for Entity {
 
 impl Related<super::user_refresh_token::Entity> for Entity {
     fn to() -> RelationDef {
-        Relation::RefreshToken.def()
+        Relation::RefreshTokens.def()
+    }
+}
+
+impl Related<super::user_totp::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Totp.def()
+    }
+}
+
+/// Creates a lowercase LIKE pattern matching values containing the `query`,
+/// escaping the LIKE wildcards so they are matched literally
+fn like_pattern(query: &str) -> String {
+    let mut pattern = String::with_capacity(query.len() + 2);
+    pattern.push('%');
+
+    for char in query.to_lowercase().chars() {
+        if matches!(char, '%' | '_' | '\\') {
+            pattern.push('\\');
+        }
+        pattern.push(char);
+    }
+
+    pattern.push('%');
+    pattern
+}
+
+#[cfg(test)]
+impl Model {
+    /// Creates a standard user with the provided `id` for tests, tests
+    /// override the fields they depend on
+    pub fn test(id: UserId) -> Self {
+        let now = Utc::now().naive_utc();
+        Self {
+            id,
+            email: format!("user{id}@example.com"),
+            email_verified_at: None,
+            username: format!("user{id}"),
+            name: None,
+            password: "hash".to_string(),
+            password_reset_required: false,
+            role: UserRole::Standard,
+            deactivated_at: None,
+            managed_by: None,
+            suspended_at: None,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+}
+
+impl Related<super::user_recovery_code::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::RecoveryCodes.def()
+    }
+}
+
+impl Related<super::user_passkey::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Passkeys.def()
+    }
+}
+
+impl Related<super::organization::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ManagedBy.def()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::like_pattern;
+
+    /// Tests that search patterns are lowercased and match the LIKE
+    /// wildcards and escape character literally
+    #[test]
+    fn test_like_pattern() {
+        assert_eq!(like_pattern("Jacob"), "%jacob%");
+        assert_eq!(like_pattern("100%"), "%100\\%%");
+        assert_eq!(like_pattern("a_b"), "%a\\_b%");
+        assert_eq!(like_pattern("a\\b"), "%a\\\\b%");
     }
 }
@@ -1,8 +1,9 @@
 use crate::database::DbResult;
-use crate::services::auth::AuthProvider;
+use crate::services::openid::ProviderId;
 use chrono::Utc;
 use sea_orm::entity::prelude::*;
-use sea_orm::{ActiveValue::Set, ConnectionTrait};
+use sea_orm::{ActiveValue::Set, ConnectionTrait, IntoActiveModel, QueryOrder};
+use serde::Serialize;
 use std::future::Future;
 
 use super::user::{User, UserId};
@@ -12,16 +13,25 @@ pub type UserLinkEntity = Entity;
 pub type UserLinkActiveModel = ActiveModel;
 
 /// Database structure for a user
-#[derive(Debug, Clone, PartialEq, DeriveEntityModel)]
+#[derive(Debug, Clone, PartialEq, DeriveEntityModel, Serialize)]
 #[sea_orm(table_name = "user_links")]
 pub struct Model {
     /// Unique ID for the user
     #[sea_orm(primary_key)]
+    #[serde(skip)]
     pub user_id: UserId,
-    #[sea_orm(primary_key)]
-    pub provider: AuthProvider,
+    /// ID of the configured provider the link is for
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub provider: ProviderId,
     /// When this user was created
     pub created_at: DateTime,
+    /// Issuer of the provider ID tokens
+    #[serde(skip)]
+    pub issuer: Option<String>,
+    /// Subject identifying the account at the provider, links created
+    /// before subjects were stored won't have one until next used
+    #[serde(skip)]
+    pub subject: Option<String>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -37,21 +47,58 @@ pub enum Relation {
 impl ActiveModelBehavior for ActiveModel {}
 
 impl Model {
-    /// Create a new user link
+    /// Create a new user link to the provider account identified
+    /// by the `issuer` and `subject`
     pub fn create<'db, C>(
         db: &'db C,
         user: &User,
-        provider: AuthProvider,
-    ) -> impl Future<Output = DbResult<u64>> + 'db
+        provider: ProviderId,
+        issuer: String,
+        subject: String,
+    ) -> impl Future<Output = DbResult<UserLink>> + 'db
     where
         C: ConnectionTrait,
     {
-        Entity::insert(ActiveModel {
+        ActiveModel {
             user_id: Set(user.id),
             provider: Set(provider),
             created_at: Set(Utc::now().naive_utc()),
-        })
-        .exec_without_returning(db)
+            issuer: Set(Some(issuer)),
+            subject: Set(Some(subject)),
+        }
+        .insert(db)
+    }
+
+    /// Finds the link to the provider account with the provided `subject`
+    pub fn find_by_subject<'db, C>(
+        db: &'db C,
+        provider: &str,
+        subject: &str,
+    ) -> impl Future<Output = DbResult<Option<UserLink>>> + 'db
+    where
+        C: ConnectionTrait,
+    {
+        Entity::find()
+            .filter(Column::Provider.eq(provider))
+            .filter(Column::Subject.eq(subject))
+            .one(db)
+    }
+
+    /// Binds a link created before subjects were stored to the provider
+    /// account identified by the `issuer` and `subject`
+    pub fn set_subject<C>(
+        self,
+        db: &C,
+        issuer: String,
+        subject: String,
+    ) -> impl Future<Output = DbResult<UserLink>> + '_
+    where
+        C: ConnectionTrait,
+    {
+        let mut model = self.into_active_model();
+        model.issuer = Set(Some(issuer));
+        model.subject = Set(Some(subject));
+        model.update(db)
     }
 
     /// Finds a link to the provided `provider` for the provided `user`
@@ -59,7 +106,7 @@ impl Model {
     pub fn find_by_user<'db, C>(
         db: &'db C,
         user: &User,
-        provider: AuthProvider,
+        provider: &str,
     ) -> impl Future<Output = DbResult<Option<UserLink>>> + 'db
     where
         C: ConnectionTrait,
@@ -68,6 +115,19 @@ impl Model {
             .filter(Column::Provider.eq(provider))
             .one(db)
     }
+
+    /// Finds all the provider links for the provided `user`
+    pub fn find_all_by_user<'db, C>(
+        db: &'db C,
+        user: &User,
+    ) -> impl Future<Output = DbResult<Vec<UserLink>>> + 'db
+    where
+        C: ConnectionTrait,
+    {
+        user.find_related(Entity)
+            .order_by_asc(Column::CreatedAt)
+            .all(db)
+    }
 }
 
 impl Related<super::user::Entity> for Entity {
@@ -0,0 +1,172 @@
+use crate::database::models::passkey::StoredPasskey;
+use crate::database::DbResult;
+use chrono::Utc;
+use sea_orm::{entity::prelude::*, PaginatorTrait, QueryOrder};
+use sea_orm::{ActiveValue::Set, ConnectionTrait, IntoActiveModel};
+use serde::Serialize;
+use webauthn_rs::prelude::Passkey;
+
+use std::future::Future;
+
+use super::user::{User, UserId};
+
+pub type UserPasskey = Model;
+pub type UserPasskeyEntity = Entity;
+pub type UserPasskeyActiveModel = ActiveModel;
+
+pub type PasskeyId = i32;
+
+/// Database structure for a WebAuthn passkey a user can login with
+#[derive(Debug, Clone, PartialEq, DeriveEntityModel, Serialize)]
+#[sea_orm(table_name = "user_passkeys")]
+pub struct Model {
+    /// Unique ID for the passkey
+    #[sea_orm(primary_key)]
+    pub id: PasskeyId,
+    /// The ID of the user the passkey belongs to
+    #[serde(skip)]
+    pub user_id: UserId,
+    /// Base64url encoded ID of the credential
+    #[sea_orm(unique)]
+    #[serde(skip)]
+    pub credential_id: String,
+    /// WebAuthn user handle, shared by all the passkeys of a user
+    #[serde(skip)]
+    pub user_handle: String,
+    /// Name given to the passkey by the user
+    pub name: String,
+    /// The stored credential
+    #[sea_orm(column_type = "Json")]
+    #[serde(skip)]
+    pub passkey: StoredPasskey,
+    /// When the passkey was registered
+    pub created_at: DateTime,
+    /// When the passkey was last used to login
+    pub last_used_at: Option<DateTime>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::user::Entity",
+        from = "Column::UserId",
+        to = "super::user::Column::Id"
+    )]
+    User,
+}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+impl Model {
+    /// Stores a newly registered `passkey` for the `user`
+    pub fn create<'db, C>(
+        db: &'db C,
+        user: &User,
+        user_handle: String,
+        name: String,
+        passkey: Passkey,
+    ) -> impl Future<Output = DbResult<UserPasskey>> + 'db
+    where
+        C: ConnectionTrait,
+    {
+        ActiveModel {
+            user_id: Set(user.id),
+            credential_id: Set(credential_id(&passkey)),
+            user_handle: Set(user_handle),
+            name: Set(name),
+            passkey: Set(StoredPasskey(passkey)),
+            created_at: Set(Utc::now().naive_utc()),
+            last_used_at: Set(None),
+            ..Default::default()
+        }
+        .insert(db)
+    }
+
+    /// Finds all the passkeys of the provided `user`
+    pub fn find_by_user<'db, C>(
+        db: &'db C,
+        user: &User,
+    ) -> impl Future<Output = DbResult<Vec<UserPasskey>>> + 'db
+    where
+        C: ConnectionTrait,
+    {
+        Entity::find()
+            .filter(Column::UserId.eq(user.id))
+            .order_by_asc(Column::CreatedAt)
+            .all(db)
+    }
+
+    /// Counts the passkeys of the provided `user`
+    pub fn count_by_user<'db, C>(
+        db: &'db C,
+        user: &User,
+    ) -> impl Future<Output = DbResult<u64>> + 'db
+    where
+        C: ConnectionTrait,
+    {
+        Entity::find().filter(Column::UserId.eq(user.id)).count(db)
+    }
+
+    /// Finds a passkey of the provided `user` by its ID
+    pub fn find_by_user_id<'db, C>(
+        db: &'db C,
+        user: &User,
+        id: PasskeyId,
+    ) -> impl Future<Output = DbResult<Option<UserPasskey>>> + 'db
+    where
+        C: ConnectionTrait,
+    {
+        Entity::find_by_id(id)
+            .filter(Column::UserId.eq(user.id))
+            .one(db)
+    }
+
+    /// Finds a passkey by its base64url encoded credential ID
+    pub fn find_by_credential_id<'db, C>(
+        db: &'db C,
+        credential_id: &str,
+    ) -> impl Future<Output = DbResult<Option<UserPasskey>>> + 'db
+    where
+        C: ConnectionTrait,
+    {
+        Entity::find()
+            .filter(Column::CredentialId.eq(credential_id))
+            .one(db)
+    }
+
+    /// Records that the passkey was used to login, the stored credential
+    /// is replaced with `passkey` when its state changed during the login
+    pub fn set_used<C>(
+        self,
+        db: &C,
+        passkey: Option<Passkey>,
+    ) -> impl Future<Output = DbResult<UserPasskey>> + '_
+    where
+        C: ConnectionTrait,
+    {
+        let mut model = self.into_active_model();
+        model.last_used_at = Set(Some(Utc::now().naive_utc()));
+        if let Some(passkey) = passkey {
+            model.passkey = Set(StoredPasskey(passkey));
+        }
+        model.update(db)
+    }
+}
+
+/// Creates the base64url encoded ID of the credential of a `passkey`
+pub fn credential_id(passkey: &Passkey) -> String {
+    encode_credential_id(passkey.cred_id().as_ref())
+}
+
+/// Encodes the raw credential ID bytes as base64url for storage
+pub fn encode_credential_id(credential_id: &[u8]) -> String {
+    use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+
+    URL_SAFE_NO_PAD.encode(credential_id)
+}
+
+impl Related<super::user::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::User.def()
+    }
+}
@@ -0,0 +1,109 @@
+use crate::database::DbResult;
+use chrono::Utc;
+use sea_orm::entity::prelude::*;
+use sea_orm::{ActiveValue::Set, ConnectionTrait, DeleteResult, PaginatorTrait};
+
+use std::future::Future;
+
+use super::user::{User, UserId};
+
+pub type UserRecoveryCode = Model;
+pub type UserRecoveryCodeEntity = Entity;
+pub type UserRecoveryCodeActiveModel = ActiveModel;
+
+/// Database structure for a one-time recovery code that can be used in
+/// place of a TOTP code
+#[derive(Debug, Clone, PartialEq, DeriveEntityModel)]
+#[sea_orm(table_name = "user_recovery_codes")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    /// ID of the user the code belongs to
+    pub user_id: UserId,
+    /// Keyed hash of the recovery code
+    pub code_hash: String,
+    /// When the code was created
+    pub created_at: DateTime,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::user::Entity",
+        from = "Column::UserId",
+        to = "super::user::Column::Id"
+    )]
+    User,
+}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+impl Model {
+    /// Replaces the recovery codes of the `user` with the provided
+    /// already hashed `code_hashes`
+    pub async fn replace_all<C>(db: &C, user: &User, code_hashes: Vec<String>) -> DbResult<()>
+    where
+        C: ConnectionTrait,
+    {
+        Self::delete_by_user(db, user).await?;
+
+        let now = Utc::now().naive_utc();
+        let models = code_hashes.into_iter().map(|code_hash| ActiveModel {
+            user_id: Set(user.id),
+            code_hash: Set(code_hash),
+            created_at: Set(now),
+            ..Default::default()
+        });
+
+        Entity::insert_many(models)
+            .exec_without_returning(db)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Uses up the recovery code with the provided `code_hash`, returns
+    /// whether the user had a matching code
+    pub async fn use_code<C>(db: &C, user: &User, code_hash: &str) -> DbResult<bool>
+    where
+        C: ConnectionTrait,
+    {
+        let result = Entity::delete_many()
+            .filter(Column::UserId.eq(user.id))
+            .filter(Column::CodeHash.eq(code_hash))
+            .exec(db)
+            .await?;
+
+        Ok(result.rows_affected > 0)
+    }
+
+    /// Counts the remaining recovery codes of the provided `user`
+    pub fn count_by_user<'db, C>(
+        db: &'db C,
+        user: &User,
+    ) -> impl Future<Output = DbResult<u64>> + 'db
+    where
+        C: ConnectionTrait,
+    {
+        Entity::find().filter(Column::UserId.eq(user.id)).count(db)
+    }
+
+    /// Deletes all the recovery codes of the provided `user`
+    pub fn delete_by_user<'db, C>(
+        db: &'db C,
+        user: &User,
+    ) -> impl Future<Output = DbResult<DeleteResult>> + 'db
+    where
+        C: ConnectionTrait,
+    {
+        Entity::delete_many()
+            .filter(Column::UserId.eq(user.id))
+            .exec(db)
+    }
+}
+
+impl Related<super::user::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::User.def()
+    }
+}
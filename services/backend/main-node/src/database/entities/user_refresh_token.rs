@@ -1,29 +1,96 @@
 use crate::database::DbResult;
+use crate::utils::hashing::{hash_token, verify_token_hash};
 use chrono::Utc;
-use sea_orm::sea_query::OnConflict;
-use sea_orm::{entity::prelude::*, ActiveValue};
-use sea_orm::{ActiveValue::Set, ConnectionTrait};
+use sea_orm::sea_query::{Condition, Expr};
+use sea_orm::{entity::prelude::*, ActiveValue, QueryOrder};
+use sea_orm::{ActiveValue::Set, ConnectionTrait, DeleteResult};
+use serde::Serialize;
 
 use std::future::Future;
 
 use super::user::{User, UserId};
+use super::user_rotated_refresh_token::UserRotatedRefreshToken;
 
 pub type UserRefreshToken = Model;
 pub type UserRefreshTokenEntity = Entity;
 pub type UserRefreshTokenActiveModel = ActiveModel;
 
-/// Database structure for a user
-#[derive(Debug, Clone, PartialEq, DeriveEntityModel)]
+pub type SessionId = i32;
+
+/// Length of the identifier at the start of refresh tokens, the identifier
+/// is stored in plain text for looking up the token
+pub const TOKEN_ID_LENGTH: usize = 16;
+
+/// Database structure for a refresh token, each login creates its own
+/// refresh token which represents a session on a specific device
+#[derive(Debug, Clone, PartialEq, DeriveEntityModel, Serialize)]
 #[sea_orm(table_name = "user_refresh_tokens")]
 pub struct Model {
+    /// Unique ID for the session
+    #[sea_orm(primary_key)]
+    pub id: SessionId,
     /// The ID of the user the token belongs to
-    #[sea_orm(unique)]
+    #[serde(skip)]
     pub user_id: UserId,
-    /// The refresh token itself
-    #[sea_orm(primary_key)]
-    pub refresh_token: String,
+    /// Identifier from the start of the refresh token
+    #[sea_orm(unique)]
+    #[serde(skip)]
+    pub token_id: String,
+    /// Keyed hash of the refresh token
+    #[serde(skip)]
+    pub token_hash: String,
+    /// Optional name of the device provided by the client
+    pub device_name: Option<String>,
+    /// IP address the session was last used from
+    pub ip_address: Option<String>,
+    /// User agent of the client that created the session
+    pub user_agent: Option<String>,
     /// When this refresh token was created
     pub created_at: DateTime,
+    /// When this refresh token was last used to refresh a token
+    pub last_used_at: DateTime,
+}
+
+/// Details about the client a session is being created for
+#[derive(Debug, Clone, Default)]
+pub struct SessionDetails {
+    /// Optional name of the device
+    pub device_name: Option<String>,
+    /// IP address of the client
+    pub ip_address: Option<String>,
+    /// User agent of the client
+    pub user_agent: Option<String>,
+}
+
+/// Stored form of a refresh token, the plain text token is never stored
+#[derive(Debug, Clone)]
+pub struct HashedRefreshToken {
+    /// Identifier from the start of the token
+    pub token_id: String,
+    /// Keyed hash of the token
+    pub token_hash: String,
+}
+
+impl HashedRefreshToken {
+    /// Hashes the provided `token` using the server `key`, returns [None]
+    /// if the token is too short to contain an identifier
+    pub fn new(key: &[u8], token: &str) -> Option<Self> {
+        let token_id = token_id(token)?;
+
+        Some(Self {
+            token_id: token_id.to_string(),
+            token_hash: hash_token(key, token),
+        })
+    }
+}
+
+/// Gets the identifier from the start of the provided refresh `token`
+pub fn token_id(token: &str) -> Option<&str> {
+    if token.len() <= TOKEN_ID_LENGTH {
+        return None;
+    }
+
+    token.get(..TOKEN_ID_LENGTH)
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -34,11 +101,13 @@ pub enum Relation {
         to = "super::user::Column::Id"
     )]
     User,
+    #[sea_orm(has_many = "super::user_rotated_refresh_token::Entity")]
+    RotatedTokens,
 }
 
 #[async_trait::async_trait]
 impl ActiveModelBehavior for ActiveModel {
-    /// Handles updating the `updated_at` field before the model is saved, using
+    /// Handles updating the `last_used_at` field before the model is saved, using
     /// the current date time.
     ///
     /// If the save is an insertion the `created_at` field will also be updated
@@ -47,6 +116,8 @@ impl ActiveModelBehavior for ActiveModel {
         C: ConnectionTrait,
     {
         let now = Utc::now().naive_utc();
+        self.last_used_at = ActiveValue::Set(now);
+
         if insert {
             self.created_at = ActiveValue::Set(now);
         }
@@ -56,37 +127,185 @@ impl ActiveModelBehavior for ActiveModel {
 }
 
 impl Model {
-    /// Create a new refresh token for the provided `user`
+    /// Create a new refresh token session for the provided `user`
     pub fn create<'db, C>(
         db: &'db C,
         user: &User,
-        refresh_token: String,
+        token: HashedRefreshToken,
+        details: SessionDetails,
     ) -> impl Future<Output = DbResult<UserRefreshToken>> + 'db
     where
         C: ConnectionTrait,
     {
-        Entity::insert(ActiveModel {
+        ActiveModel {
             user_id: Set(user.id),
-            refresh_token: Set(refresh_token),
-            created_at: Set(Utc::now().naive_utc()),
-        })
-        .on_conflict(
-            OnConflict::column(Column::UserId)
-                .update_columns([Column::RefreshToken, Column::CreatedAt])
-                .to_owned(),
-        )
-        .exec_with_returning(db)
+            token_id: Set(token.token_id),
+            token_hash: Set(token.token_hash),
+            device_name: Set(details.device_name),
+            ip_address: Set(details.ip_address),
+            user_agent: Set(details.user_agent),
+            ..Default::default()
+        }
+        .insert(db)
     }
 
-    /// Find a refresh token by token
-    pub fn find_by_token<'db, C>(
-        db: &'db C,
+    /// Finds a session by its refresh token, the session is found using the
+    /// token identifier and the hash of the token is compared in constant time
+    pub async fn find_by_token<C>(
+        db: &C,
+        key: &[u8],
         refresh_token: &str,
+    ) -> DbResult<Option<UserRefreshToken>>
+    where
+        C: ConnectionTrait,
+    {
+        let Some(token_id) = token_id(refresh_token) else {
+            return Ok(None);
+        };
+
+        let session = Self::find_by_token_id(db, token_id).await?;
+
+        Ok(session.filter(|session| verify_token_hash(key, refresh_token, &session.token_hash)))
+    }
+
+    /// Finds a session by the identifier of its refresh token
+    pub fn find_by_token_id<'db, C>(
+        db: &'db C,
+        token_id: &str,
     ) -> impl Future<Output = DbResult<Option<UserRefreshToken>>> + 'db
     where
         C: ConnectionTrait,
     {
-        Entity::find_by_id(refresh_token).one(db)
+        Entity::find().filter(Column::TokenId.eq(token_id)).one(db)
+    }
+
+    /// Finds a session by its ID
+    pub fn find_by_id<C>(
+        db: &C,
+        id: SessionId,
+    ) -> impl Future<Output = DbResult<Option<UserRefreshToken>>> + '_
+    where
+        C: ConnectionTrait,
+    {
+        Entity::find_by_id(id).one(db)
+    }
+
+    /// Finds a session belonging to the provided `user` by its ID
+    pub fn find_by_user_id<'db, C>(
+        db: &'db C,
+        user: &User,
+        id: SessionId,
+    ) -> impl Future<Output = DbResult<Option<UserRefreshToken>>> + 'db
+    where
+        C: ConnectionTrait,
+    {
+        Entity::find_by_id(id)
+            .filter(Column::UserId.eq(user.id))
+            .one(db)
+    }
+
+    /// Finds all the sessions belonging to the provided `user`, most
+    /// recently used first
+    pub fn find_by_user<'db, C>(
+        db: &'db C,
+        user: &User,
+    ) -> impl Future<Output = DbResult<Vec<UserRefreshToken>>> + 'db
+    where
+        C: ConnectionTrait,
+    {
+        Entity::find()
+            .filter(Column::UserId.eq(user.id))
+            .order_by_desc(Column::LastUsedAt)
+            .all(db)
+    }
+
+    /// Replaces the refresh token for this session after it has been used,
+    /// updating the last used time and IP address.
+    ///
+    /// The replacement only happens if the session still has the token
+    /// it was loaded with, returning [None] if another request already
+    /// rotated the token
+    pub async fn rotate<C>(
+        self,
+        db: &C,
+        token: HashedRefreshToken,
+        ip_address: Option<String>,
+    ) -> DbResult<Option<UserRefreshToken>>
+    where
+        C: ConnectionTrait,
+    {
+        let now = Utc::now().naive_utc();
+        let mut update = Entity::update_many()
+            .col_expr(Column::TokenId, Expr::value(token.token_id.clone()))
+            .col_expr(Column::TokenHash, Expr::value(token.token_hash.clone()))
+            .col_expr(Column::LastUsedAt, Expr::value(now));
+
+        if let Some(ip_address) = ip_address.clone() {
+            update = update.col_expr(Column::IpAddress, Expr::value(ip_address));
+        }
+
+        let result = update
+            .filter(Column::Id.eq(self.id))
+            .filter(Column::TokenId.eq(self.token_id.as_str()))
+            .exec(db)
+            .await?;
+
+        if result.rows_affected == 0 {
+            return Ok(None);
+        }
+
+        // Remember the old token to detect it being replayed
+        UserRotatedRefreshToken::create(
+            db,
+            self.id,
+            HashedRefreshToken {
+                token_id: self.token_id,
+                token_hash: self.token_hash,
+            },
+        )
+        .await?;
+
+        Ok(Some(UserRefreshToken {
+            token_id: token.token_id,
+            token_hash: token.token_hash,
+            ip_address: ip_address.or(self.ip_address),
+            last_used_at: now,
+            ..self
+        }))
+    }
+
+    /// Deletes the sessions of the provided `user` that were created before
+    /// `created_before` or haven't been used since `used_before`
+    pub fn delete_expired<'db, C>(
+        db: &'db C,
+        user: &User,
+        created_before: DateTime,
+        used_before: DateTime,
+    ) -> impl Future<Output = DbResult<DeleteResult>> + 'db
+    where
+        C: ConnectionTrait,
+    {
+        Entity::delete_many()
+            .filter(Column::UserId.eq(user.id))
+            .filter(
+                Condition::any()
+                    .add(Column::CreatedAt.lt(created_before))
+                    .add(Column::LastUsedAt.lt(used_before)),
+            )
+            .exec(db)
+    }
+
+    /// Deletes all the refresh tokens belonging to the provided `user`
+    pub fn delete_by_user<'db, C>(
+        db: &'db C,
+        user: &User,
+    ) -> impl Future<Output = DbResult<DeleteResult>> + 'db
+    where
+        C: ConnectionTrait,
+    {
+        Entity::delete_many()
+            .filter(Column::UserId.eq(user.id))
+            .exec(db)
     }
 }
 
@@ -95,3 +314,9 @@ impl Related<super::user::Entity> for Entity {
         Relation::User.def()
     }
 }
+
+impl Related<super::user_rotated_refresh_token::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::RotatedTokens.def()
+    }
+}
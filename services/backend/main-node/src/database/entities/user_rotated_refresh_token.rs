@@ -0,0 +1,114 @@
+use crate::database::DbResult;
+use crate::utils::hashing::verify_token_hash;
+use chrono::Utc;
+use sea_orm::entity::prelude::*;
+use sea_orm::{ActiveValue::Set, ConnectionTrait, DeleteResult};
+
+use std::future::Future;
+
+use super::user_refresh_token::{token_id, HashedRefreshToken, SessionId};
+
+pub type UserRotatedRefreshToken = Model;
+pub type UserRotatedRefreshTokenEntity = Entity;
+pub type UserRotatedRefreshTokenActiveModel = ActiveModel;
+
+/// Database structure for a refresh token that has already been replaced
+/// by a newer token in the same session, kept so that replaying the old
+/// token can be detected
+#[derive(Debug, Clone, PartialEq, DeriveEntityModel)]
+#[sea_orm(table_name = "user_rotated_refresh_tokens")]
+pub struct Model {
+    /// Identifier from the start of the refresh token that was replaced
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub token_id: String,
+    /// Keyed hash of the refresh token that was replaced
+    pub token_hash: String,
+    /// The session the token belonged to
+    pub session_id: SessionId,
+    /// When the token was replaced
+    pub rotated_at: DateTime,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::user_refresh_token::Entity",
+        from = "Column::SessionId",
+        to = "super::user_refresh_token::Column::Id"
+    )]
+    Session,
+}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+impl Model {
+    /// Records that the provided `token` was replaced
+    pub fn create<C>(
+        db: &C,
+        session_id: SessionId,
+        token: HashedRefreshToken,
+    ) -> impl Future<Output = DbResult<UserRotatedRefreshToken>> + '_
+    where
+        C: ConnectionTrait,
+    {
+        ActiveModel {
+            token_id: Set(token.token_id),
+            token_hash: Set(token.token_hash),
+            session_id: Set(session_id),
+            rotated_at: Set(Utc::now().naive_utc()),
+        }
+        .insert(db)
+    }
+
+    /// Finds a rotated refresh token by token, the token is found using its
+    /// identifier and the hash of the token is compared in constant time
+    pub async fn find_by_token<C>(
+        db: &C,
+        key: &[u8],
+        refresh_token: &str,
+    ) -> DbResult<Option<UserRotatedRefreshToken>>
+    where
+        C: ConnectionTrait,
+    {
+        let Some(token_id) = token_id(refresh_token) else {
+            return Ok(None);
+        };
+
+        let rotated = Self::find_by_token_id(db, token_id).await?;
+
+        Ok(rotated.filter(|rotated| verify_token_hash(key, refresh_token, &rotated.token_hash)))
+    }
+
+    /// Finds a rotated refresh token by its identifier
+    pub fn find_by_token_id<'db, C>(
+        db: &'db C,
+        token_id: &str,
+    ) -> impl Future<Output = DbResult<Option<UserRotatedRefreshToken>>> + 'db
+    where
+        C: ConnectionTrait,
+    {
+        Entity::find_by_id(token_id).one(db)
+    }
+
+    /// Deletes the tokens of a session that were rotated before the
+    /// provided `before` date time
+    pub fn delete_before<C>(
+        db: &C,
+        session_id: SessionId,
+        before: DateTime,
+    ) -> impl Future<Output = DbResult<DeleteResult>> + '_
+    where
+        C: ConnectionTrait,
+    {
+        Entity::delete_many()
+            .filter(Column::SessionId.eq(session_id))
+            .filter(Column::RotatedAt.lt(before))
+            .exec(db)
+    }
+}
+
+impl Related<super::user_refresh_token::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Session.def()
+    }
+}
@@ -0,0 +1,145 @@
+use crate::database::DbResult;
+use chrono::Utc;
+use sea_orm::entity::prelude::*;
+use sea_orm::sea_query::OnConflict;
+use sea_orm::{ActiveValue::Set, Condition, ConnectionTrait, DeleteResult, IntoActiveModel};
+
+use std::future::Future;
+
+use super::user::{User, UserId};
+
+pub type UserTotp = Model;
+pub type UserTotpEntity = Entity;
+pub type UserTotpActiveModel = ActiveModel;
+
+/// Database structure for the TOTP secret of a user
+#[derive(Debug, Clone, PartialEq, DeriveEntityModel)]
+#[sea_orm(table_name = "user_totp")]
+pub struct Model {
+    /// ID of the user the secret belongs to
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub user_id: UserId,
+    /// Base32 encoded TOTP secret, encrypted with the server key
+    /// (see [crate::utils::totp::SecretKey])
+    pub secret: String,
+    /// Time step of the last accepted code, codes from this step or
+    /// earlier can't be used again
+    pub last_used_step: Option<i64>,
+    /// When the user confirmed the secret, two-factor authentication is
+    /// only enabled once the secret is confirmed
+    pub confirmed_at: Option<DateTime>,
+    /// When the secret was created
+    pub created_at: DateTime,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::user::Entity",
+        from = "Column::UserId",
+        to = "super::user::Column::Id"
+    )]
+    User,
+}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+impl Model {
+    /// Stores a new unconfirmed encrypted `secret` for the user, replacing
+    /// any existing secret
+    pub async fn create<C>(db: &C, user: &User, secret: String) -> DbResult<UserTotp>
+    where
+        C: ConnectionTrait,
+    {
+        let model = ActiveModel {
+            user_id: Set(user.id),
+            secret: Set(secret),
+            last_used_step: Set(None),
+            confirmed_at: Set(None),
+            created_at: Set(Utc::now().naive_utc()),
+        };
+
+        Entity::insert(model)
+            .on_conflict(
+                OnConflict::column(Column::UserId)
+                    .update_columns([
+                        Column::Secret,
+                        Column::LastUsedStep,
+                        Column::ConfirmedAt,
+                        Column::CreatedAt,
+                    ])
+                    .to_owned(),
+            )
+            .exec_with_returning(db)
+            .await
+    }
+
+    /// Finds the TOTP secret of the provided `user`
+    pub fn find_by_user<'db, C>(
+        db: &'db C,
+        user: &User,
+    ) -> impl Future<Output = DbResult<Option<UserTotp>>> + 'db
+    where
+        C: ConnectionTrait,
+    {
+        Entity::find_by_id(user.id).one(db)
+    }
+
+    /// Whether the secret has been confirmed by the user
+    pub fn is_confirmed(&self) -> bool {
+        self.confirmed_at.is_some()
+    }
+
+    /// Confirms the secret, the code from `step` was used to confirm it
+    pub fn set_confirmed<C>(
+        self,
+        db: &C,
+        step: i64,
+    ) -> impl Future<Output = DbResult<UserTotp>> + '_
+    where
+        C: ConnectionTrait,
+    {
+        let mut model = self.into_active_model();
+        model.last_used_step = Set(Some(step));
+        model.confirmed_at = Set(Some(Utc::now().naive_utc()));
+        model.update(db)
+    }
+
+    /// Marks the code from `step` as used, the update only succeeds if no
+    /// code from the same or a later step has been used yet. Returns
+    /// whether the step was marked as used
+    pub async fn use_step<C>(&self, db: &C, step: i64) -> DbResult<bool>
+    where
+        C: ConnectionTrait,
+    {
+        let result = Entity::update_many()
+            .col_expr(Column::LastUsedStep, Expr::value(step))
+            .filter(Column::UserId.eq(self.user_id))
+            .filter(
+                Condition::any()
+                    .add(Column::LastUsedStep.is_null())
+                    .add(Column::LastUsedStep.lt(step)),
+            )
+            .exec(db)
+            .await?;
+
+        Ok(result.rows_affected > 0)
+    }
+
+    /// Deletes the TOTP secret of the provided `user`
+    pub fn delete_by_user<'db, C>(
+        db: &'db C,
+        user: &User,
+    ) -> impl Future<Output = DbResult<DeleteResult>> + 'db
+    where
+        C: ConnectionTrait,
+    {
+        Entity::delete_by_id(user.id).exec(db)
+    }
+}
+
+impl Related<super::user::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::User.def()
+    }
+}
@@ -3,6 +3,7 @@ use sea_orm::{Database, DatabaseConnection, DbErr};
 use tracing::debug;
 
 pub mod entities;
+pub mod models;
 
 pub type DbResult<T> = Result<T, DbErr>;
 
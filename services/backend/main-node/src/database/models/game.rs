@@ -0,0 +1,23 @@
+//! Structures for the results of a played game stored in the `results`
+//! column of an [ActiveQuiz](crate::database::entities::active_quiz::ActiveQuiz)
+
+use sea_orm::FromJsonQueryResult;
+use serde::{Deserialize, Serialize};
+
+/// Final results for a game
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, FromJsonQueryResult)]
+pub struct GameResults {
+    /// The final leaderboard ordered by score
+    pub leaderboard: Vec<PlayerResult>,
+}
+
+/// Final result for a single player
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlayerResult {
+    /// The display name the player used
+    pub name: String,
+    /// The total score of the player
+    pub score: u32,
+    /// Number of questions the player answered correctly
+    pub correct_answers: u32,
+}
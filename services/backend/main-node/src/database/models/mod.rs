@@ -0,0 +1,6 @@
+//! Models for structured data stored within entity columns
+
+pub mod game;
+pub mod passkey;
+pub mod quiz;
+pub mod resource;
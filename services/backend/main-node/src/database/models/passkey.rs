@@ -0,0 +1,19 @@
+//! Structure for the WebAuthn credential stored in the `passkey` column
+//! of a [UserPasskey](crate::database::entities::user_passkey::UserPasskey)
+
+use sea_orm::FromJsonQueryResult;
+use serde::{Deserialize, Serialize};
+use webauthn_rs::prelude::Passkey;
+
+/// WebAuthn credential stored for a passkey
+#[derive(Debug, Clone, Serialize, Deserialize, FromJsonQueryResult)]
+#[serde(transparent)]
+pub struct StoredPasskey(pub Passkey);
+
+/// Passkeys don't implement equality themselves, credentials are
+/// considered equal when they share the same credential ID
+impl PartialEq for StoredPasskey {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.cred_id() == other.0.cred_id()
+    }
+}
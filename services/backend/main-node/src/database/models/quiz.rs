@@ -0,0 +1,353 @@
+//! Typed structure for the JSON data stored in the `data` column of a
+//! [Quiz](crate::database::entities::quiz::Quiz)
+
+use crate::database::entities::resource::ResourceId;
+use sea_orm::FromJsonQueryResult;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/// The current version of the quiz data structure, bumped whenever
+/// a breaking change is made to the structure
+pub const QUIZ_DATA_VERSION: u32 = 1;
+
+/// Unique ID for a question within a quiz
+pub type QuestionId = u32;
+
+/// Data for a quiz, contains the questions that make up the quiz
+#[derive(
+    Debug, Clone, PartialEq, Eq, Serialize, Deserialize, FromJsonQueryResult, garde::Validate,
+)]
+pub struct QuizData {
+    /// The version of the data structure
+    #[serde(default = "default_version")]
+    #[garde(custom(validate_version))]
+    pub version: u32,
+    /// The questions within the quiz
+    #[serde(default)]
+    #[garde(length(max = 100), custom(validate_unique_ids), dive)]
+    pub questions: Vec<Question>,
+}
+
+impl Default for QuizData {
+    fn default() -> Self {
+        Self {
+            version: QUIZ_DATA_VERSION,
+            questions: Vec::new(),
+        }
+    }
+}
+
+impl QuizData {
+    /// Finds a question within the quiz by its ID
+    pub fn question(&self, id: QuestionId) -> Option<&Question> {
+        self.questions.iter().find(|question| question.id == id)
+    }
+}
+
+/// Structure for a question within a quiz
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, garde::Validate)]
+pub struct Question {
+    /// Unique ID of the question within the quiz
+    #[garde(skip)]
+    pub id: QuestionId,
+    /// The question text
+    #[garde(length(min = 1, max = 300))]
+    pub text: String,
+    /// Optional image resource to display with the question
+    #[serde(default)]
+    #[garde(skip)]
+    pub image: Option<ResourceId>,
+    /// Time in seconds that players have to answer the question
+    #[garde(range(min = 5, max = 300))]
+    pub time_limit: u32,
+    /// The maximum number of points awarded for answering correctly
+    #[garde(range(max = 10000))]
+    pub points: u32,
+    /// The type of question and its answers
+    #[serde(flatten)]
+    #[garde(dive)]
+    pub kind: QuestionKind,
+}
+
+/// The different types of question
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, garde::Validate)]
+#[serde(tag = "type")]
+pub enum QuestionKind {
+    /// Single choice question, exactly one answer is correct
+    Single {
+        #[garde(length(min = 2, max = 8), custom(validate_single_answers), dive)]
+        answers: Vec<AnswerOption>,
+    },
+    /// Multiple choice question, one or more answers are correct
+    Multiple {
+        #[garde(length(min = 2, max = 8), custom(validate_multiple_answers), dive)]
+        answers: Vec<AnswerOption>,
+    },
+    /// True or false question
+    TrueFalse {
+        /// The correct answer
+        #[garde(skip)]
+        answer: bool,
+    },
+    /// Question where the answer is typed by the player
+    Typed {
+        /// Collection of accepted answers
+        #[garde(length(min = 1, max = 10), inner(length(min = 1, max = 100)))]
+        answers: Vec<String>,
+        /// Whether casing should be ignored when comparing answers
+        #[serde(default)]
+        #[garde(skip)]
+        ignore_case: bool,
+    },
+    /// Question where the player must place the items in the correct
+    /// order, the items are stored in their correct order
+    Ordering {
+        #[garde(length(min = 2, max = 8), inner(length(min = 1, max = 100)))]
+        items: Vec<String>,
+    },
+}
+
+/// Answer option for a choice question
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, garde::Validate)]
+pub struct AnswerOption {
+    /// The answer text
+    #[garde(length(min = 1, max = 100))]
+    pub text: String,
+    /// Whether this answer is a correct answer
+    #[garde(skip)]
+    pub correct: bool,
+}
+
+/// Default version for data that was created before versioning
+fn default_version() -> u32 {
+    QUIZ_DATA_VERSION
+}
+
+/// Ensures the data version matches the current version
+fn validate_version(value: &u32, _: &()) -> garde::Result {
+    if *value != QUIZ_DATA_VERSION {
+        return Err(garde::Error::new(format!(
+            "unsupported version, expected {QUIZ_DATA_VERSION}"
+        )));
+    }
+
+    Ok(())
+}
+
+/// Ensures that no two questions share the same ID
+fn validate_unique_ids(value: &[Question], _: &()) -> garde::Result {
+    let mut seen: HashSet<QuestionId> = HashSet::with_capacity(value.len());
+
+    if !value.iter().all(|question| seen.insert(question.id)) {
+        return Err(garde::Error::new("question IDs must be unique"));
+    }
+
+    Ok(())
+}
+
+/// Ensures exactly one of the answers is marked as correct
+fn validate_single_answers(value: &[AnswerOption], _: &()) -> garde::Result {
+    let correct = value.iter().filter(|answer| answer.correct).count();
+
+    if correct != 1 {
+        return Err(garde::Error::new("exactly one answer must be correct"));
+    }
+
+    Ok(())
+}
+
+/// Ensures at least one of the answers is marked as correct
+fn validate_multiple_answers(value: &[AnswerOption], _: &()) -> garde::Result {
+    if !value.iter().any(|answer| answer.correct) {
+        return Err(garde::Error::new("at least one answer must be correct"));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{AnswerOption, Question, QuestionKind, QuizData, QUIZ_DATA_VERSION};
+    use garde::Validate;
+
+    fn question(id: u32, kind: QuestionKind) -> Question {
+        Question {
+            id,
+            text: "Question".to_string(),
+            image: None,
+            time_limit: 20,
+            points: 1000,
+            kind,
+        }
+    }
+
+    fn options(correct: &[bool]) -> Vec<AnswerOption> {
+        correct
+            .iter()
+            .map(|correct| AnswerOption {
+                text: "Answer".to_string(),
+                correct: *correct,
+            })
+            .collect()
+    }
+
+    fn data(questions: Vec<Question>) -> QuizData {
+        QuizData {
+            version: QUIZ_DATA_VERSION,
+            questions,
+        }
+    }
+
+    /// Tests that valid data for every question type passes validation
+    #[test]
+    fn test_valid_data() {
+        let data = data(vec![
+            question(
+                0,
+                QuestionKind::Single {
+                    answers: options(&[true, false]),
+                },
+            ),
+            question(
+                1,
+                QuestionKind::Multiple {
+                    answers: options(&[true, true, false]),
+                },
+            ),
+            question(2, QuestionKind::TrueFalse { answer: false }),
+            question(
+                3,
+                QuestionKind::Typed {
+                    answers: vec!["Answer".to_string()],
+                    ignore_case: true,
+                },
+            ),
+            question(
+                4,
+                QuestionKind::Ordering {
+                    items: vec!["A".to_string(), "B".to_string()],
+                },
+            ),
+        ]);
+
+        assert!(data.validate(&()).is_ok());
+        assert!(QuizData::default().validate(&()).is_ok());
+    }
+
+    /// Tests that unsupported data versions are rejected
+    #[test]
+    fn test_invalid_version() {
+        let mut data = data(Vec::new());
+        data.version = QUIZ_DATA_VERSION + 1;
+
+        assert!(data.validate(&()).is_err());
+    }
+
+    /// Tests that question IDs must be unique
+    #[test]
+    fn test_duplicate_ids() {
+        let data = data(vec![
+            question(1, QuestionKind::TrueFalse { answer: true }),
+            question(1, QuestionKind::TrueFalse { answer: false }),
+        ]);
+
+        assert!(data.validate(&()).is_err());
+    }
+
+    /// Tests that single choice questions need exactly one correct answer
+    #[test]
+    fn test_single_answers() {
+        for correct in [&[false, false][..], &[true, true]] {
+            let data = data(vec![question(
+                0,
+                QuestionKind::Single {
+                    answers: options(correct),
+                },
+            )]);
+
+            assert!(data.validate(&()).is_err(), "{correct:?} should be invalid");
+        }
+    }
+
+    /// Tests that multiple choice questions need a correct answer
+    #[test]
+    fn test_multiple_answers() {
+        let data = data(vec![question(
+            0,
+            QuestionKind::Multiple {
+                answers: options(&[false, false]),
+            },
+        )]);
+
+        assert!(data.validate(&()).is_err());
+    }
+
+    /// Tests the bounds on question fields and answer counts
+    #[test]
+    fn test_question_bounds() {
+        let valid = question(0, QuestionKind::TrueFalse { answer: true });
+
+        let invalid = [
+            Question {
+                text: String::new(),
+                ..valid.clone()
+            },
+            Question {
+                time_limit: 4,
+                ..valid.clone()
+            },
+            Question {
+                time_limit: 301,
+                ..valid.clone()
+            },
+            Question {
+                points: 10001,
+                ..valid.clone()
+            },
+            question(
+                0,
+                QuestionKind::Single {
+                    answers: options(&[true]),
+                },
+            ),
+            question(
+                0,
+                QuestionKind::Typed {
+                    answers: Vec::new(),
+                    ignore_case: false,
+                },
+            ),
+            question(
+                0,
+                QuestionKind::Ordering {
+                    items: vec!["A".to_string()],
+                },
+            ),
+        ];
+
+        for question in invalid {
+            let data = data(vec![question.clone()]);
+            assert!(
+                data.validate(&()).is_err(),
+                "{question:?} should be invalid"
+            );
+        }
+    }
+
+    /// Tests that the number of questions is limited
+    #[test]
+    fn test_question_limit() {
+        let questions: Vec<Question> = (0..101)
+            .map(|id| question(id, QuestionKind::TrueFalse { answer: true }))
+            .collect();
+
+        assert!(data(questions).validate(&()).is_err());
+    }
+
+    /// Tests that data stored before versioning defaults to the current version
+    #[test]
+    fn test_default_version() {
+        let data: QuizData = serde_json::from_str(r#"{"questions":[]}"#).unwrap();
+        assert_eq!(data.version, QUIZ_DATA_VERSION);
+    }
+}
@@ -0,0 +1,68 @@
+//! Structures for the processed image variants stored in the `variants`
+//! column of a [Resource](crate::database::entities::resource::Resource)
+
+use sea_orm::FromJsonQueryResult;
+use serde::{Deserialize, Serialize};
+
+/// Derived sizes generated for uploaded images
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImageVariant {
+    /// Small image used on quiz cards
+    Thumbnail,
+    /// Medium image used in the player view
+    Medium,
+}
+
+impl ImageVariant {
+    /// All the variants in order of size
+    pub const ALL: [ImageVariant; 2] = [ImageVariant::Thumbnail, ImageVariant::Medium];
+
+    /// Maximum width and height of the variant in pixels
+    pub fn max_size(&self) -> u32 {
+        match self {
+            ImageVariant::Thumbnail => 320,
+            ImageVariant::Medium => 1280,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ImageVariant::Thumbnail => "thumbnail",
+            ImageVariant::Medium => "medium",
+        }
+    }
+}
+
+/// Collection of processed variants for a resource, variants are only
+/// generated when the original image is larger than the variant
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, FromJsonQueryResult)]
+#[serde(transparent)]
+pub struct ResourceVariants(pub Vec<ResourceVariant>);
+
+impl ResourceVariants {
+    /// Finds the details of the provided `variant`
+    pub fn get(&self, variant: ImageVariant) -> Option<&ResourceVariant> {
+        self.0.iter().find(|value| value.variant == variant)
+    }
+}
+
+/// Processed variant of an image resource
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResourceVariant {
+    /// The variant size
+    pub variant: ImageVariant,
+    /// Width of the variant in pixels
+    pub width: u32,
+    /// Height of the variant in pixels
+    pub height: u32,
+}
+
+/// Creates the storage key for a `variant` of the content stored
+/// under the provided `key`
+pub fn variant_key(key: &str, variant: ImageVariant) -> String {
+    match key.rsplit_once('.') {
+        Some((stem, extension)) => format!("{stem}_{}.{extension}", variant.name()),
+        None => format!("{key}_{}", variant.name()),
+    }
+}
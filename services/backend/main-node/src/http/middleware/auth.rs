@@ -1,9 +1,9 @@
-use std::sync::Arc;
+use std::{marker::PhantomData, sync::Arc};
 
 use crate::{
-    database::entities::user::User,
+    database::entities::user::{User, UserRole},
     http::models::error::{HttpError, HttpErrorResponse},
-    services::auth::{AuthService, TokenError},
+    services::auth::{AuthService, TokenError, UserClaims},
 };
 use async_trait::async_trait;
 use axum::{
@@ -22,10 +22,38 @@ use thiserror::Error;
 /// the authorized user
 pub struct Auth(pub User);
 
-/// Middleware for gating authorization without actually looking
-/// up the user in question
+/// Middleware for authorizing users through tokens, contains the
+/// authorized user along with the claims from their token
+pub struct AuthWithClaims(pub User, pub UserClaims);
+
+/// Middleware for gating authorization on a valid token for a user that
+/// can still login, without needing the user in question
 pub struct AuthGate;
 
+/// Middleware for authorizing users that have at least the role `R`,
+/// contains the authorized user
+pub struct RequireRole<R>(pub User, pub PhantomData<R>);
+
+/// Roles that can be required through [RequireRole]
+pub trait RequiredRole: Send + Sync {
+    /// The minimum role the user must have
+    const ROLE: UserRole;
+}
+
+/// Requires the user to be a moderator or administrator
+pub struct Moderator;
+
+impl RequiredRole for Moderator {
+    const ROLE: UserRole = UserRole::Moderator;
+}
+
+/// Requires the user to be an administrator
+pub struct Administrator;
+
+impl RequiredRole for Administrator {
+    const ROLE: UserRole = UserRole::Administrator;
+}
+
 #[derive(Debug, Error)]
 pub enum AuthError {
     /// Header was missing or invalid
@@ -37,6 +65,12 @@ pub enum AuthError {
     /// Token user no longer exists
     #[error("Invalid token")]
     UnknownUser,
+    /// Token user has been deactivated or suspended
+    #[error("Account has been disabled")]
+    Disabled,
+    /// Token user doesn't have the required role
+    #[error("Missing required role")]
+    MissingRole,
 }
 
 impl HttpError for AuthError {
@@ -45,6 +79,15 @@ impl HttpError for AuthError {
             AuthError::Header(_) | AuthError::Token(_) | AuthError::UnknownUser => {
                 StatusCode::BAD_REQUEST
             }
+            AuthError::Disabled | AuthError::MissingRole => StatusCode::FORBIDDEN,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            AuthError::Disabled => "auth:account_disabled",
+            AuthError::MissingRole => "auth:missing_role",
+            _ => "server",
         }
     }
 }
@@ -56,6 +99,20 @@ where
 {
     type Rejection = HttpErrorResponse;
 
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let AuthWithClaims(user, _claims) =
+            AuthWithClaims::from_request_parts(parts, state).await?;
+        Ok(Self(user))
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for AuthWithClaims
+where
+    S: Send + Sync,
+{
+    type Rejection = HttpErrorResponse;
+
     async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
         let auth: Arc<AuthService> = parts
             .extensions
@@ -72,12 +129,47 @@ where
                 .await
                 .map_err(AuthError::Header)?;
         let token = authorization.token();
-        let claims = auth.verify_user_token(token).map_err(AuthError::Token)?;
+        let claims = auth
+            .verify_user_token(&db, token)
+            .await
+            .map_err(AuthError::Token)?;
         let user = User::find_by_id(&db, claims.user_id)
             .await?
             .ok_or(AuthError::UnknownUser)?;
 
-        Ok(Self(user))
+        if !user.can_login() {
+            return Err(AuthError::Disabled.into());
+        }
+
+        Ok(Self(user, claims))
+    }
+}
+
+#[async_trait]
+impl<S, R> FromRequestParts<S> for RequireRole<R>
+where
+    S: Send + Sync,
+    R: RequiredRole,
+{
+    type Rejection = HttpErrorResponse;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Auth(user) = Auth::from_request_parts(parts, state).await?;
+        Self::authorize(user).map_err(Into::into)
+    }
+}
+
+impl<R> RequireRole<R>
+where
+    R: RequiredRole,
+{
+    /// Ensures the authenticated `user` has at least the required role
+    fn authorize(user: User) -> Result<Self, AuthError> {
+        if !user.has_role(&R::ROLE) {
+            return Err(AuthError::MissingRole);
+        }
+
+        Ok(Self(user, PhantomData))
     }
 }
 
@@ -89,18 +181,50 @@ where
     type Rejection = HttpErrorResponse;
 
     async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
-        let auth: Arc<AuthService> = parts
-            .extensions
-            .get::<Arc<AuthService>>()
-            .expect("Missing auth service")
-            .clone();
-        let TypedHeader(authorization) =
-            TypedHeader::<Authorization<Bearer>>::from_request_parts(parts, state)
-                .await
-                .map_err(AuthError::Header)?;
-        let token = authorization.token();
-        let _claims = auth.verify_user_token(token).map_err(AuthError::Token)?;
+        // Disabled users must not pass the gate so the user is still checked
+        let AuthWithClaims(_user, _claims) =
+            AuthWithClaims::from_request_parts(parts, state).await?;
 
         Ok(Self)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{Administrator, AuthError, Moderator, RequireRole, RequiredRole};
+    use crate::database::entities::user::{User, UserRole};
+
+    fn user(role: UserRole) -> User {
+        User {
+            role,
+            ..User::test(1)
+        }
+    }
+
+    fn allowed<R: RequiredRole>(role: UserRole) -> bool {
+        match RequireRole::<R>::authorize(user(role)) {
+            Ok(RequireRole(user, ..)) => {
+                assert_eq!(user.id, 1);
+                true
+            }
+            Err(AuthError::MissingRole) => false,
+            Err(err) => panic!("unexpected error: {err}"),
+        }
+    }
+
+    /// Tests that moderator routes allow moderators and administrators
+    #[test]
+    fn test_require_moderator() {
+        assert!(!allowed::<Moderator>(UserRole::Standard));
+        assert!(allowed::<Moderator>(UserRole::Moderator));
+        assert!(allowed::<Moderator>(UserRole::Administrator));
+    }
+
+    /// Tests that administrator routes only allow administrators
+    #[test]
+    fn test_require_administrator() {
+        assert!(!allowed::<Administrator>(UserRole::Standard));
+        assert!(!allowed::<Administrator>(UserRole::Moderator));
+        assert!(allowed::<Administrator>(UserRole::Administrator));
+    }
+}
@@ -0,0 +1,177 @@
+use crate::database::entities::user_refresh_token::SessionDetails;
+use anyhow::Context;
+use async_trait::async_trait;
+use axum::{
+    extract::{ConnectInfo, FromRequestParts},
+    http::{header, request::Parts, HeaderMap},
+};
+use std::{
+    convert::Infallible,
+    net::{IpAddr, SocketAddr},
+};
+
+/// Extracts details about the client making the request for use when
+/// creating sessions
+pub struct ClientDetails(pub SessionDetails);
+
+/// Header the client can use to provide a name for its device
+pub const DEVICE_NAME_HEADER: &str = "x-device-name";
+/// Header containing the client IP when behind a reverse proxy
+const FORWARDED_FOR_HEADER: &str = "x-forwarded-for";
+
+/// Environment variable for the number of trusted reverse proxies
+const TRUST_PROXY_HEADERS: &str = "TRUST_PROXY_HEADERS";
+
+/// Maximum length of the stored device name
+const MAX_DEVICE_NAME_LENGTH: usize = 64;
+/// Maximum length of the stored user agent
+const MAX_USER_AGENT_LENGTH: usize = 512;
+
+/// Configuration for reading the client address from the headers set by
+/// reverse proxies, loaded once at startup
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ProxyConfig {
+    /// Number of trusted reverse proxies in front of the server, forwarded
+    /// headers are ignored when there are none
+    trusted_hops: usize,
+}
+
+impl ProxyConfig {
+    /// Loads the configuration from TRUST_PROXY_HEADERS which is either
+    /// true for a single trusted proxy, false for none or the number of
+    /// trusted proxies
+    pub fn from_env() -> anyhow::Result<Self> {
+        let value = std::env::var(TRUST_PROXY_HEADERS).unwrap_or_default();
+        let trusted_hops = match value.trim() {
+            "" | "false" => 0,
+            "true" => 1,
+            value => value
+                .parse()
+                .context("TRUST_PROXY_HEADERS must be true, false or a number of proxies")?,
+        };
+
+        Ok(Self { trusted_hops })
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for ClientDetails
+where
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let headers = &parts.headers;
+        let proxy: ProxyConfig = *parts
+            .extensions
+            .get::<ProxyConfig>()
+            .expect("Missing proxy config");
+
+        let forwarded_ip = forwarded_client_ip(headers, proxy.trusted_hops);
+
+        let ip_address = forwarded_ip.or_else(|| {
+            parts
+                .extensions
+                .get::<ConnectInfo<SocketAddr>>()
+                .map(|ConnectInfo(addr)| addr.ip().to_string())
+        });
+
+        Ok(Self(SessionDetails {
+            device_name: header_value(headers, DEVICE_NAME_HEADER, MAX_DEVICE_NAME_LENGTH),
+            ip_address,
+            user_agent: header_value(headers, header::USER_AGENT.as_str(), MAX_USER_AGENT_LENGTH),
+        }))
+    }
+}
+
+/// Finds the client address in the X-Forwarded-For `headers` added by
+/// the `trusted_hops` proxies in front of the server. Each proxy appends
+/// the address it received the request from, so the address added by the
+/// outermost trusted proxy is `trusted_hops` entries from the end. The
+/// entries before it are set by the client and can't be trusted
+fn forwarded_client_ip(headers: &HeaderMap, trusted_hops: usize) -> Option<String> {
+    if trusted_hops == 0 {
+        return None;
+    }
+
+    // Proxies may add separate headers rather than appending to one
+    let entries: Vec<&str> = headers
+        .get_all(FORWARDED_FOR_HEADER)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+        .collect();
+
+    // Requests that didn't pass through every trusted proxy
+    let index = entries.len().checked_sub(trusted_hops)?;
+
+    entries[index]
+        .parse::<IpAddr>()
+        .ok()
+        .map(|value| value.to_string())
+}
+
+/// Gets a non-empty header value as a string truncated to `max_length`
+/// characters
+fn header_value(headers: &HeaderMap, name: &str, max_length: usize) -> Option<String> {
+    let value = headers.get(name)?.to_str().ok()?.trim();
+    if value.is_empty() {
+        return None;
+    }
+
+    Some(value.chars().take(max_length).collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::forwarded_client_ip;
+    use axum::http::{HeaderMap, HeaderValue};
+
+    fn headers(values: &[&'static str]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for value in values {
+            headers.append("x-forwarded-for", HeaderValue::from_static(value));
+        }
+        headers
+    }
+
+    #[test]
+    fn test_untrusted_ignored() {
+        let headers = headers(&["203.0.113.1"]);
+        assert_eq!(forwarded_client_ip(&headers, 0), None);
+    }
+
+    /// Entries added by the client before the proxy are ignored
+    #[test]
+    fn test_single_proxy_uses_rightmost() {
+        let headers = headers(&["198.51.100.7, 203.0.113.1"]);
+        assert_eq!(
+            forwarded_client_ip(&headers, 1).as_deref(),
+            Some("203.0.113.1")
+        );
+    }
+
+    #[test]
+    fn test_multiple_proxies() {
+        let headers = headers(&["198.51.100.7, 203.0.113.1", "10.0.0.2"]);
+        assert_eq!(
+            forwarded_client_ip(&headers, 2).as_deref(),
+            Some("203.0.113.1")
+        );
+    }
+
+    #[test]
+    fn test_fewer_entries_than_proxies() {
+        let headers = headers(&["203.0.113.1"]);
+        assert_eq!(forwarded_client_ip(&headers, 2), None);
+    }
+
+    #[test]
+    fn test_invalid_address() {
+        let headers = headers(&["not-an-ip"]);
+        assert_eq!(forwarded_client_ip(&headers, 1), None);
+    }
+}
@@ -1,3 +1,7 @@
 pub mod auth;
+pub mod client;
 pub mod json;
+pub mod permission;
+pub mod query;
 pub mod recaptcha;
+pub mod scim;
@@ -0,0 +1,107 @@
+//! Shared authorization for actions performed on quizzes and organizations,
+//! all routes that access a quiz on behalf of a user should go through
+//! [authorize_quiz] and routes that access an organization should go
+//! through [authorize_organization]
+
+use crate::database::entities::{
+    organization::{Organization, OrganizationId},
+    organization_member::{OrgAction, OrgRole, OrganizationMember},
+    quiz::{Quiz, QuizId},
+    quiz_permission::{QuizAction, QuizPermission, QuizRole},
+    user::User,
+};
+use crate::http::models::{error::HttpResult, organization::OrgError, quiz::QuizError};
+use crate::utils::assert::assert;
+use sea_orm::ConnectionTrait;
+
+/// Access the user has to a quiz
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuizAccess {
+    /// User owns the quiz
+    Owner,
+    /// User was granted a role on the quiz
+    Collaborator(QuizRole),
+    /// User is a member of the organization that owns the quiz
+    Organization(OrgRole),
+}
+
+impl QuizAccess {
+    /// Checks whether this access allows the provided `action`
+    pub fn allows(&self, action: QuizAction) -> bool {
+        match self {
+            QuizAccess::Owner => true,
+            QuizAccess::Collaborator(role) => role.allows(action),
+            // Members can host the quizzes of their organization
+            QuizAccess::Organization(role) => {
+                role.allows(OrgAction::ManageContent) || QuizRole::Host.allows(action)
+            }
+        }
+    }
+}
+
+/// Finds the quiz with the provided `id` ensuring that the provided
+/// `user` is allowed to perform the `action` on it
+pub async fn authorize_quiz<C>(
+    db: &C,
+    user: &User,
+    id: QuizId,
+    action: QuizAction,
+) -> HttpResult<(Quiz, QuizAccess)>
+where
+    C: ConnectionTrait,
+{
+    let quiz = Quiz::find_by_id(db, id).await?.ok_or(QuizError::NotFound)?;
+
+    let access = if quiz.owner == user.id {
+        QuizAccess::Owner
+    } else {
+        let collaborator = QuizPermission::find(db, quiz.id, user.id)
+            .await?
+            .map(|permission| QuizAccess::Collaborator(permission.role));
+
+        let organization = match quiz.organization_id {
+            Some(organization_id) => OrganizationMember::find(db, organization_id, user.id)
+                .await?
+                .map(|member| QuizAccess::Organization(member.role)),
+            None => None,
+        };
+
+        // Prefer whichever access allows the action when the user has both
+        [collaborator, organization]
+            .into_iter()
+            .flatten()
+            .find(|access| access.allows(action))
+            .or(collaborator)
+            .or(organization)
+            .ok_or(QuizError::MissingPermission)?
+    };
+
+    assert(access.allows(action), QuizError::MissingPermission)?;
+
+    Ok((quiz, access))
+}
+
+/// Finds the organization with the provided `id` ensuring that the
+/// provided `user` is a member allowed to perform the `action` on it
+pub async fn authorize_organization<C>(
+    db: &C,
+    user: &User,
+    id: OrganizationId,
+    action: OrgAction,
+) -> HttpResult<(Organization, OrganizationMember)>
+where
+    C: ConnectionTrait,
+{
+    // Organizations are hidden from users that aren't members
+    let member = OrganizationMember::find(db, id, user.id)
+        .await?
+        .ok_or(OrgError::NotFound)?;
+
+    assert(member.role.allows(action), OrgError::MissingPermission)?;
+
+    let organization = Organization::find_by_id(db, id)
+        .await?
+        .ok_or(OrgError::NotFound)?;
+
+    Ok((organization, member))
+}
@@ -0,0 +1,29 @@
+use crate::http::models::error::HttpErrorResponse;
+use async_trait::async_trait;
+use axum::{extract::FromRequestParts, http::request::Parts};
+use serde::de::DeserializeOwned;
+
+/// Wrapper around [Query](axum::extract::Query) providing the correct error
+/// response structures
+#[derive(FromRequestParts)]
+#[from_request(via(axum::extract::Query), rejection(HttpErrorResponse))]
+pub struct ExtractQuery<T>(pub T);
+
+/// Wrapper around [ExtractQuery] that validates the deserialized result
+/// responding with any validation errors
+pub struct ValidQuery<T>(pub T);
+
+#[async_trait]
+impl<S, T> FromRequestParts<S> for ValidQuery<T>
+where
+    S: Send + Sync,
+    T: DeserializeOwned + garde::Validate<Context = ()>,
+{
+    type Rejection = HttpErrorResponse;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let ExtractQuery(value) = ExtractQuery::<T>::from_request_parts(parts, state).await?;
+        value.validate(&())?;
+        Ok(Self(value))
+    }
+}
@@ -0,0 +1,102 @@
+use std::sync::Arc;
+
+use crate::{
+    database::entities::scim_token::ScimToken,
+    http::models::{error::HttpErrorResponse, scim::ScimError},
+    services::auth::AuthService,
+};
+use async_trait::async_trait;
+use axum::{
+    extract::{FromRequest, FromRequestParts, Request},
+    http::{header, request::Parts, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use axum_extra::{
+    headers::{authorization::Bearer, Authorization},
+    TypedHeader,
+};
+use sea_orm::DatabaseConnection;
+use serde::{de::DeserializeOwned, Serialize};
+
+/// Content type of SCIM requests and responses
+pub const SCIM_CONTENT_TYPE: &str = "application/scim+json";
+
+/// Middleware for authorizing directories provisioning users through
+/// SCIM, contains the token the directory authorized with
+pub struct ScimAuth(pub ScimToken);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for ScimAuth
+where
+    S: Send + Sync,
+{
+    type Rejection = HttpErrorResponse;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let auth: Arc<AuthService> = parts
+            .extensions
+            .get::<Arc<AuthService>>()
+            .expect("Missing auth service")
+            .clone();
+        let db: DatabaseConnection = parts
+            .extensions
+            .get::<DatabaseConnection>()
+            .expect("Missing database connection")
+            .clone();
+        let TypedHeader(authorization) =
+            TypedHeader::<Authorization<Bearer>>::from_request_parts(parts, state)
+                .await
+                .map_err(|_| ScimError::InvalidToken)?;
+        let scim_token = auth
+            .verify_scim_token(&db, authorization.token())
+            .await?
+            .ok_or(ScimError::InvalidToken)?;
+
+        Ok(Self(scim_token))
+    }
+}
+
+/// JSON body using the SCIM content type, as an extractor both the SCIM
+/// and standard JSON content types are accepted
+pub struct ScimJson<T>(pub T);
+
+#[async_trait]
+impl<S, T> FromRequest<S> for ScimJson<T>
+where
+    S: Send + Sync,
+    T: DeserializeOwned,
+{
+    type Rejection = HttpErrorResponse;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let Json(value) = Json::<T>::from_request(req, state)
+            .await
+            .map_err(ScimError::InvalidSyntax)?;
+        Ok(Self(value))
+    }
+}
+
+impl<T> ScimJson<T>
+where
+    T: Serialize,
+{
+    /// Creates the response using the provided `status` code
+    pub fn into_response_with(self, status: StatusCode) -> Response {
+        (
+            status,
+            [(header::CONTENT_TYPE, SCIM_CONTENT_TYPE)],
+            Json(self.0),
+        )
+            .into_response()
+    }
+}
+
+impl<T> IntoResponse for ScimJson<T>
+where
+    T: Serialize,
+{
+    fn into_response(self) -> Response {
+        self.into_response_with(StatusCode::OK)
+    }
+}
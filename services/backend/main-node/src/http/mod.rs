@@ -2,4 +2,5 @@ mod middleware;
 mod models;
 mod routes;
 
+pub use middleware::client::ProxyConfig;
 pub use routes::init_router;
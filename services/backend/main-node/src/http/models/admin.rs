@@ -0,0 +1,125 @@
+use axum::http::StatusCode;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::database::entities::user::{User, UserId, UserRole};
+
+use super::error::HttpError;
+
+#[derive(Debug, Error)]
+pub enum AdminError {
+    /// No matching user found
+    #[error("User not found")]
+    UserNotFound,
+    /// No matching quiz found
+    #[error("Quiz not found")]
+    QuizNotFound,
+    /// Administrators can't change the role of, suspend or force a
+    /// password reset on themselves
+    #[error("You can't make this change to your own account")]
+    OwnAccount,
+    /// Only public quizzes can be unpublished or hidden
+    #[error("Only public quizzes can be moderated")]
+    QuizNotPublic,
+    /// Quiz is not published
+    #[error("Quiz is not published")]
+    QuizNotPublished,
+    /// Quiz hasn't been hidden
+    #[error("Quiz is not hidden")]
+    QuizNotHidden,
+}
+
+impl HttpError for AdminError {
+    fn name(&self) -> &'static str {
+        match self {
+            AdminError::UserNotFound => "admin:user_not_found",
+            AdminError::QuizNotFound => "admin:quiz_not_found",
+            AdminError::OwnAccount => "admin:own_account",
+            AdminError::QuizNotPublic => "admin:quiz_not_public",
+            AdminError::QuizNotPublished => "admin:quiz_not_published",
+            AdminError::QuizNotHidden => "admin:quiz_not_hidden",
+        }
+    }
+
+    fn status_code(&self) -> StatusCode {
+        match self {
+            AdminError::UserNotFound | AdminError::QuizNotFound => StatusCode::NOT_FOUND,
+            AdminError::OwnAccount => StatusCode::BAD_REQUEST,
+            AdminError::QuizNotPublic
+            | AdminError::QuizNotPublished
+            | AdminError::QuizNotHidden => StatusCode::CONFLICT,
+        }
+    }
+}
+
+/// Query for listing and searching users
+#[derive(Deserialize, garde::Validate)]
+pub struct ListUsersQuery {
+    /// Text to search for within the username, email and name of users
+    #[serde(default)]
+    #[garde(length(min = 1, max = 100))]
+    pub query: Option<String>,
+    /// Only include users with this role
+    #[serde(default)]
+    #[garde(skip)]
+    pub role: Option<UserRole>,
+    /// The page to view, starting at zero
+    #[serde(default)]
+    #[garde(skip)]
+    pub page: u64,
+    /// The number of users to include in each page
+    #[serde(default = "default_page_size")]
+    #[garde(range(min = 1, max = 100))]
+    pub size: u64,
+}
+
+/// Query for listing the quizzes hidden by moderators
+#[derive(Deserialize, garde::Validate)]
+pub struct ListHiddenQuizzesQuery {
+    /// The page to view, starting at zero
+    #[serde(default)]
+    #[garde(skip)]
+    pub page: u64,
+    /// The number of quizzes to include in each page
+    #[serde(default = "default_page_size")]
+    #[garde(range(min = 1, max = 100))]
+    pub size: u64,
+}
+
+fn default_page_size() -> u64 {
+    20
+}
+
+/// User along with their ID which isn't included in the standard
+/// user responses
+#[derive(Serialize)]
+pub struct AdminUser {
+    /// ID of the user
+    pub id: UserId,
+    #[serde(flatten)]
+    pub user: User,
+}
+
+impl From<User> for AdminUser {
+    fn from(user: User) -> Self {
+        Self { id: user.id, user }
+    }
+}
+
+/// Response containing a page of users
+#[derive(Serialize)]
+pub struct UserListResponse {
+    /// The users on the requested page
+    pub users: Vec<AdminUser>,
+    /// The total number of users
+    pub total_items: u64,
+    /// The total number of pages
+    pub total_pages: u64,
+}
+
+/// Request to change the role of a user
+#[derive(Deserialize)]
+pub struct UpdateRoleRequest {
+    /// The new role for the user
+    pub role: UserRole,
+}
@@ -1,21 +1,30 @@
 use axum::http::StatusCode;
-use openid::{IdToken, StandardClaims};
 use reqwest::Url;
 use serde::{Deserialize, Serialize};
 use serde_with::serde_as;
 use thiserror::Error;
+use webauthn_rs::prelude::{PublicKeyCredential, RegisterPublicKeyCredential};
 
 use crate::{
-    services::auth::{AuthProvider, UserTokenData},
+    database::entities::user_refresh_token::UserRefreshToken,
+    services::{
+        auth::{MfaError, UserTokenData},
+        openid::ProviderId,
+        saml::SamlError,
+        webauthn::PasskeyError,
+    },
     utils::types::{EmailAddress, Password, Username},
 };
 
-use super::error::HttpError;
+use super::error::{HttpError, HttpErrorResponse};
 
 #[derive(Debug, Error)]
 pub enum AuthError {
     #[error("Failed to create token, try logging in again")]
     FailedTokenIssue,
+    /// Account has been deactivated or suspended and can't be logged into
+    #[error("This account has been disabled")]
+    AccountDisabled,
     /// Account already exists
     #[error("That email address is already in use")]
     EmailExists,
@@ -27,16 +36,81 @@ pub enum AuthError {
     EmailNotFound,
     #[error("Incorrect password provided")]
     IncorrectPassword,
+    /// Password must be reset before it can be used to login
+    #[error("Your password must be reset, use the forgot password link to continue")]
+    PasswordResetRequired,
+    /// Email verification token was invalid or expired
+    #[error("Verification link is invalid or has expired")]
+    InvalidVerifyToken,
+    /// Email address is already verified
+    #[error("Email address is already verified")]
+    EmailAlreadyVerified,
+    /// Password reset token was invalid, expired or already used
+    #[error("Reset link is invalid or has expired")]
+    InvalidResetToken,
+    /// Email was requested too recently
+    #[error("Please wait before requesting another email")]
+    TooManyRequests,
+    /// Refresh token was invalid, expired or already used
+    #[error("Session has expired, please login again")]
+    InvalidRefreshToken,
+    /// Session doesn't exist or belongs to another user
+    #[error("Unknown session")]
+    SessionNotFound,
+    /// MFA challenge token was invalid or expired
+    #[error("Login has expired, please login again")]
+    InvalidMfaToken,
+    /// Second factor code was incorrect or already used
+    #[error("Invalid authentication code")]
+    InvalidMfaCode,
+    /// Two-factor authentication isn't enabled for the account
+    #[error("Two-factor authentication is not enabled")]
+    MfaNotEnabled,
+    /// Two-factor authentication is already enabled for the account
+    #[error("Two-factor authentication is already enabled")]
+    MfaAlreadyEnabled,
+    /// Two-factor authentication can't be disabled for the account
+    #[error("Two-factor authentication is required for your account")]
+    MfaRequired,
+    /// Passkey challenge doesn't exist or has expired
+    #[error("Passkey request has expired, please try again")]
+    PasskeyChallengeExpired,
+    /// Passkey response failed verification or isn't registered
+    #[error("Passkey could not be verified")]
+    InvalidPasskey,
+    /// Passkey doesn't exist or belongs to another user
+    #[error("Unknown passkey")]
+    PasskeyNotFound,
+    /// Passkeys aren't configured on the server
+    #[error("Passkeys are not available")]
+    PasskeysDisabled,
 }
 
 impl HttpError for AuthError {
     fn name(&self) -> &'static str {
         match self {
             AuthError::FailedTokenIssue => "auth:token_create_failed",
+            AuthError::AccountDisabled => "auth:account_disabled",
             AuthError::EmailExists => "auth:email_exists",
             AuthError::UsernameExists => "auth:username_exists",
             AuthError::EmailNotFound => "auth:email_not_found",
             AuthError::IncorrectPassword => "auth:incorrect_password",
+            AuthError::PasswordResetRequired => "auth:password_reset_required",
+            AuthError::InvalidVerifyToken => "auth:invalid_verify_token",
+            AuthError::InvalidResetToken => "auth:invalid_reset_token",
+            AuthError::EmailAlreadyVerified => "auth:email_already_verified",
+            AuthError::TooManyRequests => "auth:too_many_requests",
+            AuthError::InvalidRefreshToken => "auth:invalid_refresh_token",
+            AuthError::SessionNotFound => "auth:session_not_found",
+            AuthError::InvalidMfaToken => "auth:invalid_mfa_token",
+            AuthError::InvalidMfaCode => "auth:invalid_mfa_code",
+            AuthError::MfaNotEnabled => "auth:mfa_not_enabled",
+            AuthError::MfaAlreadyEnabled => "auth:mfa_already_enabled",
+            AuthError::MfaRequired => "auth:mfa_required",
+            AuthError::PasskeyChallengeExpired => "auth:passkey_challenge_expired",
+            AuthError::InvalidPasskey => "auth:invalid_passkey",
+            AuthError::PasskeyNotFound => "auth:passkey_not_found",
+            AuthError::PasskeysDisabled => "auth:passkeys_disabled",
         }
     }
 
@@ -44,8 +118,49 @@ impl HttpError for AuthError {
         match self {
             AuthError::FailedTokenIssue => StatusCode::INTERNAL_SERVER_ERROR,
             AuthError::EmailExists | AuthError::UsernameExists => StatusCode::CONFLICT,
-            AuthError::EmailNotFound => StatusCode::NOT_FOUND,
-            AuthError::IncorrectPassword => StatusCode::BAD_REQUEST,
+            AuthError::EmailNotFound
+            | AuthError::SessionNotFound
+            | AuthError::PasskeyNotFound
+            | AuthError::PasskeysDisabled => StatusCode::NOT_FOUND,
+            AuthError::IncorrectPassword
+            | AuthError::InvalidVerifyToken
+            | AuthError::InvalidResetToken
+            | AuthError::InvalidMfaCode
+            | AuthError::MfaNotEnabled
+            | AuthError::PasskeyChallengeExpired => StatusCode::BAD_REQUEST,
+            AuthError::EmailAlreadyVerified | AuthError::MfaAlreadyEnabled => StatusCode::CONFLICT,
+            AuthError::TooManyRequests => StatusCode::TOO_MANY_REQUESTS,
+            AuthError::InvalidRefreshToken
+            | AuthError::InvalidMfaToken
+            | AuthError::InvalidPasskey => StatusCode::UNAUTHORIZED,
+            AuthError::MfaRequired
+            | AuthError::AccountDisabled
+            | AuthError::PasswordResetRequired => StatusCode::FORBIDDEN,
+        }
+    }
+}
+
+impl From<MfaError> for HttpErrorResponse {
+    fn from(value: MfaError) -> Self {
+        match value {
+            MfaError::Database(err) => err.into(),
+            MfaError::NotEnabled => AuthError::MfaNotEnabled.into(),
+            MfaError::AlreadyEnabled => AuthError::MfaAlreadyEnabled.into(),
+            MfaError::InvalidCode => AuthError::InvalidMfaCode.into(),
+            MfaError::TooManyAttempts => AuthError::TooManyRequests.into(),
+        }
+    }
+}
+
+impl From<PasskeyError> for HttpErrorResponse {
+    fn from(value: PasskeyError) -> Self {
+        match value {
+            PasskeyError::Database(err) => err.into(),
+            PasskeyError::UnknownChallenge => AuthError::PasskeyChallengeExpired.into(),
+            PasskeyError::UnknownPasskey | PasskeyError::Webauthn(_) => {
+                AuthError::InvalidPasskey.into()
+            }
+            PasskeyError::Disabled => AuthError::PasskeysDisabled.into(),
         }
     }
 }
@@ -71,6 +186,21 @@ pub enum OIDError {
     /// Token claim was missing an email, OAuth is likely mis-configured
     #[error("Failed to determine account email address.")]
     ClaimMissingEmail,
+    /// Login state is unknown, expired or was already used
+    #[error("Login attempt has expired, try again.")]
+    InvalidState,
+    /// Provider is already linked to the account
+    #[error("That provider is already linked to your account")]
+    AlreadyLinked,
+    /// Provider account is already linked to a user
+    #[error("That provider account is already linked to another account")]
+    AccountLinked,
+    /// Provider isn't linked to the account
+    #[error("That provider is not linked to your account")]
+    LinkNotFound,
+    /// Unlinking would leave the account without a way to login
+    #[error("You must have another way to login before removing this provider")]
+    LastLoginMethod,
 }
 
 impl HttpError for OIDError {
@@ -81,6 +211,11 @@ impl HttpError for OIDError {
             OIDError::InvalidToken => "oid:invalid_token",
             OIDError::Authentication => "oid:auth_failed",
             OIDError::ClaimMissingEmail => "oid:claim_missing_email",
+            OIDError::InvalidState => "oid:invalid_state",
+            OIDError::AlreadyLinked => "oid:already_linked",
+            OIDError::AccountLinked => "oid:account_linked",
+            OIDError::LinkNotFound => "oid:link_not_found",
+            OIDError::LastLoginMethod => "oid:last_login_method",
         }
     }
 
@@ -91,17 +226,80 @@ impl HttpError for OIDError {
             OIDError::InvalidToken => StatusCode::BAD_REQUEST,
             OIDError::Authentication => StatusCode::BAD_REQUEST,
             OIDError::ClaimMissingEmail => StatusCode::BAD_REQUEST,
+            OIDError::InvalidState => StatusCode::BAD_REQUEST,
+            OIDError::AlreadyLinked => StatusCode::CONFLICT,
+            OIDError::AccountLinked => StatusCode::CONFLICT,
+            OIDError::LinkNotFound => StatusCode::NOT_FOUND,
+            OIDError::LastLoginMethod => StatusCode::CONFLICT,
         }
     }
 }
 
-/// Request to check an OpenID token with the provider
-#[derive(Deserialize)]
-pub struct OIDConfirmRequest {
-    /// The provider the token is from
-    pub provider: AuthProvider,
-    /// The token itself
-    pub token: IdToken<StandardClaims>,
+#[derive(Debug, Error)]
+pub enum SAMLError {
+    /// Organization doesn't have a SAML connection
+    #[error("Single sign-on is not available for that organization")]
+    UnknownConnection,
+    /// Failed to load the identity provider
+    #[error("Your organization's identity provider is currently unavailable, try again later.")]
+    ProviderUnavailable,
+    /// Login is unknown, expired or was already completed
+    #[error("Login attempt has expired, try again.")]
+    InvalidState,
+    /// Response from the identity provider failed validation
+    #[error("Failed to verify the response from your identity provider")]
+    InvalidResponse,
+    /// Assertion was missing an email, the attribute mapping is
+    /// likely mis-configured
+    #[error("Failed to determine account email address.")]
+    MissingEmail,
+    /// Tried to login with an identity provider that isn't linked
+    /// to an existing account
+    #[error(
+        "An account already exists with the same email, please login to the \
+        existing account instead"
+    )]
+    NotLinked,
+    /// Login code is unknown, expired or was already used
+    #[error("Login has expired, please login again")]
+    InvalidLoginCode,
+}
+
+impl HttpError for SAMLError {
+    fn name(&self) -> &'static str {
+        match self {
+            SAMLError::UnknownConnection => "saml:unknown_connection",
+            SAMLError::ProviderUnavailable => "saml:provider_unavailable",
+            SAMLError::InvalidState => "saml:invalid_state",
+            SAMLError::InvalidResponse => "saml:invalid_response",
+            SAMLError::MissingEmail => "saml:missing_email",
+            SAMLError::NotLinked => "saml:not_linked",
+            SAMLError::InvalidLoginCode => "saml:invalid_login_code",
+        }
+    }
+
+    fn status_code(&self) -> StatusCode {
+        match self {
+            SAMLError::UnknownConnection => StatusCode::NOT_FOUND,
+            SAMLError::ProviderUnavailable => StatusCode::INTERNAL_SERVER_ERROR,
+            SAMLError::InvalidState
+            | SAMLError::InvalidResponse
+            | SAMLError::MissingEmail
+            | SAMLError::InvalidLoginCode => StatusCode::BAD_REQUEST,
+            SAMLError::NotLinked => StatusCode::CONFLICT,
+        }
+    }
+}
+
+impl From<SamlError> for HttpErrorResponse {
+    fn from(value: SamlError) -> Self {
+        match value {
+            SamlError::UnknownConnection => SAMLError::UnknownConnection.into(),
+            SamlError::ProviderUnavailable => SAMLError::ProviderUnavailable.into(),
+            SamlError::UnknownLogin => SAMLError::InvalidState.into(),
+            SamlError::InvalidResponse => SAMLError::InvalidResponse.into(),
+        }
+    }
 }
 
 /// Request to refresh a token
@@ -111,15 +309,12 @@ pub struct RefreshTokenRequest {
     pub refresh_token: String,
 }
 
-/// Request for creating an account from an OpenID token
+/// Request for creating an account from a completed OpenID login
 #[derive(Deserialize, garde::Validate)]
 pub struct OIDCreateRequest {
-    /// The provider the token is from
-    #[garde(skip)]
-    pub provider: AuthProvider,
-    /// The token itself
+    /// The signup token from authenticating with the provider
     #[garde(skip)]
-    pub token: IdToken<StandardClaims>,
+    pub token: String,
     /// The username for the user
     #[garde(dive)]
     pub username: Username,
@@ -131,8 +326,18 @@ pub struct OIDCreateRequest {
 /// Request to authenticate an OpenID code
 #[derive(Deserialize)]
 pub struct OIDAuthenticateRequest {
-    /// The provider the code is from
-    pub provider: AuthProvider,
+    /// The state the provider redirected back with
+    pub state: String,
+    /// The auth code
+    pub code: String,
+}
+
+/// Request to link an OpenID provider to the current user using
+/// an OpenID code
+#[derive(Deserialize)]
+pub struct OIDLinkRequest {
+    /// The state the provider redirected back with
+    pub state: String,
     /// The auth code
     pub code: String,
 }
@@ -142,13 +347,59 @@ pub struct OIDAuthenticateRequest {
 pub enum OIDAuthenticateResponse {
     /// Account doesn't exist, prepare account creation
     CreateAccount {
-        /// The auth token to authenticate with OpenID
-        token: Box<IdToken<StandardClaims>>,
+        /// The provider the token is from
+        provider: ProviderId,
+        /// Single use token for creating the account, the provider
+        /// account details are kept on the server
+        token: String,
         /// The default username based on the name present in the claim
         default_username: Option<Username>,
     },
     /// Account exists and is linked to this method, logged in
     ExistingLinked(TokenResponse),
+    /// Account exists and is linked to this method but requires a second
+    /// factor, completed the same way as [LoginResponse::MfaRequired]
+    MfaRequired {
+        /// Token for completing the login
+        mfa_token: String,
+    },
+    /// Account exists and is linked to this method but must enroll in
+    /// two-factor authentication, see [LoginResponse::MfaSetupRequired]
+    MfaSetupRequired {
+        /// Token for enrolling and completing the login
+        mfa_token: String,
+    },
+}
+
+impl From<LoginResponse> for OIDAuthenticateResponse {
+    fn from(value: LoginResponse) -> Self {
+        match value {
+            LoginResponse::Authenticated(token) => Self::ExistingLinked(token),
+            LoginResponse::MfaRequired { mfa_token } => Self::MfaRequired { mfa_token },
+            LoginResponse::MfaSetupRequired { mfa_token } => Self::MfaSetupRequired { mfa_token },
+        }
+    }
+}
+
+/// Response to a login request using basic credentials, a passkey or
+/// single sign-on
+#[derive(Serialize)]
+#[serde(tag = "type")]
+pub enum LoginResponse {
+    /// Login is complete
+    Authenticated(TokenResponse),
+    /// Login requires a second factor, the code must be provided along
+    /// with the token to complete the login
+    MfaRequired {
+        /// Token for completing the login
+        mfa_token: String,
+    },
+    /// Login requires the account to enroll in two-factor authentication
+    /// first, the token is used in place of an auth token for enrolling
+    MfaSetupRequired {
+        /// Token for enrolling and completing the login
+        mfa_token: String,
+    },
 }
 
 /// Response containing an authorization token
@@ -165,16 +416,49 @@ pub struct TokenResponse {
 pub struct OIDProvidersResponse {
     /// Collection of available providers
     #[serde_as(as = "serde_with::Map<_, _>")]
-    pub providers: Vec<(AuthProvider, OIDProvider)>,
+    pub providers: Vec<(ProviderId, OIDProvider)>,
 }
 
 /// Details about an OpenID auth provider
 #[derive(Serialize)]
 pub struct OIDProvider {
+    /// Name of the provider to show to users
+    pub name: String,
+    /// Optional icon to show alongside the provider name
+    pub icon_url: Option<Url>,
     /// The URL for authenticating with the provider
     pub auth_url: Url,
 }
 
+/// Response containing the URL to start a SAML login at
+#[derive(Serialize)]
+pub struct SAMLLoginResponse {
+    /// The URL of the identity provider to send the user to
+    pub auth_url: Url,
+}
+
+/// Form posted to the assertion consumer service by the identity
+/// provider using the HTTP-POST binding
+#[derive(Deserialize)]
+pub struct SAMLResponseForm {
+    /// Base64 encoded SAML response
+    #[serde(rename = "SAMLResponse")]
+    pub saml_response: String,
+    /// The relay state the login was started with
+    #[serde(rename = "RelayState")]
+    pub relay_state: String,
+}
+
+/// Request to complete a SAML login using the code the hub
+/// was redirected with
+#[derive(Deserialize)]
+pub struct SAMLExchangeRequest {
+    /// The single use login code
+    pub code: String,
+    /// The relay state of the login started by the browser
+    pub state: String,
+}
+
 /// Request to register an account with basic details
 #[derive(Deserialize, garde::Validate)]
 pub struct BasicRegisterRequest {
@@ -199,3 +483,144 @@ pub struct BasicLoginRequest {
     #[garde(dive)]
     pub password: Password,
 }
+
+/// Request to verify an email address
+#[derive(Deserialize)]
+pub struct VerifyEmailRequest {
+    /// The verification token from the email
+    pub token: String,
+}
+
+/// Request to send a password reset email
+#[derive(Deserialize, garde::Validate)]
+pub struct ForgotPasswordRequest {
+    /// The email of the account
+    #[garde(dive)]
+    pub email: EmailAddress,
+}
+
+/// Request to reset the password of an account
+#[derive(Deserialize, garde::Validate)]
+pub struct ResetPasswordRequest {
+    /// The reset token from the email
+    #[garde(skip)]
+    pub token: String,
+    /// The new password for the account
+    #[garde(dive)]
+    pub password: Password,
+}
+
+/// Request to complete a login using a second factor
+#[derive(Deserialize)]
+pub struct MfaVerifyRequest {
+    /// The token from the login response
+    pub mfa_token: String,
+    /// TOTP code or recovery code
+    pub code: String,
+}
+
+/// Request containing a second factor code for confirming an action
+#[derive(Deserialize)]
+pub struct MfaCodeRequest {
+    /// TOTP code or recovery code
+    pub code: String,
+}
+
+/// Request to start enrolling in TOTP two-factor authentication
+#[derive(Deserialize)]
+pub struct TotpSetupRequest {
+    /// The token from the login response when enrollment is required
+    /// before login, not needed when already logged in
+    #[serde(default)]
+    pub mfa_token: Option<String>,
+}
+
+/// Request to confirm enrollment in TOTP two-factor authentication
+#[derive(Deserialize)]
+pub struct TotpConfirmRequest {
+    /// The token from the login response when enrollment is required
+    /// before login, not needed when already logged in
+    #[serde(default)]
+    pub mfa_token: Option<String>,
+    /// Code from the authenticator app
+    pub code: String,
+}
+
+/// Response containing a new TOTP secret
+#[derive(Serialize)]
+pub struct TotpSetupResponse {
+    /// Base32 encoded secret for entering manually
+    pub secret: String,
+    /// otpauth:// URI for displaying as a QR code
+    pub uri: String,
+}
+
+/// Response to confirming TOTP enrollment
+#[derive(Serialize)]
+pub struct TotpConfirmResponse {
+    /// Recovery codes, these are only shown once
+    pub recovery_codes: Vec<String>,
+    /// Login token when enrollment was completed as part of logging in
+    #[serde(flatten)]
+    pub token: Option<TokenResponse>,
+}
+
+/// Response containing newly generated recovery codes
+#[derive(Serialize)]
+pub struct RecoveryCodesResponse {
+    /// Recovery codes, these are only shown once
+    pub recovery_codes: Vec<String>,
+}
+
+/// Two-factor authentication status of the current user
+#[derive(Serialize)]
+pub struct MfaStatusResponse {
+    /// Whether two-factor authentication is enabled
+    pub enabled: bool,
+    /// Whether the account is required to use two-factor authentication
+    pub required: bool,
+    /// Number of unused recovery codes
+    pub recovery_codes_remaining: u64,
+}
+
+/// Response containing a passkey challenge for the authenticator
+#[derive(Serialize)]
+pub struct PasskeyChallengeResponse<O> {
+    /// ID for completing the challenge
+    pub challenge_id: String,
+    /// Options to pass to navigator.credentials
+    pub options: O,
+}
+
+/// Request to complete registering a passkey
+#[derive(Deserialize, garde::Validate)]
+pub struct PasskeyRegisterRequest {
+    /// The challenge ID from starting the registration
+    #[garde(skip)]
+    pub challenge_id: String,
+    /// Name to identify the passkey by
+    #[garde(length(min = 1, max = 64))]
+    pub name: String,
+    /// The credential created by the authenticator
+    #[garde(skip)]
+    pub credential: RegisterPublicKeyCredential,
+}
+
+/// Request to complete logging in with a passkey
+#[derive(Deserialize)]
+pub struct PasskeyLoginRequest {
+    /// The challenge ID from starting the login
+    pub challenge_id: String,
+    /// The assertion from the authenticator
+    pub credential: PublicKeyCredential,
+}
+
+/// Details about an active session of the current user
+#[derive(Serialize)]
+pub struct SessionResponse {
+    /// The session details
+    #[serde(flatten)]
+    pub session: UserRefreshToken,
+    /// Whether this is the session making the request
+    pub current: bool,
+}
@@ -1,5 +1,5 @@
 use axum::{
-    extract::rejection::JsonRejection,
+    extract::rejection::{JsonRejection, QueryRejection},
     http::StatusCode,
     response::{IntoResponse, Response},
     Json,
@@ -64,6 +64,28 @@ impl From<JsonRejection> for HttpErrorResponse {
     }
 }
 
+/// Wrapper around [QueryRejection] for changing the error response
+/// format to match the standard format
+#[derive(Debug, Error)]
+#[error(transparent)]
+pub struct QueryErrorAdapter(#[from] QueryRejection);
+
+impl HttpError for QueryErrorAdapter {
+    fn name(&self) -> &'static str {
+        "query_parse"
+    }
+
+    fn status_code(&self) -> StatusCode {
+        StatusCode::BAD_REQUEST
+    }
+}
+
+impl From<QueryRejection> for HttpErrorResponse {
+    fn from(value: QueryRejection) -> Self {
+        Self(Box::new(QueryErrorAdapter(value)))
+    }
+}
+
 /// Type adapter that allows anyhow to meet the std::error::Error bounds
 #[derive(Debug, Error)]
 #[error("Internal server error")]
@@ -166,6 +188,14 @@ impl From<garde::Report> for HttpErrorResponse {
 #[derive(Debug)]
 pub struct HttpErrorResponse(Box<dyn HttpError>);
 
+impl HttpErrorResponse {
+    /// Name of the underlying error, for responses that report
+    /// errors without the standard error body
+    pub fn name(&self) -> &'static str {
+        self.0.name()
+    }
+}
+
 /// JSON structure for an error response with some generic data
 /// value that can be provided
 #[derive(Debug, Serialize)]
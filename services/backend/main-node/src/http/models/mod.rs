@@ -1,3 +1,9 @@
+pub mod admin;
 pub mod auth;
 pub mod error;
+pub mod organization;
+pub mod play;
 pub mod quiz;
+pub mod reports;
+pub mod resource;
+pub mod scim;
@@ -0,0 +1,205 @@
+use axum::http::StatusCode;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    database::entities::{
+        organization::Organization, organization_domain::OrganizationDomain,
+        organization_member::OrgRole, scim_token::ScimToken,
+    },
+    services::domain::DomainService,
+    utils::types::Username,
+};
+
+use super::error::HttpError;
+
+#[derive(Debug, Error)]
+pub enum OrgError {
+    /// No matching organization found or the user isn't a member
+    #[error("Organization not found")]
+    NotFound,
+    /// Member role doesn't allow the action
+    #[error("Missing permission")]
+    MissingPermission,
+    /// User to add as a member doesn't exist
+    #[error("User not found")]
+    UserNotFound,
+    /// User is already a member
+    #[error("User is already a member")]
+    AlreadyMember,
+    /// User is not a member
+    #[error("Member not found")]
+    MemberNotFound,
+    /// Change would leave the organization without an owner
+    #[error("Organization must have at least one owner")]
+    LastOwner,
+    /// No matching domain claim found
+    #[error("Domain not found")]
+    DomainNotFound,
+    /// Organization has already claimed the domain
+    #[error("Domain has already been added")]
+    DomainAlreadyAdded,
+    /// Domain claim is already verified
+    #[error("Domain is already verified")]
+    DomainAlreadyVerified,
+    /// Another organization has verified the domain
+    #[error("Domain is verified by another organization")]
+    DomainTaken,
+    /// Verification record was missing or didn't contain the token
+    #[error("Verification record not found")]
+    DomainNotVerified,
+    /// Verification record couldn't be looked up
+    #[error("Failed to lookup verification record")]
+    DomainLookup,
+    /// No matching SCIM token found
+    #[error("SCIM token not found")]
+    ScimTokenNotFound,
+}
+
+impl HttpError for OrgError {
+    fn name(&self) -> &'static str {
+        match self {
+            OrgError::NotFound => "org:not_found",
+            OrgError::MissingPermission => "org:missing_permission",
+            OrgError::UserNotFound => "org:user_not_found",
+            OrgError::AlreadyMember => "org:already_member",
+            OrgError::MemberNotFound => "org:member_not_found",
+            OrgError::LastOwner => "org:last_owner",
+            OrgError::DomainNotFound => "org:domain_not_found",
+            OrgError::DomainAlreadyAdded => "org:domain_already_added",
+            OrgError::DomainAlreadyVerified => "org:domain_already_verified",
+            OrgError::DomainTaken => "org:domain_taken",
+            OrgError::DomainNotVerified => "org:domain_not_verified",
+            OrgError::DomainLookup => "org:domain_lookup",
+            OrgError::ScimTokenNotFound => "org:scim_token_not_found",
+        }
+    }
+
+    fn status_code(&self) -> StatusCode {
+        match self {
+            OrgError::NotFound
+            | OrgError::UserNotFound
+            | OrgError::MemberNotFound
+            | OrgError::DomainNotFound
+            | OrgError::ScimTokenNotFound => StatusCode::NOT_FOUND,
+            OrgError::MissingPermission => StatusCode::FORBIDDEN,
+            OrgError::DomainNotVerified => StatusCode::BAD_REQUEST,
+            OrgError::AlreadyMember
+            | OrgError::LastOwner
+            | OrgError::DomainAlreadyAdded
+            | OrgError::DomainAlreadyVerified
+            | OrgError::DomainTaken => StatusCode::CONFLICT,
+            OrgError::DomainLookup => StatusCode::SERVICE_UNAVAILABLE,
+        }
+    }
+}
+
+/// Request to create an organization
+#[derive(Deserialize, garde::Validate)]
+pub struct CreateOrganizationRequest {
+    /// The name of the organization
+    #[garde(length(min = 2, max = 100))]
+    pub name: String,
+}
+
+/// Request to update the details of an organization
+#[derive(Deserialize, garde::Validate)]
+pub struct UpdateOrganizationRequest {
+    /// The name of the organization
+    #[garde(length(min = 2, max = 100))]
+    pub name: String,
+}
+
+/// Organization along with the role of the current user
+#[derive(Serialize)]
+pub struct OrganizationResponse {
+    #[serde(flatten)]
+    pub organization: Organization,
+    /// The role of the current user within the organization
+    pub role: OrgRole,
+}
+
+/// Request to add a user as a member of an organization
+#[derive(Deserialize, garde::Validate)]
+pub struct AddMemberRequest {
+    /// Username of the user to add
+    #[garde(dive)]
+    pub username: Username,
+    /// The role to give the user
+    #[garde(skip)]
+    pub role: OrgRole,
+}
+
+/// Request to change the role of a member
+#[derive(Deserialize)]
+pub struct UpdateMemberRequest {
+    /// The new role for the member
+    pub role: OrgRole,
+}
+
+/// Request to claim an email domain for an organization
+#[derive(Deserialize, garde::Validate)]
+pub struct AddDomainRequest {
+    /// The email domain to claim
+    #[garde(length(max = 253), custom(validate_domain))]
+    pub domain: String,
+}
+
+/// Domain claim along with the DNS record required to verify it
+#[derive(Serialize)]
+pub struct DomainResponse {
+    #[serde(flatten)]
+    pub domain: OrganizationDomain,
+    /// Name of the TXT record to create
+    pub record_name: String,
+    /// Value the TXT record must contain
+    pub record_value: String,
+}
+
+impl From<OrganizationDomain> for DomainResponse {
+    fn from(domain: OrganizationDomain) -> Self {
+        Self {
+            record_name: DomainService::record_name(&domain.domain),
+            record_value: DomainService::record_value(&domain.verification_token),
+            domain,
+        }
+    }
+}
+
+/// Request to create a token for SCIM provisioning
+#[derive(Deserialize, garde::Validate)]
+pub struct CreateScimTokenRequest {
+    /// Name to identify the token by
+    #[garde(length(min = 1, max = 100))]
+    pub name: String,
+}
+
+/// Newly created SCIM token along with the plain text token, the
+/// token can't be viewed again after this response
+#[derive(Serialize)]
+pub struct CreatedScimTokenResponse {
+    #[serde(flatten)]
+    pub scim_token: ScimToken,
+    /// The bearer token to configure the directory with
+    pub token: String,
+}
+
+/// Ensures the value is a domain name made up of at least two labels
+fn validate_domain(value: &str, _: &()) -> garde::Result {
+    let labels: Vec<&str> = value.split('.').collect();
+
+    let valid = labels.len() >= 2
+        && labels.iter().all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        });
+
+    if !valid {
+        return Err(garde::Error::new("invalid domain"));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,24 @@
+use serde::{Deserialize, Serialize};
+
+use crate::database::entities::{
+    active_quiz::{ActiveQuizId, ActiveQuizState},
+    quiz::QuizId,
+};
+
+/// Request to host a new game of a quiz
+#[derive(Deserialize)]
+pub struct CreateSessionRequest {
+    /// The quiz to play
+    pub quiz_id: QuizId,
+}
+
+/// Response for a created game
+#[derive(Serialize)]
+pub struct CreateSessionResponse {
+    /// The ID of the game session
+    pub id: ActiveQuizId,
+    /// The code players use to join the game
+    pub code: String,
+    /// The state of the game
+    pub state: ActiveQuizState,
+}
@@ -1,7 +1,21 @@
 use axum::http::StatusCode;
-use serde::Deserialize;
+use sea_orm::Order;
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
+use crate::{
+    database::{
+        entities::{
+            organization::OrganizationId,
+            quiz::{self, BrowseCursor, BrowseSort, Quiz, QuizSummary, QuizVisibility},
+            quiz_permission::QuizRole,
+            resource::ResourceId,
+        },
+        models::quiz::QuizData,
+    },
+    utils::types::Username,
+};
+
 use super::error::HttpError;
 
 #[derive(Debug, Error)]
@@ -12,6 +26,36 @@ pub enum QuizError {
     /// No permission to access
     #[error("Missing permission")]
     MissingPermission,
+    /// Quiz cannot be published without any questions
+    #[error("Quiz must contain at least one question to be published")]
+    NoQuestions,
+    /// Quiz is already published
+    #[error("Quiz is already published")]
+    AlreadyPublished,
+    /// Quiz is not published
+    #[error("Quiz is not published")]
+    NotPublished,
+    /// Browse cursor was malformed
+    #[error("Invalid cursor")]
+    InvalidCursor,
+    /// User to share the quiz with doesn't exist
+    #[error("User not found")]
+    UserNotFound,
+    /// Quiz cannot be shared with its owner
+    #[error("Quiz cannot be shared with its owner")]
+    SharedWithOwner,
+    /// User is already a collaborator
+    #[error("User is already a collaborator")]
+    AlreadyCollaborator,
+    /// User is not a collaborator
+    #[error("Collaborator not found")]
+    CollaboratorNotFound,
+    /// Referenced image resource is missing or can't be used
+    #[error("Image must be a public resource owned by you or the quiz owner")]
+    InvalidImage,
+    /// Quiz was hidden by a moderator and can't be made public
+    #[error("Quiz has been hidden by a moderator")]
+    Hidden,
 }
 
 impl HttpError for QuizError {
@@ -19,13 +63,32 @@ impl HttpError for QuizError {
         match self {
             QuizError::NotFound => "quiz:not_found",
             QuizError::MissingPermission => "quiz:missing_permission",
+            QuizError::NoQuestions => "quiz:no_questions",
+            QuizError::AlreadyPublished => "quiz:already_published",
+            QuizError::NotPublished => "quiz:not_published",
+            QuizError::InvalidCursor => "quiz:invalid_cursor",
+            QuizError::UserNotFound => "quiz:user_not_found",
+            QuizError::SharedWithOwner => "quiz:shared_with_owner",
+            QuizError::AlreadyCollaborator => "quiz:already_collaborator",
+            QuizError::CollaboratorNotFound => "quiz:collaborator_not_found",
+            QuizError::InvalidImage => "quiz:invalid_image",
+            QuizError::Hidden => "quiz:hidden",
         }
     }
 
     fn status_code(&self) -> StatusCode {
         match self {
-            QuizError::NotFound => StatusCode::NOT_FOUND,
-            QuizError::MissingPermission => StatusCode::FORBIDDEN,
+            QuizError::NotFound | QuizError::UserNotFound | QuizError::CollaboratorNotFound => {
+                StatusCode::NOT_FOUND
+            }
+            QuizError::MissingPermission | QuizError::Hidden => StatusCode::FORBIDDEN,
+            QuizError::NoQuestions
+            | QuizError::InvalidCursor
+            | QuizError::SharedWithOwner
+            | QuizError::InvalidImage => StatusCode::BAD_REQUEST,
+            QuizError::AlreadyPublished
+            | QuizError::NotPublished
+            | QuizError::AlreadyCollaborator => StatusCode::CONFLICT,
         }
     }
 }
@@ -36,4 +99,280 @@ pub struct CreateQuizRequest {
     /// The title of the quiz
     #[garde(length(min = 4, max = 100))]
     pub title: String,
+    /// Organization to create the quiz within
+    #[serde(default)]
+    #[garde(skip)]
+    pub organization: Option<OrganizationId>,
+}
+
+/// Request to update the details of a quiz, fields that are
+/// not provided are left unchanged
+#[derive(Deserialize, garde::Validate)]
+pub struct UpdateQuizRequest {
+    /// The title of the quiz
+    #[serde(default)]
+    #[garde(length(min = 4, max = 100))]
+    pub title: Option<String>,
+    /// The description of the quiz
+    #[serde(default)]
+    #[garde(length(max = 1000))]
+    pub description: Option<String>,
+    /// The image resource to use as the cover image of the quiz, null to
+    /// remove the cover image
+    #[serde(default, with = "::serde_with::rust::double_option")]
+    #[garde(skip)]
+    pub cover_image: Option<Option<ResourceId>>,
+    /// The visibility of the quiz
+    #[serde(default)]
+    #[garde(skip)]
+    pub visibility: Option<QuizVisibility>,
+    /// Tags for discovering the quiz
+    #[serde(default)]
+    #[garde(length(max = 10), inner(length(min = 1, max = 32)))]
+    pub tags: Option<Vec<String>>,
+    /// The quiz questions and data
+    #[serde(default)]
+    #[garde(dive)]
+    pub data: Option<QuizData>,
+}
+
+/// Request to share a quiz with another user
+#[derive(Deserialize, garde::Validate)]
+pub struct AddCollaboratorRequest {
+    /// Username of the user to share with
+    #[garde(dive)]
+    pub username: Username,
+    /// The role to grant the user
+    #[garde(skip)]
+    pub role: QuizRole,
+}
+
+/// Request to change the role of a collaborator
+#[derive(Deserialize)]
+pub struct UpdateCollaboratorRequest {
+    /// The new role for the collaborator
+    pub role: QuizRole,
+}
+
+/// Query for listing the quizzes within the users library
+#[derive(Deserialize, garde::Validate)]
+pub struct ListQuizzesQuery {
+    /// The page to view, starting at zero
+    #[serde(default)]
+    #[garde(skip)]
+    pub page: u64,
+    /// The number of quizzes to include in each page
+    #[serde(default = "default_page_size")]
+    #[garde(range(min = 1, max = 100))]
+    pub size: u64,
+    /// Field to sort the quizzes by
+    #[serde(default)]
+    #[garde(skip)]
+    pub sort: QuizSort,
+    /// Order to sort the quizzes in
+    #[serde(default)]
+    #[garde(skip)]
+    pub order: SortOrder,
+}
+
+fn default_page_size() -> u64 {
+    20
+}
+
+/// Fields quizzes can be sorted by
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QuizSort {
+    CreatedAt,
+    #[default]
+    UpdatedAt,
+    Title,
+}
+
+impl QuizSort {
+    /// Provides the quiz column that this sort uses
+    pub fn column(&self) -> quiz::Column {
+        match self {
+            QuizSort::CreatedAt => quiz::Column::CreatedAt,
+            QuizSort::UpdatedAt => quiz::Column::UpdatedAt,
+            QuizSort::Title => quiz::Column::Title,
+        }
+    }
+}
+
+/// Direction to sort values in
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortOrder {
+    Asc,
+    #[default]
+    Desc,
+}
+
+impl From<SortOrder> for Order {
+    fn from(value: SortOrder) -> Self {
+        match value {
+            SortOrder::Asc => Order::Asc,
+            SortOrder::Desc => Order::Desc,
+        }
+    }
+}
+
+/// Response containing a page of quizzes
+#[derive(Serialize)]
+pub struct QuizListResponse {
+    /// The quizzes on the requested page
+    pub quizzes: Vec<Quiz>,
+    /// The total number of quizzes
+    pub total_items: u64,
+    /// The total number of pages
+    pub total_pages: u64,
+}
+
+/// Query for browsing public quizzes
+#[derive(Deserialize, garde::Validate)]
+pub struct BrowseQuizzesQuery {
+    /// Full-text search query
+    #[serde(default)]
+    #[garde(length(min = 1, max = 200))]
+    pub query: Option<String>,
+    /// Tag the quizzes must have
+    #[serde(default)]
+    #[garde(length(min = 1, max = 32))]
+    pub tag: Option<String>,
+    /// Username of the quiz owner
+    #[serde(default)]
+    #[garde(length(min = 1, max = 100))]
+    pub owner: Option<String>,
+    /// Minimum number of questions
+    #[serde(default)]
+    #[garde(skip)]
+    pub min_questions: Option<u32>,
+    /// Maximum number of questions
+    #[serde(default)]
+    #[garde(skip)]
+    pub max_questions: Option<u32>,
+    /// Ordering for the quizzes
+    #[serde(default)]
+    #[garde(skip)]
+    pub sort: BrowseSort,
+    /// Cursor from a previous response to continue from
+    #[serde(default)]
+    #[garde(skip)]
+    pub cursor: Option<String>,
+    /// The number of quizzes to include
+    #[serde(default = "default_page_size")]
+    #[garde(range(min = 1, max = 100))]
+    pub size: u64,
+}
+
+/// Response containing a page of public quizzes
+#[derive(Serialize)]
+pub struct BrowseQuizzesResponse {
+    /// The quizzes on this page
+    pub quizzes: Vec<QuizSummary>,
+    /// Cursor for the next page, [None] when there are no more quizzes
+    pub next_cursor: Option<String>,
+}
+
+/// Encodes a browse cursor into its string form
+pub fn encode_cursor(cursor: BrowseCursor) -> String {
+    format!("{}_{}", cursor.value, cursor.id)
+}
+
+/// Decodes a browse cursor from its string form, the cursor value must
+/// be valid for the `sort` being used
+pub fn decode_cursor(value: &str, sort: BrowseSort) -> Result<BrowseCursor, QuizError> {
+    let (value, id) = value.split_once('_').ok_or(QuizError::InvalidCursor)?;
+    let value = value.parse().map_err(|_| QuizError::InvalidCursor)?;
+    let id = id.parse().map_err(|_| QuizError::InvalidCursor)?;
+
+    let cursor = BrowseCursor { value, id };
+    cursor.sort_value(sort).ok_or(QuizError::InvalidCursor)?;
+
+    Ok(cursor)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{decode_cursor, encode_cursor, QuizError, UpdateQuizRequest};
+    use crate::database::entities::quiz::{BrowseCursor, BrowseSort};
+    use garde::Validate;
+
+    /// Tests that quiz data is validated as part of update requests
+    #[test]
+    fn test_update_validates_data() {
+        let request = |correct: bool| -> UpdateQuizRequest {
+            serde_json::from_value(serde_json::json!({
+                "data": {
+                    "questions": [{
+                        "id": 0,
+                        "text": "Question",
+                        "time_limit": 20,
+                        "points": 1000,
+                        "type": "Single",
+                        "answers": [
+                            { "text": "A", "correct": correct },
+                            { "text": "B", "correct": false }
+                        ]
+                    }]
+                }
+            }))
+            .unwrap()
+        };
+
+        assert!(request(true).validate(&()).is_ok());
+        assert!(request(false).validate(&()).is_err());
+    }
+
+    /// Tests that encoded cursors decode back to the same position
+    #[test]
+    fn test_cursor_round_trip() {
+        let cursor = BrowseCursor {
+            value: 1_707_955_200_000_000,
+            id: 42,
+        };
+        let encoded = encode_cursor(cursor);
+
+        assert_eq!(encoded, "1707955200000000_42");
+        assert_eq!(decode_cursor(&encoded, BrowseSort::Recent).unwrap(), cursor);
+        assert_eq!(
+            decode_cursor(&encoded, BrowseSort::Popular).unwrap(),
+            cursor
+        );
+    }
+
+    /// Tests that malformed cursors are rejected
+    #[test]
+    fn test_cursor_malformed() {
+        for value in [
+            "",
+            "1707955200000000",
+            "abc_42",
+            "1707955200000000_abc",
+            "_42",
+            "1_",
+        ] {
+            assert!(
+                matches!(
+                    decode_cursor(value, BrowseSort::Recent),
+                    Err(QuizError::InvalidCursor)
+                ),
+                "cursor {value:?} should be invalid"
+            );
+        }
+    }
+
+    /// Tests that timestamps outside the representable range are rejected
+    /// for the recent sort but are still valid play counts
+    #[test]
+    fn test_cursor_timestamp_range() {
+        let value = format!("{}_1", i64::MAX);
+
+        assert!(matches!(
+            decode_cursor(&value, BrowseSort::Recent),
+            Err(QuizError::InvalidCursor)
+        ));
+        assert!(decode_cursor(&value, BrowseSort::Popular).is_ok());
+    }
 }
@@ -0,0 +1,148 @@
+use axum::http::StatusCode;
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::database::entities::{
+    active_quiz::ActiveQuiz,
+    analytics::{PlayerStats, QuestionStats},
+};
+
+use super::error::HttpError;
+
+#[derive(Debug, Error)]
+pub enum ReportError {
+    /// No matching game session found
+    #[error("Game session not found")]
+    SessionNotFound,
+}
+
+impl HttpError for ReportError {
+    fn name(&self) -> &'static str {
+        match self {
+            ReportError::SessionNotFound => "report:session_not_found",
+        }
+    }
+
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ReportError::SessionNotFound => StatusCode::NOT_FOUND,
+        }
+    }
+}
+
+/// Report across every game played of a quiz
+#[derive(Serialize)]
+pub struct QuizReportResponse {
+    /// The finished games of the quiz
+    pub sessions: Vec<ActiveQuiz>,
+    /// Statistics for each question across all games
+    pub questions: Vec<QuestionStats>,
+}
+
+/// Report for a single game
+#[derive(Serialize)]
+pub struct SessionReportResponse {
+    /// The game session
+    pub session: ActiveQuiz,
+    /// Statistics for each question
+    pub questions: Vec<QuestionStats>,
+    /// Statistics for each player ordered by score
+    pub players: Vec<PlayerStats>,
+    /// Number of players within each range of scores
+    pub score_distribution: Vec<ScoreBucket>,
+}
+
+/// Range of scores and the number of players that scored within it
+#[derive(Serialize)]
+pub struct ScoreBucket {
+    /// Minimum score (inclusive)
+    pub min: i64,
+    /// Maximum score (inclusive)
+    pub max: i64,
+    /// Number of players within the range
+    pub count: u32,
+}
+
+impl ScoreBucket {
+    /// Number of buckets scores are distributed into
+    const BUCKETS: i64 = 10;
+
+    /// Distributes the provided `scores` into evenly sized buckets
+    /// covering zero to the highest score
+    pub fn distribution(scores: &[i64]) -> Vec<ScoreBucket> {
+        let highest = scores.iter().copied().max().unwrap_or_default();
+        // Size of each bucket rounded up so the highest score is covered
+        let size = (highest / Self::BUCKETS + 1).max(1);
+
+        let mut buckets: Vec<ScoreBucket> = (0..Self::BUCKETS)
+            .map(|index| ScoreBucket {
+                min: index * size,
+                max: (index + 1) * size - 1,
+                count: 0,
+            })
+            .collect();
+
+        for score in scores {
+            let index = (score / size).clamp(0, Self::BUCKETS - 1) as usize;
+            buckets[index].count += 1;
+        }
+
+        buckets
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ScoreBucket;
+
+    fn counts(buckets: &[ScoreBucket]) -> Vec<u32> {
+        buckets.iter().map(|bucket| bucket.count).collect()
+    }
+
+    /// Asserts that the buckets are contiguous and cover the `highest` score
+    fn assert_covers(buckets: &[ScoreBucket], highest: i64) {
+        assert_eq!(buckets.len(), ScoreBucket::BUCKETS as usize);
+        assert_eq!(buckets[0].min, 0);
+        assert!(buckets
+            .windows(2)
+            .all(|pair| pair[0].max + 1 == pair[1].min));
+        assert!(buckets.last().unwrap().max >= highest);
+    }
+
+    /// Tests that no scores produces empty buckets
+    #[test]
+    fn test_empty_distribution() {
+        let buckets = ScoreBucket::distribution(&[]);
+
+        assert_covers(&buckets, 0);
+        assert!(buckets.iter().all(|bucket| bucket.count == 0));
+    }
+
+    /// Tests that players who scored nothing are placed in the first bucket
+    #[test]
+    fn test_zero_scores() {
+        let buckets = ScoreBucket::distribution(&[0, 0, 0]);
+
+        assert_covers(&buckets, 0);
+        assert_eq!(counts(&buckets), [3, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+    }
+
+    /// Tests that the highest score is placed in the last bucket
+    #[test]
+    fn test_highest_score() {
+        let buckets = ScoreBucket::distribution(&[0, 500, 1000]);
+
+        assert_covers(&buckets, 1000);
+        assert_eq!(counts(&buckets), [1, 0, 0, 0, 1, 0, 0, 0, 0, 1]);
+    }
+
+    /// Tests scores lower than the number of buckets use single point buckets
+    #[test]
+    fn test_small_scores() {
+        let buckets = ScoreBucket::distribution(&[0, 9]);
+
+        assert_covers(&buckets, 9);
+        assert!(buckets.iter().all(|bucket| bucket.min == bucket.max));
+        assert_eq!(counts(&buckets), [1, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
+    }
+}
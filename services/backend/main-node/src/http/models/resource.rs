@@ -0,0 +1,114 @@
+use axum::http::StatusCode;
+use serde::Deserialize;
+use thiserror::Error;
+use tracing::error;
+
+use crate::{
+    database::{entities::resource::ResourceVisibility, models::resource::ImageVariant},
+    services::image::ImageError,
+};
+
+use super::error::HttpError;
+
+/// Maximum size of an uploaded file in bytes (10MiB)
+pub const MAX_UPLOAD_SIZE: usize = 10 * 1024 * 1024;
+
+#[derive(Debug, Error)]
+pub enum ResourceError {
+    /// No matching resource found
+    #[error("Resource not found")]
+    NotFound,
+    /// No permission to access
+    #[error("Missing permission")]
+    MissingPermission,
+    /// Upload was missing the file
+    #[error("Missing file")]
+    MissingFile,
+    /// Upload was not a supported type or didn't match its declared type
+    #[error("Unsupported file type")]
+    UnsupportedType,
+    /// Upload exceeded the maximum size
+    #[error("File exceeds the maximum size of 10MiB")]
+    TooLarge,
+    /// Image dimensions exceeded the maximum size
+    #[error("Image dimensions are too large")]
+    ImageTooLarge,
+    /// Image content could not be processed
+    #[error("Invalid image")]
+    InvalidImage,
+    /// Multipart body was malformed
+    #[error("Invalid upload")]
+    InvalidUpload,
+    /// Field within the upload was invalid
+    #[error("Invalid upload field \"{0}\"")]
+    InvalidField(&'static str),
+    /// Failed to read or write to storage
+    #[error("Server error")]
+    Storage,
+    /// Failed to process the image
+    #[error("Server error")]
+    Processing,
+}
+
+impl HttpError for ResourceError {
+    fn name(&self) -> &'static str {
+        match self {
+            ResourceError::NotFound => "resource:not_found",
+            ResourceError::MissingPermission => "resource:missing_permission",
+            ResourceError::MissingFile => "resource:missing_file",
+            ResourceError::UnsupportedType => "resource:unsupported_type",
+            ResourceError::TooLarge => "resource:too_large",
+            ResourceError::ImageTooLarge => "resource:image_too_large",
+            ResourceError::InvalidImage => "resource:invalid_image",
+            ResourceError::InvalidUpload => "resource:invalid_upload",
+            ResourceError::InvalidField(_) => "resource:invalid_field",
+            ResourceError::Storage => "resource:storage",
+            ResourceError::Processing => "resource:processing",
+        }
+    }
+
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ResourceError::NotFound => StatusCode::NOT_FOUND,
+            ResourceError::MissingPermission => StatusCode::FORBIDDEN,
+            ResourceError::MissingFile
+            | ResourceError::InvalidUpload
+            | ResourceError::InvalidField(_)
+            | ResourceError::InvalidImage => StatusCode::BAD_REQUEST,
+            ResourceError::UnsupportedType => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            ResourceError::TooLarge | ResourceError::ImageTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            ResourceError::Storage | ResourceError::Processing => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+/// Query for requesting the content of a resource
+#[derive(Deserialize)]
+pub struct ResourceContentQuery {
+    /// Processed variant to request, the original is provided when the
+    /// resource doesn't have the variant
+    #[serde(default)]
+    pub variant: Option<ImageVariant>,
+}
+
+/// Parses the visibility field of an upload
+pub fn parse_visibility(value: &str) -> Option<ResourceVisibility> {
+    match value {
+        "Private" => Some(ResourceVisibility::Private),
+        "Public" => Some(ResourceVisibility::Public),
+        _ => None,
+    }
+}
+
+impl From<ImageError> for ResourceError {
+    fn from(value: ImageError) -> Self {
+        match value {
+            ImageError::TooLarge => ResourceError::ImageTooLarge,
+            ImageError::Invalid => ResourceError::InvalidImage,
+            ImageError::Encode(error) => {
+                error!(name: "err_image_encode", %error, "Failed to encode image");
+                ResourceError::Processing
+            }
+        }
+    }
+}
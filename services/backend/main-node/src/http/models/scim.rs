@@ -0,0 +1,515 @@
+use axum::{
+    extract::rejection::JsonRejection,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use chrono::NaiveDateTime;
+use sea_orm::{DbErr, TransactionError};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use thiserror::Error;
+
+use crate::{
+    database::entities::{scim_group::ScimGroup, scim_group_member::GroupMember, user::User},
+    http::middleware::scim::ScimJson,
+};
+
+use super::error::{HttpError, HttpErrorResponse};
+
+pub type ScimResult<T> = Result<T, ScimError>;
+
+pub const USER_SCHEMA: &str = "urn:ietf:params:scim:schemas:core:2.0:User";
+pub const GROUP_SCHEMA: &str = "urn:ietf:params:scim:schemas:core:2.0:Group";
+pub const LIST_RESPONSE_SCHEMA: &str = "urn:ietf:params:scim:api:messages:2.0:ListResponse";
+pub const ERROR_SCHEMA: &str = "urn:ietf:params:scim:api:messages:2.0:Error";
+pub const SERVICE_PROVIDER_CONFIG_SCHEMA: &str =
+    "urn:ietf:params:scim:schemas:core:2.0:ServiceProviderConfig";
+
+/// Errors from the SCIM routes, these are responded with using
+/// the SCIM error format rather than the standard format
+#[derive(Debug, Error)]
+pub enum ScimError {
+    #[error(transparent)]
+    Database(#[from] DbErr),
+    /// Bearer token was missing or didn't match a SCIM token
+    #[error("Invalid bearer token")]
+    InvalidToken,
+    /// Request body wasn't valid JSON for the request
+    #[error("Invalid request body: {0}")]
+    InvalidSyntax(#[from] JsonRejection),
+    /// Filter isn't supported
+    #[error("Unsupported filter, only \"{0} eq\" filters are supported")]
+    InvalidFilter(&'static str),
+    /// Patch operation isn't add, remove or replace
+    #[error("Unsupported patch operation \"{0}\"")]
+    InvalidPatchOp(String),
+    /// Value provided for an attribute was invalid
+    #[error("Invalid value for \"{0}\"")]
+    InvalidValue(&'static str),
+    /// Group member isn't a user provisioned by the organization
+    #[error("Unknown group member \"{0}\"")]
+    InvalidMember(String),
+    /// No user provisioned by the organization has the ID
+    #[error("User not found")]
+    UserNotFound,
+    /// No group belonging to the organization has the ID
+    #[error("Group not found")]
+    GroupNotFound,
+    /// Username is already in use by another user
+    #[error("userName is already in use")]
+    UsernameTaken,
+    /// Email address is already in use by another user
+    #[error("Email address is already in use")]
+    EmailTaken,
+    /// Organization already has a group with the name
+    #[error("displayName is already in use")]
+    GroupNameTaken,
+}
+
+impl From<TransactionError<ScimError>> for ScimError {
+    fn from(value: TransactionError<ScimError>) -> Self {
+        match value {
+            TransactionError::Connection(err) => ScimError::Database(err),
+            TransactionError::Transaction(err) => err,
+        }
+    }
+}
+
+impl IntoResponse for ScimError {
+    fn into_response(self) -> Response {
+        HttpErrorResponse::from(self).into_response()
+    }
+}
+
+/// Error response in the format defined by SCIM
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ScimErrorResponse {
+    schemas: [&'static str; 1],
+    /// Status code as a string
+    status: String,
+    /// Type of error for 400 and 409 responses
+    #[serde(skip_serializing_if = "Option::is_none")]
+    scim_type: Option<&'static str>,
+    /// Message describing the error
+    detail: String,
+}
+
+impl ScimError {
+    /// The SCIM error type of the error
+    fn scim_type(&self) -> Option<&'static str> {
+        match self {
+            ScimError::InvalidSyntax(_) | ScimError::InvalidPatchOp(_) => Some("invalidSyntax"),
+            ScimError::InvalidFilter(_) => Some("invalidFilter"),
+            ScimError::InvalidValue(_) | ScimError::InvalidMember(_) => Some("invalidValue"),
+            ScimError::UsernameTaken | ScimError::EmailTaken | ScimError::GroupNameTaken => {
+                Some("uniqueness")
+            }
+            _ => None,
+        }
+    }
+}
+
+impl HttpError for ScimError {
+    fn name(&self) -> &'static str {
+        match self {
+            ScimError::Database(_) => "server",
+            ScimError::InvalidToken => "scim:invalid_token",
+            ScimError::InvalidSyntax(_) => "scim:invalid_syntax",
+            ScimError::InvalidFilter(_) => "scim:invalid_filter",
+            ScimError::InvalidPatchOp(_) => "scim:invalid_patch_op",
+            ScimError::InvalidValue(_) => "scim:invalid_value",
+            ScimError::InvalidMember(_) => "scim:invalid_member",
+            ScimError::UserNotFound => "scim:user_not_found",
+            ScimError::GroupNotFound => "scim:group_not_found",
+            ScimError::UsernameTaken => "scim:username_taken",
+            ScimError::EmailTaken => "scim:email_taken",
+            ScimError::GroupNameTaken => "scim:group_name_taken",
+        }
+    }
+
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ScimError::Database(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            ScimError::InvalidToken => StatusCode::UNAUTHORIZED,
+            ScimError::InvalidSyntax(_)
+            | ScimError::InvalidFilter(_)
+            | ScimError::InvalidPatchOp(_)
+            | ScimError::InvalidValue(_)
+            | ScimError::InvalidMember(_) => StatusCode::BAD_REQUEST,
+            ScimError::UserNotFound | ScimError::GroupNotFound => StatusCode::NOT_FOUND,
+            ScimError::UsernameTaken | ScimError::EmailTaken | ScimError::GroupNameTaken => {
+                StatusCode::CONFLICT
+            }
+        }
+    }
+
+    fn message(&self) -> String {
+        match self {
+            ScimError::Database(_) => "Internal server error".to_string(),
+            err => err.to_string(),
+        }
+    }
+
+    fn into_response(self: Box<Self>) -> Response {
+        let status = self.status_code();
+
+        ScimJson(ScimErrorResponse {
+            schemas: [ERROR_SCHEMA],
+            status: status.as_u16().to_string(),
+            scim_type: self.scim_type(),
+            detail: self.message(),
+        })
+        .into_response_with(status)
+    }
+}
+
+/// Query parameters for listing resources
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScimListQuery {
+    /// Filter expression
+    pub filter: Option<String>,
+    /// 1-based index of the first result
+    pub start_index: Option<u64>,
+    /// Maximum number of results
+    pub count: Option<u64>,
+}
+
+/// Page of resources
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScimListResponse<T> {
+    pub schemas: [&'static str; 1],
+    /// Total number of matching resources
+    pub total_results: u64,
+    /// 1-based index of the first resource in the page
+    pub start_index: u64,
+    /// Number of resources in the page
+    pub items_per_page: usize,
+    #[serde(rename = "Resources")]
+    pub resources: Vec<T>,
+}
+
+impl<T> ScimListResponse<T> {
+    pub fn new(resources: Vec<T>, total_results: u64, start_index: u64) -> Self {
+        Self {
+            schemas: [LIST_RESPONSE_SCHEMA],
+            total_results,
+            start_index,
+            items_per_page: resources.len(),
+            resources,
+        }
+    }
+}
+
+/// Resource metadata
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScimMeta {
+    /// Type of the resource
+    pub resource_type: &'static str,
+    /// When the resource was created
+    pub created: String,
+    /// When the resource was last modified
+    pub last_modified: String,
+}
+
+impl ScimMeta {
+    fn new(
+        resource_type: &'static str,
+        created: NaiveDateTime,
+        last_modified: NaiveDateTime,
+    ) -> Self {
+        Self {
+            resource_type,
+            created: created.and_utc().to_rfc3339(),
+            last_modified: last_modified.and_utc().to_rfc3339(),
+        }
+    }
+}
+
+/// Name components of a user
+#[derive(Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScimName {
+    /// Full name of the user
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub formatted: Option<String>,
+    /// First name of the user
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub given_name: Option<String>,
+    /// Last name of the user
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub family_name: Option<String>,
+}
+
+/// Email address of a user
+#[derive(Serialize, Deserialize)]
+pub struct ScimEmail {
+    /// The email address
+    pub value: String,
+    /// Whether this is the primary email address
+    #[serde(default)]
+    pub primary: bool,
+    /// Type of email address such as "work"
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+}
+
+/// User created or replaced by the directory, also used as the
+/// working copy of a user when applying patch operations
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScimUserRequest {
+    /// Unique username of the user
+    pub user_name: String,
+    /// Display name of the user
+    pub display_name: Option<String>,
+    /// Name components of the user
+    pub name: Option<ScimName>,
+    /// Email addresses of the user, only the primary email is stored
+    #[serde(default)]
+    pub emails: Vec<ScimEmail>,
+    /// Whether the user can login
+    #[serde(default = "default_active")]
+    pub active: bool,
+}
+
+fn default_active() -> bool {
+    true
+}
+
+impl ScimUserRequest {
+    /// The primary email address, or the first email address when
+    /// none are marked as primary
+    pub fn primary_email(&self) -> Option<&str> {
+        self.emails
+            .iter()
+            .find(|email| email.primary)
+            .or_else(|| self.emails.first())
+            .map(|email| email.value.as_str())
+    }
+
+    /// The name to store for the user, using the display name before
+    /// the formatted name and finally the name components
+    pub fn full_name(&self) -> Option<String> {
+        let name = self.name.as_ref();
+
+        self.display_name
+            .clone()
+            .or_else(|| name.and_then(|name| name.formatted.clone()))
+            .or_else(|| {
+                let parts: Vec<&str> = name
+                    .into_iter()
+                    .flat_map(|name| [&name.given_name, &name.family_name])
+                    .filter_map(|part| part.as_deref())
+                    .collect();
+
+                (!parts.is_empty()).then(|| parts.join(" "))
+            })
+            .map(|name| name.trim().to_string())
+            .filter(|name| !name.is_empty())
+    }
+}
+
+impl From<&User> for ScimUserRequest {
+    fn from(user: &User) -> Self {
+        Self {
+            user_name: user.username.clone(),
+            display_name: user.name.clone(),
+            name: None,
+            emails: vec![ScimEmail {
+                value: user.email.clone(),
+                primary: true,
+                kind: None,
+            }],
+            active: !user.is_deactivated(),
+        }
+    }
+}
+
+/// User as represented by SCIM
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScimUser {
+    pub schemas: [&'static str; 1],
+    pub id: String,
+    pub user_name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<ScimName>,
+    pub emails: Vec<ScimEmail>,
+    pub active: bool,
+    pub meta: ScimMeta,
+}
+
+impl From<User> for ScimUser {
+    fn from(user: User) -> Self {
+        Self {
+            schemas: [USER_SCHEMA],
+            id: user.id.to_string(),
+            active: !user.is_deactivated(),
+            meta: ScimMeta::new("User", user.created_at, user.updated_at),
+            user_name: user.username,
+            name: user.name.clone().map(|name| ScimName {
+                formatted: Some(name),
+                ..Default::default()
+            }),
+            display_name: user.name,
+            emails: vec![ScimEmail {
+                value: user.email,
+                primary: true,
+                kind: Some("work".to_string()),
+            }],
+        }
+    }
+}
+
+/// Reference to a group member
+#[derive(Deserialize)]
+pub struct ScimMemberRef {
+    /// ID of the member user
+    pub value: String,
+}
+
+/// Group created or replaced by the directory
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScimGroupRequest {
+    /// Name of the group
+    pub display_name: String,
+    /// ID of the group within the directory
+    pub external_id: Option<String>,
+    /// Members of the group
+    #[serde(default)]
+    pub members: Vec<ScimMemberRef>,
+}
+
+/// Member of a group as represented by SCIM
+#[derive(Serialize)]
+pub struct ScimMember {
+    /// ID of the member user
+    pub value: String,
+    /// Username of the member user
+    pub display: String,
+}
+
+/// Group as represented by SCIM
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScimGroupResponse {
+    pub schemas: [&'static str; 1],
+    pub id: String,
+    pub display_name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub external_id: Option<String>,
+    pub members: Vec<ScimMember>,
+    pub meta: ScimMeta,
+}
+
+impl ScimGroupResponse {
+    /// Creates the response for the `group` from its `members`, members
+    /// of other groups are ignored
+    pub fn new(group: ScimGroup, members: &[GroupMember]) -> Self {
+        Self {
+            schemas: [GROUP_SCHEMA],
+            id: group.id.to_string(),
+            members: members
+                .iter()
+                .filter(|member| member.group_id == group.id)
+                .map(|member| ScimMember {
+                    value: member.user_id.to_string(),
+                    display: member.username.clone(),
+                })
+                .collect(),
+            meta: ScimMeta::new("Group", group.created_at, group.updated_at),
+            display_name: group.display_name,
+            external_id: group.external_id,
+        }
+    }
+}
+
+/// Request containing a list of patch operations
+#[derive(Deserialize)]
+pub struct ScimPatchRequest {
+    #[serde(rename = "Operations")]
+    pub operations: Vec<ScimPatchOperation>,
+}
+
+/// Single patch operation
+#[derive(Deserialize)]
+pub struct ScimPatchOperation {
+    /// The operation, either add, remove or replace in any case
+    pub op: String,
+    /// Path to the attribute being changed, when missing the value is
+    /// an object containing the attributes to change
+    pub path: Option<String>,
+    /// The value for the operation
+    pub value: Option<Value>,
+}
+
+/// Patch operations supported by the SCIM routes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PatchOp {
+    Add,
+    Remove,
+    Replace,
+}
+
+impl ScimPatchOperation {
+    /// Parses the operation, directories differ in the case they use
+    pub fn kind(&self) -> Result<PatchOp, ScimError> {
+        match self.op.to_ascii_lowercase().as_str() {
+            "add" => Ok(PatchOp::Add),
+            "remove" => Ok(PatchOp::Remove),
+            "replace" => Ok(PatchOp::Replace),
+            _ => Err(ScimError::InvalidPatchOp(self.op.clone())),
+        }
+    }
+}
+
+/// Features of the SCIM implementation, used by directories to
+/// discover which requests they can make
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ServiceProviderConfig {
+    pub schemas: [&'static str; 1],
+    pub patch: Supported,
+    pub bulk: BulkSupport,
+    pub filter: FilterSupport,
+    pub change_password: Supported,
+    pub sort: Supported,
+    pub etag: Supported,
+    pub authentication_schemes: [AuthenticationScheme; 1],
+}
+
+#[derive(Serialize)]
+pub struct Supported {
+    pub supported: bool,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BulkSupport {
+    pub supported: bool,
+    pub max_operations: u32,
+    pub max_payload_size: u32,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FilterSupport {
+    pub supported: bool,
+    pub max_results: u64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuthenticationScheme {
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub name: &'static str,
+    pub description: &'static str,
+}
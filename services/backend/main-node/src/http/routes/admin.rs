@@ -0,0 +1,288 @@
+use crate::database::entities::quiz::{Quiz, QuizId, QuizState, QuizVisibility};
+use crate::database::entities::user::{User, UserId};
+use crate::http::middleware::auth::{Administrator, Moderator, RequireRole};
+use crate::http::middleware::json::ExtractJson;
+use crate::http::middleware::query::ValidQuery;
+use crate::http::models::admin::{
+    AdminError, AdminUser, ListHiddenQuizzesQuery, ListUsersQuery, UpdateRoleRequest,
+    UserListResponse,
+};
+use crate::http::models::error::HttpResult;
+use crate::http::models::quiz::QuizListResponse;
+use crate::services::auth::{AuthService, EmailTokenPurpose};
+use crate::services::mail::MailService;
+use crate::utils::assert::assert;
+use axum::extract::Path;
+use axum::http::StatusCode;
+use axum::routing::{get, post, put};
+use axum::{Extension, Json, Router};
+use sea_orm::DatabaseConnection;
+use std::sync::Arc;
+use tracing::{error, info};
+
+/// Defines the routes under the route group of /admin
+pub fn routes() -> Router {
+    Router::new()
+        // User management, requires the administrator role
+        .route("/users", get(list_users))
+        .route("/users/:id", get(get_user))
+        .route("/users/:id/role", put(update_role))
+        .route(
+            "/users/:id/suspend",
+            post(suspend_user).delete(unsuspend_user),
+        )
+        .route("/users/:id/reset-password", post(force_password_reset))
+        // Quiz moderation, requires the moderator role
+        .route("/quizzes/hidden", get(list_hidden_quizzes))
+        .route("/quizzes/:id/unpublish", post(unpublish_quiz))
+        .route("/quizzes/:id/hide", post(hide_quiz).delete(unhide_quiz))
+}
+
+/// GET /admin/users
+///
+/// Requests a page of users optionally filtered by a search query
+/// and role
+async fn list_users(
+    _: RequireRole<Administrator>,
+    Extension(db): Extension<DatabaseConnection>,
+    ValidQuery(query): ValidQuery<ListUsersQuery>,
+) -> HttpResult<Json<UserListResponse>> {
+    let paginator = User::search(&db, query.query.as_deref(), query.role, query.size);
+
+    let totals = paginator.num_items_and_pages().await?;
+    let users = paginator.fetch_page(query.page).await?;
+
+    Ok(Json(UserListResponse {
+        users: users.into_iter().map(AdminUser::from).collect(),
+        total_items: totals.number_of_items,
+        total_pages: totals.number_of_pages,
+    }))
+}
+
+/// GET /admin/users/:id
+///
+/// Requests the details of a specific user
+async fn get_user(
+    _: RequireRole<Administrator>,
+    Path(id): Path<UserId>,
+    Extension(db): Extension<DatabaseConnection>,
+) -> HttpResult<Json<AdminUser>> {
+    let user = User::find_by_id(&db, id)
+        .await?
+        .ok_or(AdminError::UserNotFound)?;
+
+    Ok(Json(user.into()))
+}
+
+/// PUT /admin/users/:id/role
+///
+/// Request to change the role of a user, administrators can't change
+/// their own role
+async fn update_role(
+    RequireRole(admin, ..): RequireRole<Administrator>,
+    Path(id): Path<UserId>,
+    Extension(db): Extension<DatabaseConnection>,
+    ExtractJson(req): ExtractJson<UpdateRoleRequest>,
+) -> HttpResult<Json<AdminUser>> {
+    assert(admin.id != id, AdminError::OwnAccount)?;
+
+    let user = User::find_by_id(&db, id)
+        .await?
+        .ok_or(AdminError::UserNotFound)?;
+
+    info!(
+        name: "admin_update_role",
+        admin = admin.id,
+        user = user.id,
+        role = ?req.role,
+        "Changed role of user"
+    );
+
+    let user = user.set_role(&db, req.role).await?;
+
+    Ok(Json(user.into()))
+}
+
+/// POST /admin/users/:id/suspend
+///
+/// Request to suspend a user, suspended users are logged out and
+/// can't login until the suspension is lifted
+async fn suspend_user(
+    RequireRole(admin, ..): RequireRole<Administrator>,
+    Path(id): Path<UserId>,
+    Extension(auth): Extension<Arc<AuthService>>,
+    Extension(db): Extension<DatabaseConnection>,
+) -> HttpResult<Json<AdminUser>> {
+    assert(admin.id != id, AdminError::OwnAccount)?;
+
+    let user = User::find_by_id(&db, id)
+        .await?
+        .ok_or(AdminError::UserNotFound)?;
+
+    info!(name: "admin_suspend_user", admin = admin.id, user = user.id, "Suspended user");
+
+    let user = auth.set_user_suspended(&db, user, true).await?;
+
+    Ok(Json(user.into()))
+}
+
+/// DELETE /admin/users/:id/suspend
+///
+/// Request to lift the suspension of a user
+async fn unsuspend_user(
+    RequireRole(admin, ..): RequireRole<Administrator>,
+    Path(id): Path<UserId>,
+    Extension(auth): Extension<Arc<AuthService>>,
+    Extension(db): Extension<DatabaseConnection>,
+) -> HttpResult<Json<AdminUser>> {
+    assert(admin.id != id, AdminError::OwnAccount)?;
+
+    let user = User::find_by_id(&db, id)
+        .await?
+        .ok_or(AdminError::UserNotFound)?;
+
+    info!(name: "admin_unsuspend_user", admin = admin.id, user = user.id, "Lifted user suspension");
+
+    let user = auth.set_user_suspended(&db, user, false).await?;
+
+    Ok(Json(user.into()))
+}
+
+/// POST /admin/users/:id/reset-password
+///
+/// Request to force a user to reset their password, the current password
+/// stops working, the user is logged out and a reset email is sent
+async fn force_password_reset(
+    RequireRole(admin, ..): RequireRole<Administrator>,
+    Path(id): Path<UserId>,
+    Extension(auth): Extension<Arc<AuthService>>,
+    Extension(mail): Extension<Arc<MailService>>,
+    Extension(db): Extension<DatabaseConnection>,
+) -> HttpResult<StatusCode> {
+    assert(admin.id != id, AdminError::OwnAccount)?;
+
+    let user = User::find_by_id(&db, id)
+        .await?
+        .ok_or(AdminError::UserNotFound)?;
+
+    info!(name: "admin_reset_password", admin = admin.id, user = user.id, "Forced password reset");
+
+    let user = auth.force_password_reset(&db, user).await?;
+
+    // The user can still request another reset email if this one fails
+    tokio::spawn(async move {
+        let token = match auth.create_email_token(EmailTokenPurpose::ResetPassword, &user) {
+            Ok(value) => value,
+            Err(error) => {
+                error!(name: "err_issue_reset_token", %error, "Failed to issue reset token");
+                return;
+            }
+        };
+
+        if let Err(error) = mail.send_forgot_password(&user, &token).await {
+            error!(name: "err_send_forgot_password", %error, "Failed to send reset email");
+        }
+    });
+
+    Ok(StatusCode::ACCEPTED)
+}
+
+/// GET /admin/quizzes/hidden
+///
+/// Requests a page of the quizzes hidden by moderators
+async fn list_hidden_quizzes(
+    _: RequireRole<Moderator>,
+    Extension(db): Extension<DatabaseConnection>,
+    ValidQuery(query): ValidQuery<ListHiddenQuizzesQuery>,
+) -> HttpResult<Json<QuizListResponse>> {
+    let paginator = Quiz::find_hidden(&db, query.size);
+
+    let totals = paginator.num_items_and_pages().await?;
+    let quizzes = paginator.fetch_page(query.page).await?;
+
+    Ok(Json(QuizListResponse {
+        quizzes,
+        total_items: totals.number_of_items,
+        total_pages: totals.number_of_pages,
+    }))
+}
+
+/// POST /admin/quizzes/:id/unpublish
+///
+/// Request to unpublish a public quiz, moving it back to a draft
+async fn unpublish_quiz(
+    RequireRole(moderator, ..): RequireRole<Moderator>,
+    Path(id): Path<QuizId>,
+    Extension(db): Extension<DatabaseConnection>,
+) -> HttpResult<Json<Quiz>> {
+    let quiz = Quiz::find_by_id(&db, id)
+        .await?
+        .ok_or(AdminError::QuizNotFound)?;
+
+    assert(
+        quiz.visibility == QuizVisibility::Public,
+        AdminError::QuizNotPublic,
+    )?;
+    assert(
+        quiz.state == QuizState::Published,
+        AdminError::QuizNotPublished,
+    )?;
+
+    info!(
+        name: "admin_unpublish_quiz",
+        moderator = moderator.id,
+        quiz = quiz.id,
+        "Unpublished quiz"
+    );
+
+    let quiz = quiz.set_state(&db, QuizState::Draft).await?;
+
+    Ok(Json(quiz))
+}
+
+/// POST /admin/quizzes/:id/hide
+///
+/// Request to hide a public quiz, hidden quizzes are made private and
+/// their owners can't make them public again until they're unhidden
+async fn hide_quiz(
+    RequireRole(moderator, ..): RequireRole<Moderator>,
+    Path(id): Path<QuizId>,
+    Extension(db): Extension<DatabaseConnection>,
+) -> HttpResult<Json<Quiz>> {
+    let quiz = Quiz::find_by_id(&db, id)
+        .await?
+        .ok_or(AdminError::QuizNotFound)?;
+
+    assert(
+        quiz.visibility == QuizVisibility::Public,
+        AdminError::QuizNotPublic,
+    )?;
+
+    info!(name: "admin_hide_quiz", moderator = moderator.id, quiz = quiz.id, "Hid quiz");
+
+    let quiz = quiz.set_hidden(&db, true).await?;
+
+    Ok(Json(quiz))
+}
+
+/// DELETE /admin/quizzes/:id/hide
+///
+/// Request to unhide a quiz, the quiz stays private until its owner
+/// chooses to make it public again
+async fn unhide_quiz(
+    RequireRole(moderator, ..): RequireRole<Moderator>,
+    Path(id): Path<QuizId>,
+    Extension(db): Extension<DatabaseConnection>,
+) -> HttpResult<Json<Quiz>> {
+    let quiz = Quiz::find_by_id(&db, id)
+        .await?
+        .ok_or(AdminError::QuizNotFound)?;
+
+    assert(quiz.hidden_at.is_some(), AdminError::QuizNotHidden)?;
+
+    info!(name: "admin_unhide_quiz", moderator = moderator.id, quiz = quiz.id, "Unhid quiz");
+
+    let quiz = quiz.set_hidden(&db, false).await?;
+
+    Ok(Json(quiz))
+}
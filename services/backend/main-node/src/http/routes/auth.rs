@@ -1,19 +1,40 @@
-use crate::database::entities::user::{CreateUser, User};
+use crate::database::entities::organization::OrganizationId;
+use crate::database::entities::organization_domain::OrganizationDomain;
+use crate::database::entities::organization_member::OrganizationMember;
+use crate::database::entities::user::{CreateUser, User, UserId};
 use crate::database::entities::user_link::UserLink;
+use crate::database::entities::user_passkey::{PasskeyId, UserPasskey};
+use crate::database::entities::user_recovery_code::UserRecoveryCode;
+use crate::database::entities::user_refresh_token::{SessionDetails, SessionId, UserRefreshToken};
+use crate::database::DbResult;
+use crate::http::middleware::auth::{Auth, AuthWithClaims};
+use crate::http::middleware::client::ClientDetails;
 use crate::http::middleware::json::{ExtractJson, ValidJson};
 use crate::http::middleware::recaptcha::ProtectReCaptcha;
 use crate::http::models::{auth::*, error::HttpResult};
-use crate::services::auth::{AuthProvider, AuthService};
+use crate::services::auth::{AuthService, EmailTokenPurpose, MfaChallenge, TokenError};
+use crate::services::mail::MailService;
+use crate::services::openid::{
+    OpenIdClient, OpenIdLogin, OpenIdService, OpenIdSignup, ProviderClaims, ProviderId,
+};
+use crate::services::saml::{ConnectionId, SamlService};
+use crate::services::webauthn::WebauthnService;
 use crate::utils::assert::assert;
 use crate::utils::hashing::{hash_password, verify_password};
+use crate::utils::pkce;
 use crate::utils::types::{EmailAddress, Username};
 use anyhow::Context;
-use axum::routing::get;
+use axum::extract::{Form, Path};
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Redirect};
+use axum::routing::{delete, get};
 use axum::{routing::post, Extension, Json, Router};
-use openid::{DiscoveredClient, IdToken, StandardClaims, Token};
-use sea_orm::{DatabaseConnection, DbErr, TransactionTrait};
+use openid::{IdToken, Token};
+use rand::{distributions::Alphanumeric, rngs::StdRng, Rng, SeedableRng};
+use sea_orm::{DatabaseConnection, DbErr, ModelTrait, TransactionError, TransactionTrait};
 use std::sync::Arc;
-use tracing::error;
+use tracing::{debug, error};
+use webauthn_rs::prelude::{CreationChallengeResponse, RequestChallengeResponse};
 
 /// Defines the routes under the route group of /auth
 pub fn routes() -> Router {
@@ -22,7 +43,9 @@ pub fn routes() -> Router {
             "/basic",
             Router::new()
                 .route("/register", post(basic_register))
-                .route("/login", post(basic_login)),
+                .route("/login", post(basic_login))
+                .route("/forgot", post(basic_forgot_password))
+                .route("/reset", post(basic_reset_password)),
         )
         // OpenID routes
         .nest(
@@ -33,13 +56,76 @@ pub fn routes() -> Router {
                 // Authenticate OpenID code
                 .route("/authenticate", post(openid_authenticate))
                 // Confirm OpenID token
-                .route("/create", post(openid_create)),
+                .route("/create", post(openid_create))
+                // Link an OpenID provider to the current user
+                .route("/link/providers", get(openid_link_providers))
+                .route("/link", post(openid_link))
+                // View and remove linked providers of the current user
+                .route("/links", get(openid_links))
+                .route("/links/:provider", delete(openid_unlink)),
+        )
+        // SAML single sign-on routes
+        .nest(
+            "/saml",
+            Router::new()
+                // Service provider metadata for the identity provider
+                .route("/:connection/metadata", get(saml_metadata))
+                // Start a login with the identity provider
+                .route("/:connection/login", get(saml_login))
+                // Assertion consumer service the identity provider posts to
+                .route("/:connection/acs", post(saml_acs))
+                // Complete a login from the hub
+                .route("/exchange", post(saml_exchange)),
         )
         // Token routes
         .nest(
             "/token",
             Router::new().route("/refresh", post(refresh_token)),
         )
+        // End the current session
+        .route("/logout", post(logout))
+        // Two-factor authentication routes
+        .nest(
+            "/mfa",
+            Router::new()
+                // View or disable two-factor authentication
+                .route("/", get(get_mfa_status).delete(disable_mfa))
+                // Complete a login using a second factor
+                .route("/verify", post(verify_mfa))
+                // Enroll in TOTP two-factor authentication
+                .route("/totp/setup", post(setup_totp))
+                .route("/totp/confirm", post(confirm_totp))
+                // Replace the recovery codes
+                .route("/recovery-codes", post(regenerate_recovery_codes)),
+        )
+        // Passkey routes
+        .nest(
+            "/webauthn",
+            Router::new()
+                // Register a passkey for the current user
+                .route("/register/start", post(start_passkey_register))
+                .route("/register/finish", post(finish_passkey_register))
+                // Login using a passkey
+                .route("/login/start", post(start_passkey_login))
+                .route("/login/finish", post(finish_passkey_login))
+                // View and remove passkeys of the current user
+                .route("/passkeys", get(get_passkeys))
+                .route("/passkeys/:id", delete(delete_passkey)),
+        )
+        // Session routes
+        .nest(
+            "/sessions",
+            Router::new()
+                .route("/", get(get_sessions).delete(revoke_all_sessions))
+                .route("/:id", delete(revoke_session)),
+        )
+        // Email verification routes
+        .nest(
+            "/verify-email",
+            Router::new()
+                .route("/", post(verify_email))
+                .route("/resend", post(resend_verify_email)),
+        )
 }
 
 /// GET /auth/basic/register
@@ -49,7 +135,9 @@ pub fn routes() -> Router {
 async fn basic_register(
     _: ProtectReCaptcha,
     Extension(auth): Extension<Arc<AuthService>>,
+    Extension(mail): Extension<Arc<MailService>>,
     Extension(db): Extension<DatabaseConnection>,
+    ClientDetails(details): ClientDetails,
     ValidJson(req): ValidJson<BasicRegisterRequest>,
 ) -> HttpResult<Json<TokenResponse>> {
     // Ensure the email address isn't already in use
@@ -73,26 +161,36 @@ async fn basic_register(
         CreateUser {
             email: req.email.into_inner(),
             username: req.username.into_inner(),
+            name: None,
             password: hashed_password,
         },
     )
     .await?;
 
+    // Send the verification email in the background
+    spawn_verify_email(auth.clone(), mail, user.clone());
+
     // Create an auth token
-    let user_token_data = auth.create_user_token(&db, &user).await.map_err(|error| {
-        error!(name: "err_issue_token", %error, "Failed to issue user token");
-        AuthError::FailedTokenIssue
-    })?;
+    let user_token_data = auth
+        .create_user_token(&db, &user, details)
+        .await
+        .map_err(token_issue_error)?;
 
     Ok(Json(TokenResponse { user_token_data }))
 }
 
+/// POST /auth/basic/login
+///
+/// Request to login using basic email and password credentials, when the
+/// account requires a second factor a challenge is returned instead of
+/// the auth token
 async fn basic_login(
     _: ProtectReCaptcha,
     Extension(auth): Extension<Arc<AuthService>>,
     Extension(db): Extension<DatabaseConnection>,
+    ClientDetails(details): ClientDetails,
     ValidJson(req): ValidJson<BasicLoginRequest>,
-) -> HttpResult<Json<TokenResponse>> {
+) -> HttpResult<Json<LoginResponse>> {
     let user = User::find_by_email(&db, &req.email)
         .await?
         .ok_or(AuthError::EmailNotFound)?;
@@ -100,43 +198,200 @@ async fn basic_login(
     verify_password(req.password.as_str(), &user.password)
         .map_err(|_| AuthError::IncorrectPassword)?;
 
+    // Passwords that an administrator required to be reset can't be used
+    assert(
+        !user.password_reset_required,
+        AuthError::PasswordResetRequired,
+    )?;
+
+    let response = complete_login(&auth, &db, &user, LoginMethod::FirstFactor, details).await?;
+
+    Ok(Json(response))
+}
+
+/// Method the user proved their identity with when logging in
+enum LoginMethod {
+    /// A single factor such as a password or a single sign-on provider,
+    /// a second factor is still required when the account uses one
+    FirstFactor,
+    /// A passkey, passkeys are possessed by the user and verify the user
+    /// on the device so they count as both factors
+    Passkey,
+}
+
+/// Completes a login for the `user` authenticated using the `method`.
+/// All logins go through here so that the second factor requirements
+/// are applied the same way regardless of how the user logged in
+async fn complete_login(
+    auth: &AuthService,
+    db: &DatabaseConnection,
+    user: &User,
+    method: LoginMethod,
+    details: SessionDetails,
+) -> HttpResult<LoginResponse> {
+    // Disabled accounts shouldn't be sent through the second factor
+    assert(user.can_login(), AuthError::AccountDisabled)?;
+
+    if let LoginMethod::FirstFactor = method {
+        let mfa_enabled = auth.is_mfa_enabled(db, user).await?;
+
+        // Logins requiring a second factor are completed through the MFA routes
+        if mfa_enabled || auth.is_mfa_required(user) {
+            let mfa_token = auth.create_mfa_token(user).map_err(|error| {
+                error!(name: "err_issue_mfa_token", %error, "Failed to issue MFA token");
+                AuthError::FailedTokenIssue
+            })?;
+
+            return Ok(if mfa_enabled {
+                LoginResponse::MfaRequired { mfa_token }
+            } else {
+                LoginResponse::MfaSetupRequired { mfa_token }
+            });
+        }
+    }
+
     // Create an auth token
-    let user_token_data = auth.create_user_token(&db, &user).await.map_err(|error| {
-        error!(name: "err_issue_token", %error, "Failed to issue user token");
-        AuthError::FailedTokenIssue
-    })?;
+    let user_token_data = auth
+        .create_user_token(db, user, details)
+        .await
+        .map_err(token_issue_error)?;
 
-    Ok(Json(TokenResponse { user_token_data }))
+    Ok(LoginResponse::Authenticated(TokenResponse {
+        user_token_data,
+    }))
+}
+
+/// POST /auth/basic/forgot
+///
+/// Requests a password reset email be sent to the account with the
+/// provided email. Responds the same way regardless of whether the
+/// account exists to prevent discovering which emails are in use
+async fn basic_forgot_password(
+    _: ProtectReCaptcha,
+    Extension(auth): Extension<Arc<AuthService>>,
+    Extension(mail): Extension<Arc<MailService>>,
+    Extension(db): Extension<DatabaseConnection>,
+    ValidJson(req): ValidJson<ForgotPasswordRequest>,
+) -> StatusCode {
+    // Lookup and sending happen in the background so the response
+    // timing doesn't reveal whether the account exists
+    tokio::spawn(async move {
+        let user = match User::find_by_email(&db, &req.email).await {
+            Ok(Some(value)) => value,
+            Ok(None) => return,
+            Err(error) => {
+                error!(name: "err_forgot_password", %error, "Failed to find user");
+                return;
+            }
+        };
+
+        if !mail.try_request(&user, "reset_password").await {
+            return;
+        }
+
+        let token = match auth.create_email_token(EmailTokenPurpose::ResetPassword, &user) {
+            Ok(value) => value,
+            Err(error) => {
+                error!(name: "err_issue_reset_token", %error, "Failed to issue reset token");
+                return;
+            }
+        };
+
+        if let Err(error) = mail.send_forgot_password(&user, &token).await {
+            error!(name: "err_send_forgot_password", %error, "Failed to send reset email");
+        }
+    });
+
+    StatusCode::ACCEPTED
+}
+
+/// POST /auth/basic/reset
+///
+/// Resets the password of an account using the token from the password
+/// reset email, all existing sessions for the account are logged out
+async fn basic_reset_password(
+    Extension(auth): Extension<Arc<AuthService>>,
+    Extension(db): Extension<DatabaseConnection>,
+    ValidJson(req): ValidJson<ResetPasswordRequest>,
+) -> HttpResult<StatusCode> {
+    // Tokens are bound to the current password so they can only be used once
+    let user = auth
+        .verify_email_token(&db, EmailTokenPurpose::ResetPassword, &req.token)
+        .await
+        .map_err(|_| AuthError::InvalidResetToken)?;
+
+    let hashed_password: String =
+        hash_password(req.password.as_str()).context("Hashing password")?;
+
+    let user_id = user.id;
+    db.transaction(move |db| {
+        Box::pin(async move {
+            let user = user.set_password(db, hashed_password).await?;
+            UserRefreshToken::delete_by_user(db, &user).await?;
+
+            Ok::<_, DbErr>(())
+        })
+    })
+    .await?;
+
+    auth.forget_sessions(user_id);
+
+    Ok(StatusCode::NO_CONTENT)
 }
 
 /// GET /auth/oid/providers
 ///
-/// Requests a collection of OpenID providers and their associated
-/// auth URL
+/// Requests a collection of the configured OpenID providers, their display
+/// details and associated auth URL, each auth URL starts a new login
+/// with its own state
 async fn openid_providers(
-    Extension(auth): Extension<Arc<AuthService>>,
+    Extension(openid): Extension<Arc<OpenIdService>>,
 ) -> HttpResult<Json<OIDProvidersResponse>> {
-    // Load the available providers
-    let providers = auth.get_all_providers().await;
+    openid_provider_logins(&openid, None).await
+}
 
-    let providers: Vec<(AuthProvider, OIDProvider)> = providers
-        .into_iter()
-        // Remove any providers with un-initialized clients
-        .filter_map(|(provider, client)| client.map(|client| (provider, client)))
-        // Create the OIDProvider
-        .map(|(provider, client)| {
-            // Create an auth URL for the provider
-            let auth_url = client.auth_url(&openid::Options {
-                scope: Some(AuthProvider::SCOPES.to_string()),
-                state: Some(provider.to_string()),
-                ..Default::default()
-            });
+/// GET /auth/oid/link/providers
+///
+/// Requests the configured OpenID providers with auth URLs for linking
+/// a provider to the current user, the logins can only be completed
+/// through the link route by the same user
+async fn openid_link_providers(
+    Auth(user): Auth,
+    Extension(openid): Extension<Arc<OpenIdService>>,
+) -> HttpResult<Json<OIDProvidersResponse>> {
+    openid_provider_logins(&openid, Some(user.id)).await
+}
 
-            (provider, OIDProvider { auth_url })
-        })
-        .collect();
+/// Starts a login with each of the available providers for the `user_id`
+/// if linking, responds with the providers and their auth URLs
+async fn openid_provider_logins(
+    openid: &OpenIdService,
+    user_id: Option<UserId>,
+) -> HttpResult<Json<OIDProvidersResponse>> {
+    let mut response: Vec<(ProviderId, OIDProvider)> = Vec::new();
+
+    for provider in openid.providers() {
+        // Skip any providers with un-initialized clients
+        let Some(client) = openid.get_client(provider).await else {
+            continue;
+        };
+
+        // Start a login with the provider
+        let auth_url = openid.start_login(provider, &client, user_id).await;
 
-    Ok(Json(OIDProvidersResponse { providers }))
+        response.push((
+            provider.id.clone(),
+            OIDProvider {
+                name: provider.name.clone(),
+                icon_url: provider.icon_url.clone(),
+                auth_url,
+            },
+        ));
+    }
+
+    Ok(Json(OIDProvidersResponse {
+        providers: response,
+    }))
 }
 
 /// POST /auth/oid/authenticate
@@ -145,65 +400,266 @@ async fn openid_providers(
 /// provider
 async fn openid_authenticate(
     Extension(auth): Extension<Arc<AuthService>>,
+    Extension(openid): Extension<Arc<OpenIdService>>,
     Extension(db): Extension<DatabaseConnection>,
+    ClientDetails(details): ClientDetails,
     ExtractJson(req): ExtractJson<OIDAuthenticateRequest>,
 ) -> HttpResult<Json<OIDAuthenticateResponse>> {
-    let client = auth
-        .get_provider(req.provider)
+    let login = openid
+        .take_login(&req.state, None)
         .await
-        .ok_or(OIDError::ProviderUnavailable)?;
+        .ok_or(OIDError::InvalidState)?;
 
-    // Exchange the code for a token
-    let token: Token = client
-        .request_token(&req.code)
+    let provider = openid
+        .get_provider(&login.provider)
+        .ok_or(OIDError::ProviderUnavailable)?;
+    let client = openid
+        .get_client(provider)
         .await
-        .map_err(|_| OIDError::Authentication)?
-        .into();
-
-    let token: IdToken<StandardClaims> = token.id_token.ok_or(OIDError::Authentication)?;
+        .ok_or(OIDError::ProviderUnavailable)?;
 
-    // Decode the token claim
-    let claims: StandardClaims = decode_openid_token(&client, token.clone())?;
+    let claims = exchange_openid_code(&client, &req.code, &login).await?;
 
-    // Obtain the email address from user info
-    let email: EmailAddress = claims
-        .userinfo
-        .email
+    // Obtain the email address from the claims
+    let email: EmailAddress = provider
+        .claims
+        .email(&claims)
         .ok_or(OIDError::ClaimMissingEmail)?
         .parse()?;
 
     // Obtain the default username if one is present
-    let default_username: Option<Username> = claims
-        .userinfo
-        .preferred_username
+    let default_username: Option<Username> = provider
+        .claims
+        .username(&claims)
         .and_then(|value| value.parse().ok());
 
-    let existing = User::find_by_email(&db, &email).await?;
+    // Only trust the email for finding legacy links when the provider has
+    // verified it and the token came from the configured issuer
+    let email_trusted =
+        provider.claims.email_verified(&claims) && claims.issuer() == provider.issuer();
 
-    if let Some(existing) = existing {
-        // Find an existing link
-        let _ = UserLink::find_by_user(&db, &existing, req.provider)
-            .await?
-            .ok_or(OIDError::NotLinked)?;
-
-        // Create an auth token
-        let user_token_data = auth
-            .create_user_token(&db, &existing)
-            .await
-            .map_err(|err| {
-                error!(name: "err_issue_token", error = %err, "Failed to issue user token");
-                AuthError::FailedTokenIssue
-            })?;
+    if let Some(existing) = find_linked_user(
+        &db,
+        &provider.id,
+        claims.issuer().as_str(),
+        claims.subject(),
+        &email,
+        email_trusted,
+    )
+    .await?
+    {
+        // The account email is owned by the account, changes at the provider
+        // aren't carried over as the address could belong to another account
+        if existing.email != email.as_str() {
+            debug!(
+                user_id = existing.id,
+                "OpenID email differs from account email, leaving account email unchanged"
+            );
+        }
+
+        let response =
+            complete_login(&auth, &db, &existing, LoginMethod::FirstFactor, details).await?;
 
-        Ok(Json(OIDAuthenticateResponse::ExistingLinked(
-            TokenResponse { user_token_data },
-        )))
-    } else {
-        Ok(Json(OIDAuthenticateResponse::CreateAccount {
-            token: Box::new(token),
-            default_username,
-        }))
+        return Ok(Json(response.into()));
     }
+
+    // Accounts using the same email must link the provider from settings
+    // rather than being taken over by the provider account
+    assert(
+        !User::is_email_taken(&db, &email).await?,
+        OIDError::NotLinked,
+    )?;
+
+    // The validated claims are kept on the server until the user has
+    // chosen their account details
+    let token = openid
+        .create_signup(OpenIdSignup {
+            provider: login.provider.clone(),
+            issuer: claims.issuer().to_string(),
+            subject: claims.subject().to_string(),
+            email,
+            email_verified: provider.claims.email_verified(&claims),
+        })
+        .await;
+
+    Ok(Json(OIDAuthenticateResponse::CreateAccount {
+        provider: login.provider,
+        token,
+        default_username,
+    }))
+}
+
+/// Finds the user linked to the provider account identified by the
+/// `issuer` and `subject`.
+///
+/// Links created before provider subjects were stored are found using
+/// the account `email` and are bound to the provider account when used.
+/// This is only done when the `email_trusted` by the provider, otherwise
+/// the provider must be linked again from the account settings
+async fn find_linked_user(
+    db: &DatabaseConnection,
+    provider: &str,
+    issuer: &str,
+    subject: &str,
+    email: &EmailAddress,
+    email_trusted: bool,
+) -> Result<Option<User>, DbErr> {
+    if let Some(link) = UserLink::find_by_subject(db, provider, subject).await? {
+        return User::find_by_id(db, link.user_id).await;
+    }
+
+    // Unverified emails could be set to the email of any account
+    if !email_trusted {
+        return Ok(None);
+    }
+
+    let Some(user) = User::find_by_email(db, email).await? else {
+        return Ok(None);
+    };
+
+    match UserLink::find_by_user(db, &user, provider).await? {
+        Some(link) if link.subject.is_none() => {
+            link.set_subject(db, issuer.to_string(), subject.to_string())
+                .await?;
+            Ok(Some(user))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Creates a new user from the `create` details that is linked to the
+/// provider account identified by the `issuer` and `subject`. Used when
+/// creating accounts through OpenID and SAML providers
+///
+/// Users with a verified email join the organization that has verified
+/// the email domain, they only become managed by the `organization` that
+/// owns the provider
+async fn create_linked_user(
+    db: &DatabaseConnection,
+    create: CreateUser,
+    email_verified: bool,
+    organization: Option<OrganizationId>,
+    provider: ProviderId,
+    issuer: String,
+    subject: String,
+) -> Result<User, TransactionError<DbErr>> {
+    db.transaction(move |db| {
+        Box::pin(async move {
+            // Create the new user
+            let mut user = User::create(db, create).await?;
+
+            // Verify the email if the provider says its verified
+            if email_verified {
+                user = user.set_email_verified(db).await?;
+
+                // Join the organization that has verified the email domain,
+                // only the organization providing the user manages it
+                OrganizationMember::join_by_email_domain(db, &user, organization).await?;
+            }
+
+            // Create a link for the provider to the user
+            _ = UserLink::create(db, &user, provider, issuer, subject).await?;
+
+            Ok::<_, DbErr>(user)
+        })
+    })
+    .await
+}
+
+/// POST /auth/oid/link
+///
+/// Links an OpenID provider to the current user using an OpenID code,
+/// the provider account can then be used to login to the user
+async fn openid_link(
+    Auth(user): Auth,
+    Extension(openid): Extension<Arc<OpenIdService>>,
+    Extension(db): Extension<DatabaseConnection>,
+    ExtractJson(req): ExtractJson<OIDLinkRequest>,
+) -> HttpResult<Json<UserLink>> {
+    // Links must be completed by the user that started them, otherwise a
+    // user could be tricked into linking an attacker's provider account
+    let login = openid
+        .take_login(&req.state, Some(user.id))
+        .await
+        .ok_or(OIDError::InvalidState)?;
+
+    assert(
+        UserLink::find_by_user(&db, &user, &login.provider)
+            .await?
+            .is_none(),
+        OIDError::AlreadyLinked,
+    )?;
+
+    let provider = openid
+        .get_provider(&login.provider)
+        .ok_or(OIDError::ProviderUnavailable)?;
+    let client = openid
+        .get_client(provider)
+        .await
+        .ok_or(OIDError::ProviderUnavailable)?;
+
+    let claims = exchange_openid_code(&client, &req.code, &login).await?;
+
+    // Provider accounts can only be linked to one user
+    assert(
+        UserLink::find_by_subject(&db, &provider.id, claims.subject())
+            .await?
+            .is_none(),
+        OIDError::AccountLinked,
+    )?;
+
+    let link = UserLink::create(
+        &db,
+        &user,
+        provider.id.clone(),
+        claims.issuer().to_string(),
+        claims.subject().to_string(),
+    )
+    .await?;
+
+    Ok(Json(link))
+}
+
+/// GET /auth/oid/links
+///
+/// Requests the OpenID providers linked to the current user
+async fn openid_links(
+    Auth(user): Auth,
+    Extension(db): Extension<DatabaseConnection>,
+) -> HttpResult<Json<Vec<UserLink>>> {
+    let links = UserLink::find_all_by_user(&db, &user).await?;
+
+    Ok(Json(links))
+}
+
+/// DELETE /auth/oid/links/:provider
+///
+/// Unlinks an OpenID provider from the current user, refused when the
+/// provider is the only remaining way to login to the account
+async fn openid_unlink(
+    Auth(user): Auth,
+    Path(provider): Path<ProviderId>,
+    Extension(db): Extension<DatabaseConnection>,
+) -> HttpResult<StatusCode> {
+    let links = UserLink::find_all_by_user(&db, &user).await?;
+
+    let (link, other_links): (Vec<UserLink>, Vec<UserLink>) = links
+        .into_iter()
+        .partition(|link| link.provider == provider);
+    let link = link.into_iter().next().ok_or(OIDError::LinkNotFound)?;
+
+    // Accounts without a password can only login through other links or passkeys
+    let has_password = !user.password.is_empty();
+    let has_passkeys = UserPasskey::count_by_user(&db, &user).await? > 0;
+
+    assert(
+        has_password || has_passkeys || !other_links.is_empty(),
+        OIDError::LastLoginMethod,
+    )?;
+
+    link.delete(&db).await?;
+
+    Ok(StatusCode::NO_CONTENT)
 }
 
 /// POST /auth/token/refresh
@@ -212,25 +668,484 @@ async fn openid_authenticate(
 async fn refresh_token(
     Extension(auth): Extension<Arc<AuthService>>,
     Extension(db): Extension<DatabaseConnection>,
+    ClientDetails(details): ClientDetails,
     ExtractJson(req): ExtractJson<RefreshTokenRequest>,
 ) -> HttpResult<Json<TokenResponse>> {
     let user_token_data = auth
-        .refresh_user_token(&db, &req.refresh_token)
+        .refresh_user_token(&db, &req.refresh_token, details.ip_address)
         .await
-        .map_err(|err| {
-            error!(name: "err_refresh_token", error = %err, "Failed to refresh user token");
-            AuthError::FailedTokenIssue
+        .map_err(|err| match err {
+            TokenError::InvalidRefreshToken
+            | TokenError::RefreshTokenExpired
+            | TokenError::RefreshTokenReused => AuthError::InvalidRefreshToken,
+            err => {
+                error!(name: "err_refresh_token", error = %err, "Failed to refresh user token");
+                AuthError::FailedTokenIssue
+            }
         })?;
 
     Ok(Json(TokenResponse { user_token_data }))
 }
 
+/// POST /auth/logout
+///
+/// Logs out of the current session, the refresh token for the session
+/// is revoked and the current user token can no longer be used
+async fn logout(
+    AuthWithClaims(user, claims): AuthWithClaims,
+    Extension(auth): Extension<Arc<AuthService>>,
+    Extension(db): Extension<DatabaseConnection>,
+) -> HttpResult<StatusCode> {
+    if let Some(session) = UserRefreshToken::find_by_user_id(&db, &user, claims.session_id).await? {
+        auth.revoke_session(&db, session).await?;
+    }
+
+    auth.revoke_user_token(&db, &claims).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// GET /auth/mfa
+///
+/// Requests the two-factor authentication status of the current user
+async fn get_mfa_status(
+    Auth(user): Auth,
+    Extension(auth): Extension<Arc<AuthService>>,
+    Extension(db): Extension<DatabaseConnection>,
+) -> HttpResult<Json<MfaStatusResponse>> {
+    let enabled = auth.is_mfa_enabled(&db, &user).await?;
+    let recovery_codes_remaining = UserRecoveryCode::count_by_user(&db, &user).await?;
+
+    Ok(Json(MfaStatusResponse {
+        enabled,
+        required: auth.is_mfa_required(&user),
+        recovery_codes_remaining,
+    }))
+}
+
+/// POST /auth/mfa/verify
+///
+/// Completes a login that requires a second factor using the token
+/// from the login response and a TOTP or recovery code
+async fn verify_mfa(
+    Extension(auth): Extension<Arc<AuthService>>,
+    Extension(db): Extension<DatabaseConnection>,
+    ClientDetails(details): ClientDetails,
+    ExtractJson(req): ExtractJson<MfaVerifyRequest>,
+) -> HttpResult<Json<TokenResponse>> {
+    let challenge = auth
+        .verify_mfa_token(&db, &req.mfa_token)
+        .await
+        .map_err(|_| AuthError::InvalidMfaToken)?;
+
+    auth.verify_mfa_code(&db, &challenge.user, &req.code)
+        .await?;
+
+    // Challenge tokens can only complete a single login
+    auth.complete_mfa_challenge(&db, &challenge)
+        .await
+        .map_err(|_| AuthError::InvalidMfaToken)?;
+
+    // Create an auth token
+    let user_token_data = auth
+        .create_user_token(&db, &challenge.user, details)
+        .await
+        .map_err(token_issue_error)?;
+
+    Ok(Json(TokenResponse { user_token_data }))
+}
+
+/// POST /auth/mfa/totp/setup
+///
+/// Starts enrolling in TOTP two-factor authentication, responds with a
+/// new secret for adding to an authenticator app. Can be used by the
+/// current user or with the token from a login requiring enrollment
+async fn setup_totp(
+    current: Option<Auth>,
+    Extension(auth): Extension<Arc<AuthService>>,
+    Extension(db): Extension<DatabaseConnection>,
+    ExtractJson(req): ExtractJson<TotpSetupRequest>,
+) -> HttpResult<Json<TotpSetupResponse>> {
+    let enrolling = enrolling_user(&auth, &db, current, req.mfa_token.as_deref()).await?;
+    let generated = auth.begin_totp(&db, enrolling.user()).await?;
+
+    Ok(Json(TotpSetupResponse {
+        secret: generated.secret,
+        uri: generated.uri,
+    }))
+}
+
+/// POST /auth/mfa/totp/confirm
+///
+/// Confirms enrollment in TOTP two-factor authentication using a code
+/// from the authenticator app, responds with the recovery codes. When
+/// enrolling as part of a login the login is completed
+async fn confirm_totp(
+    current: Option<Auth>,
+    Extension(auth): Extension<Arc<AuthService>>,
+    Extension(db): Extension<DatabaseConnection>,
+    ClientDetails(details): ClientDetails,
+    ExtractJson(req): ExtractJson<TotpConfirmRequest>,
+) -> HttpResult<Json<TotpConfirmResponse>> {
+    let enrolling = enrolling_user(&auth, &db, current, req.mfa_token.as_deref()).await?;
+    let recovery_codes = auth.confirm_totp(&db, enrolling.user(), &req.code).await?;
+
+    let token = match enrolling {
+        Enrolling::Login(challenge) => {
+            // Challenge tokens can only complete a single login
+            auth.complete_mfa_challenge(&db, &challenge)
+                .await
+                .map_err(|_| AuthError::InvalidMfaToken)?;
+
+            let user_token_data = auth
+                .create_user_token(&db, &challenge.user, details)
+                .await
+                .map_err(token_issue_error)?;
+
+            Some(TokenResponse { user_token_data })
+        }
+        Enrolling::Current(_) => None,
+    };
+
+    Ok(Json(TotpConfirmResponse {
+        recovery_codes,
+        token,
+    }))
+}
+
+/// User enrolling in two-factor authentication
+enum Enrolling {
+    /// The current user enrolling from their settings
+    Current(User),
+    /// A user enrolling to complete a login that requires it
+    Login(MfaChallenge),
+}
+
+impl Enrolling {
+    fn user(&self) -> &User {
+        match self {
+            Enrolling::Current(user) => user,
+            Enrolling::Login(challenge) => &challenge.user,
+        }
+    }
+}
+
+/// Finds the user enrolling in two-factor authentication, either the
+/// `current` user or the user the provided `mfa_token` was issued for
+async fn enrolling_user(
+    auth: &AuthService,
+    db: &DatabaseConnection,
+    current: Option<Auth>,
+    mfa_token: Option<&str>,
+) -> HttpResult<Enrolling> {
+    if let Some(Auth(user)) = current {
+        return Ok(Enrolling::Current(user));
+    }
+
+    let mfa_token = mfa_token.ok_or(AuthError::InvalidMfaToken)?;
+    let challenge = auth
+        .verify_mfa_token(db, mfa_token)
+        .await
+        .map_err(|_| AuthError::InvalidMfaToken)?;
+
+    Ok(Enrolling::Login(challenge))
+}
+
+/// DELETE /auth/mfa
+///
+/// Disables two-factor authentication for the current user, requires
+/// a TOTP or recovery code
+async fn disable_mfa(
+    Auth(user): Auth,
+    Extension(auth): Extension<Arc<AuthService>>,
+    Extension(db): Extension<DatabaseConnection>,
+    ExtractJson(req): ExtractJson<MfaCodeRequest>,
+) -> HttpResult<StatusCode> {
+    assert(!auth.is_mfa_required(&user), AuthError::MfaRequired)?;
+
+    auth.verify_mfa_code(&db, &user, &req.code).await?;
+    auth.disable_mfa(&db, &user).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// POST /auth/mfa/recovery-codes
+///
+/// Replaces the recovery codes of the current user with new codes,
+/// requires a TOTP or recovery code
+async fn regenerate_recovery_codes(
+    Auth(user): Auth,
+    Extension(auth): Extension<Arc<AuthService>>,
+    Extension(db): Extension<DatabaseConnection>,
+    ExtractJson(req): ExtractJson<MfaCodeRequest>,
+) -> HttpResult<Json<RecoveryCodesResponse>> {
+    auth.verify_mfa_code(&db, &user, &req.code).await?;
+    let recovery_codes = auth.replace_recovery_codes(&db, &user).await?;
+
+    Ok(Json(RecoveryCodesResponse { recovery_codes }))
+}
+
+/// POST /auth/webauthn/register/start
+///
+/// Starts registering a passkey for the current user, responds with the
+/// options for creating the credential on the authenticator
+async fn start_passkey_register(
+    Auth(user): Auth,
+    Extension(webauthn): Extension<Arc<WebauthnService>>,
+    Extension(db): Extension<DatabaseConnection>,
+) -> HttpResult<Json<PasskeyChallengeResponse<CreationChallengeResponse>>> {
+    let challenge = webauthn.start_registration(&db, &user).await?;
+
+    Ok(Json(PasskeyChallengeResponse {
+        challenge_id: challenge.challenge_id,
+        options: challenge.options,
+    }))
+}
+
+/// POST /auth/webauthn/register/finish
+///
+/// Completes registering a passkey for the current user using the
+/// credential created by the authenticator
+async fn finish_passkey_register(
+    Auth(user): Auth,
+    Extension(webauthn): Extension<Arc<WebauthnService>>,
+    Extension(db): Extension<DatabaseConnection>,
+    ValidJson(req): ValidJson<PasskeyRegisterRequest>,
+) -> HttpResult<Json<UserPasskey>> {
+    let passkey = webauthn
+        .finish_registration(&db, &user, &req.challenge_id, req.name, &req.credential)
+        .await?;
+
+    Ok(Json(passkey))
+}
+
+/// POST /auth/webauthn/login/start
+///
+/// Starts logging in with a passkey, responds with the options for
+/// requesting an assertion from the authenticator
+async fn start_passkey_login(
+    Extension(webauthn): Extension<Arc<WebauthnService>>,
+) -> HttpResult<Json<PasskeyChallengeResponse<RequestChallengeResponse>>> {
+    let challenge = webauthn.start_authentication().await?;
+
+    Ok(Json(PasskeyChallengeResponse {
+        challenge_id: challenge.challenge_id,
+        options: challenge.options,
+    }))
+}
+
+/// POST /auth/webauthn/login/finish
+///
+/// Completes logging in with a passkey using the assertion from the
+/// authenticator. Passkeys count as both factors so the login doesn't
+/// require another second factor
+async fn finish_passkey_login(
+    Extension(auth): Extension<Arc<AuthService>>,
+    Extension(webauthn): Extension<Arc<WebauthnService>>,
+    Extension(db): Extension<DatabaseConnection>,
+    ClientDetails(details): ClientDetails,
+    ExtractJson(req): ExtractJson<PasskeyLoginRequest>,
+) -> HttpResult<Json<LoginResponse>> {
+    let user = webauthn
+        .finish_authentication(&db, &req.challenge_id, &req.credential)
+        .await?;
+
+    let response = complete_login(&auth, &db, &user, LoginMethod::Passkey, details).await?;
+
+    Ok(Json(response))
+}
+
+/// GET /auth/webauthn/passkeys
+///
+/// Requests the passkeys registered to the current user
+async fn get_passkeys(
+    Auth(user): Auth,
+    Extension(db): Extension<DatabaseConnection>,
+) -> HttpResult<Json<Vec<UserPasskey>>> {
+    let passkeys = UserPasskey::find_by_user(&db, &user).await?;
+
+    Ok(Json(passkeys))
+}
+
+/// DELETE /auth/webauthn/passkeys/:id
+///
+/// Removes a passkey from the current user, it can no longer be used
+/// to login
+async fn delete_passkey(
+    Auth(user): Auth,
+    Path(passkey_id): Path<PasskeyId>,
+    Extension(db): Extension<DatabaseConnection>,
+) -> HttpResult<StatusCode> {
+    let passkey = UserPasskey::find_by_user_id(&db, &user, passkey_id)
+        .await?
+        .ok_or(AuthError::PasskeyNotFound)?;
+
+    passkey.delete(&db).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// GET /auth/sessions
+///
+/// Requests the active sessions of the current user
+async fn get_sessions(
+    AuthWithClaims(user, claims): AuthWithClaims,
+    Extension(auth): Extension<Arc<AuthService>>,
+    Extension(db): Extension<DatabaseConnection>,
+) -> HttpResult<Json<Vec<SessionResponse>>> {
+    let sessions = auth
+        .get_user_sessions(&db, &user)
+        .await?
+        .into_iter()
+        .map(|session| SessionResponse {
+            current: session.id == claims.session_id,
+            session,
+        })
+        .collect();
+
+    Ok(Json(sessions))
+}
+
+/// DELETE /auth/sessions/:id
+///
+/// Revokes a session of the current user, the refresh token for the
+/// session can no longer be used
+async fn revoke_session(
+    Auth(user): Auth,
+    Path(session_id): Path<SessionId>,
+    Extension(auth): Extension<Arc<AuthService>>,
+    Extension(db): Extension<DatabaseConnection>,
+) -> HttpResult<StatusCode> {
+    let session = UserRefreshToken::find_by_user_id(&db, &user, session_id)
+        .await?
+        .ok_or(AuthError::SessionNotFound)?;
+
+    auth.revoke_session(&db, session).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// DELETE /auth/sessions
+///
+/// Revokes every session of the current user, logging them out
+/// everywhere including the current session
+async fn revoke_all_sessions(
+    Auth(user): Auth,
+    Extension(auth): Extension<Arc<AuthService>>,
+    Extension(db): Extension<DatabaseConnection>,
+) -> HttpResult<StatusCode> {
+    auth.revoke_all_sessions(&db, &user).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// POST /auth/verify-email
+///
+/// Verifies the email address of an account using the token from the
+/// verification email
+async fn verify_email(
+    Extension(auth): Extension<Arc<AuthService>>,
+    Extension(db): Extension<DatabaseConnection>,
+    ExtractJson(req): ExtractJson<VerifyEmailRequest>,
+) -> HttpResult<StatusCode> {
+    // Tokens are bound to the email address they were sent to
+    let user = auth
+        .verify_email_token(&db, EmailTokenPurpose::VerifyEmail, &req.token)
+        .await
+        .map_err(|_| AuthError::InvalidVerifyToken)?;
+
+    if user.email_verified_at.is_none() {
+        let user = user.set_email_verified(&db).await?;
+
+        // Join the organization that has verified the email domain
+        OrganizationMember::join_by_email_domain(&db, &user, None).await?;
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// POST /auth/verify-email/resend
+///
+/// Requests that the verification email be sent again to the current user
+async fn resend_verify_email(
+    Auth(user): Auth,
+    Extension(auth): Extension<Arc<AuthService>>,
+    Extension(mail): Extension<Arc<MailService>>,
+) -> HttpResult<StatusCode> {
+    assert(
+        user.email_verified_at.is_none(),
+        AuthError::EmailAlreadyVerified,
+    )?;
+    assert(
+        mail.try_request(&user, "verify_email").await,
+        AuthError::TooManyRequests,
+    )?;
+
+    spawn_verify_email(auth, mail, user);
+
+    Ok(StatusCode::ACCEPTED)
+}
+
+/// Maps errors from issuing a user token to the response error, failures
+/// other than the account being disabled are logged
+fn token_issue_error(error: TokenError) -> AuthError {
+    match error {
+        TokenError::AccountDisabled => AuthError::AccountDisabled,
+        error => {
+            error!(name: "err_issue_token", %error, "Failed to issue user token");
+            AuthError::FailedTokenIssue
+        }
+    }
+}
+
+/// Creates a verification token for the provided `user` and sends them
+/// the verification email in the background
+fn spawn_verify_email(auth: Arc<AuthService>, mail: Arc<MailService>, user: User) {
+    tokio::spawn(async move {
+        let token = match auth.create_email_token(EmailTokenPurpose::VerifyEmail, &user) {
+            Ok(value) => value,
+            Err(error) => {
+                error!(name: "err_issue_verify_token", %error, "Failed to issue verification token");
+                return;
+            }
+        };
+
+        if let Err(error) = mail.send_verify_email(&user, &token).await {
+            error!(name: "err_send_verify_email", %error, "Failed to send verification email");
+        }
+    });
+}
+
+/// Exchanges the provided OpenID `code` with the provider `client`
+/// using the PKCE verifier of the `login`, returning the claims present
+/// in the ID token
+async fn exchange_openid_code(
+    client: &OpenIdClient,
+    code: &str,
+    login: &OpenIdLogin,
+) -> Result<ProviderClaims, OIDError> {
+    // Exchange the code for a token
+    let token: Token<ProviderClaims> = pkce::request_token(client, code, &login.code_verifier)
+        .await
+        .map_err(|err| {
+            error!(name: "openid_request_token", error = %err, "Failed to exchange code");
+            OIDError::Authentication
+        })?
+        .into();
+
+    let token: IdToken<ProviderClaims> = token.id_token.ok_or(OIDError::Authentication)?;
+
+    // Decode the token claim, the token must contain the nonce of the login
+    decode_openid_token(client, token, &login.nonce)
+}
+
 /// Decodes the provided `token` returning either the claims present
-/// in the token or an error
+/// in the token or an error, the token must contain the `nonce`
 fn decode_openid_token(
-    client: &DiscoveredClient,
-    mut token: IdToken<StandardClaims>,
-) -> Result<StandardClaims, OIDError> {
+    client: &OpenIdClient,
+    mut token: IdToken<ProviderClaims>,
+    nonce: &str,
+) -> Result<ProviderClaims, OIDError> {
     // Decode the token
     client
         .decode_token(&mut token)
@@ -242,7 +1157,7 @@ fn decode_openid_token(
 
     // Validate the token
     client
-        .validate_token(&token, None, None)
+        .validate_token(&token, Some(nonce), None)
         // Handle invalid token error
         .map_err(|err| {
             error!(name: "openid_validate_token", error = %err, "Token failed validation");
@@ -257,33 +1172,31 @@ fn decode_openid_token(
 
 /// POST /auth/oid/create
 ///
-/// Creates an account from an OpenID token and user provided details
+/// Creates an account for the provider account of a completed OpenID
+/// login using the signup token and user provided details
 async fn openid_create(
     Extension(auth): Extension<Arc<AuthService>>,
+    Extension(openid): Extension<Arc<OpenIdService>>,
     Extension(db): Extension<DatabaseConnection>,
+    ClientDetails(details): ClientDetails,
     ValidJson(req): ValidJson<OIDCreateRequest>,
 ) -> HttpResult<Json<TokenResponse>> {
-    let client = auth
-        .get_provider(req.provider)
+    let signup = openid
+        .take_signup(&req.token)
         .await
-        .ok_or(OIDError::ProviderUnavailable)?;
-
-    // Decode the token claim
-    let claims = decode_openid_token(&client, req.token)?;
-
-    // Obtain the email address from user info
-    let email: EmailAddress = claims
-        .userinfo
-        .email
-        .ok_or(OIDError::ClaimMissingEmail)?
-        .parse()?;
+        .ok_or(OIDError::InvalidState)?;
 
-    // Check if they've verified the email
-    let email_verified = claims.userinfo.email_verified;
+    // Ensure the provider account isn't already linked to a user
+    assert(
+        UserLink::find_by_subject(&db, &signup.provider, &signup.subject)
+            .await?
+            .is_none(),
+        OIDError::AccountLinked,
+    )?;
 
     // Ensure the email address isn't already in use
     assert(
-        !User::is_email_taken(&db, &email).await?,
+        !User::is_email_taken(&db, &signup.email).await?,
         AuthError::EmailExists,
     )?;
 
@@ -296,41 +1209,230 @@ async fn openid_create(
     let hashed_password: String =
         hash_password(req.password.as_str()).context("Hashing password")?;
 
-    let user: User = db
-        .transaction(move |db| {
-            Box::pin(async move {
-                // Create the new user
-                let mut user = User::create(
-                    db,
-                    CreateUser {
-                        email: email.into_inner(),
-                        username: req.username.into_inner(),
-                        password: hashed_password,
-                    },
-                )
-                .await?;
+    let user: User = create_linked_user(
+        &db,
+        CreateUser {
+            email: signup.email.into_inner(),
+            username: req.username.into_inner(),
+            name: None,
+            password: hashed_password,
+        },
+        signup.email_verified,
+        // OpenID providers are configured for the whole server rather
+        // than belonging to an organization
+        None,
+        signup.provider,
+        signup.issuer,
+        signup.subject,
+    )
+    .await?;
+
+    let user_token_data = auth
+        .create_user_token(&db, &user, details)
+        .await
+        .map_err(token_issue_error)?;
+
+    Ok(Json(TokenResponse { user_token_data }))
+}
 
-                // Verify the email if the provider says its verified
-                if email_verified {
-                    user = user.set_email_verified(db).await?;
-                }
+/// GET /auth/saml/:connection/metadata
+///
+/// Requests the service provider metadata for a SAML connection, this is
+/// given to the identity provider when setting up the connection
+async fn saml_metadata(
+    Path(connection): Path<ConnectionId>,
+    Extension(saml): Extension<Arc<SamlService>>,
+) -> HttpResult<impl IntoResponse> {
+    let connection = saml.get_connection(&connection)?;
+    let metadata = saml.metadata(connection);
 
-                // Create a link for the provider to the user
-                _ = UserLink::create(db, &user, req.provider).await?;
+    Ok((
+        [(header::CONTENT_TYPE, "application/samlmetadata+xml")],
+        metadata,
+    ))
+}
 
-                Ok::<_, DbErr>(user)
-            })
-        })
+/// GET /auth/saml/:connection/login
+///
+/// Starts a login with the identity provider of a SAML connection,
+/// responds with the URL to send the user to
+async fn saml_login(
+    Path(connection): Path<ConnectionId>,
+    Extension(saml): Extension<Arc<SamlService>>,
+) -> HttpResult<Json<SAMLLoginResponse>> {
+    let connection = saml.get_connection(&connection)?;
+    let auth_url = saml.start_login(connection).await?;
+
+    Ok(Json(SAMLLoginResponse { auth_url }))
+}
+
+/// POST /auth/saml/:connection/acs
+///
+/// Assertion consumer service the identity provider posts the SAML
+/// response to. The user is redirected to the hub with a login code
+/// to complete the login, or the name of the error when it failed
+async fn saml_acs(
+    Path(connection): Path<ConnectionId>,
+    Extension(saml): Extension<Arc<SamlService>>,
+    Extension(db): Extension<DatabaseConnection>,
+    Form(form): Form<SAMLResponseForm>,
+) -> HttpResult<Redirect> {
+    let relay_state = form.relay_state.clone();
+    let url = match saml_provision_user(&db, &saml, &connection, form).await {
+        Ok(user_id) => {
+            let code = saml.create_login_code(user_id, relay_state).await;
+            saml.complete_url(Ok(&code))
+        }
+        Err(err) => {
+            error!(name: "err_saml_login", connection = %connection, error = %err.name(), "Failed to complete SAML login");
+            saml.complete_url(Err(err.name()))
+        }
+    };
+
+    // There isn't a hub page to redirect to without any connections
+    let url = url.ok_or(SAMLError::UnknownConnection)?;
+
+    Ok(Redirect::to(url.as_str()))
+}
+
+/// Validates the SAML response `form` posted to the `connection` and
+/// finds the user linked to the asserted identity. Accounts are created
+/// just in time for identities that haven't logged in before
+async fn saml_provision_user(
+    db: &DatabaseConnection,
+    saml: &SamlService,
+    connection: &str,
+    form: SAMLResponseForm,
+) -> HttpResult<UserId> {
+    let connection = saml.get_connection(connection)?;
+    let identity = saml
+        .finish_login(connection, &form.relay_state, &form.saml_response)
         .await?;
 
-    let user_token_data = auth
-        .create_user_token(&db, &user)
+    let email: EmailAddress = identity
+        .email
+        .as_deref()
+        .ok_or(SAMLError::MissingEmail)?
+        .parse()?;
+
+    let provider = connection.provider_id();
+
+    // The identity provider is only trusted to vouch for emails on the
+    // domains verified by the organization that owns the connection
+    let email_verified = match connection.organization_id {
+        Some(organization_id) => {
+            OrganizationDomain::is_email_verified(db, organization_id, email.as_str()).await?
+        }
+        None => false,
+    };
+
+    if let Some(existing) = find_linked_user(
+        db,
+        &provider,
+        &identity.issuer,
+        &identity.subject,
+        &email,
+        email_verified,
+    )
+    .await?
+    {
+        return Ok(existing.id);
+    }
+
+    // Accounts using the same email aren't taken over by the identity provider
+    assert(
+        !User::is_email_taken(db, &email).await?,
+        SAMLError::NotLinked,
+    )?;
+
+    let username = provision_username(db, identity.username.as_deref(), &email).await?;
+
+    // Accounts created through SAML don't have a password
+    let user = create_linked_user(
+        db,
+        CreateUser {
+            email: email.into_inner(),
+            username: username.into_inner(),
+            name: identity.name,
+            password: String::new(),
+        },
+        email_verified,
+        connection.organization_id,
+        provider,
+        identity.issuer,
+        identity.subject,
+    )
+    .await?;
+
+    Ok(user.id)
+}
+
+/// Creates an unused username for an account created through an identity
+/// provider. The `preferred` username is used when it is valid, otherwise
+/// the username is based on the `email`, random digits are appended when
+/// the username is already in use
+async fn provision_username(
+    db: &DatabaseConnection,
+    preferred: Option<&str>,
+    email: &EmailAddress,
+) -> DbResult<Username> {
+    /// Number of attempts at finding an unused username
+    const ATTEMPTS: usize = 5;
+
+    let base: String = preferred
+        .filter(|value| value.parse::<Username>().is_ok())
+        .unwrap_or_else(|| email.as_str().split('@').next().unwrap_or_default())
+        .chars()
+        .filter(char::is_ascii_alphanumeric)
+        .take(90)
+        .collect();
+
+    let mut rng = StdRng::from_entropy();
+    let mut candidate = base.clone();
+
+    for _ in 0..ATTEMPTS {
+        if let Ok(username) = candidate.parse::<Username>() {
+            if !User::is_username_taken(db, &username).await? {
+                return Ok(username);
+            }
+        }
+
+        candidate = format!("{base}{:04}", rng.gen_range(0..10_000));
+    }
+
+    // Fallback to a random username the user can change later
+    let random: String = (&mut rng)
+        .sample_iter(Alphanumeric)
+        .take(12)
+        .map(char::from)
+        .collect();
+
+    Ok(Username::from(format!("user{random}")))
+}
+
+/// POST /auth/saml/exchange
+///
+/// Completes a SAML login using the code the hub was redirected
+/// with and the relay state the browser started the login with,
+/// issuing a token for the user unless the account requires a
+/// second factor
+async fn saml_exchange(
+    Extension(auth): Extension<Arc<AuthService>>,
+    Extension(saml): Extension<Arc<SamlService>>,
+    Extension(db): Extension<DatabaseConnection>,
+    ClientDetails(details): ClientDetails,
+    ExtractJson(req): ExtractJson<SAMLExchangeRequest>,
+) -> HttpResult<Json<LoginResponse>> {
+    let user_id = saml
+        .take_login_code(&req.code, &req.state)
         .await
-        // Handle errors issuing
-        .map_err(|err| {
-            error!(name: "err_issue_token", error = %err, "Failed to issue user token");
-            AuthError::FailedTokenIssue
-        })?;
+        .ok_or(SAMLError::InvalidLoginCode)?;
 
-    Ok(Json(TokenResponse { user_token_data }))
+    let user = User::find_by_id(&db, user_id)
+        .await?
+        .ok_or(SAMLError::InvalidLoginCode)?;
+
+    let response = complete_login(&auth, &db, &user, LoginMethod::FirstFactor, details).await?;
+
+    Ok(Json(response))
 }
@@ -9,11 +9,18 @@ use tower_http::{
 };
 use tracing::Level;
 
-use super::middleware::recaptcha::RECAPTCHA_HEADER;
+use super::middleware::{client::DEVICE_NAME_HEADER, recaptcha::RECAPTCHA_HEADER};
 
+mod admin;
 mod auth;
+mod organization;
+mod play;
 mod quiz;
+mod reports;
+mod resource;
+mod scim;
 mod user;
+mod well_known;
 
 /// Initializes the router and all routes in the app
 pub fn init_router() -> Router {
@@ -34,6 +41,7 @@ pub fn init_router() -> Router {
             header::AUTHORIZATION,
             header::CONTENT_TYPE,
             HeaderName::from_static(RECAPTCHA_HEADER),
+            HeaderName::from_static(DEVICE_NAME_HEADER),
         ])
         .allow_credentials(true)
         .allow_origin(hub_url);
@@ -41,7 +49,14 @@ pub fn init_router() -> Router {
     Router::new()
         .nest("/auth", auth::routes())
         .nest("/user", user::routes())
+        .nest("/admin", admin::routes())
+        .nest("/org", organization::routes())
         .nest("/quiz", quiz::routes())
+        .nest("/play", play::routes())
+        .nest("/reports", reports::routes())
+        .nest("/resource", resource::routes())
+        .nest("/scim/v2", scim::routes())
+        .nest("/.well-known", well_known::routes())
         // Request tracing
         .layer(
             TraceLayer::new_for_http()
@@ -0,0 +1,467 @@
+use crate::database::entities::organization::{Membership, Organization, OrganizationId};
+use crate::database::entities::organization_domain::{DomainId, OrganizationDomain};
+use crate::database::entities::organization_member::{
+    Member, OrgAction, OrgRole, OrganizationMember,
+};
+use crate::database::entities::quiz::Quiz;
+use crate::database::entities::resource::Resource;
+use crate::database::entities::scim_token::{ScimToken, ScimTokenId};
+use crate::database::entities::user::{User, UserId};
+use crate::http::middleware::auth::Auth;
+use crate::http::middleware::json::{ExtractJson, ValidJson};
+use crate::http::middleware::permission::authorize_organization;
+use crate::http::models::error::HttpResult;
+use crate::http::models::organization::{
+    AddDomainRequest, AddMemberRequest, CreateOrganizationRequest, CreateScimTokenRequest,
+    CreatedScimTokenResponse, DomainResponse, OrgError, OrganizationResponse, UpdateMemberRequest,
+    UpdateOrganizationRequest,
+};
+use crate::services::auth::AuthService;
+use crate::services::domain::DomainService;
+use crate::utils::assert::assert;
+use axum::extract::Path;
+use axum::http::StatusCode;
+use axum::routing::{delete, get, post, put};
+use axum::{Extension, Json, Router};
+use sea_orm::{DatabaseConnection, DbErr, ModelTrait, TransactionTrait};
+use std::sync::Arc;
+
+/// Defines the routes under the route group of /org
+pub fn routes() -> Router {
+    Router::new()
+        // Organizations of the current user
+        .route("/", get(list_organizations))
+        .route("/create", post(create_organization))
+        .nest(
+            "/:id",
+            Router::new()
+                .route(
+                    "/",
+                    get(get_organization)
+                        .patch(update_organization)
+                        .delete(delete_organization),
+                )
+                .route("/members", get(list_members).post(add_member))
+                .route(
+                    "/members/:user_id",
+                    put(update_member).delete(remove_member),
+                )
+                .route("/domains", get(list_domains).post(add_domain))
+                .route("/domains/:domain_id", delete(remove_domain))
+                .route("/domains/:domain_id/verify", post(verify_domain))
+                .route(
+                    "/scim-tokens",
+                    get(list_scim_tokens).post(create_scim_token),
+                )
+                .route("/scim-tokens/:token_id", delete(revoke_scim_token))
+                .route("/quizzes", get(list_quizzes))
+                .route("/resources", get(list_resources)),
+        )
+}
+
+/// GET /org
+///
+/// Requests the organizations the current user is a member of
+async fn list_organizations(
+    Auth(user): Auth,
+    Extension(db): Extension<DatabaseConnection>,
+) -> HttpResult<Json<Vec<Membership>>> {
+    let memberships = Organization::find_by_member(&db, &user).await?;
+
+    Ok(Json(memberships))
+}
+
+/// POST /org/create
+///
+/// Requests the creation of a new organization, the current user
+/// becomes its owner
+async fn create_organization(
+    Auth(user): Auth,
+    Extension(db): Extension<DatabaseConnection>,
+    ValidJson(req): ValidJson<CreateOrganizationRequest>,
+) -> HttpResult<Json<OrganizationResponse>> {
+    let organization = db
+        .transaction(move |db| {
+            Box::pin(async move {
+                let organization = Organization::create(db, req.name).await?;
+                OrganizationMember::create(db, organization.id, user.id, OrgRole::Owner).await?;
+
+                Ok::<_, DbErr>(organization)
+            })
+        })
+        .await?;
+
+    Ok(Json(OrganizationResponse {
+        organization,
+        role: OrgRole::Owner,
+    }))
+}
+
+/// GET /org/:id
+///
+/// Requests the details of an organization
+async fn get_organization(
+    Auth(user): Auth,
+    Path(id): Path<OrganizationId>,
+    Extension(db): Extension<DatabaseConnection>,
+) -> HttpResult<Json<OrganizationResponse>> {
+    let (organization, member) = authorize_organization(&db, &user, id, OrgAction::View).await?;
+
+    Ok(Json(OrganizationResponse {
+        organization,
+        role: member.role,
+    }))
+}
+
+/// PATCH /org/:id
+///
+/// Requests changes to the details of an organization
+async fn update_organization(
+    Auth(user): Auth,
+    Path(id): Path<OrganizationId>,
+    Extension(db): Extension<DatabaseConnection>,
+    ValidJson(req): ValidJson<UpdateOrganizationRequest>,
+) -> HttpResult<Json<OrganizationResponse>> {
+    let (organization, member) = authorize_organization(&db, &user, id, OrgAction::Update).await?;
+
+    let organization = organization.set_name(&db, req.name).await?;
+
+    Ok(Json(OrganizationResponse {
+        organization,
+        role: member.role,
+    }))
+}
+
+/// DELETE /org/:id
+///
+/// Requests the deletion of an organization, quizzes and resources
+/// owned by the organization return to the users that created them
+async fn delete_organization(
+    Auth(user): Auth,
+    Path(id): Path<OrganizationId>,
+    Extension(db): Extension<DatabaseConnection>,
+) -> HttpResult<StatusCode> {
+    let (organization, _) = authorize_organization(&db, &user, id, OrgAction::Delete).await?;
+
+    organization.delete(&db).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// GET /org/:id/members
+///
+/// Requests the members of an organization and their roles
+async fn list_members(
+    Auth(user): Auth,
+    Path(id): Path<OrganizationId>,
+    Extension(db): Extension<DatabaseConnection>,
+) -> HttpResult<Json<Vec<Member>>> {
+    let (organization, _) = authorize_organization(&db, &user, id, OrgAction::View).await?;
+
+    let members = OrganizationMember::find_members(&db, organization.id).await?;
+
+    Ok(Json(members))
+}
+
+/// POST /org/:id/members
+///
+/// Requests that a user be added to the organization, only owners
+/// can add admins and owners
+async fn add_member(
+    Auth(user): Auth,
+    Path(id): Path<OrganizationId>,
+    Extension(db): Extension<DatabaseConnection>,
+    ValidJson(req): ValidJson<AddMemberRequest>,
+) -> HttpResult<Json<OrganizationMember>> {
+    let (organization, member) =
+        authorize_organization(&db, &user, id, OrgAction::ManageMembers).await?;
+
+    assert(
+        can_manage_role(member.role, req.role),
+        OrgError::MissingPermission,
+    )?;
+
+    let target = User::find_by_username(&db, &req.username)
+        .await?
+        .ok_or(OrgError::UserNotFound)?;
+
+    let existing = OrganizationMember::find(&db, organization.id, target.id).await?;
+    assert(existing.is_none(), OrgError::AlreadyMember)?;
+
+    let member = OrganizationMember::create(&db, organization.id, target.id, req.role).await?;
+
+    Ok(Json(member))
+}
+
+/// PUT /org/:id/members/:user_id
+///
+/// Requests a change to the role of a member, only owners can change
+/// the role of admins and owners or grant those roles
+async fn update_member(
+    Auth(user): Auth,
+    Path((id, user_id)): Path<(OrganizationId, UserId)>,
+    Extension(db): Extension<DatabaseConnection>,
+    ExtractJson(req): ExtractJson<UpdateMemberRequest>,
+) -> HttpResult<Json<OrganizationMember>> {
+    let (organization, member) =
+        authorize_organization(&db, &user, id, OrgAction::ManageMembers).await?;
+
+    let target = OrganizationMember::find(&db, organization.id, user_id)
+        .await?
+        .ok_or(OrgError::MemberNotFound)?;
+
+    assert(
+        can_manage_role(member.role, target.role) && can_manage_role(member.role, req.role),
+        OrgError::MissingPermission,
+    )?;
+
+    if target.role == OrgRole::Owner && req.role != OrgRole::Owner {
+        ensure_other_owner(&db, organization.id).await?;
+    }
+
+    let target = target.set_role(&db, req.role).await?;
+
+    Ok(Json(target))
+}
+
+/// DELETE /org/:id/members/:user_id
+///
+/// Requests that a member be removed from the organization, members
+/// are always able to leave unless they are the last owner
+async fn remove_member(
+    Auth(user): Auth,
+    Path((id, user_id)): Path<(OrganizationId, UserId)>,
+    Extension(db): Extension<DatabaseConnection>,
+) -> HttpResult<StatusCode> {
+    // Leaving an organization only requires being a member
+    let action = if user_id == user.id {
+        OrgAction::View
+    } else {
+        OrgAction::ManageMembers
+    };
+
+    let (organization, member) = authorize_organization(&db, &user, id, action).await?;
+
+    let target = OrganizationMember::find(&db, organization.id, user_id)
+        .await?
+        .ok_or(OrgError::MemberNotFound)?;
+
+    assert(
+        user_id == user.id || can_manage_role(member.role, target.role),
+        OrgError::MissingPermission,
+    )?;
+
+    if target.role == OrgRole::Owner {
+        ensure_other_owner(&db, organization.id).await?;
+    }
+
+    target.remove(&db).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// GET /org/:id/domains
+///
+/// Requests the email domains claimed by the organization along with
+/// the DNS records required to verify them
+async fn list_domains(
+    Auth(user): Auth,
+    Path(id): Path<OrganizationId>,
+    Extension(db): Extension<DatabaseConnection>,
+) -> HttpResult<Json<Vec<DomainResponse>>> {
+    let (organization, _) =
+        authorize_organization(&db, &user, id, OrgAction::ManageDomains).await?;
+
+    let domains = OrganizationDomain::find_by_organization(&db, organization.id)
+        .await?
+        .into_iter()
+        .map(DomainResponse::from)
+        .collect();
+
+    Ok(Json(domains))
+}
+
+/// POST /org/:id/domains
+///
+/// Requests that an email domain be claimed by the organization, the
+/// claim must be verified before users are added from the domain
+async fn add_domain(
+    Auth(user): Auth,
+    Path(id): Path<OrganizationId>,
+    Extension(db): Extension<DatabaseConnection>,
+    ValidJson(req): ValidJson<AddDomainRequest>,
+) -> HttpResult<Json<DomainResponse>> {
+    let (organization, _) =
+        authorize_organization(&db, &user, id, OrgAction::ManageDomains).await?;
+
+    let domain = req.domain.to_ascii_lowercase();
+
+    let existing = OrganizationDomain::find_by_domain(&db, organization.id, &domain).await?;
+    assert(existing.is_none(), OrgError::DomainAlreadyAdded)?;
+
+    let domain =
+        OrganizationDomain::create(&db, organization.id, domain, DomainService::create_token())
+            .await?;
+
+    Ok(Json(domain.into()))
+}
+
+/// POST /org/:id/domains/:domain_id/verify
+///
+/// Requests verification of a claimed domain by checking for its DNS
+/// record, new users on the domain created through the identity provider
+/// of the organization will join the organization
+async fn verify_domain(
+    Auth(user): Auth,
+    Path((id, domain_id)): Path<(OrganizationId, DomainId)>,
+    Extension(db): Extension<DatabaseConnection>,
+    Extension(domains): Extension<Arc<DomainService>>,
+) -> HttpResult<Json<DomainResponse>> {
+    let (organization, _) =
+        authorize_organization(&db, &user, id, OrgAction::ManageDomains).await?;
+
+    let domain = OrganizationDomain::find_by_id(&db, organization.id, domain_id)
+        .await?
+        .ok_or(OrgError::DomainNotFound)?;
+
+    assert(
+        domain.verified_at.is_none(),
+        OrgError::DomainAlreadyVerified,
+    )?;
+
+    let taken = OrganizationDomain::find_verified(&db, &domain.domain).await?;
+    assert(taken.is_none(), OrgError::DomainTaken)?;
+
+    let verified = domains
+        .verify(&domain.domain, &domain.verification_token)
+        .await
+        .map_err(|_| OrgError::DomainLookup)?;
+    assert(verified, OrgError::DomainNotVerified)?;
+
+    let domain = domain.set_verified(&db).await?;
+
+    Ok(Json(domain.into()))
+}
+
+/// DELETE /org/:id/domains/:domain_id
+///
+/// Requests that a domain claim be removed, users that already joined
+/// from the domain remain members
+async fn remove_domain(
+    Auth(user): Auth,
+    Path((id, domain_id)): Path<(OrganizationId, DomainId)>,
+    Extension(db): Extension<DatabaseConnection>,
+) -> HttpResult<StatusCode> {
+    let (organization, _) =
+        authorize_organization(&db, &user, id, OrgAction::ManageDomains).await?;
+
+    let domain = OrganizationDomain::find_by_id(&db, organization.id, domain_id)
+        .await?
+        .ok_or(OrgError::DomainNotFound)?;
+
+    domain.remove(&db).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// GET /org/:id/scim-tokens
+///
+/// Requests the tokens the organization provisions users through
+/// SCIM with, the tokens themselves aren't included
+async fn list_scim_tokens(
+    Auth(user): Auth,
+    Path(id): Path<OrganizationId>,
+    Extension(db): Extension<DatabaseConnection>,
+) -> HttpResult<Json<Vec<ScimToken>>> {
+    let (organization, _) = authorize_organization(&db, &user, id, OrgAction::ManageScim).await?;
+
+    let tokens = ScimToken::find_by_organization(&db, organization.id).await?;
+
+    Ok(Json(tokens))
+}
+
+/// POST /org/:id/scim-tokens
+///
+/// Requests a new token for provisioning users through SCIM, the
+/// token is only included in this response
+async fn create_scim_token(
+    Auth(user): Auth,
+    Path(id): Path<OrganizationId>,
+    Extension(db): Extension<DatabaseConnection>,
+    Extension(auth): Extension<Arc<AuthService>>,
+    ValidJson(req): ValidJson<CreateScimTokenRequest>,
+) -> HttpResult<Json<CreatedScimTokenResponse>> {
+    let (organization, _) = authorize_organization(&db, &user, id, OrgAction::ManageScim).await?;
+
+    let (scim_token, token) = auth
+        .create_scim_token(&db, organization.id, req.name)
+        .await?;
+
+    Ok(Json(CreatedScimTokenResponse { scim_token, token }))
+}
+
+/// DELETE /org/:id/scim-tokens/:token_id
+///
+/// Requests that a SCIM token be revoked, the directory using the
+/// token can no longer provision users
+async fn revoke_scim_token(
+    Auth(user): Auth,
+    Path((id, token_id)): Path<(OrganizationId, ScimTokenId)>,
+    Extension(db): Extension<DatabaseConnection>,
+) -> HttpResult<StatusCode> {
+    let (organization, _) = authorize_organization(&db, &user, id, OrgAction::ManageScim).await?;
+
+    let scim_token = ScimToken::find_by_id(&db, organization.id, token_id)
+        .await?
+        .ok_or(OrgError::ScimTokenNotFound)?;
+
+    scim_token.remove(&db).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// GET /org/:id/quizzes
+///
+/// Requests the quizzes owned by the organization
+async fn list_quizzes(
+    Auth(user): Auth,
+    Path(id): Path<OrganizationId>,
+    Extension(db): Extension<DatabaseConnection>,
+) -> HttpResult<Json<Vec<Quiz>>> {
+    let (organization, _) = authorize_organization(&db, &user, id, OrgAction::View).await?;
+
+    let quizzes = Quiz::find_by_organization(&db, organization.id).await?;
+
+    Ok(Json(quizzes))
+}
+
+/// GET /org/:id/resources
+///
+/// Requests the resources owned by the organization
+async fn list_resources(
+    Auth(user): Auth,
+    Path(id): Path<OrganizationId>,
+    Extension(db): Extension<DatabaseConnection>,
+) -> HttpResult<Json<Vec<Resource>>> {
+    let (organization, _) = authorize_organization(&db, &user, id, OrgAction::View).await?;
+
+    let resources = Resource::find_by_organization(&db, organization.id).await?;
+
+    Ok(Json(resources))
+}
+
+/// Ensures the organization has more than one owner so that an owner
+/// can be demoted or removed
+async fn ensure_other_owner(db: &DatabaseConnection, id: OrganizationId) -> HttpResult<()> {
+    let owners = OrganizationMember::count_owners(db, id).await?;
+    assert(owners > 1, OrgError::LastOwner)?;
+
+    Ok(())
+}
+
+/// Checks whether a member with the provided `role` can add, change or
+/// remove members with the `target` role, admins and owners are managed
+/// by owners
+fn can_manage_role(role: OrgRole, target: OrgRole) -> bool {
+    role == OrgRole::Owner || target == OrgRole::Member
+}
@@ -0,0 +1,61 @@
+use crate::database::entities::quiz::QuizState;
+use crate::database::entities::quiz_permission::QuizAction;
+use crate::http::middleware::auth::Auth;
+use crate::http::middleware::json::ExtractJson;
+use crate::http::middleware::permission::authorize_quiz;
+use crate::http::models::error::HttpResult;
+use crate::http::models::play::{CreateSessionRequest, CreateSessionResponse};
+use crate::http::models::quiz::QuizError;
+use crate::services::auth::AuthService;
+use crate::services::game::{socket::handle_socket, GameService};
+use crate::utils::assert::assert;
+use axum::extract::WebSocketUpgrade;
+use axum::response::Response;
+use axum::routing::{get, post};
+use axum::{Extension, Json, Router};
+use sea_orm::DatabaseConnection;
+use std::sync::Arc;
+
+/// Defines the routes under the route group of /play
+pub fn routes() -> Router {
+    Router::new()
+        // Host a new game
+        .route("/session", post(create_session))
+        // Game connection for hosts and players
+        .route("/ws", get(connect))
+}
+
+/// POST /play/session
+///
+/// Requests a new game session for a published quiz, responds with
+/// the code players can use to join
+async fn create_session(
+    Auth(user): Auth,
+    Extension(db): Extension<DatabaseConnection>,
+    Extension(games): Extension<Arc<GameService>>,
+    ExtractJson(req): ExtractJson<CreateSessionRequest>,
+) -> HttpResult<Json<CreateSessionResponse>> {
+    let (quiz, _) = authorize_quiz(&db, &user, req.quiz_id, QuizAction::Host).await?;
+
+    assert(quiz.state == QuizState::Published, QuizError::NotPublished)?;
+
+    let session = games.create_game(db, &user, quiz).await?;
+
+    Ok(Json(CreateSessionResponse {
+        id: session.id,
+        code: session.code,
+        state: session.state,
+    }))
+}
+
+/// GET /play/ws
+///
+/// WebSocket connection for game hosts and players
+async fn connect(
+    ws: WebSocketUpgrade,
+    Extension(games): Extension<Arc<GameService>>,
+    Extension(auth): Extension<Arc<AuthService>>,
+    Extension(db): Extension<DatabaseConnection>,
+) -> Response {
+    ws.on_upgrade(move |socket| handle_socket(socket, games, auth, db))
+}
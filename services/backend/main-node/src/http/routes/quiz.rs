@@ -1,35 +1,152 @@
-use crate::database::entities::quiz::{Quiz, QuizId};
-use crate::database::entities::user::User;
+use crate::database::entities::organization_member::{OrgAction, OrgRole};
+use crate::database::entities::quiz::{BrowseFilter, Quiz, QuizId, QuizState, UpdateQuiz};
+use crate::database::entities::quiz_permission::{
+    Collaborator, QuizAction, QuizPermission, QuizRole,
+};
+use crate::database::entities::resource::{Resource, ResourceId, ResourceVisibility};
+use crate::database::entities::user::{User, UserId};
 use crate::http::middleware::auth::Auth;
-use crate::http::middleware::json::ValidJson;
+use crate::http::middleware::json::{ExtractJson, ValidJson};
+use crate::http::middleware::permission::{authorize_organization, authorize_quiz, QuizAccess};
+use crate::http::middleware::query::ValidQuery;
 use crate::http::models::error::HttpResult;
-use crate::http::models::quiz::{CreateQuizRequest, QuizError};
+use crate::http::models::quiz::{
+    decode_cursor, encode_cursor, AddCollaboratorRequest, BrowseQuizzesQuery,
+    BrowseQuizzesResponse, CreateQuizRequest, ListQuizzesQuery, QuizError, QuizListResponse,
+    UpdateCollaboratorRequest, UpdateQuizRequest,
+};
+use crate::utils::assert::assert;
 use axum::extract::Path;
-use axum::routing::{get, post};
+use axum::http::StatusCode;
+use axum::routing::{get, post, put};
 use axum::{Extension, Json, Router};
-use sea_orm::DatabaseConnection;
+use garde::Validate;
+use sea_orm::{DatabaseConnection, ModelTrait};
 
-/// Defines the routes under the route group of /user
+/// Defines the routes under the route group of /quiz
 pub fn routes() -> Router {
     Router::new()
-        // Self route
+        // Library of the current user
+        .route("/", get(list_quizzes))
         .route("/create", post(create_quiz))
-        .nest("/:id", Router::new().route("/", get(get_quiz)))
+        // Quizzes shared with the current user
+        .route("/shared", get(list_shared_quizzes))
+        // Discovery of public quizzes
+        .route("/browse", get(browse_quizzes))
+        .nest(
+            "/:id",
+            Router::new()
+                .route("/", get(get_quiz).patch(update_quiz).delete(delete_quiz))
+                .route("/publish", post(publish_quiz))
+                .route("/unpublish", post(unpublish_quiz))
+                .route(
+                    "/collaborators",
+                    get(list_collaborators).post(add_collaborator),
+                )
+                .route(
+                    "/collaborators/:user_id",
+                    put(update_collaborator).delete(remove_collaborator),
+                ),
+        )
+}
+
+/// GET /quiz
+///
+/// Requests a page of the quizzes owned by the current user
+async fn list_quizzes(
+    Auth(user): Auth,
+    Extension(db): Extension<DatabaseConnection>,
+    ValidQuery(query): ValidQuery<ListQuizzesQuery>,
+) -> HttpResult<Json<QuizListResponse>> {
+    let paginator = Quiz::find_by_owner(
+        &db,
+        &user,
+        query.sort.column(),
+        query.order.into(),
+        query.size,
+    );
+
+    let totals = paginator.num_items_and_pages().await?;
+    let quizzes = paginator.fetch_page(query.page).await?;
+
+    Ok(Json(QuizListResponse {
+        quizzes,
+        total_items: totals.number_of_items,
+        total_pages: totals.number_of_pages,
+    }))
+}
+
+/// GET /quiz/shared
+///
+/// Requests the quizzes that other users have shared with the current user
+async fn list_shared_quizzes(
+    Auth(user): Auth,
+    Extension(db): Extension<DatabaseConnection>,
+) -> HttpResult<Json<Vec<Quiz>>> {
+    let quizzes = Quiz::find_shared_with(&db, &user).await?;
+
+    Ok(Json(quizzes))
+}
+
+/// GET /quiz/browse
+///
+/// Requests a page of public quizzes matching the provided filters
+async fn browse_quizzes(
+    _: Option<Auth>,
+    Extension(db): Extension<DatabaseConnection>,
+    ValidQuery(query): ValidQuery<BrowseQuizzesQuery>,
+) -> HttpResult<Json<BrowseQuizzesResponse>> {
+    let cursor = query
+        .cursor
+        .as_deref()
+        .map(|cursor| decode_cursor(cursor, query.sort))
+        .transpose()?;
+
+    let filter = BrowseFilter {
+        query: query.query,
+        tag: query.tag.map(|tag| tag.to_lowercase()),
+        owner: query.owner.map(|owner| owner.trim().to_lowercase()),
+        min_questions: query.min_questions,
+        max_questions: query.max_questions,
+    };
+
+    // Request an additional quiz to determine if there is another page
+    let mut quizzes = Quiz::browse(&db, filter, query.sort, cursor, query.size + 1).await?;
+
+    let next_cursor = if quizzes.len() as u64 > query.size {
+        quizzes.truncate(query.size as usize);
+        quizzes
+            .last()
+            .map(|quiz| encode_cursor(quiz.cursor(query.sort)))
+    } else {
+        None
+    };
+
+    Ok(Json(BrowseQuizzesResponse {
+        quizzes,
+        next_cursor,
+    }))
 }
 
 /// POST /quiz/create
 ///
-/// Requests the creation of a new quiz
+/// Requests the creation of a new quiz, optionally owned by an
+/// organization the current user is a member of
 async fn create_quiz(
     Auth(user): Auth,
     Extension(db): Extension<DatabaseConnection>,
     ValidJson(req): ValidJson<CreateQuizRequest>,
 ) -> HttpResult<Json<Quiz>> {
+    if let Some(organization) = req.organization {
+        authorize_organization(&db, &user, organization, OrgAction::CreateContent).await?;
+    }
+
     // Create the new quiz
-    let quiz = Quiz::create(&db, &user, req.title).await?;
+    let quiz = Quiz::create(&db, &user, req.organization, req.title).await?;
 
     Ok(Json(quiz))
 }
+
 /// GET /quiz/:id
 ///
 /// Requests the details of a quiz
@@ -38,14 +155,267 @@ async fn get_quiz(
     Path(id): Path<QuizId>,
     Extension(db): Extension<DatabaseConnection>,
 ) -> HttpResult<Json<Quiz>> {
-    // Create the new quiz
-    let quiz = Quiz::find_by_id(&db, id)
-        .await?
-        .ok_or(QuizError::NotFound)?;
+    let (quiz, _) = authorize_quiz(&db, &user, id, QuizAction::View).await?;
+
+    Ok(Json(quiz))
+}
+
+/// PATCH /quiz/:id
+///
+/// Requests changes to the details and data of a quiz
+async fn update_quiz(
+    Auth(user): Auth,
+    Path(id): Path<QuizId>,
+    Extension(db): Extension<DatabaseConnection>,
+    ValidJson(req): ValidJson<UpdateQuizRequest>,
+) -> HttpResult<Json<Quiz>> {
+    let (quiz, access) = authorize_quiz(&db, &user, id, QuizAction::Edit).await?;
 
-    if !quiz.owner.eq(&user.id) {
-        return Err(QuizError::MissingPermission.into());
+    // Visibility controls who can discover the quiz, treated like publishing
+    if let Some(visibility) = &req.visibility {
+        assert(
+            access.allows(QuizAction::Publish),
+            QuizError::MissingPermission,
+        )?;
+
+        assert(quiz.allows_visibility(visibility), QuizError::Hidden)?;
+    }
+
+    // Published quizzes must always remain playable
+    if let (QuizState::Published, Some(data)) = (&quiz.state, &req.data) {
+        assert(!data.questions.is_empty(), QuizError::NoQuestions)?;
     }
 
+    // Ensure any newly referenced images can be displayed to players
+    let images: Vec<ResourceId> = req
+        .cover_image
+        .flatten()
+        .into_iter()
+        .chain(
+            req.data
+                .iter()
+                .flat_map(|data| data.questions.iter().filter_map(|question| question.image)),
+        )
+        .collect();
+
+    validate_images(&db, &quiz, &user, images).await?;
+
+    let quiz = quiz
+        .update(
+            &db,
+            UpdateQuiz {
+                title: req.title,
+                description: req.description,
+                cover_image: req.cover_image,
+                visibility: req.visibility,
+                tags: req
+                    .tags
+                    .map(|tags| tags.into_iter().map(|tag| tag.to_lowercase()).collect()),
+                data: req.data,
+            },
+        )
+        .await?;
+
+    Ok(Json(quiz))
+}
+
+/// DELETE /quiz/:id
+///
+/// Requests the deletion of a quiz
+async fn delete_quiz(
+    Auth(user): Auth,
+    Path(id): Path<QuizId>,
+    Extension(db): Extension<DatabaseConnection>,
+) -> HttpResult<StatusCode> {
+    let (quiz, _) = authorize_quiz(&db, &user, id, QuizAction::Delete).await?;
+
+    quiz.delete(&db).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// POST /quiz/:id/publish
+///
+/// Requests that a draft quiz be published, the quiz must pass
+/// validation before it can be published
+async fn publish_quiz(
+    Auth(user): Auth,
+    Path(id): Path<QuizId>,
+    Extension(db): Extension<DatabaseConnection>,
+) -> HttpResult<Json<Quiz>> {
+    let (quiz, _) = authorize_quiz(&db, &user, id, QuizAction::Publish).await?;
+
+    assert(quiz.state == QuizState::Draft, QuizError::AlreadyPublished)?;
+
+    // Ensure the quiz is complete and valid
+    quiz.data.validate(&())?;
+    assert(!quiz.data.questions.is_empty(), QuizError::NoQuestions)?;
+
+    let quiz = quiz.set_state(&db, QuizState::Published).await?;
+
+    Ok(Json(quiz))
+}
+
+/// POST /quiz/:id/unpublish
+///
+/// Requests that a published quiz be moved back to a draft
+async fn unpublish_quiz(
+    Auth(user): Auth,
+    Path(id): Path<QuizId>,
+    Extension(db): Extension<DatabaseConnection>,
+) -> HttpResult<Json<Quiz>> {
+    let (quiz, _) = authorize_quiz(&db, &user, id, QuizAction::Publish).await?;
+
+    assert(quiz.state == QuizState::Published, QuizError::NotPublished)?;
+
+    let quiz = quiz.set_state(&db, QuizState::Draft).await?;
+
     Ok(Json(quiz))
 }
+
+/// GET /quiz/:id/collaborators
+///
+/// Requests the users the quiz has been shared with and their roles
+async fn list_collaborators(
+    Auth(user): Auth,
+    Path(id): Path<QuizId>,
+    Extension(db): Extension<DatabaseConnection>,
+) -> HttpResult<Json<Vec<Collaborator>>> {
+    let (quiz, _) = authorize_quiz(&db, &user, id, QuizAction::View).await?;
+
+    let collaborators = QuizPermission::find_collaborators(&db, quiz.id).await?;
+
+    Ok(Json(collaborators))
+}
+
+/// POST /quiz/:id/collaborators
+///
+/// Requests that the quiz be shared with another user, only the
+/// owner can grant the admin role
+async fn add_collaborator(
+    Auth(user): Auth,
+    Path(id): Path<QuizId>,
+    Extension(db): Extension<DatabaseConnection>,
+    ValidJson(req): ValidJson<AddCollaboratorRequest>,
+) -> HttpResult<Json<QuizPermission>> {
+    let (quiz, access) = authorize_quiz(&db, &user, id, QuizAction::ManageCollaborators).await?;
+
+    assert(
+        can_manage_role(access, req.role),
+        QuizError::MissingPermission,
+    )?;
+
+    let target = User::find_by_username(&db, &req.username)
+        .await?
+        .ok_or(QuizError::UserNotFound)?;
+
+    assert(target.id != quiz.owner, QuizError::SharedWithOwner)?;
+
+    let existing = QuizPermission::find(&db, quiz.id, target.id).await?;
+    assert(existing.is_none(), QuizError::AlreadyCollaborator)?;
+
+    let permission = QuizPermission::create(&db, quiz.id, target.id, req.role).await?;
+
+    Ok(Json(permission))
+}
+
+/// PUT /quiz/:id/collaborators/:user_id
+///
+/// Requests a change to the role of a collaborator, only the owner
+/// can change the role of an admin or grant the admin role
+async fn update_collaborator(
+    Auth(user): Auth,
+    Path((id, user_id)): Path<(QuizId, UserId)>,
+    Extension(db): Extension<DatabaseConnection>,
+    ExtractJson(req): ExtractJson<UpdateCollaboratorRequest>,
+) -> HttpResult<Json<QuizPermission>> {
+    let (quiz, access) = authorize_quiz(&db, &user, id, QuizAction::ManageCollaborators).await?;
+
+    let permission = QuizPermission::find(&db, quiz.id, user_id)
+        .await?
+        .ok_or(QuizError::CollaboratorNotFound)?;
+
+    assert(
+        can_manage_role(access, permission.role) && can_manage_role(access, req.role),
+        QuizError::MissingPermission,
+    )?;
+
+    let permission = permission.set_role(&db, req.role).await?;
+
+    Ok(Json(permission))
+}
+
+/// DELETE /quiz/:id/collaborators/:user_id
+///
+/// Requests that a collaborator be removed from the quiz, collaborators
+/// are always able to remove themselves
+async fn remove_collaborator(
+    Auth(user): Auth,
+    Path((id, user_id)): Path<(QuizId, UserId)>,
+    Extension(db): Extension<DatabaseConnection>,
+) -> HttpResult<StatusCode> {
+    // Leaving a quiz only requires access to the quiz
+    let action = if user_id == user.id {
+        QuizAction::View
+    } else {
+        QuizAction::ManageCollaborators
+    };
+
+    let (quiz, access) = authorize_quiz(&db, &user, id, action).await?;
+
+    let permission = QuizPermission::find(&db, quiz.id, user_id)
+        .await?
+        .ok_or(QuizError::CollaboratorNotFound)?;
+
+    assert(
+        user_id == user.id || can_manage_role(access, permission.role),
+        QuizError::MissingPermission,
+    )?;
+
+    permission.revoke(&db).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Ensures each of the image resources referenced by the quiz exists, is public
+/// and is owned by either the quiz owner, the `user` making the change or the
+/// organization that owns the quiz
+async fn validate_images(
+    db: &DatabaseConnection,
+    quiz: &Quiz,
+    user: &User,
+    mut ids: Vec<ResourceId>,
+) -> HttpResult<()> {
+    ids.sort_unstable();
+    ids.dedup();
+
+    if ids.is_empty() {
+        return Ok(());
+    }
+
+    let expected = ids.len();
+    let resources = Resource::find_by_ids(db, ids).await?;
+
+    let valid = resources.len() == expected
+        && resources.iter().all(|resource| {
+            resource.visibility == ResourceVisibility::Public
+                && (resource.owner == quiz.owner
+                    || resource.owner == user.id
+                    || (resource.organization_id.is_some()
+                        && resource.organization_id == quiz.organization_id))
+        });
+
+    assert(valid, QuizError::InvalidImage)?;
+
+    Ok(())
+}
+
+/// Checks whether the provided `access` allows granting, changing or
+/// revoking the provided `role`, admin roles are managed by the owner
+/// and the admins of the organization that owns the quiz
+fn can_manage_role(access: QuizAccess, role: QuizRole) -> bool {
+    matches!(
+        access,
+        QuizAccess::Owner | QuizAccess::Organization(OrgRole::Admin | OrgRole::Owner)
+    ) || role != QuizRole::Admin
+}
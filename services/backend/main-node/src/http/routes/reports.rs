@@ -0,0 +1,70 @@
+use crate::database::entities::active_quiz::{ActiveQuiz, ActiveQuizId};
+use crate::database::entities::analytics::AnswerEvent;
+use crate::database::entities::quiz::QuizId;
+use crate::database::entities::quiz_permission::QuizAction;
+use crate::http::middleware::auth::Auth;
+use crate::http::middleware::permission::authorize_quiz;
+use crate::http::models::error::HttpResult;
+use crate::http::models::reports::{
+    QuizReportResponse, ReportError, ScoreBucket, SessionReportResponse,
+};
+use axum::extract::Path;
+use axum::routing::get;
+use axum::{Extension, Json, Router};
+use sea_orm::DatabaseConnection;
+
+/// Defines the routes under the route group of /reports
+pub fn routes() -> Router {
+    Router::new()
+        .route("/quiz/:id", get(get_quiz_report))
+        .route("/session/:id", get(get_session_report))
+}
+
+/// GET /reports/quiz/:id
+///
+/// Requests the finished games of a quiz along with the question
+/// statistics across all of those games
+async fn get_quiz_report(
+    Auth(user): Auth,
+    Path(id): Path<QuizId>,
+    Extension(db): Extension<DatabaseConnection>,
+) -> HttpResult<Json<QuizReportResponse>> {
+    let (quiz, _) = authorize_quiz(&db, &user, id, QuizAction::ViewReports).await?;
+
+    let sessions = ActiveQuiz::find_finished_by_quiz(&db, quiz.id).await?;
+    let questions = AnswerEvent::question_stats(&db, quiz.id, None).await?;
+
+    Ok(Json(QuizReportResponse {
+        sessions,
+        questions,
+    }))
+}
+
+/// GET /reports/session/:id
+///
+/// Requests the question, player and score statistics for a
+/// single game
+async fn get_session_report(
+    Auth(user): Auth,
+    Path(id): Path<ActiveQuizId>,
+    Extension(db): Extension<DatabaseConnection>,
+) -> HttpResult<Json<SessionReportResponse>> {
+    let session = ActiveQuiz::find_by_id(&db, id)
+        .await?
+        .ok_or(ReportError::SessionNotFound)?;
+
+    let (quiz, _) = authorize_quiz(&db, &user, session.quiz_id, QuizAction::ViewReports).await?;
+
+    let questions = AnswerEvent::question_stats(&db, quiz.id, Some(session.id)).await?;
+    let players = AnswerEvent::player_stats(&db, session.id).await?;
+
+    let scores: Vec<i64> = players.iter().map(|player| player.score).collect();
+    let score_distribution = ScoreBucket::distribution(&scores);
+
+    Ok(Json(SessionReportResponse {
+        session,
+        questions,
+        players,
+        score_distribution,
+    }))
+}
@@ -0,0 +1,349 @@
+use crate::database::entities::organization::OrganizationId;
+use crate::database::entities::organization_member::{OrgAction, OrganizationMember};
+use crate::database::entities::resource::{
+    CreateResource, Resource, ResourceId, ResourceVisibility,
+};
+use crate::database::models::resource::{variant_key, ResourceVariant, ResourceVariants};
+use crate::http::middleware::auth::Auth;
+use crate::http::middleware::permission::authorize_organization;
+use crate::http::middleware::query::ExtractQuery;
+use crate::http::models::error::HttpResult;
+use crate::http::models::resource::{
+    parse_visibility, ResourceContentQuery, ResourceError, MAX_UPLOAD_SIZE,
+};
+use crate::services::image::{process_image, ImageType};
+use crate::services::storage::{create_key, SharedStorage, Storage};
+use crate::utils::assert::assert;
+use axum::body::Bytes;
+use axum::extract::multipart::{Field, MultipartError};
+use axum::extract::{DefaultBodyLimit, Multipart, Path};
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Extension, Json, Router};
+use sea_orm::{DatabaseConnection, ModelTrait};
+use tracing::error;
+
+/// Defines the routes under the route group of /resource
+pub fn routes() -> Router {
+    Router::new()
+        // Resources of the current user
+        .route("/", get(list_resources))
+        .route(
+            "/upload",
+            post(upload_resource)
+                // Allow for the multipart overhead on top of the file
+                .layer(DefaultBodyLimit::max(MAX_UPLOAD_SIZE + 64 * 1024)),
+        )
+        .route("/:id", get(get_resource).delete(delete_resource))
+        .route("/:id/content", get(get_resource_content))
+}
+
+/// GET /resource
+///
+/// Requests all the resources uploaded by the current user
+async fn list_resources(
+    Auth(user): Auth,
+    Extension(db): Extension<DatabaseConnection>,
+) -> HttpResult<Json<Vec<Resource>>> {
+    let resources = Resource::find_by_owner(&db, &user).await?;
+
+    Ok(Json(resources))
+}
+
+/// POST /resource/upload
+///
+/// Uploads a new resource as multipart form data, the "file" field contains
+/// the file content with optional "name", "description", "visibility" and
+/// "organization" fields. Resources uploaded to an organization are owned
+/// by the organization
+///
+/// Images are re-encoded which strips their metadata and the smaller
+/// variants are generated and stored alongside the original
+async fn upload_resource(
+    Auth(user): Auth,
+    Extension(db): Extension<DatabaseConnection>,
+    Extension(storage): Extension<SharedStorage>,
+    mut multipart: Multipart,
+) -> HttpResult<Json<Resource>> {
+    let mut file: Option<(Option<String>, ImageType, Bytes)> = None;
+    let mut name: Option<String> = None;
+    let mut description: Option<String> = None;
+    let mut visibility = ResourceVisibility::default();
+    let mut organization: Option<OrganizationId> = None;
+
+    while let Some(mut field) = multipart.next_field().await.map_err(multipart_error)? {
+        match field.name() {
+            Some("file") => {
+                let declared = field
+                    .content_type()
+                    .and_then(ImageType::from_mime)
+                    .ok_or(ResourceError::UnsupportedType)?;
+                let file_name = field.file_name().map(str::to_string);
+                let data = read_file(&mut field).await?;
+
+                // Content must match the type it claims to be
+                let detected = ImageType::sniff(&data).ok_or(ResourceError::UnsupportedType)?;
+                assert(declared == detected, ResourceError::UnsupportedType)?;
+
+                file = Some((file_name, detected, data));
+            }
+            Some("name") => name = Some(read_text(field, "name", 100).await?),
+            Some("description") => description = Some(read_text(field, "description", 1000).await?),
+            Some("visibility") => {
+                let value = read_text(field, "visibility", 16).await?;
+                visibility =
+                    parse_visibility(&value).ok_or(ResourceError::InvalidField("visibility"))?;
+            }
+            Some("organization") => {
+                let value = read_text(field, "organization", 16).await?;
+                organization = Some(
+                    value
+                        .parse()
+                        .map_err(|_| ResourceError::InvalidField("organization"))?,
+                );
+            }
+            _ => {}
+        }
+    }
+
+    let (file_name, image_type, data) = file.ok_or(ResourceError::MissingFile)?;
+
+    if let Some(organization) = organization {
+        authorize_organization(&db, &user, organization, OrgAction::CreateContent).await?;
+    }
+
+    let name = name
+        .or(file_name.map(|value| value.trim().chars().take(100).collect()))
+        .filter(|value| !value.is_empty())
+        .unwrap_or_else(|| "Untitled".to_string());
+
+    let processed = tokio::task::spawn_blocking(move || process_image(&data, image_type))
+        .await
+        .map_err(|error| {
+            error!(name: "err_image_task", %error, "Image processing task failed");
+            ResourceError::Processing
+        })?
+        .map_err(ResourceError::from)?;
+
+    let key = create_key(processed.image_type.extension());
+
+    let mut uploads: Vec<(String, Bytes)> = vec![(key.clone(), processed.original.data.into())];
+    let mut variants: Vec<ResourceVariant> = Vec::with_capacity(processed.variants.len());
+
+    for (variant, encoded) in processed.variants {
+        uploads.push((variant_key(&key, variant), encoded.data.into()));
+        variants.push(ResourceVariant {
+            variant,
+            width: encoded.width,
+            height: encoded.height,
+        });
+    }
+
+    let keys: Vec<String> = uploads.iter().map(|(key, _)| key.clone()).collect();
+
+    for (key, data) in uploads {
+        if let Err(error) = storage.put(&key, data).await {
+            error!(name: "err_storage_put", %error, "Failed to store resource");
+            delete_content(storage.as_ref(), &keys).await;
+            return Err(ResourceError::Storage.into());
+        }
+    }
+
+    let resource = Resource::create(
+        &db,
+        &user,
+        CreateResource {
+            mime_type: processed.image_type.mime().to_string(),
+            name,
+            description,
+            path: key,
+            organization_id: organization,
+            visibility,
+            width: processed.original.width as i32,
+            height: processed.original.height as i32,
+            variants: ResourceVariants(variants),
+        },
+    )
+    .await;
+
+    let resource = match resource {
+        Ok(value) => value,
+        Err(err) => {
+            // Don't leave behind content that isn't referenced
+            delete_content(storage.as_ref(), &keys).await;
+            return Err(err.into());
+        }
+    };
+
+    Ok(Json(resource))
+}
+
+/// GET /resource/:id
+///
+/// Requests the details of a resource
+async fn get_resource(
+    auth: Option<Auth>,
+    Path(id): Path<ResourceId>,
+    Extension(db): Extension<DatabaseConnection>,
+) -> HttpResult<Json<Resource>> {
+    let resource = find_visible_resource(&db, auth, id).await?;
+
+    Ok(Json(resource))
+}
+
+/// GET /resource/:id/content
+///
+/// Requests the content of a resource, public resources can be requested
+/// without authentication. The "variant" query parameter requests one of
+/// the smaller processed variants
+async fn get_resource_content(
+    auth: Option<Auth>,
+    Path(id): Path<ResourceId>,
+    ExtractQuery(query): ExtractQuery<ResourceContentQuery>,
+    Extension(db): Extension<DatabaseConnection>,
+    Extension(storage): Extension<SharedStorage>,
+) -> HttpResult<Response> {
+    let resource = find_visible_resource(&db, auth, id).await?;
+
+    let data = storage
+        .get(&resource.content_key(query.variant))
+        .await
+        .map_err(|error| {
+            error!(name: "err_storage_get", %error, "Failed to read resource");
+            ResourceError::Storage
+        })?
+        .ok_or(ResourceError::NotFound)?;
+
+    let cache_control = match resource.visibility {
+        ResourceVisibility::Public => "public, max-age=86400",
+        ResourceVisibility::Private => "private, max-age=3600",
+    };
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, resource.mime_type),
+            (header::CACHE_CONTROL, cache_control.to_string()),
+            (header::X_CONTENT_TYPE_OPTIONS, "nosniff".to_string()),
+        ],
+        data,
+    )
+        .into_response())
+}
+
+/// DELETE /resource/:id
+///
+/// Requests the deletion of a resource and its content, resources owned by
+/// an organization can also be deleted by the organization admins
+async fn delete_resource(
+    Auth(user): Auth,
+    Path(id): Path<ResourceId>,
+    Extension(db): Extension<DatabaseConnection>,
+    Extension(storage): Extension<SharedStorage>,
+) -> HttpResult<StatusCode> {
+    let resource = Resource::find_by_id(&db, id)
+        .await?
+        .ok_or(ResourceError::NotFound)?;
+
+    let allowed = resource.owner == user.id
+        || match resource.organization_id {
+            Some(organization) => OrganizationMember::find(&db, organization, user.id)
+                .await?
+                .is_some_and(|member| member.role.allows(OrgAction::ManageContent)),
+            None => false,
+        };
+
+    assert(allowed, ResourceError::MissingPermission)?;
+
+    let keys = resource.storage_keys();
+    resource.delete(&db).await?;
+
+    delete_content(storage.as_ref(), &keys).await;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Finds the resource with the provided `id` ensuring that it is visible
+/// to the authenticated user, resources that aren't visible are treated
+/// as missing
+async fn find_visible_resource(
+    db: &DatabaseConnection,
+    auth: Option<Auth>,
+    id: ResourceId,
+) -> HttpResult<Resource> {
+    let user = auth.map(|Auth(user)| user);
+    let resource = Resource::find_by_id(db, id)
+        .await?
+        .ok_or(ResourceError::NotFound)?;
+
+    if resource.is_visible_to(user.as_ref()) {
+        return Ok(resource);
+    }
+
+    // Private resources of an organization are visible to its members
+    let member = match (&user, resource.organization_id) {
+        (Some(user), Some(organization)) => {
+            OrganizationMember::find(db, organization, user.id).await?
+        }
+        _ => None,
+    };
+
+    assert(member.is_some(), ResourceError::NotFound)?;
+
+    Ok(resource)
+}
+
+/// Deletes the content stored under each of the provided `keys`,
+/// failures are logged rather than returned
+async fn delete_content(storage: &dyn Storage, keys: &[String]) {
+    for key in keys {
+        if let Err(error) = storage.delete(key).await {
+            error!(name: "err_storage_delete", %error, %key, "Failed to delete resource content");
+        }
+    }
+}
+
+/// Reads the content of an uploaded file field, rejecting files
+/// larger than [MAX_UPLOAD_SIZE]
+async fn read_file(field: &mut Field<'_>) -> Result<Bytes, ResourceError> {
+    let mut data: Vec<u8> = Vec::new();
+
+    while let Some(chunk) = field.chunk().await.map_err(multipart_error)? {
+        assert(
+            data.len() + chunk.len() <= MAX_UPLOAD_SIZE,
+            ResourceError::TooLarge,
+        )?;
+        data.extend_from_slice(&chunk);
+    }
+
+    Ok(Bytes::from(data))
+}
+
+/// Reads a text field, the text is trimmed and must be between
+/// 1 and `max_length` characters
+async fn read_text(
+    field: Field<'_>,
+    name: &'static str,
+    max_length: usize,
+) -> Result<String, ResourceError> {
+    let value = field.text().await.map_err(multipart_error)?;
+    let value = value.trim();
+    let length = value.chars().count();
+
+    assert(
+        length > 0 && length <= max_length,
+        ResourceError::InvalidField(name),
+    )?;
+
+    Ok(value.to_string())
+}
+
+/// Maps a multipart error, exceeding the body limit is reported as
+/// the file being too large
+fn multipart_error(err: MultipartError) -> ResourceError {
+    if err.status() == StatusCode::PAYLOAD_TOO_LARGE {
+        ResourceError::TooLarge
+    } else {
+        ResourceError::InvalidUpload
+    }
+}
@@ -0,0 +1,971 @@
+use crate::database::entities::organization::OrganizationId;
+use crate::database::entities::organization_domain::OrganizationDomain;
+use crate::database::entities::organization_member::{OrgRole, OrganizationMember};
+use crate::database::entities::scim_group::{ScimGroup, ScimGroupId};
+use crate::database::entities::scim_group_member::ScimGroupMember;
+use crate::database::entities::user::{CreateUser, User, UserId};
+use crate::http::middleware::query::ExtractQuery;
+use crate::http::middleware::scim::{ScimAuth, ScimJson};
+use crate::http::models::scim::*;
+use crate::services::auth::AuthService;
+use crate::utils::assert::assert;
+use crate::utils::types::{EmailAddress, Username};
+use axum::extract::Path;
+use axum::http::StatusCode;
+use axum::response::Response;
+use axum::routing::get;
+use axum::{Extension, Router};
+use sea_orm::{ConnectionTrait, DatabaseConnection, TransactionTrait};
+use serde_json::Value;
+use std::sync::Arc;
+
+/// Maximum number of resources included in a single page
+const MAX_PAGE_SIZE: u64 = 100;
+
+/// Defines the routes under the route group of /scim/v2
+pub fn routes() -> Router {
+    Router::new()
+        .route("/ServiceProviderConfig", get(service_provider_config))
+        .route("/Users", get(list_users).post(create_user))
+        .route(
+            "/Users/:id",
+            get(get_user)
+                .put(replace_user)
+                .patch(patch_user)
+                .delete(delete_user),
+        )
+        .route("/Groups", get(list_groups).post(create_group))
+        .route(
+            "/Groups/:id",
+            get(get_group)
+                .put(replace_group)
+                .patch(patch_group)
+                .delete(delete_group),
+        )
+}
+
+/// Validated details of a user from the directory
+struct UserDetails {
+    username: Username,
+    name: Option<String>,
+    email: EmailAddress,
+    active: bool,
+}
+
+/// GET /scim/v2/ServiceProviderConfig
+///
+/// Describes the SCIM features supported for directories
+async fn service_provider_config() -> ScimJson<ServiceProviderConfig> {
+    ScimJson(ServiceProviderConfig {
+        schemas: [SERVICE_PROVIDER_CONFIG_SCHEMA],
+        patch: Supported { supported: true },
+        bulk: BulkSupport {
+            supported: false,
+            max_operations: 0,
+            max_payload_size: 0,
+        },
+        filter: FilterSupport {
+            supported: true,
+            max_results: MAX_PAGE_SIZE,
+        },
+        change_password: Supported { supported: false },
+        sort: Supported { supported: false },
+        etag: Supported { supported: false },
+        authentication_schemes: [AuthenticationScheme {
+            kind: "oauthbearertoken",
+            name: "Bearer token",
+            description: "Token created from the SCIM settings of the organization",
+        }],
+    })
+}
+
+/// GET /scim/v2/Users
+///
+/// Requests the users provisioned by the organization, supports
+/// filtering by `userName eq "value"`
+async fn list_users(
+    ScimAuth(scim_token): ScimAuth,
+    Extension(db): Extension<DatabaseConnection>,
+    ExtractQuery(query): ExtractQuery<ScimListQuery>,
+) -> ScimResult<ScimJson<ScimListResponse<ScimUser>>> {
+    let username = username_filter(query.filter.as_deref())?;
+
+    let (start_index, offset, limit) = page(&query);
+    let (users, total) = User::find_managed(
+        &db,
+        scim_token.organization_id,
+        username.as_deref(),
+        offset,
+        limit,
+    )
+    .await?;
+
+    let users = users.into_iter().map(ScimUser::from).collect();
+
+    Ok(ScimJson(ScimListResponse::new(users, total, start_index)))
+}
+
+/// POST /scim/v2/Users
+///
+/// Requests the creation of a user managed by the organization, the
+/// user is added as a member of the organization
+async fn create_user(
+    ScimAuth(scim_token): ScimAuth,
+    Extension(db): Extension<DatabaseConnection>,
+    ScimJson(req): ScimJson<ScimUserRequest>,
+) -> ScimResult<Response> {
+    let details = user_details(&req)?;
+    let organization_id = scim_token.organization_id;
+
+    let user = db
+        .transaction(move |db| {
+            Box::pin(async move {
+                assert(
+                    !User::is_username_taken(db, &details.username).await?,
+                    ScimError::UsernameTaken,
+                )?;
+                assert(
+                    !User::is_email_taken(db, &details.email).await?,
+                    ScimError::EmailTaken,
+                )?;
+
+                let email_verified = OrganizationDomain::is_email_verified(
+                    db,
+                    organization_id,
+                    details.email.as_str(),
+                )
+                .await?;
+
+                // Provisioned users don't have a password, they login through
+                // the identity provider of the organization
+                let user = User::create_managed(
+                    db,
+                    CreateUser {
+                        email: details.email.into_inner(),
+                        username: details.username.into_inner(),
+                        name: details.name,
+                        password: String::new(),
+                    },
+                    organization_id,
+                    email_verified,
+                    details.active,
+                )
+                .await?;
+
+                OrganizationMember::create(db, organization_id, user.id, OrgRole::Member).await?;
+
+                Ok::<_, ScimError>(user)
+            })
+        })
+        .await?;
+
+    Ok(ScimJson(ScimUser::from(user)).into_response_with(StatusCode::CREATED))
+}
+
+/// GET /scim/v2/Users/:id
+///
+/// Requests a user provisioned by the organization
+async fn get_user(
+    ScimAuth(scim_token): ScimAuth,
+    Path(id): Path<UserId>,
+    Extension(db): Extension<DatabaseConnection>,
+) -> ScimResult<ScimJson<ScimUser>> {
+    let user = User::find_managed_by_id(&db, scim_token.organization_id, id)
+        .await?
+        .ok_or(ScimError::UserNotFound)?;
+
+    Ok(ScimJson(user.into()))
+}
+
+/// PUT /scim/v2/Users/:id
+///
+/// Requests that the details of a user provisioned by the organization
+/// be replaced, setting `active` to false deactivates the user
+async fn replace_user(
+    ScimAuth(scim_token): ScimAuth,
+    Path(id): Path<UserId>,
+    Extension(db): Extension<DatabaseConnection>,
+    Extension(auth): Extension<Arc<AuthService>>,
+    ScimJson(req): ScimJson<ScimUserRequest>,
+) -> ScimResult<ScimJson<ScimUser>> {
+    let user = User::find_managed_by_id(&db, scim_token.organization_id, id)
+        .await?
+        .ok_or(ScimError::UserNotFound)?;
+
+    let details = user_details(&req)?;
+    let user = update_user(&db, auth, scim_token.organization_id, user, details).await?;
+
+    Ok(ScimJson(user.into()))
+}
+
+/// PATCH /scim/v2/Users/:id
+///
+/// Requests changes to a user provisioned by the organization, changes
+/// to attributes that aren't stored are ignored
+async fn patch_user(
+    ScimAuth(scim_token): ScimAuth,
+    Path(id): Path<UserId>,
+    Extension(db): Extension<DatabaseConnection>,
+    Extension(auth): Extension<Arc<AuthService>>,
+    ScimJson(req): ScimJson<ScimPatchRequest>,
+) -> ScimResult<ScimJson<ScimUser>> {
+    let user = User::find_managed_by_id(&db, scim_token.organization_id, id)
+        .await?
+        .ok_or(ScimError::UserNotFound)?;
+
+    let mut patched = ScimUserRequest::from(&user);
+    patch_user_request(&mut patched, req.operations)?;
+
+    let details = user_details(&patched)?;
+    let user = update_user(&db, auth, scim_token.organization_id, user, details).await?;
+
+    Ok(ScimJson(user.into()))
+}
+
+/// DELETE /scim/v2/Users/:id
+///
+/// Requests that a user be deprovisioned, the user is deactivated and
+/// removed from the organization and its groups. The account and its
+/// content are kept but the organization no longer manages it
+async fn delete_user(
+    ScimAuth(scim_token): ScimAuth,
+    Path(id): Path<UserId>,
+    Extension(db): Extension<DatabaseConnection>,
+    Extension(auth): Extension<Arc<AuthService>>,
+) -> ScimResult<StatusCode> {
+    let organization_id = scim_token.organization_id;
+    let user = User::find_managed_by_id(&db, organization_id, id)
+        .await?
+        .ok_or(ScimError::UserNotFound)?;
+
+    let user = auth.set_user_active(&db, user, false).await?;
+
+    ScimGroupMember::remove_by_organization(&db, organization_id, user.id).await?;
+
+    if let Some(member) = OrganizationMember::find(&db, organization_id, user.id).await? {
+        // The organization must keep at least one owner
+        let last_owner = member.role == OrgRole::Owner
+            && OrganizationMember::count_owners(&db, organization_id).await? <= 1;
+
+        if !last_owner {
+            member.remove(&db).await?;
+        }
+    }
+
+    user.set_managed_by(&db, None).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// GET /scim/v2/Groups
+///
+/// Requests the groups of the organization, supports filtering
+/// by `displayName eq "value"`
+async fn list_groups(
+    ScimAuth(scim_token): ScimAuth,
+    Extension(db): Extension<DatabaseConnection>,
+    ExtractQuery(query): ExtractQuery<ScimListQuery>,
+) -> ScimResult<ScimJson<ScimListResponse<ScimGroupResponse>>> {
+    let display_name = query
+        .filter
+        .as_deref()
+        .map(|filter| parse_eq_filter(filter, "displayName"))
+        .transpose()?;
+
+    let (start_index, offset, limit) = page(&query);
+    let (groups, total) = ScimGroup::find_by_organization(
+        &db,
+        scim_token.organization_id,
+        display_name.as_deref(),
+        offset,
+        limit,
+    )
+    .await?;
+
+    let group_ids = groups.iter().map(|group| group.id).collect();
+    let members = ScimGroupMember::find_by_groups(&db, group_ids).await?;
+
+    let groups = groups
+        .into_iter()
+        .map(|group| ScimGroupResponse::new(group, &members))
+        .collect();
+
+    Ok(ScimJson(ScimListResponse::new(groups, total, start_index)))
+}
+
+/// POST /scim/v2/Groups
+///
+/// Requests the creation of a group within the organization, members
+/// must be users provisioned by the organization
+async fn create_group(
+    ScimAuth(scim_token): ScimAuth,
+    Extension(db): Extension<DatabaseConnection>,
+    ScimJson(req): ScimJson<ScimGroupRequest>,
+) -> ScimResult<Response> {
+    let organization_id = scim_token.organization_id;
+
+    let group = db
+        .transaction(move |db| {
+            Box::pin(async move {
+                assert(
+                    ScimGroup::find_by_name(db, organization_id, &req.display_name)
+                        .await?
+                        .is_none(),
+                    ScimError::GroupNameTaken,
+                )?;
+
+                let member_ids = find_members(db, organization_id, &req.members).await?;
+                let group =
+                    ScimGroup::create(db, organization_id, req.display_name, req.external_id)
+                        .await?;
+
+                for user_id in member_ids {
+                    ScimGroupMember::create(db, group.id, user_id).await?;
+                }
+
+                Ok::<_, ScimError>(group)
+            })
+        })
+        .await?;
+
+    let group = group_response(&db, group).await?;
+
+    Ok(ScimJson(group).into_response_with(StatusCode::CREATED))
+}
+
+/// GET /scim/v2/Groups/:id
+///
+/// Requests a group of the organization along with its members
+async fn get_group(
+    ScimAuth(scim_token): ScimAuth,
+    Path(id): Path<ScimGroupId>,
+    Extension(db): Extension<DatabaseConnection>,
+) -> ScimResult<ScimJson<ScimGroupResponse>> {
+    let group = ScimGroup::find_by_id(&db, scim_token.organization_id, id)
+        .await?
+        .ok_or(ScimError::GroupNotFound)?;
+
+    Ok(ScimJson(group_response(&db, group).await?))
+}
+
+/// PUT /scim/v2/Groups/:id
+///
+/// Requests that the name and members of a group be replaced
+async fn replace_group(
+    ScimAuth(scim_token): ScimAuth,
+    Path(id): Path<ScimGroupId>,
+    Extension(db): Extension<DatabaseConnection>,
+    ScimJson(req): ScimJson<ScimGroupRequest>,
+) -> ScimResult<ScimJson<ScimGroupResponse>> {
+    let organization_id = scim_token.organization_id;
+
+    let group = db
+        .transaction(move |db| {
+            Box::pin(async move {
+                let group = ScimGroup::find_by_id(db, organization_id, id)
+                    .await?
+                    .ok_or(ScimError::GroupNotFound)?;
+
+                let group = rename_group(db, group, req.display_name, req.external_id).await?;
+                let member_ids = find_members(db, organization_id, &req.members).await?;
+
+                ScimGroupMember::remove_all(db, group.id).await?;
+
+                for user_id in member_ids {
+                    ScimGroupMember::create(db, group.id, user_id).await?;
+                }
+
+                Ok::<_, ScimError>(group)
+            })
+        })
+        .await?;
+
+    Ok(ScimJson(group_response(&db, group).await?))
+}
+
+/// PATCH /scim/v2/Groups/:id
+///
+/// Requests changes to the name or members of a group, changes to
+/// attributes that aren't stored are ignored
+async fn patch_group(
+    ScimAuth(scim_token): ScimAuth,
+    Path(id): Path<ScimGroupId>,
+    Extension(db): Extension<DatabaseConnection>,
+    ScimJson(req): ScimJson<ScimPatchRequest>,
+) -> ScimResult<ScimJson<ScimGroupResponse>> {
+    let organization_id = scim_token.organization_id;
+
+    let group = db
+        .transaction(move |db| {
+            Box::pin(async move {
+                let mut group = ScimGroup::find_by_id(db, organization_id, id)
+                    .await?
+                    .ok_or(ScimError::GroupNotFound)?;
+
+                for operation in req.operations {
+                    let op = operation.kind()?;
+
+                    match operation.path {
+                        Some(path) => {
+                            group = patch_group_attribute(db, group, op, &path, operation.value)
+                                .await?;
+                        }
+                        // Operations without a path contain an object of the
+                        // attributes to change
+                        None => {
+                            let Some(Value::Object(values)) = operation.value else {
+                                return Err(ScimError::InvalidValue("value"));
+                            };
+
+                            for (path, value) in values {
+                                group = patch_group_attribute(db, group, op, &path, Some(value))
+                                    .await?;
+                            }
+                        }
+                    }
+                }
+
+                Ok::<_, ScimError>(group)
+            })
+        })
+        .await?;
+
+    Ok(ScimJson(group_response(&db, group).await?))
+}
+
+/// DELETE /scim/v2/Groups/:id
+///
+/// Requests that a group be removed, its members are not affected
+async fn delete_group(
+    ScimAuth(scim_token): ScimAuth,
+    Path(id): Path<ScimGroupId>,
+    Extension(db): Extension<DatabaseConnection>,
+) -> ScimResult<StatusCode> {
+    let group = ScimGroup::find_by_id(&db, scim_token.organization_id, id)
+        .await?
+        .ok_or(ScimError::GroupNotFound)?;
+
+    group.remove(&db).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Validates the user details provided by the directory, only the
+/// primary email address is used
+fn user_details(req: &ScimUserRequest) -> ScimResult<UserDetails> {
+    let username: Username = req
+        .user_name
+        .parse()
+        .map_err(|_| ScimError::InvalidValue("userName"))?;
+
+    let email: EmailAddress = req
+        .primary_email()
+        .ok_or(ScimError::InvalidValue("emails"))?
+        .parse()
+        .map_err(|_| ScimError::InvalidValue("emails"))?;
+
+    Ok(UserDetails {
+        username,
+        name: req.full_name(),
+        email,
+        active: req.active,
+    })
+}
+
+/// Replaces the details of the `user` with the `details` from the
+/// directory, deactivating or reactivating the user when `active`
+/// has changed
+async fn update_user(
+    db: &DatabaseConnection,
+    auth: Arc<AuthService>,
+    organization_id: OrganizationId,
+    user: User,
+    details: UserDetails,
+) -> ScimResult<User> {
+    let user = db
+        .transaction(move |db| {
+            Box::pin(async move {
+                if details.username.as_str() != user.username {
+                    assert(
+                        !User::is_username_taken(db, &details.username).await?,
+                        ScimError::UsernameTaken,
+                    )?;
+                }
+
+                let mut email_verified = false;
+
+                if details.email.as_str() != user.email {
+                    assert(
+                        !User::is_email_taken(db, &details.email).await?,
+                        ScimError::EmailTaken,
+                    )?;
+
+                    email_verified = OrganizationDomain::is_email_verified(
+                        db,
+                        organization_id,
+                        details.email.as_str(),
+                    )
+                    .await?;
+                }
+
+                let user = user
+                    .set_managed_details(
+                        db,
+                        details.username.into_inner(),
+                        details.name,
+                        details.email.into_inner(),
+                        email_verified,
+                    )
+                    .await?;
+
+                if is_active_changed(&user, details.active) {
+                    return Ok(auth.set_user_active(db, user, details.active).await?);
+                }
+
+                Ok::<_, ScimError>(user)
+            })
+        })
+        .await?;
+
+    Ok(user)
+}
+
+/// Whether the `active` state from the directory differs from the
+/// current state of the `user`
+fn is_active_changed(user: &User, active: bool) -> bool {
+    user.is_deactivated() == active
+}
+
+/// Applies the patch `operations` to the working copy of a user
+fn patch_user_request(
+    user: &mut ScimUserRequest,
+    operations: Vec<ScimPatchOperation>,
+) -> ScimResult<()> {
+    for operation in operations {
+        let op = operation.kind()?;
+
+        match operation.path {
+            Some(path) => patch_user_attribute(user, op, &path, operation.value)?,
+            // Operations without a path contain an object of the attributes to change
+            None => {
+                let Some(Value::Object(values)) = operation.value else {
+                    return Err(ScimError::InvalidValue("value"));
+                };
+
+                for (path, value) in values {
+                    patch_user_attribute(user, op, &path, Some(value))?;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Applies a patch operation to a single attribute of the `user`
+fn patch_user_attribute(
+    user: &mut ScimUserRequest,
+    op: PatchOp,
+    path: &str,
+    value: Option<Value>,
+) -> ScimResult<()> {
+    let path = path.to_ascii_lowercase();
+
+    // Attributes can be prefixed with the schema they belong to
+    let path = path
+        .strip_prefix(&format!("{}:", USER_SCHEMA.to_ascii_lowercase()))
+        .unwrap_or(&path);
+
+    if op == PatchOp::Remove {
+        // Only the name can be removed, the other attributes are required
+        if matches!(path, "displayname" | "name" | "name.formatted") {
+            user.display_name = None;
+            user.name = None;
+        }
+
+        return Ok(());
+    }
+
+    let Some(value) = value else {
+        return Err(ScimError::InvalidValue("value"));
+    };
+
+    match path {
+        "active" => {
+            // Some directories send booleans as strings
+            user.active = match &value {
+                Value::Bool(value) => *value,
+                Value::String(value) if value.eq_ignore_ascii_case("true") => true,
+                Value::String(value) if value.eq_ignore_ascii_case("false") => false,
+                _ => return Err(ScimError::InvalidValue("active")),
+            };
+        }
+        "username" => {
+            user.user_name = string_value(value).ok_or(ScimError::InvalidValue("userName"))?;
+        }
+        "displayname" | "name.formatted" => {
+            user.display_name =
+                Some(string_value(value).ok_or(ScimError::InvalidValue("displayName"))?);
+        }
+        "name" => {
+            user.name =
+                Some(serde_json::from_value(value).map_err(|_| ScimError::InvalidValue("name"))?);
+            user.display_name = None;
+        }
+        "emails" => {
+            user.emails =
+                serde_json::from_value(value).map_err(|_| ScimError::InvalidValue("emails"))?;
+        }
+        // Filtered email paths such as emails[type eq "work"].value
+        path if path.starts_with("emails[") && path.ends_with("].value") => {
+            user.emails = vec![ScimEmail {
+                value: string_value(value).ok_or(ScimError::InvalidValue("emails"))?,
+                primary: true,
+                kind: None,
+            }];
+        }
+        // Attributes that aren't stored are ignored
+        _ => {}
+    }
+
+    Ok(())
+}
+
+/// Applies a patch operation to a single attribute of the `group`
+async fn patch_group_attribute<C>(
+    db: &C,
+    group: ScimGroup,
+    op: PatchOp,
+    path: &str,
+    value: Option<Value>,
+) -> ScimResult<ScimGroup>
+where
+    C: ConnectionTrait,
+{
+    let path = path.to_ascii_lowercase();
+    let path = path
+        .strip_prefix(&format!("{}:", GROUP_SCHEMA.to_ascii_lowercase()))
+        .unwrap_or(&path);
+
+    match (path, op) {
+        ("displayname", PatchOp::Add | PatchOp::Replace) => {
+            let display_name = value
+                .and_then(string_value)
+                .ok_or(ScimError::InvalidValue("displayName"))?;
+            let external_id = group.external_id.clone();
+
+            rename_group(db, group, display_name, external_id).await
+        }
+        ("externalid", _) => {
+            let external_id = match op {
+                PatchOp::Remove => None,
+                _ => Some(
+                    value
+                        .and_then(string_value)
+                        .ok_or(ScimError::InvalidValue("externalId"))?,
+                ),
+            };
+            let display_name = group.display_name.clone();
+
+            Ok(group.set_details(db, display_name, external_id).await?)
+        }
+        ("members", PatchOp::Add | PatchOp::Replace) => {
+            let members: Vec<ScimMemberRef> = value
+                .map(serde_json::from_value)
+                .transpose()
+                .map_err(|_| ScimError::InvalidValue("members"))?
+                .unwrap_or_default();
+            let member_ids = find_members(db, group.organization_id, &members).await?;
+
+            if op == PatchOp::Replace {
+                ScimGroupMember::remove_all(db, group.id).await?;
+            }
+
+            for user_id in member_ids {
+                ScimGroupMember::create(db, group.id, user_id).await?;
+            }
+
+            Ok(group)
+        }
+        ("members", PatchOp::Remove) => {
+            match value {
+                Some(value) => {
+                    let members: Vec<ScimMemberRef> = serde_json::from_value(value)
+                        .map_err(|_| ScimError::InvalidValue("members"))?;
+
+                    for member in members {
+                        if let Ok(user_id) = member.value.parse::<UserId>() {
+                            ScimGroupMember::remove(db, group.id, user_id).await?;
+                        }
+                    }
+                }
+                // Removing without a value removes every member
+                None => {
+                    ScimGroupMember::remove_all(db, group.id).await?;
+                }
+            }
+
+            Ok(group)
+        }
+        // Filtered member paths such as members[value eq "1"]
+        (path, PatchOp::Remove) if path.starts_with("members[") && path.ends_with(']') => {
+            let filter = &path["members[".len()..path.len() - 1];
+            let user_id = parse_eq_filter(filter, "value")?;
+
+            if let Ok(user_id) = user_id.parse::<UserId>() {
+                ScimGroupMember::remove(db, group.id, user_id).await?;
+            }
+
+            Ok(group)
+        }
+        // Attributes that aren't stored are ignored
+        _ => Ok(group),
+    }
+}
+
+/// Changes the name and directory ID of the `group`, ensuring no other
+/// group within the organization has the name
+async fn rename_group<C>(
+    db: &C,
+    group: ScimGroup,
+    display_name: String,
+    external_id: Option<String>,
+) -> ScimResult<ScimGroup>
+where
+    C: ConnectionTrait,
+{
+    if display_name != group.display_name {
+        assert(
+            ScimGroup::find_by_name(db, group.organization_id, &display_name)
+                .await?
+                .is_none(),
+            ScimError::GroupNameTaken,
+        )?;
+    }
+
+    Ok(group.set_details(db, display_name, external_id).await?)
+}
+
+/// Finds the IDs of the group `members`, every member must be a
+/// user provisioned by the organization
+async fn find_members<C>(
+    db: &C,
+    organization_id: OrganizationId,
+    members: &[ScimMemberRef],
+) -> ScimResult<Vec<UserId>>
+where
+    C: ConnectionTrait,
+{
+    let mut user_ids = Vec::with_capacity(members.len());
+
+    for member in members {
+        let user_id = member
+            .value
+            .parse::<UserId>()
+            .map_err(|_| ScimError::InvalidMember(member.value.clone()))?;
+
+        User::find_managed_by_id(db, organization_id, user_id)
+            .await?
+            .ok_or_else(|| ScimError::InvalidMember(member.value.clone()))?;
+
+        user_ids.push(user_id);
+    }
+
+    Ok(user_ids)
+}
+
+/// Creates the response for the `group` including its members
+async fn group_response(
+    db: &DatabaseConnection,
+    group: ScimGroup,
+) -> ScimResult<ScimGroupResponse> {
+    let members = ScimGroupMember::find_by_groups(db, vec![group.id]).await?;
+
+    Ok(ScimGroupResponse::new(group, &members))
+}
+
+/// Parses the optional `userName eq "value"` filter used when listing
+/// users, usernames are stored in lowercase
+fn username_filter(filter: Option<&str>) -> ScimResult<Option<String>> {
+    let username = filter
+        .map(|filter| parse_eq_filter(filter, "userName"))
+        .transpose()?
+        .map(|username| username.to_lowercase());
+
+    Ok(username)
+}
+
+/// Parses a filter comparing the `attribute` to a value, the only
+/// filters supported are in the form `attribute eq "value"`
+fn parse_eq_filter(filter: &str, attribute: &'static str) -> ScimResult<String> {
+    let invalid = || ScimError::InvalidFilter(attribute);
+
+    let (name, rest) = filter
+        .trim()
+        .split_once(char::is_whitespace)
+        .ok_or_else(invalid)?;
+    let (op, value) = rest
+        .trim_start()
+        .split_once(char::is_whitespace)
+        .ok_or_else(invalid)?;
+
+    assert(
+        name.eq_ignore_ascii_case(attribute) && op.eq_ignore_ascii_case("eq"),
+        invalid(),
+    )?;
+
+    let value = value
+        .trim()
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+        .ok_or_else(invalid)?;
+
+    Ok(value.replace("\\\"", "\"").replace("\\\\", "\\"))
+}
+
+/// Converts the 1-based start index and count of the `query` into the
+/// start index to respond with along with the offset and limit
+fn page(query: &ScimListQuery) -> (u64, u64, u64) {
+    let start_index = query.start_index.unwrap_or(1).max(1);
+    let count = query.count.unwrap_or(MAX_PAGE_SIZE).min(MAX_PAGE_SIZE);
+
+    (start_index, start_index - 1, count)
+}
+
+/// Gets the string from a JSON string `value`
+fn string_value(value: Value) -> Option<String> {
+    match value {
+        Value::String(value) => Some(value),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{is_active_changed, patch_user_request, user_details, username_filter};
+    use crate::{
+        database::entities::user::User,
+        http::models::scim::{ScimError, ScimPatchRequest, ScimUserRequest},
+    };
+    use chrono::Utc;
+    use serde_json::json;
+
+    fn user(active: bool) -> User {
+        User {
+            email_verified_at: Some(Utc::now().naive_utc()),
+            deactivated_at: (!active).then(|| Utc::now().naive_utc()),
+            managed_by: Some(1),
+            ..User::test(1)
+        }
+    }
+
+    /// Applies the patch request `body` to the `user` responding with
+    /// the resulting active state
+    fn patch_active(user: &User, body: serde_json::Value) -> Result<bool, ScimError> {
+        let request: ScimPatchRequest = serde_json::from_value(body).unwrap();
+        let mut patched = ScimUserRequest::from(user);
+        patch_user_request(&mut patched, request.operations)?;
+        Ok(user_details(&patched)?.active)
+    }
+
+    /// Tests the userName filter used by directories to find existing users
+    #[test]
+    fn test_username_filter() {
+        assert_eq!(username_filter(None).unwrap(), None);
+        assert_eq!(
+            username_filter(Some(r#"userName eq "Jane""#)).unwrap(),
+            Some("jane".to_string())
+        );
+        assert_eq!(
+            username_filter(Some(r#"  username  EQ  "jane"  "#)).unwrap(),
+            Some("jane".to_string())
+        );
+        assert_eq!(
+            username_filter(Some(r#"userName eq "ja\"ne""#)).unwrap(),
+            Some("ja\"ne".to_string())
+        );
+    }
+
+    /// Tests that unsupported filters are rejected
+    #[test]
+    fn test_username_filter_invalid() {
+        for filter in [
+            r#"displayName eq "jane""#,
+            r#"userName co "jane""#,
+            r#"userName eq jane"#,
+            r#"userName eq "jane"#,
+            "userName",
+            "",
+        ] {
+            assert!(
+                matches!(
+                    username_filter(Some(filter)),
+                    Err(ScimError::InvalidFilter("userName"))
+                ),
+                "filter {filter:?} should be rejected"
+            );
+        }
+    }
+
+    /// Tests deprovisioning through a patch of the active attribute
+    #[test]
+    fn test_patch_deactivate() {
+        let user = user(true);
+
+        let active = patch_active(
+            &user,
+            json!({
+                "schemas": ["urn:ietf:params:scim:api:messages:2.0:PatchOp"],
+                "Operations": [{ "op": "replace", "path": "active", "value": false }]
+            }),
+        )
+        .unwrap();
+
+        assert!(!active);
+        assert!(is_active_changed(&user, active));
+    }
+
+    /// Tests the path-less patch form and string booleans used by some
+    /// directories to deprovision users
+    #[test]
+    fn test_patch_deactivate_without_path() {
+        let user = user(true);
+
+        let active = patch_active(
+            &user,
+            json!({ "Operations": [{ "op": "Replace", "value": { "active": "False" } }] }),
+        )
+        .unwrap();
+
+        assert!(!active);
+        assert!(is_active_changed(&user, active));
+    }
+
+    /// Tests that patches leave the active state alone unless it changes
+    #[test]
+    fn test_patch_active_unchanged() {
+        let active_user = user(true);
+        let active = patch_active(
+            &active_user,
+            json!({ "Operations": [{ "op": "replace", "path": "displayName", "value": "Jane" }] }),
+        )
+        .unwrap();
+        assert!(active);
+        assert!(!is_active_changed(&active_user, active));
+
+        let inactive_user = user(false);
+        assert!(!is_active_changed(&inactive_user, false));
+        assert!(is_active_changed(&inactive_user, true));
+    }
+
+    /// Tests that invalid active values are rejected
+    #[test]
+    fn test_patch_active_invalid() {
+        let result = patch_active(
+            &user(true),
+            json!({ "Operations": [{ "op": "replace", "path": "active", "value": "no" }] }),
+        );
+
+        assert!(matches!(result, Err(ScimError::InvalidValue("active"))));
+    }
+}
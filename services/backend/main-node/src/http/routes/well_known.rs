@@ -0,0 +1,22 @@
+use crate::services::auth::AuthService;
+use axum::http::header;
+use axum::response::IntoResponse;
+use axum::routing::get;
+use axum::{Extension, Json, Router};
+use std::sync::Arc;
+
+/// Defines the routes under the route group of /.well-known
+pub fn routes() -> Router {
+    Router::new().route("/jwks.json", get(get_jwks))
+}
+
+/// GET /.well-known/jwks.json
+///
+/// Requests the public keys that user tokens can be verified with
+async fn get_jwks(Extension(auth): Extension<Arc<AuthService>>) -> impl IntoResponse {
+    (
+        // Allow caching for a short time so rotated keys are picked up
+        [(header::CACHE_CONTROL, "public, max-age=300")],
+        Json(auth.jwks().clone()),
+    )
+}
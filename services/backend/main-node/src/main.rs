@@ -1,10 +1,13 @@
 use anyhow::Context;
 use axum::{routing::get, Extension, Router};
 use dotenvy::dotenv;
-use http::init_router;
+use http::{init_router, ProxyConfig};
 use sea_orm::DatabaseConnection;
-use services::auth::AuthService;
-use std::{error::Error, sync::Arc};
+use services::{
+    auth::AuthService, domain::DomainService, game::GameService, mail::MailService,
+    openid::OpenIdService, saml::SamlService, storage::SharedStorage, webauthn::WebauthnService,
+};
+use std::{error::Error, net::SocketAddr, sync::Arc};
 use tracing::{info, Level};
 
 pub mod database;
@@ -20,21 +23,46 @@ async fn main() -> Result<(), Box<dyn Error>> {
     utils::tracing::init_tracing()?;
 
     let authentication: Arc<AuthService> = services::auth::AuthService::new();
+    let proxy: ProxyConfig = ProxyConfig::from_env().context("Loading proxy config")?;
+    let domains: Arc<DomainService> = DomainService::new().context("Creating domain service")?;
+    let games: Arc<GameService> = GameService::new();
+    let mail: Arc<MailService> = MailService::new().context("Creating mail service")?;
+    let openid: Arc<OpenIdService> = OpenIdService::new().context("Creating OpenID service")?;
+    let saml: Arc<SamlService> = SamlService::new().context("Creating SAML service")?;
+    let webauthn: Arc<WebauthnService> =
+        WebauthnService::new().context("Creating WebAuthn service")?;
+    let storage: SharedStorage = services::storage::from_env().context("Creating storage")?;
     let db: DatabaseConnection = database::connect()
         .await
         .context("Connecting to database")?;
 
+    games
+        .cancel_abandoned_games(&db)
+        .await
+        .context("Cancelling abandoned games")?;
+
     let app = init_router()
         .layer(Extension(db))
-        .layer(Extension(authentication));
+        .layer(Extension(authentication))
+        .layer(Extension(domains))
+        .layer(Extension(games))
+        .layer(Extension(mail))
+        .layer(Extension(openid))
+        .layer(Extension(saml))
+        .layer(Extension(webauthn))
+        .layer(Extension(storage))
+        .layer(Extension(proxy));
 
     // run our app with hyper, listening globally on port 3000
     let listener = tokio::net::TcpListener::bind("0.0.0.0:3000")
         .await
         .context("Binding server listener")?;
-    axum::serve(listener, app)
-        .await
-        .context("Serving application")?;
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await
+    .context("Serving application")?;
 
     Ok(())
 }
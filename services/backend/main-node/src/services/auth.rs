@@ -1,94 +1,160 @@
 use crate::{
     database::entities::{
-        user::{User, UserId},
-        user_refresh_token::UserRefreshToken,
+        organization::OrganizationId,
+        revoked_token::RevokedToken,
+        scim_token::ScimToken,
+        user::{User, UserId, UserRole},
+        user_recovery_code::UserRecoveryCode,
+        user_refresh_token::{HashedRefreshToken, SessionDetails, SessionId, UserRefreshToken},
+        user_rotated_refresh_token::UserRotatedRefreshToken,
+        user_totp::UserTotp,
+    },
+    services::jwt::JwtKeys,
+    utils::{
+        env::require_env,
+        hashing::hash_token,
+        totp::{self, GeneratedSecret, SecretKey},
     },
-    utils::env::{require_env, require_env_prefixed},
 };
-use anyhow::Context;
-use chrono::{Duration, Utc};
-use futures::{stream::FuturesUnordered, FutureExt, StreamExt};
-use jsonwebtoken::{decode, Algorithm, DecodingKey, EncodingKey, Header, Validation};
-use moka::future::Cache;
-use openid::DiscoveredClient;
+use chrono::{DateTime, Duration, Utc};
+use jsonwebtoken::{decode, jwk::JwkSet, Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use moka::{future::Cache, Expiry};
 use rand::{
     distributions::{Alphanumeric, DistString},
     rngs::StdRng,
     SeedableRng,
 };
-use reqwest::Url;
-use sea_orm::{ConnectionTrait, DbErr, DeriveActiveEnum, Iterable};
+use sea_orm::{ConnectionTrait, DbErr, ModelTrait, TransactionTrait};
 use serde::{Deserialize, Serialize};
-use std::{ops::Add, sync::Arc};
-use strum::Display;
+use std::{ops::Add, sync::Arc, time::Instant};
 use thiserror::Error;
-use tracing::{debug, error};
+use tracing::warn;
 
 pub struct AuthService {
-    /// OpenID providers
-    providers: Cache<AuthProvider, SharedClient>,
-
-    /// Header for JWT tokens
-    jwt_header: Header,
-    /// Validation for JWT tokens
-    jwt_validation: Validation,
-    /// Key for encoding JWT tokens
-    encoding_key: EncodingKey,
-    /// Key for decoding JWT tokens
-    decoding_key: DecodingKey,
+    /// Secret used for deriving the keys of email tokens
+    email_token_secret: String,
+    /// Key used for hashing stored refresh tokens, recovery codes and
+    /// SCIM tokens
+    refresh_token_key: Vec<u8>,
+    /// Key used for encrypting stored TOTP secrets
+    totp_key: SecretKey,
+
+    /// Keys for signing and verifying user tokens
+    keys: JwtKeys,
+    /// Cache of whether token IDs have been revoked, backed by the
+    /// `revoked_tokens` table
+    revoked_tokens: Cache<String, bool>,
+    /// Cache of the sessions known to still exist and the user they belong
+    /// to, only briefly cached so sessions revoked on other nodes are
+    /// picked up
+    sessions: Cache<SessionId, UserId>,
+
+    /// Whether moderators and administrators must use two-factor
+    /// authentication
+    require_staff_mfa: bool,
+    /// Number of recent second factor attempts for each user that
+    /// haven't succeeded
+    mfa_attempts: Cache<UserId, u32>,
 }
 
-/// Alias for a OpenID client that is shared
-type SharedClient = Arc<DiscoveredClient>;
-
-/// Provider for authentication
-#[derive(
-    Debug,
-    Display,
-    Clone,
-    Copy,
-    Hash,
-    PartialEq,
-    Eq,
-    sea_orm::EnumIter,
-    Serialize,
-    Deserialize,
-    DeriveActiveEnum,
-)]
-#[sea_orm(rs_type = "String", db_type = "String(None)")]
-pub enum AuthProvider {
-    #[sea_orm(string_value = "GOOGLE")]
-    Google,
-    #[sea_orm(string_value = "MICROSOFT")]
-    Microsoft,
+/// Expiry policy for the revoked tokens cache, revoked tokens are kept
+/// for as long as they could be valid while tokens that aren't revoked
+/// are only briefly cached so revocations from other nodes are picked up
+struct RevokedTokenExpiry;
+
+impl Expiry<String, bool> for RevokedTokenExpiry {
+    fn expire_after_create(
+        &self,
+        _key: &String,
+        revoked: &bool,
+        _created_at: Instant,
+    ) -> Option<std::time::Duration> {
+        Some(if *revoked {
+            std::time::Duration::from_secs(AuthService::USER_TOKEN_EXPIRY_MINUTES as u64 * 60)
+        } else {
+            std::time::Duration::from_secs(30)
+        })
+    }
 }
 
-impl AuthProvider {
-    pub const SCOPES: &'static str = "openid profile email";
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UserClaims {
+    /// ID of the user this claim represents
+    #[serde(rename = "sub")]
+    pub user_id: UserId,
+    /// ID of the session the token was issued for
+    #[serde(rename = "sid")]
+    pub session_id: SessionId,
+    /// Unique ID of the token used for revoking it
+    pub jti: String,
+    /// Expiry time UTC timestamp
+    pub exp: i64,
+}
+
+const API_JWT_TOKEN_KEY: &str = "API_JWT_TOKEN_KEY";
+const REFRESH_TOKEN_HASH_KEY: &str = "REFRESH_TOKEN_HASH_KEY";
+const TOTP_ENCRYPTION_KEY: &str = "TOTP_ENCRYPTION_KEY";
+const REQUIRE_STAFF_MFA: &str = "REQUIRE_STAFF_MFA";
 
-    pub fn all() -> [AuthProvider; 2] {
-        [AuthProvider::Google, AuthProvider::Microsoft]
+/// Purposes for the signed tokens that are sent to users by email, each
+/// purpose signs using a different key so that a token can't be used
+/// for anything other than its purpose
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmailTokenPurpose {
+    /// Verifying the email address of an account
+    VerifyEmail,
+    /// Resetting the password of an account
+    ResetPassword,
+}
+
+impl EmailTokenPurpose {
+    fn name(&self) -> &'static str {
+        match self {
+            EmailTokenPurpose::VerifyEmail => "verify_email",
+            EmailTokenPurpose::ResetPassword => "reset_password",
+        }
     }
 
-    /// Environment variable prefix for this providers keys
-    pub fn env_prefix(&self) -> &'static str {
+    /// Duration the token is valid for
+    fn expiry(&self) -> Duration {
         match self {
-            AuthProvider::Google => "GOOGLE_OPENID",
-            AuthProvider::Microsoft => "MICROSOFT_OPENID",
+            EmailTokenPurpose::VerifyEmail => Duration::hours(24),
+            EmailTokenPurpose::ResetPassword => Duration::hours(1),
+        }
+    }
+
+    /// Value from the user that the token is bound to, the token becomes
+    /// invalid once this value changes. Reset tokens are bound to the
+    /// password hash making them single use
+    fn binding<'a>(&self, user: &'a User) -> &'a str {
+        match self {
+            EmailTokenPurpose::VerifyEmail => &user.email,
+            EmailTokenPurpose::ResetPassword => &user.password,
         }
     }
 }
 
+/// Claims of tokens signed with a key derived from the user they were
+/// issued for, used by email and MFA challenge tokens
 #[derive(Debug, Serialize, Deserialize)]
-pub struct UserClaims {
-    /// ID of the user this claim represents
+pub struct BoundTokenClaims {
+    /// ID of the user the token was issued for
     #[serde(rename = "sub")]
     pub user_id: UserId,
+    /// Unique ID of the token used for making it single use
+    jti: String,
     /// Expiry time UTC timestamp
     exp: i64,
 }
 
-const API_JWT_TOKEN_KEY: &str = "API_JWT_TOKEN_KEY";
+/// Pending login that requires a second factor, created by verifying
+/// the MFA challenge token from the login response
+pub struct MfaChallenge {
+    /// The user logging in
+    pub user: User,
+    /// Claims of the challenge token
+    claims: BoundTokenClaims,
+}
 
 #[derive(Serialize)]
 pub struct UserTokenData {
@@ -106,10 +172,32 @@ pub enum TokenError {
     Database(#[from] DbErr),
     #[error("Invalid refresh token")]
     InvalidRefreshToken,
+    #[error("Refresh token has expired")]
+    RefreshTokenExpired,
+    #[error("Refresh token was already used")]
+    RefreshTokenReused,
     #[error("Invalid token")]
     InvalidToken,
+    #[error("Token has been revoked")]
+    RevokedToken,
     #[error("Failed to create token")]
     CreateToken(#[from] jsonwebtoken::errors::Error),
+    #[error("Account has been deactivated or suspended")]
+    AccountDisabled,
+}
+
+#[derive(Debug, Error)]
+pub enum MfaError {
+    #[error(transparent)]
+    Database(#[from] DbErr),
+    #[error("Two-factor authentication is not enabled")]
+    NotEnabled,
+    #[error("Two-factor authentication is already enabled")]
+    AlreadyEnabled,
+    #[error("Invalid code")]
+    InvalidCode,
+    #[error("Too many attempts")]
+    TooManyAttempts,
 }
 
 impl AuthService {
@@ -117,67 +205,206 @@ impl AuthService {
     const USER_TOKEN_EXPIRY_MINUTES: i64 = 30;
     /// Length of refresh tokens
     const REFRESH_TOKEN_LENGTH: usize = 128;
+    /// Length of the unique ID of user tokens
+    const USER_TOKEN_ID_LENGTH: usize = 32;
+    /// Sessions expire if their refresh token isn't used for 7 days
+    const REFRESH_TOKEN_IDLE_DAYS: i64 = 7;
+    /// Sessions expire 30 days after logging in regardless of use
+    const REFRESH_TOKEN_LIFETIME_DAYS: i64 = 30;
+    /// MFA challenges must be completed within 5 minutes of logging in
+    const MFA_TOKEN_EXPIRY_MINUTES: i64 = 5;
+    /// Number of failed second factor attempts allowed before the user
+    /// must wait for the attempts to expire
+    const MAX_MFA_ATTEMPTS: u32 = 5;
+    /// Number of recovery codes generated for a user
+    const RECOVERY_CODE_COUNT: usize = 10;
+    /// Length of each recovery code
+    const RECOVERY_CODE_LENGTH: usize = 10;
+    /// Length of SCIM bearer tokens
+    const SCIM_TOKEN_LENGTH: usize = 64;
 
-    /// Creates the authentication service and initializes the
-    /// providers in the background
+    /// Creates the authentication service
     pub fn new() -> Arc<Self> {
-        let providers = Cache::builder()
-            // Refresh providers every 48 hours
-            .time_to_live(std::time::Duration::from_secs(60 * 60 * 48))
-            .initial_capacity(2)
-            .build();
+        let email_token_secret = require_env(API_JWT_TOKEN_KEY).unwrap();
+        let keys = JwtKeys::from_env(email_token_secret.as_bytes()).unwrap();
 
-        // Create JWT keys and header
-        let key = require_env(API_JWT_TOKEN_KEY).unwrap();
-        let key_bytes = key.as_bytes();
-        let encoding_key = EncodingKey::from_secret(key_bytes);
-        let decoding_key = DecodingKey::from_secret(key_bytes);
-        let jwt_header = Header::new(Algorithm::HS256);
-        let jwt_validation = Validation::new(Algorithm::HS256);
-
-        let service = Arc::new(Self {
-            providers,
-            encoding_key,
-            decoding_key,
-            jwt_header,
-            jwt_validation,
-        });
+        let revoked_tokens = Cache::builder().expire_after(RevokedTokenExpiry).build();
+        let sessions = Cache::builder()
+            .time_to_live(std::time::Duration::from_secs(30))
+            .support_invalidation_closures()
+            .build();
 
-        let init_service = service.clone();
+        let refresh_token_key = require_env(REFRESH_TOKEN_HASH_KEY).unwrap().into_bytes();
+        let totp_key = SecretKey::new(require_env(TOTP_ENCRYPTION_KEY).unwrap().as_bytes());
 
-        // Initial load of the auth providers ahead of time
-        tokio::spawn(async move {
-            _ = init_service.get_all_providers().await;
-        });
+        let require_staff_mfa = std::env::var(REQUIRE_STAFF_MFA).is_ok_and(|value| value == "true");
+        let mfa_attempts = Cache::builder()
+            // Failed attempts are forgotten once a challenge would expire
+            .time_to_live(std::time::Duration::from_secs(
+                Self::MFA_TOKEN_EXPIRY_MINUTES as u64 * 60,
+            ))
+            .build();
 
-        service
+        Arc::new(Self {
+            email_token_secret,
+            refresh_token_key,
+            totp_key,
+            keys,
+            revoked_tokens,
+            sessions,
+            require_staff_mfa,
+            mfa_attempts,
+        })
     }
 
-    /// Creates a JWT token for the user
+    /// Creates a new session for the user on the device described by
+    /// `details` and issues a JWT token for it
     pub async fn create_user_token<C>(
         &self,
         db: &C,
         user: &User,
+        details: SessionDetails,
+    ) -> Result<UserTokenData, TokenError>
+    where
+        C: ConnectionTrait,
+    {
+        if !user.can_login() {
+            return Err(TokenError::AccountDisabled);
+        }
+
+        // Create a refresh token for the session
+        let (refresh_token, hashed) = self.create_refresh_token(db).await?;
+        let session = UserRefreshToken::create(db, user, hashed, details).await?;
+
+        self.issue_user_token(&session, refresh_token)
+    }
+
+    /// Refreshes a user token using the provided `refresh_token`, the
+    /// session is given a new refresh token and the used token can't be
+    /// used again.
+    ///
+    /// Using a token that has already been rotated means the session was
+    /// likely stolen so the whole session is revoked
+    pub async fn refresh_user_token<C>(
+        &self,
+        db: &C,
+        refresh_token: &str,
+        ip_address: Option<String>,
     ) -> Result<UserTokenData, TokenError>
+    where
+        C: ConnectionTrait + TransactionTrait,
+    {
+        // Find the session for the token
+        let session =
+            match UserRefreshToken::find_by_token(db, &self.refresh_token_key, refresh_token)
+                .await?
+            {
+                Some(value) => value,
+                None => {
+                    let rotated = UserRotatedRefreshToken::find_by_token(
+                        db,
+                        &self.refresh_token_key,
+                        refresh_token,
+                    )
+                    .await?
+                    .ok_or(TokenError::InvalidRefreshToken)?;
+
+                    if let Some(session) =
+                        UserRefreshToken::find_by_id(db, rotated.session_id).await?
+                    {
+                        warn!(
+                            name: "refresh_token_reuse",
+                            session_id = session.id,
+                            user_id = session.user_id,
+                            ip_address = ?ip_address,
+                            "Rotated refresh token was reused, revoking session"
+                        );
+
+                        self.revoke_session(db, session).await?;
+                    }
+
+                    return Err(TokenError::RefreshTokenReused);
+                }
+            };
+
+        let now = Utc::now().naive_utc();
+        let expires_at = session.created_at + Duration::days(Self::REFRESH_TOKEN_LIFETIME_DAYS);
+        let idle_expires_at = session.last_used_at + Duration::days(Self::REFRESH_TOKEN_IDLE_DAYS);
+
+        if now > expires_at || now > idle_expires_at {
+            self.revoke_session(db, session).await?;
+            return Err(TokenError::RefreshTokenExpired);
+        }
+
+        let session_id = session.id;
+        let (refresh_token, hashed) = self.create_refresh_token(db).await?;
+
+        let txn = db.begin().await?;
+
+        // Another request using the same token got to it first
+        let session = session
+            .rotate(&txn, hashed, ip_address)
+            .await?
+            .ok_or(TokenError::InvalidRefreshToken)?;
+
+        // Tokens rotated before the idle lifetime are forgotten, replaying
+        // them is no longer detected but they remain invalid
+        UserRotatedRefreshToken::delete_before(
+            &txn,
+            session_id,
+            now - Duration::days(Self::REFRESH_TOKEN_IDLE_DAYS),
+        )
+        .await?;
+
+        txn.commit().await?;
+
+        self.issue_user_token(&session, refresh_token)
+    }
+
+    /// Finds the active sessions of the provided `user`, removing any
+    /// sessions that have expired
+    pub async fn get_user_sessions<C>(
+        &self,
+        db: &C,
+        user: &User,
+    ) -> Result<Vec<UserRefreshToken>, DbErr>
     where
         C: ConnectionTrait,
     {
+        let now = Utc::now().naive_utc();
+
+        UserRefreshToken::delete_expired(
+            db,
+            user,
+            now - Duration::days(Self::REFRESH_TOKEN_LIFETIME_DAYS),
+            now - Duration::days(Self::REFRESH_TOKEN_IDLE_DAYS),
+        )
+        .await?;
+
+        UserRefreshToken::find_by_user(db, user).await
+    }
+
+    /// Issues a JWT token for the provided `session` whose current refresh
+    /// token is `refresh_token`
+    fn issue_user_token(
+        &self,
+        session: &UserRefreshToken,
+        refresh_token: String,
+    ) -> Result<UserTokenData, TokenError> {
         let expiry = Utc::now()
             .add(Duration::minutes(Self::USER_TOKEN_EXPIRY_MINUTES))
             .timestamp();
 
         // Create the user token
-        let token = jsonwebtoken::encode(
-            &self.jwt_header,
-            &UserClaims {
-                user_id: user.id,
-                exp: expiry,
-            },
-            &self.encoding_key,
-        )?;
+        let jti =
+            Alphanumeric.sample_string(&mut StdRng::from_entropy(), Self::USER_TOKEN_ID_LENGTH);
 
-        // Create a refresh token
-        let refresh_token = Self::create_refresh_token(db, user).await?;
+        let token = self.keys.encode(&UserClaims {
+            user_id: session.user_id,
+            session_id: session.id,
+            jti,
+            exp: expiry,
+        })?;
 
         Ok(UserTokenData {
             token,
@@ -186,107 +413,736 @@ impl AuthService {
         })
     }
 
-    /// Refreshes a user token using the provided `refresh_token`
-    pub async fn refresh_user_token<C>(
+    /// Creates a refresh token with a unique identifier, returns the plain
+    /// text token for the client along with the hashed form to store
+    async fn create_refresh_token<C>(
         &self,
         db: &C,
-        refresh_token: &str,
-    ) -> Result<UserTokenData, TokenError>
+    ) -> Result<(String, HashedRefreshToken), TokenError>
     where
         C: ConnectionTrait,
     {
-        // Find the token data
-        let token = UserRefreshToken::find_by_token(db, refresh_token)
+        let mut rng = StdRng::from_entropy();
+
+        loop {
+            let token = Alphanumeric.sample_string(&mut rng, Self::REFRESH_TOKEN_LENGTH);
+            let hashed = HashedRefreshToken::new(&self.refresh_token_key, &token)
+                .ok_or(TokenError::InvalidRefreshToken)?;
+
+            // Check the identifier isn't already in use
+            if UserRefreshToken::find_by_token_id(db, &hashed.token_id)
+                .await?
+                .is_none()
+                && UserRotatedRefreshToken::find_by_token_id(db, &hashed.token_id)
+                    .await?
+                    .is_none()
+            {
+                return Ok((token, hashed));
+            }
+        }
+    }
+
+    /// Verifies the provided user token returning the associated user,
+    /// tokens that have been revoked are rejected
+    pub async fn verify_user_token<C>(&self, db: &C, token: &str) -> Result<UserClaims, TokenError>
+    where
+        C: ConnectionTrait,
+    {
+        let claims: UserClaims = self
+            .keys
+            .decode(token)
+            .map_err(|_| TokenError::InvalidToken)?;
+
+        if self.is_token_revoked(db, &claims.jti).await? {
+            return Err(TokenError::RevokedToken);
+        }
+
+        // Tokens stop working once their session has been revoked
+        if !self.is_session_active(db, &claims).await? {
+            return Err(TokenError::RevokedToken);
+        }
+
+        Ok(claims)
+    }
+
+    /// Checks whether the session the `claims` were issued for still exists,
+    /// checking the database when the session isn't cached
+    async fn is_session_active<C>(&self, db: &C, claims: &UserClaims) -> Result<bool, DbErr>
+    where
+        C: ConnectionTrait,
+    {
+        let user_id = match self.sessions.get(&claims.session_id).await {
+            Some(value) => value,
+            None => match UserRefreshToken::find_by_id(db, claims.session_id).await? {
+                Some(session) => {
+                    self.sessions.insert(session.id, session.user_id).await;
+                    session.user_id
+                }
+                None => return Ok(false),
+            },
+        };
+
+        Ok(user_id == claims.user_id)
+    }
+
+    /// Revokes the `session`, its refresh token and the user tokens issued
+    /// for it can no longer be used
+    pub async fn revoke_session<C>(&self, db: &C, session: UserRefreshToken) -> Result<(), DbErr>
+    where
+        C: ConnectionTrait,
+    {
+        let session_id = session.id;
+        session.delete(db).await?;
+        self.sessions.invalidate(&session_id).await;
+
+        Ok(())
+    }
+
+    /// Revokes all the sessions of the `user`, logging them out everywhere
+    pub async fn revoke_all_sessions<C>(&self, db: &C, user: &User) -> Result<(), DbErr>
+    where
+        C: ConnectionTrait,
+    {
+        UserRefreshToken::delete_by_user(db, user).await?;
+        self.forget_sessions(user.id);
+
+        Ok(())
+    }
+
+    /// Removes the cached sessions of the user with the `user_id`, used
+    /// once their sessions have been removed from the database
+    pub fn forget_sessions(&self, user_id: UserId) {
+        self.sessions
+            .invalidate_entries_if(move |_, value| *value == user_id)
+            .expect("Session cache must support invalidation closures");
+    }
+
+    /// Revokes the user token with the provided `claims` so it can't be
+    /// used again before it expires
+    pub async fn revoke_user_token<C>(&self, db: &C, claims: &UserClaims) -> Result<(), DbErr>
+    where
+        C: ConnectionTrait,
+    {
+        let expires_at = DateTime::from_timestamp(claims.exp, 0)
+            .unwrap_or_else(Utc::now)
+            .naive_utc();
+
+        RevokedToken::create(db, claims.jti.clone(), expires_at).await?;
+        self.revoked_tokens.insert(claims.jti.clone(), true).await;
+
+        // Clear out revoked tokens that no longer need denying
+        RevokedToken::delete_expired(db).await?;
+
+        Ok(())
+    }
+
+    /// Checks whether the token with the provided `jti` has been revoked,
+    /// checking the database when the result isn't cached
+    async fn is_token_revoked<C>(&self, db: &C, jti: &str) -> Result<bool, DbErr>
+    where
+        C: ConnectionTrait,
+    {
+        if let Some(revoked) = self.revoked_tokens.get(jti).await {
+            return Ok(revoked);
+        }
+
+        let revoked = RevokedToken::is_revoked(db, jti).await?;
+        self.revoked_tokens.insert(jti.to_string(), revoked).await;
+
+        Ok(revoked)
+    }
+
+    /// Whether the `user` must use two-factor authentication to login
+    pub fn is_mfa_required(&self, user: &User) -> bool {
+        self.require_staff_mfa && matches!(user.role, UserRole::Moderator | UserRole::Administrator)
+    }
+
+    /// Starts enrolling the `user` in TOTP two-factor authentication, the
+    /// generated secret isn't used until confirmed using
+    /// [AuthService::confirm_totp]
+    pub async fn begin_totp<C>(&self, db: &C, user: &User) -> Result<GeneratedSecret, MfaError>
+    where
+        C: ConnectionTrait,
+    {
+        if self.is_mfa_enabled(db, user).await? {
+            return Err(MfaError::AlreadyEnabled);
+        }
+
+        let generated = totp::generate_secret(&user.email);
+        let encrypted = self.totp_key.encrypt(user.id, &generated.secret);
+        UserTotp::create(db, user, encrypted).await?;
+
+        Ok(generated)
+    }
+
+    /// Confirms the pending TOTP secret of the `user` using a `code` from
+    /// their authenticator app, enabling two-factor authentication.
+    /// Returns the generated recovery codes
+    pub async fn confirm_totp<C>(
+        &self,
+        db: &C,
+        user: &User,
+        code: &str,
+    ) -> Result<Vec<String>, MfaError>
+    where
+        C: ConnectionTrait + TransactionTrait,
+    {
+        self.record_mfa_attempt(user).await?;
+
+        let totp = UserTotp::find_by_user(db, user)
             .await?
-            .ok_or(TokenError::InvalidRefreshToken)?;
+            .ok_or(MfaError::NotEnabled)?;
+
+        if totp.is_confirmed() {
+            return Err(MfaError::AlreadyEnabled);
+        }
+
+        let Some(step) = self
+            .decrypt_totp_secret(&totp)
+            .and_then(|secret| totp::verify_code(&secret, code, None))
+        else {
+            return Err(MfaError::InvalidCode);
+        };
+
+        let txn = db.begin().await?;
+        totp.set_confirmed(&txn, step).await?;
+        let recovery_codes = self.replace_recovery_codes(&txn, user).await?;
+        txn.commit().await?;
+
+        self.mfa_attempts.invalidate(&user.id).await;
 
-        // Find the user to refresh the token for
-        let user_id = token.user_id;
-        let user = User::find_by_id(db, user_id)
+        Ok(recovery_codes)
+    }
+
+    /// Verifies the second factor `code` of a `user` with two-factor
+    /// authentication enabled. The code can either be a TOTP code or one
+    /// of their recovery codes, recovery codes can only be used once
+    pub async fn verify_mfa_code<C>(&self, db: &C, user: &User, code: &str) -> Result<(), MfaError>
+    where
+        C: ConnectionTrait,
+    {
+        self.record_mfa_attempt(user).await?;
+
+        let totp = UserTotp::find_by_user(db, user)
             .await?
-            .ok_or(TokenError::InvalidRefreshToken)?;
+            .filter(|totp| totp.is_confirmed())
+            .ok_or(MfaError::NotEnabled)?;
+
+        let step = self
+            .decrypt_totp_secret(&totp)
+            .and_then(|secret| totp::verify_code(&secret, code, totp.last_used_step));
 
-        // Create the new token and refresh token
-        self.create_user_token(db, &user).await
+        let valid = match step {
+            // Marking the step as used fails if the code was used concurrently
+            Some(step) => totp.use_step(db, step).await?,
+            None => {
+                let code_hash = hash_token(&self.refresh_token_key, &normalize_recovery_code(code));
+                UserRecoveryCode::use_code(db, user, &code_hash).await?
+            }
+        };
+
+        if !valid {
+            return Err(MfaError::InvalidCode);
+        }
+
+        self.mfa_attempts.invalidate(&user.id).await;
+
+        Ok(())
+    }
+
+    /// Decrypts the stored TOTP secret, a secret that can't be decrypted
+    /// can't be used to verify codes
+    fn decrypt_totp_secret(&self, totp: &UserTotp) -> Option<String> {
+        let secret = self.totp_key.decrypt(totp.user_id, &totp.secret);
+        if secret.is_none() {
+            warn!(name: "err_decrypt_totp", user_id = totp.user_id, "Failed to decrypt TOTP secret");
+        }
+        secret
     }
 
-    /// Creates a unique refresh token for the provided `user`
-    async fn create_refresh_token<C>(db: &C, user: &User) -> Result<String, TokenError>
+    /// Whether the `user` has confirmed TOTP two-factor authentication
+    pub async fn is_mfa_enabled<C>(&self, db: &C, user: &User) -> Result<bool, DbErr>
+    where
+        C: ConnectionTrait,
+    {
+        Ok(UserTotp::find_by_user(db, user)
+            .await?
+            .is_some_and(|totp| totp.is_confirmed()))
+    }
+
+    /// Disables two-factor authentication for the `user` removing their
+    /// TOTP secret and recovery codes
+    pub async fn disable_mfa<C>(&self, db: &C, user: &User) -> Result<(), DbErr>
+    where
+        C: ConnectionTrait + TransactionTrait,
+    {
+        let txn = db.begin().await?;
+        UserTotp::delete_by_user(&txn, user).await?;
+        UserRecoveryCode::delete_by_user(&txn, user).await?;
+        txn.commit().await
+    }
+
+    /// Generates a new set of recovery codes for the `user` replacing
+    /// any existing codes, only the hashes of the codes are stored
+    pub async fn replace_recovery_codes<C>(&self, db: &C, user: &User) -> Result<Vec<String>, DbErr>
     where
         C: ConnectionTrait,
     {
         let mut rng = StdRng::from_entropy();
+        let codes: Vec<String> = (0..Self::RECOVERY_CODE_COUNT)
+            .map(|_| {
+                Alphanumeric
+                    .sample_string(&mut rng, Self::RECOVERY_CODE_LENGTH)
+                    .to_lowercase()
+            })
+            .collect();
 
-        loop {
-            let token = Alphanumeric.sample_string(&mut rng, Self::REFRESH_TOKEN_LENGTH);
+        let code_hashes = codes
+            .iter()
+            .map(|code| hash_token(&self.refresh_token_key, code))
+            .collect();
 
-            // Check the token isn't already in use
-            if UserRefreshToken::find_by_token(db, &token).await?.is_none() {
-                // Create the token
-                let token = UserRefreshToken::create(db, user, token).await?;
+        UserRecoveryCode::replace_all(db, user, code_hashes).await?;
 
-                return Ok(token.refresh_token);
-            }
+        Ok(codes)
+    }
+
+    /// Deactivates or reactivates the `user` based on `active`, all the
+    /// sessions of a deactivated user are removed and any user tokens
+    /// they still hold are rejected
+    pub async fn set_user_active<C>(&self, db: &C, user: User, active: bool) -> Result<User, DbErr>
+    where
+        C: ConnectionTrait + TransactionTrait,
+    {
+        let txn = db.begin().await?;
+
+        if !active {
+            UserRefreshToken::delete_by_user(&txn, &user).await?;
+        }
+
+        let user = user.set_active(&txn, active).await?;
+        txn.commit().await?;
+
+        if !active {
+            self.forget_sessions(user.id);
+        }
+
+        Ok(user)
+    }
+
+    /// Suspends or lifts the suspension of the `user` based on `suspended`,
+    /// all the sessions of a suspended user are removed and any user tokens
+    /// they still hold are rejected
+    pub async fn set_user_suspended<C>(
+        &self,
+        db: &C,
+        user: User,
+        suspended: bool,
+    ) -> Result<User, DbErr>
+    where
+        C: ConnectionTrait + TransactionTrait,
+    {
+        let txn = db.begin().await?;
+
+        if suspended {
+            UserRefreshToken::delete_by_user(&txn, &user).await?;
         }
+
+        let user = user.set_suspended(&txn, suspended).await?;
+        txn.commit().await?;
+
+        if suspended {
+            self.forget_sessions(user.id);
+        }
+
+        Ok(user)
     }
 
-    /// Verifies the provided user token returning the associated user
-    pub fn verify_user_token(&self, token: &str) -> Result<UserClaims, TokenError> {
-        let token_data: jsonwebtoken::TokenData<UserClaims> =
-            decode(token, &self.decoding_key, &self.jwt_validation)
-                .map_err(|_| TokenError::InvalidToken)?;
-        Ok(token_data.claims)
+    /// Forces the `user` to reset their password, the current password can
+    /// no longer be used to login and all the sessions of the user are
+    /// removed. The user regains password logins through the password
+    /// reset flow
+    pub async fn force_password_reset<C>(&self, db: &C, user: User) -> Result<User, DbErr>
+    where
+        C: ConnectionTrait + TransactionTrait,
+    {
+        let txn = db.begin().await?;
+        UserRefreshToken::delete_by_user(&txn, &user).await?;
+        let user = user.require_password_reset(&txn).await?;
+        txn.commit().await?;
+
+        self.forget_sessions(user.id);
+
+        Ok(user)
     }
 
-    /// Provides a collection of all available auth providers
-    pub async fn get_all_providers(&self) -> Vec<(AuthProvider, Option<SharedClient>)> {
-        AuthProvider::iter()
-            .map(|provider| {
-                self.get_provider(provider)
-                    .map(move |client| (provider, client))
+    /// Creates a new SCIM token named `name` for the organization, only
+    /// the hash of the token is stored so the plain text token returned
+    /// here can't be recovered later
+    pub async fn create_scim_token<C>(
+        &self,
+        db: &C,
+        organization_id: OrganizationId,
+        name: String,
+    ) -> Result<(ScimToken, String), DbErr>
+    where
+        C: ConnectionTrait,
+    {
+        let mut rng = StdRng::from_entropy();
+        let token = Alphanumeric.sample_string(&mut rng, Self::SCIM_TOKEN_LENGTH);
+        let token_hash = hash_token(&self.refresh_token_key, &token);
+
+        let scim_token = ScimToken::create(db, organization_id, name, token_hash).await?;
+
+        Ok((scim_token, token))
+    }
+
+    /// Finds the SCIM token matching the provided plain text `token`,
+    /// updating when the token was last used
+    pub async fn verify_scim_token<C>(
+        &self,
+        db: &C,
+        token: &str,
+    ) -> Result<Option<ScimToken>, DbErr>
+    where
+        C: ConnectionTrait,
+    {
+        let token_hash = hash_token(&self.refresh_token_key, token);
+
+        match ScimToken::find_by_hash(db, &token_hash).await? {
+            Some(scim_token) => scim_token.set_used(db).await.map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Records a second factor attempt for the `user`, refusing the attempt
+    /// when the allowed number of attempts has been exceeded. Attempts are
+    /// counted before the code is checked using an atomic update so that
+    /// concurrent attempts can't get past the limit, the count is cleared
+    /// once an attempt succeeds
+    async fn record_mfa_attempt(&self, user: &User) -> Result<(), MfaError> {
+        let attempts = self
+            .mfa_attempts
+            .entry(user.id)
+            .and_upsert_with(|entry| {
+                let attempts = entry.map(|entry| entry.into_value()).unwrap_or_default();
+                std::future::ready(attempts.saturating_add(1))
             })
-            // Collect the futures into a stream
-            .collect::<FuturesUnordered<_>>()
-            // Collect the future results
-            .collect()
             .await
+            .into_value();
+
+        if attempts > Self::MAX_MFA_ATTEMPTS {
+            return Err(MfaError::TooManyAttempts);
+        }
+
+        Ok(())
     }
 
-    /// Attempts to get the specified `provider` from the cache, will
-    /// initialize the provider if it is expired or not initialized
-    pub async fn get_provider(&self, provider: AuthProvider) -> Option<Arc<DiscoveredClient>> {
-        match self
-            .providers
-            .try_get_with(provider, Self::create_provider(provider))
+    /// Public keys that user tokens can be verified with
+    pub fn jwks(&self) -> &JwkSet {
+        self.keys.jwks()
+    }
+
+    /// Creates a signed token for the provided `purpose` that is sent to
+    /// the email address of the provided `user`
+    pub fn create_email_token(
+        &self,
+        purpose: EmailTokenPurpose,
+        user: &User,
+    ) -> Result<String, TokenError> {
+        let key = self.email_token_key(purpose, user);
+        Self::create_bound_token(&key, user, purpose.expiry())
+    }
+
+    /// Verifies the provided email `token` was created for the provided
+    /// `purpose` and is still valid, returning the user it was issued for
+    pub async fn verify_email_token<C>(
+        &self,
+        db: &C,
+        purpose: EmailTokenPurpose,
+        token: &str,
+    ) -> Result<User, TokenError>
+    where
+        C: ConnectionTrait,
+    {
+        Self::verify_bound_token(db, token, |user| self.email_token_key(purpose, user))
             .await
-        {
-            Ok(value) => Some(value),
-            Err(error) => {
-                error!(name: "err_initialize_provider", %provider, %error, "Failed to initialize auth provider");
-                None
-            }
+            .map(|(user, _)| user)
+    }
+
+    /// Creates a short lived token for completing the login of a `user`
+    /// that requires a second factor, the token proves the password was
+    /// already checked
+    pub fn create_mfa_token(&self, user: &User) -> Result<String, TokenError> {
+        let key = self.mfa_token_key(user);
+        Self::create_bound_token(
+            &key,
+            user,
+            Duration::minutes(Self::MFA_TOKEN_EXPIRY_MINUTES),
+        )
+    }
+
+    /// Verifies the provided MFA challenge `token` is still valid and
+    /// hasn't already been used to complete a login
+    pub async fn verify_mfa_token<C>(&self, db: &C, token: &str) -> Result<MfaChallenge, TokenError>
+    where
+        C: ConnectionTrait,
+    {
+        let (user, claims) =
+            Self::verify_bound_token(db, token, |user| self.mfa_token_key(user)).await?;
+
+        if self.is_token_revoked(db, &claims.jti).await? {
+            return Err(TokenError::RevokedToken);
         }
+
+        Ok(MfaChallenge { user, claims })
     }
 
-    /// Attempts to create a new provider for the provided [AuthProvider] type
-    /// returns the created client
-    #[tracing::instrument]
-    pub async fn create_provider(provider: AuthProvider) -> anyhow::Result<SharedClient> {
-        let env_prefix = provider.env_prefix();
+    /// Marks the `challenge` as used once the second factor has been
+    /// provided, each challenge can only complete a single login. Fails
+    /// when the challenge was already used by another request
+    pub async fn complete_mfa_challenge<C>(
+        &self,
+        db: &C,
+        challenge: &MfaChallenge,
+    ) -> Result<(), TokenError>
+    where
+        C: ConnectionTrait,
+    {
+        let expires_at = DateTime::from_timestamp(challenge.claims.exp, 0)
+            .unwrap_or_else(Utc::now)
+            .naive_utc();
 
-        let issuer: Url = require_env_prefixed(env_prefix, "ISSUER")?
-            .parse::<Url>()
-            .context("Parsing issuer URL")?;
-        let client_id = require_env_prefixed(env_prefix, "CLIENT_ID")?;
-        let client_secret = require_env_prefixed(env_prefix, "CLIENT_SECRET")?;
-        let redirect_url = require_env("OPENID_REDIRECT_URL")?;
+        if !RevokedToken::create(db, challenge.claims.jti.clone(), expires_at).await? {
+            return Err(TokenError::RevokedToken);
+        }
+
+        self.revoked_tokens
+            .insert(challenge.claims.jti.clone(), true)
+            .await;
+
+        Ok(())
+    }
+
+    /// Creates a token for the `user` signed with a `key` derived from
+    /// the user, the token expires after `expiry`
+    fn create_bound_token(key: &[u8], user: &User, expiry: Duration) -> Result<String, TokenError> {
+        let expiry = Utc::now().add(expiry).timestamp();
+        let key = EncodingKey::from_secret(key);
+        let jti =
+            Alphanumeric.sample_string(&mut StdRng::from_entropy(), Self::USER_TOKEN_ID_LENGTH);
+
+        let token = jsonwebtoken::encode(
+            &Header::new(Algorithm::HS256),
+            &BoundTokenClaims {
+                user_id: user.id,
+                jti,
+                exp: expiry,
+            },
+            &key,
+        )?;
+
+        Ok(token)
+    }
+
+    /// Verifies a token created by [AuthService::create_bound_token] using
+    /// the key from `key_for` returning the user it was issued for along
+    /// with the token claims
+    async fn verify_bound_token<C, F>(
+        db: &C,
+        token: &str,
+        key_for: F,
+    ) -> Result<(User, BoundTokenClaims), TokenError>
+    where
+        C: ConnectionTrait,
+        F: FnOnce(&User) -> Vec<u8>,
+    {
+        // The signing key depends on the user so the unverified subject
+        // is used to find the user before the signature is checked
+        let mut unverified_validation = Validation::new(Algorithm::HS256);
+        unverified_validation.insecure_disable_signature_validation();
+        let unverified: jsonwebtoken::TokenData<BoundTokenClaims> = decode(
+            token,
+            &DecodingKey::from_secret(&[]),
+            &unverified_validation,
+        )
+        .map_err(|_| TokenError::InvalidToken)?;
 
-        let client = DiscoveredClient::discover(client_id, client_secret, redirect_url, issuer)
+        let user = User::find_by_id(db, unverified.claims.user_id)
+            .await?
+            .ok_or(TokenError::InvalidToken)?;
+
+        let key = DecodingKey::from_secret(&key_for(&user));
+        let verified = decode::<BoundTokenClaims>(token, &key, &Validation::new(Algorithm::HS256))
+            .map_err(|_| TokenError::InvalidToken)?;
+
+        Ok((user, verified.claims))
+    }
+
+    /// Derives the signing key for MFA challenge tokens for the provided
+    /// `user`, the key is bound to the password hash so changing the
+    /// password invalidates pending challenges
+    fn mfa_token_key(&self, user: &User) -> Vec<u8> {
+        format!(
+            "{}:mfa_challenge:{}",
+            self.email_token_secret, user.password
+        )
+        .into_bytes()
+    }
+
+    /// Derives the signing key for email tokens of the provided `purpose`
+    /// for the provided `user`, keeping them distinct from user tokens
+    /// and each other
+    fn email_token_key(&self, purpose: EmailTokenPurpose, user: &User) -> Vec<u8> {
+        format!(
+            "{}:{}:{}",
+            self.email_token_secret,
+            purpose.name(),
+            purpose.binding(user)
+        )
+        .into_bytes()
+    }
+}
+
+/// Normalizes a recovery code entered by a user, codes are matched
+/// ignoring case and any separators
+fn normalize_recovery_code(code: &str) -> String {
+    code.chars()
+        .filter(|value| value.is_ascii_alphanumeric())
+        .map(|value| value.to_ascii_lowercase())
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{AuthService, BoundTokenClaims, MfaError, RevokedTokenExpiry, UserClaims};
+    use crate::{
+        database::entities::user::{User, UserRole},
+        services::jwt::JwtKeys,
+        utils::totp::SecretKey,
+    };
+    use jsonwebtoken::{decode, Algorithm, DecodingKey, Validation};
+    use moka::future::Cache;
+    use sea_orm::DatabaseConnection;
+
+    fn test_service() -> AuthService {
+        AuthService {
+            email_token_secret: "test".to_string(),
+            refresh_token_key: b"test".to_vec(),
+            totp_key: SecretKey::new(b"test"),
+            keys: JwtKeys::from_secret(b"test"),
+            revoked_tokens: Cache::builder().expire_after(RevokedTokenExpiry).build(),
+            sessions: Cache::builder().support_invalidation_closures().build(),
+            require_staff_mfa: true,
+            mfa_attempts: Cache::builder().build(),
+        }
+    }
+
+    fn test_user() -> User {
+        User {
+            role: UserRole::Moderator,
+            ..User::test(1)
+        }
+    }
+
+    fn decode_mfa_token(auth: &AuthService, user: &User, token: &str) -> Option<BoundTokenClaims> {
+        let key = DecodingKey::from_secret(&auth.mfa_token_key(user));
+        decode::<BoundTokenClaims>(token, &key, &Validation::new(Algorithm::HS256))
+            .ok()
+            .map(|value| value.claims)
+    }
+
+    /// Concurrent attempts must not be able to get past the limit
+    #[tokio::test]
+    async fn test_mfa_attempts_limited() {
+        let auth = test_service();
+        let user = test_user();
+
+        let results = futures::future::join_all(
+            (0..AuthService::MAX_MFA_ATTEMPTS * 2).map(|_| auth.record_mfa_attempt(&user)),
+        )
+        .await;
+
+        let allowed = results.iter().filter(|result| result.is_ok()).count();
+        assert_eq!(allowed, AuthService::MAX_MFA_ATTEMPTS as usize);
+        assert!(matches!(
+            auth.record_mfa_attempt(&user).await,
+            Err(MfaError::TooManyAttempts)
+        ));
+    }
+
+    /// Each MFA challenge token has its own ID so that using one to
+    /// complete a login doesn't affect other challenges
+    #[test]
+    fn test_mfa_token_unique_id() {
+        let auth = test_service();
+        let user = test_user();
+
+        let first = auth.create_mfa_token(&user).unwrap();
+        let second = auth.create_mfa_token(&user).unwrap();
+
+        let first = decode_mfa_token(&auth, &user, &first).unwrap();
+        let second = decode_mfa_token(&auth, &user, &second).unwrap();
+
+        assert_eq!(first.user_id, user.id);
+        assert!(!first.jti.is_empty());
+        assert_ne!(first.jti, second.jti);
+    }
+
+    /// MFA challenge tokens stop working once the password changes
+    #[test]
+    fn test_mfa_token_bound_to_password() {
+        let auth = test_service();
+        let user = test_user();
+        let token = auth.create_mfa_token(&user).unwrap();
+
+        let changed = User {
+            password: "changed".to_string(),
+            ..user
+        };
+
+        assert!(decode_mfa_token(&auth, &changed, &token).is_none());
+    }
+
+    fn test_claims(user_id: i32, session_id: i32) -> UserClaims {
+        UserClaims {
+            user_id,
+            session_id,
+            jti: "test".to_string(),
+            exp: 0,
+        }
+    }
+
+    /// Cached sessions are only valid for the user they belong to
+    #[tokio::test]
+    async fn test_session_active_cached() {
+        let auth = test_service();
+        let db = DatabaseConnection::Disconnected;
+        auth.sessions.insert(1, 1).await;
+
+        assert!(auth
+            .is_session_active(&db, &test_claims(1, 1))
+            .await
+            .unwrap());
+        assert!(!auth
+            .is_session_active(&db, &test_claims(2, 1))
             .await
-            .context("Failed to initialize OpenID client")?;
+            .unwrap());
+    }
+
+    /// Revoking all the sessions of a user forgets only their sessions
+    #[tokio::test]
+    async fn test_forget_sessions() {
+        let auth = test_service();
+        auth.sessions.insert(1, 1).await;
+        auth.sessions.insert(2, 1).await;
+        auth.sessions.insert(3, 2).await;
 
-        debug!(name: "start_auth_provider", %provider, "Started auth provider");
+        auth.forget_sessions(1);
+        auth.sessions.run_pending_tasks().await;
 
-        Ok(Arc::new(client))
+        assert!(auth.sessions.get(&1).await.is_none());
+        assert!(auth.sessions.get(&2).await.is_none());
+        assert_eq!(auth.sessions.get(&3).await, Some(2));
     }
 }
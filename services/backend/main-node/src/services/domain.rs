@@ -0,0 +1,140 @@
+//! Service for verifying that an organization controls an email domain
+//! through a DNS TXT record containing its verification token
+
+use anyhow::Context;
+use hickory_resolver::{error::ResolveErrorKind, TokioAsyncResolver};
+use rand::{
+    distributions::{Alphanumeric, DistString},
+    rngs::StdRng,
+    SeedableRng,
+};
+use std::sync::Arc;
+use thiserror::Error;
+use tracing::warn;
+
+/// Subdomain the verification record is published under
+const RECORD_PREFIX: &str = "_quizler-verification";
+/// Prefix of the verification record value before the token
+const VALUE_PREFIX: &str = "quizler-verification=";
+/// Length of the verification tokens
+const TOKEN_LENGTH: usize = 32;
+
+pub struct DomainService {
+    /// Resolver for looking up the verification records
+    resolver: TokioAsyncResolver,
+}
+
+/// Failure to check the verification record of a domain
+#[derive(Debug, Error)]
+#[error("Failed to lookup verification record")]
+pub struct LookupError;
+
+impl DomainService {
+    /// Creates the domain service using the DNS configuration of the system
+    pub fn new() -> anyhow::Result<Arc<Self>> {
+        let resolver =
+            TokioAsyncResolver::tokio_from_system_conf().context("Loading DNS configuration")?;
+
+        Ok(Arc::new(Self { resolver }))
+    }
+
+    /// Creates a new random verification token
+    pub fn create_token() -> String {
+        Alphanumeric.sample_string(&mut StdRng::from_entropy(), TOKEN_LENGTH)
+    }
+
+    /// Provides the name of the TXT record that must be created to
+    /// verify the `domain`
+    pub fn record_name(domain: &str) -> String {
+        format!("{RECORD_PREFIX}.{domain}")
+    }
+
+    /// Provides the value the TXT record must contain to verify a
+    /// domain with the provided `token`
+    pub fn record_value(token: &str) -> String {
+        format!("{VALUE_PREFIX}{token}")
+    }
+
+    /// Checks whether the `domain` has published a verification record
+    /// containing the provided `token`
+    pub async fn verify(&self, domain: &str, token: &str) -> Result<bool, LookupError> {
+        let expected = Self::record_value(token);
+
+        let lookup = match self.resolver.txt_lookup(Self::record_name(domain)).await {
+            Ok(value) => value,
+            // Missing records are expected until the record is created
+            Err(error) if matches!(error.kind(), ResolveErrorKind::NoRecordsFound { .. }) => {
+                return Ok(false)
+            }
+            Err(error) => {
+                warn!(name: "err_domain_lookup", %domain, %error, "Failed to lookup domain verification record");
+                return Err(LookupError);
+            }
+        };
+
+        let verified = lookup
+            .iter()
+            .any(|record| record_matches(record.iter().map(|data| &**data), &expected));
+
+        Ok(verified)
+    }
+}
+
+/// Checks whether the TXT record made up of the provided `parts` has
+/// the `expected` value, long records are split into multiple strings
+fn record_matches<'a>(parts: impl Iterator<Item = &'a [u8]>, expected: &str) -> bool {
+    let value: Vec<u8> = parts.flat_map(|data| data.iter().copied()).collect();
+    value == expected.as_bytes()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{record_matches, DomainService, TOKEN_LENGTH};
+
+    fn matches(parts: &[&str], token: &str) -> bool {
+        record_matches(
+            parts.iter().map(|part| part.as_bytes()),
+            &DomainService::record_value(token),
+        )
+    }
+
+    /// Tests the record name and value the organization must publish
+    #[test]
+    fn test_record() {
+        assert_eq!(
+            DomainService::record_name("example.com"),
+            "_quizler-verification.example.com"
+        );
+        assert_eq!(
+            DomainService::record_value("token"),
+            "quizler-verification=token"
+        );
+    }
+
+    /// Tests that tokens are random alphanumeric strings
+    #[test]
+    fn test_create_token() {
+        let token = DomainService::create_token();
+
+        assert_eq!(token.len(), TOKEN_LENGTH);
+        assert!(token.chars().all(|value| value.is_ascii_alphanumeric()));
+        assert_ne!(token, DomainService::create_token());
+    }
+
+    /// Tests that only records with exactly the expected value match
+    #[test]
+    fn test_record_matches() {
+        assert!(matches(&["quizler-verification=token"], "token"));
+        assert!(!matches(&["quizler-verification=other"], "token"));
+        assert!(!matches(&["quizler-verification=token2"], "token"));
+        assert!(!matches(&["token"], "token"));
+        assert!(!matches(&[], "token"));
+    }
+
+    /// Tests that records split into multiple strings are joined
+    #[test]
+    fn test_record_split() {
+        assert!(matches(&["quizler-verif", "ication=to", "ken"], "token"));
+        assert!(!matches(&["quizler-verification=", "other"], "token"));
+    }
+}
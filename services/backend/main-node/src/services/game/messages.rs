@@ -0,0 +1,189 @@
+//! Messages exchanged with clients over the game WebSocket
+
+use crate::database::{
+    entities::resource::ResourceId,
+    models::quiz::{Question, QuestionKind},
+};
+use serde::{Deserialize, Serialize};
+
+use super::GameError;
+
+/// Unique ID for a player within a game
+pub type PlayerId = u32;
+
+/// Messages sent from clients to the server
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type")]
+pub enum ClientMessage {
+    /// Connect as the host of a game, must be the first message
+    Host {
+        /// The user access token of the host
+        token: String,
+        /// The join code of the game
+        code: String,
+    },
+    /// Join a game as a player, must be the first message
+    Join {
+        /// The join code of the game
+        code: String,
+        /// The display name to use
+        name: String,
+    },
+    /// Host request to start the game
+    Start,
+    /// Host request to move on to the next step of the game
+    Next,
+    /// Host request to remove a player from the game
+    Kick { player: PlayerId },
+    /// Player answer to the current question
+    Answer { answer: PlayerAnswer },
+}
+
+/// Answer provided by a player, indexes refer to the order the
+/// options were presented in
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type")]
+pub enum PlayerAnswer {
+    Single { answer: usize },
+    Multiple { answers: Vec<usize> },
+    TrueFalse { answer: bool },
+    Typed { answer: String },
+    Ordering { order: Vec<usize> },
+}
+
+/// Messages sent from the server to clients
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type")]
+pub enum ServerMessage {
+    /// Host has connected to the game
+    HostConnected {
+        code: String,
+        title: String,
+        players: Vec<PlayerSummary>,
+    },
+    /// Player has joined the game
+    Joined {
+        id: PlayerId,
+        code: String,
+        title: String,
+    },
+    /// Another player joined the game
+    PlayerJoined { player: PlayerSummary },
+    /// Another player left the game
+    PlayerLeft { id: PlayerId },
+    /// The player was removed from the game by the host
+    Kicked,
+    /// A question has started
+    Question {
+        index: usize,
+        total: usize,
+        question: QuestionView,
+    },
+    /// The answer from the player was accepted
+    AnswerAccepted,
+    /// Number of players that have answered the current question
+    AnswerCount { answered: usize, total: usize },
+    /// Result of the current question for a player
+    QuestionResult {
+        correct: bool,
+        points: u32,
+        score: u32,
+        position: usize,
+    },
+    /// Summary of the current question for the host
+    QuestionSummary {
+        index: usize,
+        question: Question,
+        leaderboard: Vec<LeaderboardEntry>,
+    },
+    /// The game has finished
+    Finished { leaderboard: Vec<LeaderboardEntry> },
+    /// The game was ended early
+    Cancelled,
+    /// An error occurred
+    Error { name: &'static str, message: String },
+}
+
+impl ServerMessage {
+    /// Whether the connection should be closed after sending this message
+    pub fn is_terminal(&self) -> bool {
+        matches!(
+            self,
+            ServerMessage::Kicked | ServerMessage::Finished { .. } | ServerMessage::Cancelled
+        )
+    }
+}
+
+impl From<GameError> for ServerMessage {
+    fn from(value: GameError) -> Self {
+        ServerMessage::Error {
+            name: value.name(),
+            message: value.to_string(),
+        }
+    }
+}
+
+/// Public details about a player
+#[derive(Debug, Clone, Serialize)]
+pub struct PlayerSummary {
+    pub id: PlayerId,
+    pub name: String,
+}
+
+/// Entry on the game leaderboard
+#[derive(Debug, Clone, Serialize)]
+pub struct LeaderboardEntry {
+    pub id: PlayerId,
+    pub name: String,
+    pub score: u32,
+}
+
+/// View of a question shown to players with the correct
+/// answers removed
+#[derive(Debug, Clone, Serialize)]
+pub struct QuestionView {
+    pub text: String,
+    pub image: Option<ResourceId>,
+    pub time_limit: u32,
+    pub points: u32,
+    #[serde(flatten)]
+    pub kind: QuestionViewKind,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type")]
+pub enum QuestionViewKind {
+    Single { answers: Vec<String> },
+    Multiple { answers: Vec<String> },
+    TrueFalse,
+    Typed,
+    Ordering { items: Vec<String> },
+}
+
+impl QuestionView {
+    /// Creates a view of the provided `question`, ordering questions have
+    /// their items presented in the provided `order`
+    pub fn new(question: &Question, order: &[usize]) -> Self {
+        let kind = match &question.kind {
+            QuestionKind::Single { answers } => QuestionViewKind::Single {
+                answers: answers.iter().map(|answer| answer.text.clone()).collect(),
+            },
+            QuestionKind::Multiple { answers } => QuestionViewKind::Multiple {
+                answers: answers.iter().map(|answer| answer.text.clone()).collect(),
+            },
+            QuestionKind::TrueFalse { .. } => QuestionViewKind::TrueFalse,
+            QuestionKind::Typed { .. } => QuestionViewKind::Typed,
+            QuestionKind::Ordering { items } => QuestionViewKind::Ordering {
+                items: order.iter().map(|index| items[*index].clone()).collect(),
+            },
+        };
+
+        Self {
+            text: question.text.clone(),
+            image: question.image,
+            time_limit: question.time_limit,
+            points: question.points,
+            kind,
+        }
+    }
+}
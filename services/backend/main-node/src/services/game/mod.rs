@@ -0,0 +1,243 @@
+//! Service for hosting live games of published quizzes, each game runs
+//! within its own task which clients communicate with through a [GameHandle]
+
+use crate::database::entities::{
+    active_quiz::{ActiveQuiz, ActiveQuizId},
+    quiz::Quiz,
+    user::{User, UserId},
+};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use sea_orm::{DatabaseConnection, DbErr};
+use std::{collections::HashMap, sync::Arc};
+use thiserror::Error;
+use tokio::sync::{mpsc, oneshot, RwLock};
+use tracing::{debug, info};
+
+use self::{
+    messages::{PlayerAnswer, PlayerId, ServerMessage},
+    session::Game,
+};
+
+pub mod messages;
+mod session;
+pub mod socket;
+
+/// Code used by players to join a game
+pub type GameCode = String;
+
+/// Sender for messages to a connected client
+pub type MessageSender = mpsc::UnboundedSender<ServerMessage>;
+
+pub struct GameService {
+    /// Games that are currently running, keyed by their join code
+    games: RwLock<HashMap<GameCode, GameHandle>>,
+}
+
+#[derive(Debug, Error)]
+pub enum GameError {
+    #[error("No game exists with that code")]
+    UnknownGame,
+    #[error("The game has already started")]
+    AlreadyStarted,
+    #[error("The game is full")]
+    GameFull,
+    #[error("That name is already in use")]
+    NameTaken,
+    #[error("Names must be between 1 and 24 characters")]
+    InvalidName,
+    #[error("You are not the host of this game")]
+    NotHost,
+    #[error("The host is already connected")]
+    HostConnected,
+    #[error("At least one player must join before starting")]
+    NoPlayers,
+    #[error("There is no question to answer")]
+    NoQuestion,
+    #[error("You have already answered this question")]
+    AlreadyAnswered,
+    #[error("Answer does not match the question")]
+    InvalidAnswer,
+    #[error("Unexpected message")]
+    UnexpectedMessage,
+    #[error("Invalid token")]
+    InvalidToken,
+    #[error("Your account has been disabled")]
+    AccountDisabled,
+}
+
+impl GameError {
+    /// Names for each of the error types, used for handling
+    /// specific errors on the client
+    pub fn name(&self) -> &'static str {
+        match self {
+            GameError::UnknownGame => "game:unknown_game",
+            GameError::AlreadyStarted => "game:already_started",
+            GameError::GameFull => "game:full",
+            GameError::NameTaken => "game:name_taken",
+            GameError::InvalidName => "game:invalid_name",
+            GameError::NotHost => "game:not_host",
+            GameError::HostConnected => "game:host_connected",
+            GameError::NoPlayers => "game:no_players",
+            GameError::NoQuestion => "game:no_question",
+            GameError::AlreadyAnswered => "game:already_answered",
+            GameError::InvalidAnswer => "game:invalid_answer",
+            GameError::UnexpectedMessage => "game:unexpected_message",
+            GameError::InvalidToken => "game:invalid_token",
+            GameError::AccountDisabled => "game:account_disabled",
+        }
+    }
+}
+
+/// Messages sent to a running game
+pub(crate) enum GameMessage {
+    /// Host is connecting to the game
+    HostConnect {
+        user_id: UserId,
+        tx: MessageSender,
+        reply: oneshot::Sender<Result<(), GameError>>,
+    },
+    /// Host connection was closed
+    HostDisconnect,
+    /// Player is joining the game
+    Join {
+        name: String,
+        tx: MessageSender,
+        reply: oneshot::Sender<Result<PlayerId, GameError>>,
+    },
+    /// Player connection was closed
+    Leave { id: PlayerId },
+    /// Host request to start the game
+    Start,
+    /// Host request to move to the next step
+    Next,
+    /// Host request to remove a player
+    Kick { id: PlayerId },
+    /// Player answer to the current question
+    Answer { id: PlayerId, answer: PlayerAnswer },
+}
+
+/// Handle for sending messages to a running game
+#[derive(Clone)]
+pub struct GameHandle {
+    tx: mpsc::Sender<GameMessage>,
+}
+
+impl GameHandle {
+    /// Sends the provided `message` to the game
+    pub(crate) async fn send(&self, message: GameMessage) -> Result<(), GameError> {
+        self.tx
+            .send(message)
+            .await
+            .map_err(|_| GameError::UnknownGame)
+    }
+
+    /// Connects the user with the provided `user_id` as the host of the game
+    pub async fn host_connect(&self, user_id: UserId, tx: MessageSender) -> Result<(), GameError> {
+        let (reply, rx) = oneshot::channel();
+        self.send(GameMessage::HostConnect { user_id, tx, reply })
+            .await?;
+        rx.await.map_err(|_| GameError::UnknownGame)?
+    }
+
+    /// Joins the game as a player with the provided `name`
+    pub async fn join(&self, name: String, tx: MessageSender) -> Result<PlayerId, GameError> {
+        let (reply, rx) = oneshot::channel();
+        self.send(GameMessage::Join { name, tx, reply }).await?;
+        rx.await.map_err(|_| GameError::UnknownGame)?
+    }
+}
+
+impl GameService {
+    /// Length of game join codes
+    const CODE_LENGTH: usize = 6;
+    /// Characters used in join codes, excludes easily confused characters
+    const CODE_CHARSET: &'static [u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+    /// Size of the message buffer for each game
+    const GAME_BUFFER: usize = 64;
+
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self {
+            games: Default::default(),
+        })
+    }
+
+    /// Cancels the games that were left in the lobby or running when the
+    /// server last stopped, games only live in memory so they can't be
+    /// resumed and their join codes would otherwise never be released
+    pub async fn cancel_abandoned_games(&self, db: &DatabaseConnection) -> Result<(), DbErr> {
+        let result = ActiveQuiz::cancel_unfinished(db).await?;
+
+        if result.rows_affected > 0 {
+            info!(name: "games_cancelled", count = result.rows_affected, "Cancelled abandoned games");
+        }
+
+        Ok(())
+    }
+
+    /// Creates a new game for the provided `quiz` hosted by the provided
+    /// `host`, the game is stored and started in the background
+    pub async fn create_game(
+        self: &Arc<Self>,
+        db: DatabaseConnection,
+        host: &User,
+        quiz: Quiz,
+    ) -> Result<ActiveQuiz, DbErr> {
+        let (tx, rx) = mpsc::channel(Self::GAME_BUFFER);
+
+        // Reserve the code while holding the lock, the lock is released before
+        // the database insert so other games aren't blocked waiting on it
+        let code = {
+            let mut games = self.games.write().await;
+            let code = Self::create_code(&games);
+            games.insert(code.clone(), GameHandle { tx });
+            code
+        };
+
+        let active_quiz = match ActiveQuiz::create(&db, &quiz, host, code.clone()).await {
+            Ok(value) => value,
+            Err(err) => {
+                // Release the reserved code
+                self.games.write().await.remove(&code);
+                return Err(err);
+            }
+        };
+
+        let game = Game::new(active_quiz.clone(), quiz, db, self.clone());
+        tokio::spawn(game.run(rx));
+
+        debug!(name: "game_created", %code, id = active_quiz.id, "Created game");
+
+        Ok(active_quiz)
+    }
+
+    /// Finds the game with the provided join `code`
+    pub async fn get_game(&self, code: &str) -> Option<GameHandle> {
+        let code = code.trim().to_uppercase();
+        self.games.read().await.get(&code).cloned()
+    }
+
+    /// Removes the game with the provided `code` once it has ended
+    async fn remove_game(&self, code: &str, id: ActiveQuizId) {
+        self.games.write().await.remove(code);
+
+        debug!(name: "game_removed", %code, %id, "Removed game");
+    }
+
+    /// Creates a join code that isn't in use by any of the `games`
+    fn create_code(games: &HashMap<GameCode, GameHandle>) -> GameCode {
+        let mut rng = StdRng::from_entropy();
+
+        loop {
+            let code: GameCode = (0..Self::CODE_LENGTH)
+                .map(|_| {
+                    let index = rng.gen_range(0..Self::CODE_CHARSET.len());
+                    Self::CODE_CHARSET[index] as char
+                })
+                .collect();
+
+            if !games.contains_key(&code) {
+                return code;
+            }
+        }
+    }
+}
@@ -0,0 +1,813 @@
+//! Task that drives a single game, handling player answers, question
+//! timing, scoring and persisting the results
+
+use crate::database::{
+    entities::{active_quiz::ActiveQuiz, analytics::AnswerEvent, quiz::Quiz, user::UserId},
+    models::{
+        game::{GameResults, PlayerResult},
+        quiz::{Question, QuestionKind, QuizData},
+    },
+};
+use chrono::Utc;
+use indexmap::IndexMap;
+use rand::{seq::SliceRandom, thread_rng};
+use sea_orm::DatabaseConnection;
+use std::{cmp::Reverse, sync::Arc, time::Duration};
+use tokio::{
+    sync::mpsc,
+    time::{sleep_until, Instant},
+};
+use tracing::error;
+
+use super::{
+    messages::{
+        LeaderboardEntry, PlayerAnswer, PlayerId, PlayerSummary, QuestionView, ServerMessage,
+    },
+    GameError, GameMessage, GameService, MessageSender,
+};
+
+pub(super) struct Game {
+    /// The stored game session
+    active_quiz: ActiveQuiz,
+    /// Title of the quiz being played
+    title: String,
+    /// The quiz questions
+    data: QuizData,
+    /// Database connection for persisting results
+    db: DatabaseConnection,
+    /// Service the game is registered with
+    service: Arc<GameService>,
+    /// Connection to the host if connected
+    host: Option<MessageSender>,
+    /// Players within the game in join order
+    players: IndexMap<PlayerId, Player>,
+    /// ID to assign to the next player
+    next_player_id: PlayerId,
+    /// The current state of the game
+    state: GameState,
+    /// Deadline for the host to connect before the game is abandoned
+    host_deadline: Instant,
+}
+
+struct Player {
+    /// Display name of the player
+    name: String,
+    /// Connection to the player
+    tx: MessageSender,
+    /// Total score of the player
+    score: u32,
+    /// Number of correctly answered questions
+    correct_answers: u32,
+    /// Answer to the current question
+    answer: Option<AnswerResult>,
+}
+
+/// Outcome of a player answering a question
+struct AnswerResult {
+    correct: bool,
+    points: u32,
+    /// Time taken to answer the question
+    response_time: Duration,
+}
+
+enum GameState {
+    /// Waiting for players to join
+    Lobby,
+    /// A question is being answered
+    Question {
+        index: usize,
+        started_at: Instant,
+        deadline: Instant,
+        /// Order the ordering question items were presented in
+        order: Vec<usize>,
+    },
+    /// Showing the results of a question
+    Results { index: usize },
+    /// The game has ended
+    Ended,
+}
+
+impl Game {
+    /// Maximum number of players allowed in one game
+    const MAX_PLAYERS: usize = 200;
+    /// Maximum length of player names
+    const MAX_NAME_LENGTH: usize = 24;
+    /// Time the host has to connect to the game
+    const HOST_CONNECT_TIMEOUT: Duration = Duration::from_secs(60 * 5);
+
+    pub(super) fn new(
+        active_quiz: ActiveQuiz,
+        quiz: Quiz,
+        db: DatabaseConnection,
+        service: Arc<GameService>,
+    ) -> Self {
+        Self {
+            active_quiz,
+            title: quiz.title,
+            data: quiz.data,
+            db,
+            service,
+            host: None,
+            players: IndexMap::new(),
+            next_player_id: 0,
+            state: GameState::Lobby,
+            host_deadline: Instant::now() + Self::HOST_CONNECT_TIMEOUT,
+        }
+    }
+
+    /// Processes messages for the game until it has ended
+    pub(super) async fn run(mut self, mut rx: mpsc::Receiver<GameMessage>) {
+        while !matches!(self.state, GameState::Ended) {
+            let deadline = self.deadline();
+            let timeout = async move {
+                match deadline {
+                    Some(deadline) => sleep_until(deadline).await,
+                    None => std::future::pending().await,
+                }
+            };
+
+            tokio::select! {
+                message = rx.recv() => match message {
+                    Some(message) => self.handle_message(message).await,
+                    // All handles have been dropped
+                    None => self.cancel().await,
+                },
+                _ = timeout => self.handle_timeout().await,
+            }
+        }
+
+        self.service
+            .remove_game(&self.active_quiz.code, self.active_quiz.id)
+            .await;
+    }
+
+    /// Provides the time that the current state of the game ends
+    fn deadline(&self) -> Option<Instant> {
+        match &self.state {
+            GameState::Lobby if self.host.is_none() => Some(self.host_deadline),
+            GameState::Question { deadline, .. } => Some(*deadline),
+            _ => None,
+        }
+    }
+
+    async fn handle_timeout(&mut self) {
+        match &self.state {
+            // Host never connected
+            GameState::Lobby => self.cancel().await,
+            GameState::Question { .. } => self.end_question(),
+            _ => {}
+        }
+    }
+
+    async fn handle_message(&mut self, message: GameMessage) {
+        match message {
+            GameMessage::HostConnect { user_id, tx, reply } => {
+                let result = self.host_connect(user_id, tx);
+                _ = reply.send(result);
+            }
+            GameMessage::HostDisconnect => {
+                self.host = None;
+                // Games cannot continue without the host
+                self.cancel().await;
+            }
+            GameMessage::Join { name, tx, reply } => {
+                let result = self.join(name, tx);
+                _ = reply.send(result);
+            }
+            GameMessage::Leave { id } => self.remove_player(id, false),
+            GameMessage::Kick { id } => self.remove_player(id, true),
+            GameMessage::Start => {
+                if let Err(err) = self.start().await {
+                    self.send_host(err.into());
+                }
+            }
+            GameMessage::Next => self.next().await,
+            GameMessage::Answer { id, answer } => {
+                if let Err(err) = self.answer(id, answer) {
+                    self.send_player(id, err.into());
+                }
+            }
+        }
+    }
+
+    fn host_connect(&mut self, user_id: UserId, tx: MessageSender) -> Result<(), GameError> {
+        if user_id != self.active_quiz.host_id {
+            return Err(GameError::NotHost);
+        }
+
+        if self.host.is_some() {
+            return Err(GameError::HostConnected);
+        }
+
+        let players = self
+            .players
+            .iter()
+            .map(|(id, player)| PlayerSummary {
+                id: *id,
+                name: player.name.clone(),
+            })
+            .collect();
+
+        _ = tx.send(ServerMessage::HostConnected {
+            code: self.active_quiz.code.clone(),
+            title: self.title.clone(),
+            players,
+        });
+
+        self.host = Some(tx);
+
+        Ok(())
+    }
+
+    fn join(&mut self, name: String, tx: MessageSender) -> Result<PlayerId, GameError> {
+        if !matches!(self.state, GameState::Lobby) {
+            return Err(GameError::AlreadyStarted);
+        }
+
+        if self.players.len() >= Self::MAX_PLAYERS {
+            return Err(GameError::GameFull);
+        }
+
+        let name = name.trim().to_string();
+        if name.is_empty() || name.chars().count() > Self::MAX_NAME_LENGTH {
+            return Err(GameError::InvalidName);
+        }
+
+        if self
+            .players
+            .values()
+            .any(|player| player.name.eq_ignore_ascii_case(&name))
+        {
+            return Err(GameError::NameTaken);
+        }
+
+        let id = self.next_player_id;
+        self.next_player_id += 1;
+
+        _ = tx.send(ServerMessage::Joined {
+            id,
+            code: self.active_quiz.code.clone(),
+            title: self.title.clone(),
+        });
+
+        self.send_host(ServerMessage::PlayerJoined {
+            player: PlayerSummary {
+                id,
+                name: name.clone(),
+            },
+        });
+
+        self.players.insert(
+            id,
+            Player {
+                name,
+                tx,
+                score: 0,
+                correct_answers: 0,
+                answer: None,
+            },
+        );
+
+        Ok(id)
+    }
+
+    fn remove_player(&mut self, id: PlayerId, kicked: bool) {
+        let Some(player) = self.players.shift_remove(&id) else {
+            return;
+        };
+
+        if kicked {
+            _ = player.tx.send(ServerMessage::Kicked);
+        }
+
+        self.send_host(ServerMessage::PlayerLeft { id });
+
+        // Remaining players may have all answered
+        if matches!(self.state, GameState::Question { .. }) && self.all_answered() {
+            self.end_question();
+        }
+    }
+
+    async fn start(&mut self) -> Result<(), GameError> {
+        if !matches!(self.state, GameState::Lobby) {
+            return Err(GameError::AlreadyStarted);
+        }
+
+        if self.players.is_empty() {
+            return Err(GameError::NoPlayers);
+        }
+
+        match self.active_quiz.clone().set_started(&self.db).await {
+            Ok(active_quiz) => self.active_quiz = active_quiz,
+            Err(error) => {
+                error!(name: "err_game_start", %error, "Failed to store game start");
+            }
+        }
+
+        self.start_question(0);
+
+        Ok(())
+    }
+
+    async fn next(&mut self) {
+        match self.state {
+            // Host is skipping the rest of the question time
+            GameState::Question { .. } => self.end_question(),
+            GameState::Results { index } => {
+                let next = index + 1;
+                if next < self.data.questions.len() {
+                    self.start_question(next);
+                } else {
+                    self.finish().await;
+                }
+            }
+            _ => self.send_host(GameError::UnexpectedMessage.into()),
+        }
+    }
+
+    fn start_question(&mut self, index: usize) {
+        let question = &self.data.questions[index];
+
+        // Ordering questions are presented in a random order
+        let mut order: Vec<usize> = match &question.kind {
+            QuestionKind::Ordering { items } => (0..items.len()).collect(),
+            _ => Vec::new(),
+        };
+        order.shuffle(&mut thread_rng());
+
+        let message = ServerMessage::Question {
+            index,
+            total: self.data.questions.len(),
+            question: QuestionView::new(question, &order),
+        };
+
+        let started_at = Instant::now();
+        let deadline = started_at + Duration::from_secs(question.time_limit as u64);
+
+        self.state = GameState::Question {
+            index,
+            started_at,
+            deadline,
+            order,
+        };
+
+        self.send_all(message);
+    }
+
+    fn answer(&mut self, id: PlayerId, answer: PlayerAnswer) -> Result<(), GameError> {
+        let GameState::Question {
+            index,
+            started_at,
+            deadline,
+            order,
+        } = &self.state
+        else {
+            return Err(GameError::NoQuestion);
+        };
+
+        let question = &self.data.questions[*index];
+        let player = self
+            .players
+            .get_mut(&id)
+            .ok_or(GameError::UnexpectedMessage)?;
+
+        if player.answer.is_some() {
+            return Err(GameError::AlreadyAnswered);
+        }
+
+        let response_time = started_at.elapsed();
+        let correct = is_answer_correct(question, &answer, order)?;
+        let points = if correct {
+            score_answer(question, response_time, *deadline - *started_at)
+        } else {
+            0
+        };
+
+        player.answer = Some(AnswerResult {
+            correct,
+            points,
+            response_time,
+        });
+        _ = player.tx.send(ServerMessage::AnswerAccepted);
+
+        let answered = self
+            .players
+            .values()
+            .filter(|player| player.answer.is_some())
+            .count();
+
+        self.send_host(ServerMessage::AnswerCount {
+            answered,
+            total: self.players.len(),
+        });
+
+        if self.all_answered() {
+            self.end_question();
+        }
+
+        Ok(())
+    }
+
+    /// Whether every player has answered the current question
+    fn all_answered(&self) -> bool {
+        self.players.values().all(|player| player.answer.is_some())
+    }
+
+    fn end_question(&mut self) {
+        let GameState::Question { index, .. } = self.state else {
+            return;
+        };
+
+        // Apply the scores for the question
+        for player in self.players.values_mut() {
+            if let Some(answer) = &player.answer {
+                player.score += answer.points;
+                if answer.correct {
+                    player.correct_answers += 1;
+                }
+            }
+        }
+
+        self.record_answers(index);
+
+        let leaderboard = self.leaderboard();
+
+        for (id, player) in self.players.iter_mut() {
+            let answer = player.answer.take();
+            let position = leaderboard
+                .iter()
+                .position(|entry| entry.id == *id)
+                .unwrap_or_default()
+                + 1;
+
+            _ = player.tx.send(ServerMessage::QuestionResult {
+                correct: answer.as_ref().is_some_and(|answer| answer.correct),
+                points: answer
+                    .as_ref()
+                    .map(|answer| answer.points)
+                    .unwrap_or_default(),
+                score: player.score,
+                position,
+            });
+        }
+
+        self.send_host(ServerMessage::QuestionSummary {
+            index,
+            question: self.data.questions[index].clone(),
+            leaderboard,
+        });
+
+        self.state = GameState::Results { index };
+    }
+
+    /// Stores the answers of every player to the question at the provided
+    /// `index` for analytics, players without an answer are also recorded
+    fn record_answers(&self, index: usize) {
+        let question = &self.data.questions[index];
+        let time = Utc::now().naive_utc();
+
+        let events: Vec<AnswerEvent> = self
+            .players
+            .iter()
+            .map(|(id, player)| AnswerEvent {
+                session_id: self.active_quiz.id,
+                question_id: question.id as i32,
+                player_id: *id as i32,
+                time,
+                quiz_id: self.active_quiz.quiz_id,
+                player_name: player.name.clone(),
+                correct: player.answer.as_ref().is_some_and(|answer| answer.correct),
+                points: player
+                    .answer
+                    .as_ref()
+                    .map(|answer| answer.points as i32)
+                    .unwrap_or_default(),
+                response_time: player
+                    .answer
+                    .as_ref()
+                    .map(|answer| answer.response_time.as_millis() as i32),
+            })
+            .collect();
+
+        let db = self.db.clone();
+
+        // Store in the background to avoid delaying the game
+        tokio::spawn(async move {
+            if let Err(error) = AnswerEvent::create_many(&db, events).await {
+                error!(name: "err_game_analytics", %error, "Failed to store answer analytics");
+            }
+        });
+    }
+
+    async fn finish(&mut self) {
+        self.state = GameState::Ended;
+
+        let leaderboard = self.leaderboard();
+        let results = GameResults {
+            leaderboard: leaderboard
+                .iter()
+                .filter_map(|entry| self.players.get(&entry.id))
+                .map(|player| PlayerResult {
+                    name: player.name.clone(),
+                    score: player.score,
+                    correct_answers: player.correct_answers,
+                })
+                .collect(),
+        };
+
+        self.send_all(ServerMessage::Finished { leaderboard });
+
+        if let Err(error) = self
+            .active_quiz
+            .clone()
+            .set_finished(&self.db, results)
+            .await
+        {
+            error!(name: "err_game_finish", %error, "Failed to store game results");
+        }
+
+        if let Err(error) = Quiz::increment_play_count(&self.db, self.active_quiz.quiz_id).await {
+            error!(name: "err_game_play_count", %error, "Failed to update quiz play count");
+        }
+    }
+
+    async fn cancel(&mut self) {
+        self.state = GameState::Ended;
+        self.send_all(ServerMessage::Cancelled);
+
+        if let Err(error) = self.active_quiz.clone().set_cancelled(&self.db).await {
+            error!(name: "err_game_cancel", %error, "Failed to store game cancellation");
+        }
+    }
+
+    /// Creates the leaderboard of players ordered by score
+    fn leaderboard(&self) -> Vec<LeaderboardEntry> {
+        let mut leaderboard: Vec<LeaderboardEntry> = self
+            .players
+            .iter()
+            .map(|(id, player)| LeaderboardEntry {
+                id: *id,
+                name: player.name.clone(),
+                score: player.score,
+            })
+            .collect();
+
+        leaderboard.sort_by_key(|entry| Reverse(entry.score));
+        leaderboard
+    }
+
+    fn send_host(&self, message: ServerMessage) {
+        if let Some(host) = &self.host {
+            _ = host.send(message);
+        }
+    }
+
+    fn send_player(&self, id: PlayerId, message: ServerMessage) {
+        if let Some(player) = self.players.get(&id) {
+            _ = player.tx.send(message);
+        }
+    }
+
+    /// Sends the provided `message` to the host and all players
+    fn send_all(&self, message: ServerMessage) {
+        for player in self.players.values() {
+            _ = player.tx.send(message.clone());
+        }
+
+        self.send_host(message);
+    }
+}
+
+/// Determines whether the provided `answer` is correct for the `question`,
+/// ordering answers are mapped back through the presented `order`
+fn is_answer_correct(
+    question: &Question,
+    answer: &PlayerAnswer,
+    order: &[usize],
+) -> Result<bool, GameError> {
+    let correct = match (&question.kind, answer) {
+        (QuestionKind::Single { answers }, PlayerAnswer::Single { answer }) => {
+            answers
+                .get(*answer)
+                .ok_or(GameError::InvalidAnswer)?
+                .correct
+        }
+        (QuestionKind::Multiple { answers }, PlayerAnswer::Multiple { answers: chosen }) => {
+            if chosen.iter().any(|index| *index >= answers.len()) {
+                return Err(GameError::InvalidAnswer);
+            }
+
+            // Every correct answer must be chosen and nothing else
+            answers
+                .iter()
+                .enumerate()
+                .all(|(index, option)| option.correct == chosen.contains(&index))
+        }
+        (QuestionKind::TrueFalse { answer: expected }, PlayerAnswer::TrueFalse { answer }) => {
+            expected == answer
+        }
+        (
+            QuestionKind::Typed {
+                answers,
+                ignore_case,
+            },
+            PlayerAnswer::Typed { answer },
+        ) => {
+            let answer = answer.trim();
+            answers.iter().any(|expected| {
+                if *ignore_case {
+                    expected.trim().to_lowercase() == answer.to_lowercase()
+                } else {
+                    expected.trim() == answer
+                }
+            })
+        }
+        (QuestionKind::Ordering { items }, PlayerAnswer::Ordering { order: chosen }) => {
+            if chosen.len() != items.len() || chosen.iter().any(|index| *index >= order.len()) {
+                return Err(GameError::InvalidAnswer);
+            }
+
+            // Map the presented positions back to the original item positions
+            chosen
+                .iter()
+                .map(|index| order[*index])
+                .enumerate()
+                .all(|(position, item)| position == item)
+        }
+        _ => return Err(GameError::InvalidAnswer),
+    };
+
+    Ok(correct)
+}
+
+/// Scores a correct answer, answering instantly awards all the question
+/// points decreasing to half the points at the time limit
+fn score_answer(question: &Question, elapsed: Duration, time_limit: Duration) -> u32 {
+    let ratio = (elapsed.as_secs_f32() / time_limit.as_secs_f32()).clamp(0.0, 1.0);
+    (question.points as f32 * (1.0 - ratio / 2.0)).round() as u32
+}
+
+#[cfg(test)]
+mod test {
+    use super::{is_answer_correct, score_answer};
+    use crate::{
+        database::models::quiz::{AnswerOption, Question, QuestionKind},
+        services::game::{messages::PlayerAnswer, GameError},
+    };
+    use std::time::Duration;
+
+    fn question(kind: QuestionKind) -> Question {
+        Question {
+            id: 0,
+            text: "Question".to_string(),
+            image: None,
+            time_limit: 20,
+            points: 1000,
+            kind,
+        }
+    }
+
+    fn options(correct: &[bool]) -> Vec<AnswerOption> {
+        correct
+            .iter()
+            .enumerate()
+            .map(|(index, correct)| AnswerOption {
+                text: format!("Answer {index}"),
+                correct: *correct,
+            })
+            .collect()
+    }
+
+    /// Tests single choice answers including out of range indexes
+    #[test]
+    fn test_single_answer() {
+        let question = question(QuestionKind::Single {
+            answers: options(&[false, true, false]),
+        });
+
+        let check = |answer| is_answer_correct(&question, &PlayerAnswer::Single { answer }, &[]);
+
+        assert!(check(1).unwrap());
+        assert!(!check(0).unwrap());
+        assert!(matches!(check(3), Err(GameError::InvalidAnswer)));
+    }
+
+    /// Tests multiple choice answers must choose every correct answer
+    /// and nothing else
+    #[test]
+    fn test_multiple_answer() {
+        let question = question(QuestionKind::Multiple {
+            answers: options(&[true, false, true]),
+        });
+
+        let check = |answers: &[usize]| {
+            is_answer_correct(
+                &question,
+                &PlayerAnswer::Multiple {
+                    answers: answers.to_vec(),
+                },
+                &[],
+            )
+        };
+
+        assert!(check(&[0, 2]).unwrap());
+        assert!(check(&[2, 0]).unwrap());
+        assert!(!check(&[0]).unwrap());
+        assert!(!check(&[0, 1, 2]).unwrap());
+        assert!(!check(&[]).unwrap());
+        assert!(matches!(check(&[0, 3]), Err(GameError::InvalidAnswer)));
+    }
+
+    /// Tests true or false answers
+    #[test]
+    fn test_true_false_answer() {
+        let question = question(QuestionKind::TrueFalse { answer: true });
+
+        let check = |answer| is_answer_correct(&question, &PlayerAnswer::TrueFalse { answer }, &[]);
+
+        assert!(check(true).unwrap());
+        assert!(!check(false).unwrap());
+    }
+
+    /// Tests typed answers are trimmed and respect the case setting
+    #[test]
+    fn test_typed_answer() {
+        let answers = vec!["Paris".to_string(), " Lutetia ".to_string()];
+        let sensitive = question(QuestionKind::Typed {
+            answers: answers.clone(),
+            ignore_case: false,
+        });
+        let insensitive = question(QuestionKind::Typed {
+            answers,
+            ignore_case: true,
+        });
+
+        let check = |question, answer: &str| {
+            is_answer_correct(
+                question,
+                &PlayerAnswer::Typed {
+                    answer: answer.to_string(),
+                },
+                &[],
+            )
+            .unwrap()
+        };
+
+        assert!(check(&sensitive, "Paris"));
+        assert!(check(&sensitive, "  Paris "));
+        assert!(check(&sensitive, "Lutetia"));
+        assert!(!check(&sensitive, "paris"));
+        assert!(check(&insensitive, "PARIS"));
+        assert!(!check(&insensitive, "London"));
+    }
+
+    /// Tests ordering answers are mapped back through the presented order
+    #[test]
+    fn test_ordering_answer() {
+        let question = question(QuestionKind::Ordering {
+            items: vec!["A".to_string(), "B".to_string(), "C".to_string()],
+        });
+        // Items were presented as C, A, B
+        let order = [2, 0, 1];
+
+        let check = |chosen: &[usize]| {
+            is_answer_correct(
+                &question,
+                &PlayerAnswer::Ordering {
+                    order: chosen.to_vec(),
+                },
+                &order,
+            )
+        };
+
+        assert!(check(&[1, 2, 0]).unwrap());
+        assert!(!check(&[0, 1, 2]).unwrap());
+        assert!(!check(&[1, 1, 0]).unwrap());
+        assert!(matches!(check(&[1, 2]), Err(GameError::InvalidAnswer)));
+        assert!(matches!(check(&[1, 2, 3]), Err(GameError::InvalidAnswer)));
+    }
+
+    /// Tests answers of the wrong type are rejected
+    #[test]
+    fn test_mismatched_answer() {
+        let question = question(QuestionKind::TrueFalse { answer: true });
+
+        assert!(matches!(
+            is_answer_correct(&question, &PlayerAnswer::Single { answer: 0 }, &[]),
+            Err(GameError::InvalidAnswer)
+        ));
+    }
+
+    /// Tests scores decrease from the full points to half at the time limit
+    #[test]
+    fn test_score_answer() {
+        let question = question(QuestionKind::TrueFalse { answer: true });
+        let limit = Duration::from_secs(20);
+
+        assert_eq!(score_answer(&question, Duration::ZERO, limit), 1000);
+        assert_eq!(score_answer(&question, Duration::from_secs(10), limit), 750);
+        assert_eq!(score_answer(&question, limit, limit), 500);
+        // Late answers are clamped to the time limit
+        assert_eq!(score_answer(&question, Duration::from_secs(60), limit), 500);
+    }
+}
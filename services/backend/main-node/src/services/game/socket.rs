@@ -0,0 +1,175 @@
+//! Handling for WebSocket connections from game hosts and players
+
+use crate::{database::entities::user::User, services::auth::AuthService};
+use axum::extract::ws::{Message, WebSocket};
+use futures::{stream::SplitStream, SinkExt, StreamExt};
+use sea_orm::DatabaseConnection;
+use std::{sync::Arc, time::Duration};
+use tokio::sync::mpsc;
+use tracing::{debug, error};
+
+use super::{
+    messages::{ClientMessage, PlayerId, ServerMessage},
+    GameError, GameHandle, GameMessage, GameService, MessageSender,
+};
+
+/// Time the client has to identify itself after connecting
+const IDENTIFY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Role of a connected client
+enum Connection {
+    Host,
+    Player(PlayerId),
+}
+
+/// Handles a WebSocket connection, the first message from the client
+/// determines whether it is connecting as the host or a player
+pub async fn handle_socket(
+    socket: WebSocket,
+    games: Arc<GameService>,
+    auth: Arc<AuthService>,
+    db: DatabaseConnection,
+) {
+    let (mut sink, mut stream) = socket.split();
+    let (tx, mut rx) = mpsc::unbounded_channel::<ServerMessage>();
+
+    // Task for writing outgoing messages to the socket
+    let writer = tokio::spawn(async move {
+        while let Some(message) = rx.recv().await {
+            let terminal = message.is_terminal();
+
+            let text = match serde_json::to_string(&message) {
+                Ok(value) => value,
+                Err(error) => {
+                    error!(name: "err_game_serialize", %error, "Failed to serialize game message");
+                    continue;
+                }
+            };
+
+            if sink.send(Message::Text(text)).await.is_err() || terminal {
+                break;
+            }
+        }
+
+        _ = sink.close().await;
+    });
+
+    let (handle, connection) = match tokio::time::timeout(
+        IDENTIFY_TIMEOUT,
+        identify(&mut stream, &tx, &games, &auth, &db),
+    )
+    .await
+    {
+        Ok(Ok(value)) => value,
+        Ok(Err(err)) => {
+            _ = tx.send(err.into());
+            drop(tx);
+            _ = writer.await;
+            return;
+        }
+        // Client didn't identify in time
+        Err(_) => {
+            writer.abort();
+            return;
+        }
+    };
+
+    while let Some(Ok(message)) = stream.next().await {
+        let text = match message {
+            Message::Text(text) => text,
+            Message::Close(_) => break,
+            _ => continue,
+        };
+
+        let message: ClientMessage = match serde_json::from_str(&text) {
+            Ok(value) => value,
+            Err(_) => {
+                _ = tx.send(GameError::UnexpectedMessage.into());
+                continue;
+            }
+        };
+
+        let message = match (&connection, message) {
+            (Connection::Host, ClientMessage::Start) => GameMessage::Start,
+            (Connection::Host, ClientMessage::Next) => GameMessage::Next,
+            (Connection::Host, ClientMessage::Kick { player }) => GameMessage::Kick { id: player },
+            (Connection::Player(id), ClientMessage::Answer { answer }) => {
+                GameMessage::Answer { id: *id, answer }
+            }
+            _ => {
+                _ = tx.send(GameError::UnexpectedMessage.into());
+                continue;
+            }
+        };
+
+        // Game has ended
+        if handle.send(message).await.is_err() {
+            break;
+        }
+    }
+
+    let message = match connection {
+        Connection::Host => GameMessage::HostDisconnect,
+        Connection::Player(id) => GameMessage::Leave { id },
+    };
+
+    _ = handle.send(message).await;
+
+    writer.abort();
+}
+
+/// Waits for the client to identify itself as either the host or a player
+/// connecting them to the requested game
+async fn identify(
+    stream: &mut SplitStream<WebSocket>,
+    tx: &MessageSender,
+    games: &GameService,
+    auth: &AuthService,
+    db: &DatabaseConnection,
+) -> Result<(GameHandle, Connection), GameError> {
+    let message = loop {
+        match stream.next().await {
+            Some(Ok(Message::Text(text))) => break text,
+            Some(Ok(Message::Close(_))) | Some(Err(_)) | None => {
+                return Err(GameError::UnexpectedMessage)
+            }
+            _ => continue,
+        }
+    };
+
+    let message: ClientMessage =
+        serde_json::from_str(&message).map_err(|_| GameError::UnexpectedMessage)?;
+
+    match message {
+        ClientMessage::Host { token, code } => {
+            let claims = auth
+                .verify_user_token(db, &token)
+                .await
+                .map_err(|_| GameError::InvalidToken)?;
+            let user = User::find_by_id(db, claims.user_id)
+                .await
+                .ok()
+                .flatten()
+                .ok_or(GameError::InvalidToken)?;
+
+            // Disabled accounts must not keep hosting with an old token
+            if !user.can_login() {
+                return Err(GameError::AccountDisabled);
+            }
+
+            let handle = games.get_game(&code).await.ok_or(GameError::UnknownGame)?;
+            handle.host_connect(user.id, tx.clone()).await?;
+
+            debug!(name: "game_host_connected", %code, "Host connected to game");
+
+            Ok((handle, Connection::Host))
+        }
+        ClientMessage::Join { code, name } => {
+            let handle = games.get_game(&code).await.ok_or(GameError::UnknownGame)?;
+            let id = handle.join(name, tx.clone()).await?;
+
+            Ok((handle, Connection::Player(id)))
+        }
+        _ => Err(GameError::UnexpectedMessage),
+    }
+}
@@ -0,0 +1,437 @@
+//! Processing for uploaded images, images are decoded within strict limits
+//! to reject decompression bombs and then re-encoded which normalises the
+//! format and strips any metadata such as EXIF (including GPS location)
+
+use crate::database::models::resource::ImageVariant;
+use image::{
+    codecs::jpeg::JpegEncoder, imageops::FilterType, DynamicImage, ImageDecoder, ImageFormat,
+    ImageReader, Limits,
+};
+use std::io::Cursor;
+use thiserror::Error;
+
+/// Maximum width or height of an image in pixels
+const MAX_DIMENSION: u32 = 8192;
+/// Maximum number of pixels in an image
+const MAX_PIXELS: u64 = 40_000_000;
+/// Maximum memory the decoder is allowed to allocate in bytes (512MiB)
+const MAX_ALLOC: u64 = 512 * 1024 * 1024;
+/// Quality used when encoding JPEG images
+const JPEG_QUALITY: u8 = 85;
+
+/// Types of image that can be uploaded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageType {
+    Png,
+    Jpeg,
+    Webp,
+    Gif,
+}
+
+impl ImageType {
+    /// Finds the image type for the provided MIME type
+    pub fn from_mime(mime: &str) -> Option<Self> {
+        Some(match mime {
+            "image/png" => Self::Png,
+            "image/jpeg" | "image/jpg" => Self::Jpeg,
+            "image/webp" => Self::Webp,
+            "image/gif" => Self::Gif,
+            _ => return None,
+        })
+    }
+
+    /// Determines the image type from the leading "magic" bytes of
+    /// the content, the declared type of an upload is not trusted
+    pub fn sniff(data: &[u8]) -> Option<Self> {
+        if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+            Some(Self::Png)
+        } else if data.starts_with(b"\xFF\xD8\xFF") {
+            Some(Self::Jpeg)
+        } else if data.len() >= 12 && data.starts_with(b"RIFF") && &data[8..12] == b"WEBP" {
+            Some(Self::Webp)
+        } else if data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a") {
+            Some(Self::Gif)
+        } else {
+            None
+        }
+    }
+
+    /// Provides the type images of this type are normalised to, animated
+    /// GIFs are not supported so only the first frame is kept as a PNG
+    pub fn normalised(&self) -> Self {
+        match self {
+            Self::Gif => Self::Png,
+            other => *other,
+        }
+    }
+
+    pub fn mime(&self) -> &'static str {
+        match self {
+            Self::Png => "image/png",
+            Self::Jpeg => "image/jpeg",
+            Self::Webp => "image/webp",
+            Self::Gif => "image/gif",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Png => "png",
+            Self::Jpeg => "jpg",
+            Self::Webp => "webp",
+            Self::Gif => "gif",
+        }
+    }
+
+    fn format(&self) -> ImageFormat {
+        match self {
+            Self::Png => ImageFormat::Png,
+            Self::Jpeg => ImageFormat::Jpeg,
+            Self::Webp => ImageFormat::WebP,
+            Self::Gif => ImageFormat::Gif,
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ImageError {
+    /// Image dimensions or memory use exceeded the limits
+    #[error("Image is too large")]
+    TooLarge,
+    /// Image could not be decoded
+    #[error("Image could not be decoded")]
+    Invalid,
+    /// Image could not be encoded
+    #[error("Failed to encode image")]
+    Encode(#[source] image::ImageError),
+}
+
+/// Encoded image along with its dimensions
+pub struct EncodedImage {
+    pub data: Vec<u8>,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Result of processing an uploaded image
+pub struct ProcessedImage {
+    /// The type of all the encoded images
+    pub image_type: ImageType,
+    /// The normalised original image
+    pub original: EncodedImage,
+    /// Variants that were generated for the image
+    pub variants: Vec<(ImageVariant, EncodedImage)>,
+}
+
+/// Decodes the provided `data` then re-encodes it along with any of the
+/// smaller variants, this is CPU heavy and should be run on a blocking
+/// thread
+pub fn process_image(data: &[u8], image_type: ImageType) -> Result<ProcessedImage, ImageError> {
+    let image = decode(data, image_type)?;
+    let image_type = image_type.normalised();
+
+    let original = encode(&image, image_type)?;
+
+    let variants = ImageVariant::ALL
+        .into_iter()
+        // Only create variants that are smaller than the original
+        .filter(|variant| image.width().max(image.height()) > variant.max_size())
+        .map(|variant| {
+            let size = variant.max_size();
+            let resized = image.resize(size, size, FilterType::Lanczos3);
+            encode(&resized, image_type).map(|encoded| (variant, encoded))
+        })
+        .collect::<Result<Vec<_>, ImageError>>()?;
+
+    Ok(ProcessedImage {
+        image_type,
+        original,
+        variants,
+    })
+}
+
+/// Decodes the image applying its orientation, images exceeding the
+/// dimension, pixel or allocation limits are rejected before decoding
+fn decode(data: &[u8], image_type: ImageType) -> Result<DynamicImage, ImageError> {
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_DIMENSION);
+    limits.max_image_height = Some(MAX_DIMENSION);
+    limits.max_alloc = Some(MAX_ALLOC);
+
+    let mut reader = ImageReader::with_format(Cursor::new(data), image_type.format());
+    reader.limits(limits);
+
+    let mut decoder = reader.into_decoder().map_err(map_decode_error)?;
+
+    let (width, height) = decoder.dimensions();
+    if width as u64 * height as u64 > MAX_PIXELS {
+        return Err(ImageError::TooLarge);
+    }
+
+    // Orientation is stored in the metadata that is being stripped
+    // so it must be applied to the pixels
+    let orientation = decoder.orientation().map_err(map_decode_error)?;
+    let mut image = DynamicImage::from_decoder(decoder).map_err(map_decode_error)?;
+    image.apply_orientation(orientation);
+
+    Ok(image)
+}
+
+/// Encodes the image as the provided `image_type`
+fn encode(image: &DynamicImage, image_type: ImageType) -> Result<EncodedImage, ImageError> {
+    let mut data: Vec<u8> = Vec::new();
+
+    match image_type {
+        ImageType::Jpeg => {
+            // JPEG has no alpha channel
+            let image = image.to_rgb8();
+            JpegEncoder::new_with_quality(&mut data, JPEG_QUALITY)
+                .encode_image(&image)
+                .map_err(ImageError::Encode)?;
+        }
+        ImageType::Webp => {
+            // WebP encoding only supports 8-bit channels
+            let image = DynamicImage::ImageRgba8(image.to_rgba8());
+            image
+                .write_to(&mut Cursor::new(&mut data), ImageFormat::WebP)
+                .map_err(ImageError::Encode)?;
+        }
+        ImageType::Png | ImageType::Gif => {
+            image
+                .write_to(&mut Cursor::new(&mut data), ImageFormat::Png)
+                .map_err(ImageError::Encode)?;
+        }
+    }
+
+    Ok(EncodedImage {
+        data,
+        width: image.width(),
+        height: image.height(),
+    })
+}
+
+fn map_decode_error(err: image::ImageError) -> ImageError {
+    match err {
+        image::ImageError::Limits(_) => ImageError::TooLarge,
+        _ => ImageError::Invalid,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{process_image, ImageError, ImageType, MAX_DIMENSION};
+    use crate::database::models::resource::ImageVariant;
+    use image::{codecs::jpeg::JpegEncoder, DynamicImage, ImageFormat, RgbImage};
+    use std::io::Cursor;
+
+    /// Marker stored within the EXIF data to check it doesn't survive
+    const EXIF_MARKER: &[u8] = b"GPS-51.5007N-0.1246W";
+
+    fn test_image(width: u32, height: u32) -> DynamicImage {
+        DynamicImage::ImageRgb8(RgbImage::from_fn(width, height, |x, y| {
+            image::Rgb([(x % 256) as u8, (y % 256) as u8, 128])
+        }))
+    }
+
+    fn encode_png(image: &DynamicImage) -> Vec<u8> {
+        let mut data = Vec::new();
+        image
+            .write_to(&mut Cursor::new(&mut data), ImageFormat::Png)
+            .unwrap();
+        data
+    }
+
+    /// Creates a JPEG with an EXIF segment that rotates the image 90
+    /// degrees clockwise and contains the [EXIF_MARKER]
+    fn jpeg_with_exif(width: u32, height: u32) -> Vec<u8> {
+        let mut jpeg = Vec::new();
+        JpegEncoder::new(&mut jpeg)
+            .encode_image(&test_image(width, height))
+            .unwrap();
+
+        // Little endian TIFF with a single IFD entry for the orientation
+        let mut tiff = b"II*\0".to_vec();
+        tiff.extend_from_slice(&8u32.to_le_bytes());
+        tiff.extend_from_slice(&1u16.to_le_bytes());
+        tiff.extend_from_slice(&0x0112u16.to_le_bytes());
+        tiff.extend_from_slice(&3u16.to_le_bytes());
+        tiff.extend_from_slice(&1u32.to_le_bytes());
+        tiff.extend_from_slice(&[6, 0, 0, 0]);
+        tiff.extend_from_slice(&0u32.to_le_bytes());
+        tiff.extend_from_slice(EXIF_MARKER);
+
+        let mut segment = b"Exif\0\0".to_vec();
+        segment.extend_from_slice(&tiff);
+
+        // APP1 segment directly after the start of image marker
+        let mut data = jpeg[..2].to_vec();
+        data.extend_from_slice(&[0xFF, 0xE1]);
+        data.extend_from_slice(&(segment.len() as u16 + 2).to_be_bytes());
+        data.extend_from_slice(&segment);
+        data.extend_from_slice(&jpeg[2..]);
+        data
+    }
+
+    /// Rewrites the dimensions in the IHDR chunk of a PNG without
+    /// changing the image data
+    fn with_png_dimensions(mut png: Vec<u8>, width: u32, height: u32) -> Vec<u8> {
+        // Signature (8) + length (4) + chunk type (4)
+        png[16..20].copy_from_slice(&width.to_be_bytes());
+        png[20..24].copy_from_slice(&height.to_be_bytes());
+        let crc = crc32(&png[12..29]);
+        png[29..33].copy_from_slice(&crc.to_be_bytes());
+        png
+    }
+
+    fn crc32(data: &[u8]) -> u32 {
+        let mut crc = 0xFFFF_FFFFu32;
+        for byte in data {
+            crc ^= *byte as u32;
+            for _ in 0..8 {
+                crc = if crc & 1 == 1 {
+                    (crc >> 1) ^ 0xEDB8_8320
+                } else {
+                    crc >> 1
+                };
+            }
+        }
+        !crc
+    }
+
+    fn contains(data: &[u8], value: &[u8]) -> bool {
+        data.windows(value.len()).any(|window| window == value)
+    }
+
+    /// EXIF metadata is removed from JPEGs after its orientation has
+    /// been applied to the pixels
+    #[test]
+    fn test_strips_exif() {
+        let data = jpeg_with_exif(64, 32);
+        assert!(contains(&data, EXIF_MARKER));
+
+        let processed = process_image(&data, ImageType::Jpeg).unwrap();
+        let original = &processed.original;
+
+        assert_eq!(processed.image_type, ImageType::Jpeg);
+        assert_eq!(ImageType::sniff(&original.data), Some(ImageType::Jpeg));
+        assert!(!contains(&original.data, b"Exif"));
+        assert!(!contains(&original.data, EXIF_MARKER));
+
+        // Rotated by the orientation before the metadata was removed
+        assert_eq!((original.width, original.height), (32, 64));
+    }
+
+    /// Variants are only created when smaller than the original and keep
+    /// the aspect ratio of the original
+    #[test]
+    fn test_variant_sizes() {
+        let data = encode_png(&test_image(1600, 800));
+        let processed = process_image(&data, ImageType::Png).unwrap();
+
+        assert_eq!(
+            (processed.original.width, processed.original.height),
+            (1600, 800)
+        );
+
+        let sizes: Vec<_> = processed
+            .variants
+            .iter()
+            .map(|(variant, encoded)| {
+                let decoded = image::load_from_memory(&encoded.data).unwrap();
+                assert_eq!(
+                    (decoded.width(), decoded.height()),
+                    (encoded.width, encoded.height)
+                );
+                (*variant, encoded.width, encoded.height)
+            })
+            .collect();
+        assert_eq!(
+            sizes,
+            vec![
+                (ImageVariant::Thumbnail, 320, 160),
+                (ImageVariant::Medium, 1280, 640),
+            ]
+        );
+
+        // Medium would be larger than the original
+        let data = encode_png(&test_image(800, 400));
+        let processed = process_image(&data, ImageType::Png).unwrap();
+        let variants: Vec<_> = processed
+            .variants
+            .iter()
+            .map(|(variant, _)| *variant)
+            .collect();
+        assert_eq!(variants, vec![ImageVariant::Thumbnail]);
+    }
+
+    /// GIFs are normalised to PNGs
+    #[test]
+    fn test_gif_normalised() {
+        let mut data = Vec::new();
+        test_image(16, 16)
+            .write_to(&mut Cursor::new(&mut data), ImageFormat::Gif)
+            .unwrap();
+
+        let processed = process_image(&data, ImageType::Gif).unwrap();
+        assert_eq!(processed.image_type, ImageType::Png);
+        assert_eq!(
+            ImageType::sniff(&processed.original.data),
+            Some(ImageType::Png)
+        );
+    }
+
+    /// Images claiming dimensions beyond the limits are rejected before
+    /// their pixels are decoded
+    #[test]
+    fn test_rejects_oversized() {
+        let png = encode_png(&test_image(1, 1));
+
+        // Wider than the maximum dimension
+        let data = with_png_dimensions(png.clone(), MAX_DIMENSION + 1, 1);
+        assert!(matches!(
+            process_image(&data, ImageType::Png),
+            Err(ImageError::TooLarge)
+        ));
+
+        // Within the maximum dimensions but too many pixels in total
+        let data = with_png_dimensions(png, MAX_DIMENSION, MAX_DIMENSION);
+        assert!(matches!(
+            process_image(&data, ImageType::Png),
+            Err(ImageError::TooLarge)
+        ));
+    }
+
+    /// Sniffing uses the content rather than the declared type
+    #[test]
+    fn test_sniff() {
+        let png = encode_png(&test_image(4, 4));
+        let jpeg = jpeg_with_exif(4, 4);
+
+        assert_eq!(ImageType::sniff(&png), Some(ImageType::Png));
+        assert_eq!(ImageType::sniff(&jpeg), Some(ImageType::Jpeg));
+        assert_ne!(ImageType::sniff(&png), ImageType::from_mime("image/jpeg"));
+
+        assert_eq!(
+            ImageType::sniff(b"<svg xmlns=\"http://www.w3.org/2000/svg\"/>"),
+            None
+        );
+        assert_eq!(ImageType::sniff(b"<html><script></script></html>"), None);
+        assert_eq!(ImageType::sniff(b"RIFF\0\0\0\0WAVEfmt "), None);
+        assert_eq!(ImageType::sniff(b""), None);
+    }
+
+    /// Content that doesn't decode as the provided type is rejected
+    #[test]
+    fn test_rejects_mismatched_content() {
+        let png = encode_png(&test_image(4, 4));
+
+        assert!(matches!(
+            process_image(&png, ImageType::Jpeg),
+            Err(ImageError::Invalid)
+        ));
+        assert!(matches!(
+            process_image(b"not an image", ImageType::Png),
+            Err(ImageError::Invalid)
+        ));
+    }
+}
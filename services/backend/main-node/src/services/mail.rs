@@ -0,0 +1,203 @@
+//! Service for sending emails to users over SMTP, the email content is
+//! rendered from the templates in `templates/mail`
+
+use crate::{
+    database::entities::user::{User, UserId},
+    utils::env::require_env,
+};
+use anyhow::Context;
+use lettre::{
+    message::{header::ContentType, Mailbox},
+    transport::smtp::authentication::Credentials,
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+use moka::future::Cache;
+use sailfish::TemplateOnce;
+use std::{sync::Arc, time::Duration};
+use thiserror::Error;
+use tracing::{debug, warn};
+
+/// Environment variables for configuring the mailer
+const SMTP_HOST: &str = "SMTP_HOST";
+const SMTP_PORT: &str = "SMTP_PORT";
+const SMTP_SECURITY: &str = "SMTP_SECURITY";
+const SMTP_USERNAME: &str = "SMTP_USERNAME";
+const SMTP_PASSWORD: &str = "SMTP_PASSWORD";
+const MAIL_FROM: &str = "MAIL_FROM";
+const HUB_BASE_URL: &str = "HUB_BASE_URL";
+
+pub struct MailService {
+    /// The configured mailer, [None] when sending emails is disabled
+    mailer: Option<Mailer>,
+    /// Users that have recently requested an email, used to
+    /// limit how often emails can be requested
+    recent_requests: Cache<(UserId, &'static str), ()>,
+}
+
+/// SMTP details for sending emails
+struct Mailer {
+    /// Transport for sending the emails
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    /// Address the emails are sent from
+    from: Mailbox,
+    /// Base URL of the hub for links within emails
+    hub_url: String,
+}
+
+#[derive(Debug, Error)]
+pub enum MailError {
+    #[error("Failed to render email: {0}")]
+    Render(#[from] sailfish::RenderError),
+    #[error("Invalid email address: {0}")]
+    Address(#[from] lettre::address::AddressError),
+    #[error("Failed to create email: {0}")]
+    Message(#[from] lettre::error::Error),
+    #[error("Failed to send email: {0}")]
+    Transport(#[from] lettre::transport::smtp::Error),
+    #[error("Sending emails is disabled")]
+    Disabled,
+}
+
+/// Template for the email verification email
+#[derive(TemplateOnce)]
+#[template(path = "mail/verify_email.stpl")]
+struct VerifyEmailTemplate<'a> {
+    url: &'a str,
+}
+
+/// Template for the password reset email
+#[derive(TemplateOnce)]
+#[template(path = "mail/forgot_password.stpl")]
+struct ForgotPasswordTemplate<'a> {
+    url: &'a str,
+}
+
+impl MailService {
+    /// Minimum time between requested emails of the same kind for a user
+    const REQUEST_INTERVAL: Duration = Duration::from_secs(60);
+
+    /// Creates the mail service from the SMTP details in the environment,
+    /// SMTP_SECURITY can be "none", "starttls" or "tls" (default). Sending
+    /// emails is disabled when SMTP_HOST isn't set
+    pub fn new() -> anyhow::Result<Arc<Self>> {
+        let Some(host) = std::env::var(SMTP_HOST)
+            .ok()
+            .filter(|value| !value.is_empty())
+        else {
+            warn!(name: "mail_disabled", "SMTP_HOST is not set, sending emails is disabled");
+            return Ok(Self::disabled());
+        };
+        let security = std::env::var(SMTP_SECURITY).unwrap_or_else(|_| "tls".to_string());
+
+        let mut builder = match security.as_str() {
+            "none" => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&host),
+            "starttls" => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&host)
+                .context("Creating SMTP transport")?,
+            "tls" => AsyncSmtpTransport::<Tokio1Executor>::relay(&host)
+                .context("Creating SMTP transport")?,
+            other => anyhow::bail!("Unknown SMTP security \"{other}\""),
+        };
+
+        if let Ok(port) = std::env::var(SMTP_PORT) {
+            builder = builder.port(port.parse().context("Parsing SMTP port")?);
+        }
+
+        let username = std::env::var(SMTP_USERNAME)
+            .ok()
+            .filter(|value| !value.is_empty());
+        let password = std::env::var(SMTP_PASSWORD)
+            .ok()
+            .filter(|value| !value.is_empty());
+
+        if let (Some(username), Some(password)) = (username, password) {
+            builder = builder.credentials(Credentials::new(username, password));
+        }
+
+        let from: Mailbox = require_env(MAIL_FROM)?
+            .parse()
+            .context("Parsing MAIL_FROM address")?;
+        let hub_url = require_env(HUB_BASE_URL)?;
+
+        Ok(Self::with_mailer(Some(Mailer {
+            transport: builder.build(),
+            from,
+            hub_url,
+        })))
+    }
+
+    /// Creates the mail service with sending emails disabled
+    pub fn disabled() -> Arc<Self> {
+        Self::with_mailer(None)
+    }
+
+    fn with_mailer(mailer: Option<Mailer>) -> Arc<Self> {
+        let recent_requests = Cache::builder()
+            .time_to_live(Self::REQUEST_INTERVAL)
+            .build();
+
+        Arc::new(Self {
+            mailer,
+            recent_requests,
+        })
+    }
+
+    /// Provides the mailer, failing when sending emails is disabled
+    fn mailer(&self) -> Result<&Mailer, MailError> {
+        self.mailer.as_ref().ok_or(MailError::Disabled)
+    }
+
+    /// Checks whether the provided `user` is allowed to request another
+    /// email of the provided `kind`, recording the request when allowed
+    pub async fn try_request(&self, user: &User, kind: &'static str) -> bool {
+        self.recent_requests
+            .entry((user.id, kind))
+            .or_insert(())
+            .await
+            .is_fresh()
+    }
+
+    /// Sends an email to the provided `user` containing a link to verify
+    /// their email address using the provided `token`
+    pub async fn send_verify_email(&self, user: &User, token: &str) -> Result<(), MailError> {
+        // Tokens are JWTs which are already URL safe
+        let url = format!(
+            "{}/auth/verify-email?token={}",
+            self.mailer()?.hub_url,
+            token
+        );
+        let body = VerifyEmailTemplate { url: &url }.render_once()?;
+
+        self.send(user, "Verify your email address", body).await
+    }
+
+    /// Sends an email to the provided `user` containing a link to reset
+    /// their password using the provided `token`
+    pub async fn send_forgot_password(&self, user: &User, token: &str) -> Result<(), MailError> {
+        // Tokens are JWTs which are already URL safe
+        let url = format!(
+            "{}/auth/reset-password?token={}",
+            self.mailer()?.hub_url,
+            token
+        );
+        let body = ForgotPasswordTemplate { url: &url }.render_once()?;
+
+        self.send(user, "Reset your password", body).await
+    }
+
+    /// Sends an email containing the provided HTML `body` to the `user`
+    async fn send(&self, user: &User, subject: &str, body: String) -> Result<(), MailError> {
+        let mailer = self.mailer()?;
+        let message = Message::builder()
+            .from(mailer.from.clone())
+            .to(Mailbox::new(user.name.clone(), user.email.parse()?))
+            .subject(subject)
+            .header(ContentType::TEXT_HTML)
+            .body(body)?;
+
+        mailer.transport.send(message).await?;
+
+        debug!(name: "mail_sent", user_id = user.id, %subject, "Sent email");
+
+        Ok(())
+    }
+}
@@ -1 +1,10 @@
 pub mod auth;
+pub mod domain;
+pub mod game;
+pub mod image;
+pub mod jwt;
+pub mod mail;
+pub mod openid;
+pub mod saml;
+pub mod storage;
+pub mod webauthn;
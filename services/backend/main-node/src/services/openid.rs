@@ -0,0 +1,421 @@
+//! Service for logging in with OpenID providers. Providers are defined
+//! in the environment so new providers can be added without any code
+//! changes, OPENID_PROVIDERS lists the IDs of the providers and each
+//! provider is configured by variables prefixed with `{ID}_OPENID`
+
+use crate::{
+    database::entities::user::UserId,
+    utils::{
+        env::{env_prefixed, require_env, require_env_prefixed},
+        pkce,
+        types::EmailAddress,
+    },
+};
+use anyhow::Context;
+use futures::{stream::FuturesUnordered, StreamExt};
+use indexmap::IndexMap;
+use moka::future::Cache;
+use openid::{Client, CompactJson, CustomClaims, Discovered, Options, StandardClaims};
+use rand::{
+    distributions::{Alphanumeric, DistString},
+    rngs::StdRng,
+    SeedableRng,
+};
+use reqwest::Url;
+use serde::{de::Error as _, Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::{Map, Value};
+use std::{sync::Arc, time::Duration};
+use tracing::{debug, error};
+
+/// Environment variables for configuring OpenID
+const OPENID_PROVIDERS: &str = "OPENID_PROVIDERS";
+const OPENID_REDIRECT_URL: &str = "OPENID_REDIRECT_URL";
+
+/// Scopes requested when a provider doesn't configure its own
+const DEFAULT_SCOPES: &str = "openid profile email";
+
+/// Unique ID of a configured provider, stored on user links
+pub type ProviderId = String;
+
+/// OpenID client for a provider
+pub type OpenIdClient = Client<Discovered, ProviderClaims>;
+
+/// Alias for a OpenID client that is shared
+type SharedClient = Arc<OpenIdClient>;
+
+pub struct OpenIdService {
+    /// Configured providers by ID in the order they were defined
+    providers: IndexMap<ProviderId, Arc<ProviderConfig>>,
+    /// Clients for the providers, created through discovery
+    clients: Cache<ProviderId, SharedClient>,
+    /// OpenID logins that have been started by their random state,
+    /// kept until the provider redirects back to complete the login
+    logins: Cache<String, OpenIdLogin>,
+    /// Account creations waiting for the user to choose their account
+    /// details by their random token
+    signups: Cache<String, OpenIdSignup>,
+    /// URL the providers redirect back to
+    redirect_url: String,
+}
+
+/// Configuration for an OpenID provider
+#[derive(Debug)]
+pub struct ProviderConfig {
+    /// Unique ID of the provider
+    pub id: ProviderId,
+    /// Name of the provider shown to users
+    pub name: String,
+    /// Optional icon shown alongside the provider name
+    pub icon_url: Option<Url>,
+    /// Issuer URL used for discovery
+    issuer: Url,
+    /// Client credentials registered with the provider
+    client_id: String,
+    client_secret: String,
+    /// Space separated scopes to request
+    scopes: String,
+    /// Claims the account details are read from
+    pub claims: ClaimMapping,
+}
+
+/// Names of the claims account details are read from, providers don't
+/// always use the standard claims for these
+#[derive(Debug)]
+pub struct ClaimMapping {
+    /// Claim containing the email address
+    email: String,
+    /// Claim containing whether the email address is verified
+    email_verified: String,
+    /// Claim containing the preferred username
+    username: String,
+}
+
+/// OpenID login that was started and is waiting for the provider
+/// to redirect back with a code
+#[derive(Debug, Clone)]
+pub struct OpenIdLogin {
+    /// The provider the login was started with
+    pub provider: ProviderId,
+    /// Nonce the provider must include in the ID token
+    pub nonce: String,
+    /// PKCE verifier the code must be exchanged with
+    pub code_verifier: String,
+    /// User that started the login to link the provider to their account,
+    /// logins started without a user can only be used to login
+    pub user_id: Option<UserId>,
+}
+
+/// Provider account that completed a login without being linked to a
+/// user, kept until the user chooses the details for their new account.
+/// The claims are taken from the ID token validated during the login so
+/// the client never provides them
+#[derive(Debug, Clone)]
+pub struct OpenIdSignup {
+    /// The provider the account is from
+    pub provider: ProviderId,
+    /// Issuer of the provider account
+    pub issuer: String,
+    /// Subject identifying the provider account
+    pub subject: String,
+    /// Email address of the provider account
+    pub email: EmailAddress,
+    /// Whether the provider has verified the email address
+    pub email_verified: bool,
+}
+
+/// ID token claims from a provider, the raw claims are kept so
+/// that providers can map account details from any claim
+#[derive(Debug, Clone)]
+pub struct ProviderClaims {
+    /// The standard claims
+    standard: StandardClaims,
+    /// All the claims present in the token
+    raw: Map<String, Value>,
+}
+
+impl OpenIdService {
+    /// OpenID logins must be completed within 10 minutes of being started
+    const LOGIN_EXPIRY: Duration = Duration::from_secs(60 * 10);
+    /// Maximum number of OpenID logins waiting to be completed
+    const MAX_LOGINS: u64 = 10_000;
+    /// Length of the OpenID state and nonce
+    const STATE_LENGTH: usize = 32;
+
+    /// Creates the OpenID service from the providers defined in the
+    /// environment and initializes the providers in the background
+    pub fn new() -> anyhow::Result<Arc<Self>> {
+        let provider_ids = std::env::var(OPENID_PROVIDERS).unwrap_or_default();
+
+        let mut providers = IndexMap::new();
+        for id in provider_ids
+            .split(',')
+            .map(str::trim)
+            .filter(|id| !id.is_empty())
+        {
+            let config = ProviderConfig::from_env(id)
+                .with_context(|| format!("Loading OpenID provider \"{id}\""))?;
+            providers.insert(config.id.clone(), Arc::new(config));
+        }
+
+        let redirect_url = if providers.is_empty() {
+            String::new()
+        } else {
+            require_env(OPENID_REDIRECT_URL)?
+        };
+
+        let clients = Cache::builder()
+            // Refresh providers every 48 hours
+            .time_to_live(Duration::from_secs(60 * 60 * 48))
+            .initial_capacity(providers.len())
+            .build();
+
+        let logins = Cache::builder()
+            .time_to_live(Self::LOGIN_EXPIRY)
+            .max_capacity(Self::MAX_LOGINS)
+            .build();
+
+        let signups = Cache::builder()
+            .time_to_live(Self::LOGIN_EXPIRY)
+            .max_capacity(Self::MAX_LOGINS)
+            .build();
+
+        let service = Arc::new(Self {
+            providers,
+            clients,
+            logins,
+            signups,
+            redirect_url,
+        });
+
+        let init_service = service.clone();
+
+        // Initial load of the auth providers ahead of time
+        tokio::spawn(async move {
+            init_service
+                .providers()
+                .map(|provider| init_service.get_client(provider))
+                .collect::<FuturesUnordered<_>>()
+                .collect::<Vec<_>>()
+                .await;
+        });
+
+        Ok(service)
+    }
+
+    /// Provides all the configured providers
+    pub fn providers(&self) -> impl Iterator<Item = &Arc<ProviderConfig>> {
+        self.providers.values()
+    }
+
+    /// Finds the configured provider with the provided `id`
+    pub fn get_provider(&self, id: &str) -> Option<&Arc<ProviderConfig>> {
+        self.providers.get(id)
+    }
+
+    /// Attempts to get the client for the `provider` from the cache, will
+    /// initialize the client if it is expired or not initialized
+    pub async fn get_client(&self, provider: &ProviderConfig) -> Option<SharedClient> {
+        match self
+            .clients
+            .try_get_with(
+                provider.id.clone(),
+                provider.create_client(self.redirect_url.clone()),
+            )
+            .await
+        {
+            Ok(value) => Some(value),
+            Err(error) => {
+                error!(name: "err_initialize_provider", provider = %provider.id, %error, "Failed to initialize auth provider");
+                None
+            }
+        }
+    }
+
+    /// Starts an OpenID login with the `provider` using its `client`,
+    /// responds with the URL to send the user to. A random state, nonce
+    /// and PKCE verifier are created for the login and stored until the
+    /// login is completed. Logins started by a `user_id` are for linking
+    /// the provider to that user
+    pub async fn start_login(
+        &self,
+        provider: &ProviderConfig,
+        client: &OpenIdClient,
+        user_id: Option<UserId>,
+    ) -> Url {
+        let mut rng = StdRng::from_entropy();
+        let state = Alphanumeric.sample_string(&mut rng, Self::STATE_LENGTH);
+        let nonce = Alphanumeric.sample_string(&mut rng, Self::STATE_LENGTH);
+        let code_verifier = pkce::generate_code_verifier();
+
+        let mut auth_url = client.auth_url(&Options {
+            scope: Some(provider.scopes.clone()),
+            state: Some(state.clone()),
+            nonce: Some(nonce.clone()),
+            ..Default::default()
+        });
+        pkce::append_code_challenge(&mut auth_url, &code_verifier);
+
+        self.logins
+            .insert(
+                state,
+                OpenIdLogin {
+                    provider: provider.id.clone(),
+                    nonce,
+                    code_verifier,
+                    user_id,
+                },
+            )
+            .await;
+
+        auth_url
+    }
+
+    /// Takes the OpenID login that was started with the provided `state`,
+    /// each login can only be completed once. The login must have been
+    /// started by the same `user_id` so that a link started by one user
+    /// can't be completed by another
+    pub async fn take_login(&self, state: &str, user_id: Option<UserId>) -> Option<OpenIdLogin> {
+        self.logins
+            .remove(state)
+            .await
+            .filter(|login| login.user_id == user_id)
+    }
+
+    /// Stores the `signup` until the user has chosen their account details,
+    /// responds with the random token the account is created with
+    pub async fn create_signup(&self, signup: OpenIdSignup) -> String {
+        let token = Alphanumeric.sample_string(&mut StdRng::from_entropy(), Self::STATE_LENGTH);
+        self.signups.insert(token.clone(), signup).await;
+        token
+    }
+
+    /// Takes the signup stored with the provided `token`, each signup
+    /// can only be used to create one account
+    pub async fn take_signup(&self, token: &str) -> Option<OpenIdSignup> {
+        self.signups.remove(token).await
+    }
+}
+
+impl ProviderConfig {
+    /// Issuer the provider was configured with
+    pub fn issuer(&self) -> &Url {
+        &self.issuer
+    }
+
+    /// Loads the configuration for the provider with the provided `id`
+    /// from the environment variables prefixed with `{ID}_OPENID`
+    fn from_env(id: &str) -> anyhow::Result<Self> {
+        anyhow::ensure!(
+            id.chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_'),
+            "Provider IDs must only contain lowercase letters, digits, '-' and '_'"
+        );
+
+        let prefix = format!("{}_OPENID", id.to_ascii_uppercase().replace('-', "_"));
+
+        let issuer: Url = require_env_prefixed(&prefix, "ISSUER")?
+            .parse()
+            .context("Parsing issuer URL")?;
+        let icon_url: Option<Url> = env_prefixed(&prefix, "ICON_URL")
+            .map(|value| value.parse())
+            .transpose()
+            .context("Parsing icon URL")?;
+
+        Ok(Self {
+            id: id.to_string(),
+            name: env_prefixed(&prefix, "NAME").unwrap_or_else(|| id.to_string()),
+            icon_url,
+            issuer,
+            client_id: require_env_prefixed(&prefix, "CLIENT_ID")?,
+            client_secret: require_env_prefixed(&prefix, "CLIENT_SECRET")?,
+            scopes: env_prefixed(&prefix, "SCOPES").unwrap_or_else(|| DEFAULT_SCOPES.to_string()),
+            claims: ClaimMapping {
+                email: env_prefixed(&prefix, "EMAIL_CLAIM").unwrap_or_else(|| "email".to_string()),
+                email_verified: env_prefixed(&prefix, "EMAIL_VERIFIED_CLAIM")
+                    .unwrap_or_else(|| "email_verified".to_string()),
+                username: env_prefixed(&prefix, "USERNAME_CLAIM")
+                    .unwrap_or_else(|| "preferred_username".to_string()),
+            },
+        })
+    }
+
+    /// Creates a new client for the provider through discovery
+    async fn create_client(&self, redirect_url: String) -> anyhow::Result<SharedClient> {
+        let client = OpenIdClient::discover(
+            self.client_id.clone(),
+            self.client_secret.clone(),
+            redirect_url,
+            self.issuer.clone(),
+        )
+        .await
+        .context("Failed to initialize OpenID client")?;
+
+        debug!(name: "start_auth_provider", provider = %self.id, "Started auth provider");
+
+        Ok(Arc::new(client))
+    }
+}
+
+impl ClaimMapping {
+    /// Reads the email address from the `claims`
+    pub fn email<'a>(&self, claims: &'a ProviderClaims) -> Option<&'a str> {
+        claims.raw.get(&self.email).and_then(Value::as_str)
+    }
+
+    /// Reads whether the email address is verified from the `claims`,
+    /// some providers send the value as a string
+    pub fn email_verified(&self, claims: &ProviderClaims) -> bool {
+        match claims.raw.get(&self.email_verified) {
+            Some(Value::Bool(value)) => *value,
+            Some(Value::String(value)) => value == "true",
+            _ => false,
+        }
+    }
+
+    /// Reads the preferred username from the `claims`
+    pub fn username<'a>(&self, claims: &'a ProviderClaims) -> Option<&'a str> {
+        claims.raw.get(&self.username).and_then(Value::as_str)
+    }
+}
+
+impl ProviderClaims {
+    /// Subject identifying the account at the provider
+    pub fn subject(&self) -> &str {
+        &self.standard.sub
+    }
+
+    /// Issuer of the token
+    pub fn issuer(&self) -> &Url {
+        &self.standard.iss
+    }
+}
+
+impl CustomClaims for ProviderClaims {
+    fn standard_claims(&self) -> &StandardClaims {
+        &self.standard
+    }
+}
+
+impl CompactJson for ProviderClaims {}
+
+impl Serialize for ProviderClaims {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        self.raw.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for ProviderClaims {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let raw = Map::deserialize(deserializer)?;
+        let standard =
+            StandardClaims::deserialize(Value::Object(raw.clone())).map_err(D::Error::custom)?;
+
+        Ok(Self { standard, raw })
+    }
+}
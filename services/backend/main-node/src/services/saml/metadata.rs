@@ -0,0 +1,159 @@
+//! Reading identity provider metadata and creating the metadata published
+//! for the service provider side of each connection
+
+use super::xml::{escape, MD_NS};
+use anyhow::Context;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use openssl::x509::X509;
+use roxmltree::{Document, Node};
+
+/// Binding used to send authentication requests to identity providers
+pub const HTTP_REDIRECT_BINDING: &str = "urn:oasis:names:tc:SAML:2.0:bindings:HTTP-Redirect";
+/// Binding identity providers use to post responses back
+pub const HTTP_POST_BINDING: &str = "urn:oasis:names:tc:SAML:2.0:bindings:HTTP-POST";
+
+/// Details of an identity provider read from its metadata
+#[derive(Debug)]
+pub struct IdentityProvider {
+    /// Entity ID responses must be issued by
+    pub entity_id: String,
+    /// URL authentication requests are redirected to
+    pub sso_url: String,
+    /// Certificates trusted to sign responses
+    pub signing_certs: Vec<X509>,
+}
+
+impl IdentityProvider {
+    /// Reads the identity provider from its `metadata` XML, the metadata
+    /// must contain signing certificates and support the HTTP-Redirect
+    /// binding for authentication requests
+    pub fn from_metadata(metadata: &str) -> anyhow::Result<Self> {
+        let document = Document::parse(metadata).context("Parsing metadata XML")?;
+
+        // Metadata may be a single entity or a group containing the entity
+        let entity = document
+            .descendants()
+            .find(|node| {
+                node.has_tag_name((MD_NS, "EntityDescriptor"))
+                    && node
+                        .children()
+                        .any(|child| child.has_tag_name((MD_NS, "IDPSSODescriptor")))
+            })
+            .context("Metadata is missing an identity provider")?;
+
+        let entity_id = entity
+            .attribute("entityID")
+            .filter(|value| !value.is_empty())
+            .context("Metadata is missing an entity ID")?
+            .to_string();
+
+        let descriptor = entity
+            .children()
+            .find(|child| child.has_tag_name((MD_NS, "IDPSSODescriptor")))
+            .context("Metadata is missing an identity provider")?;
+
+        let sso_url = descriptor
+            .children()
+            .find(|child| {
+                child.has_tag_name((MD_NS, "SingleSignOnService"))
+                    && child.attribute("Binding") == Some(HTTP_REDIRECT_BINDING)
+            })
+            .and_then(|child| child.attribute("Location"))
+            .context("Identity provider doesn't support the HTTP-Redirect binding")?
+            .to_string();
+
+        let signing_certs = descriptor
+            .children()
+            .filter(|child| {
+                child.has_tag_name((MD_NS, "KeyDescriptor"))
+                    // Keys without a use are used for both signing and encryption
+                    && child.attribute("use").is_none_or(|value| value == "signing")
+            })
+            .flat_map(|child| child.descendants())
+            .filter(|node| node.has_tag_name((super::signature::DSIG_NS, "X509Certificate")))
+            .map(parse_certificate)
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        anyhow::ensure!(
+            !signing_certs.is_empty(),
+            "Identity provider metadata is missing signing certificates"
+        );
+
+        Ok(Self {
+            entity_id,
+            sso_url,
+            signing_certs,
+        })
+    }
+}
+
+/// Parses the base64 DER certificate contained in the `node`
+fn parse_certificate(node: Node) -> anyhow::Result<X509> {
+    let value: String = node
+        .text()
+        .unwrap_or_default()
+        .chars()
+        .filter(|c| !c.is_ascii_whitespace())
+        .collect();
+
+    let der = STANDARD
+        .decode(value)
+        .context("Invalid identity provider certificate")?;
+
+    X509::from_der(&der).context("Invalid identity provider certificate")
+}
+
+/// Creates the metadata XML for a service provider with the `entity_id`
+/// that accepts responses at the `acs_url`
+pub fn service_provider_metadata(entity_id: &str, acs_url: &str) -> String {
+    format!(
+        concat!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>"#,
+            r#"<md:EntityDescriptor xmlns:md="{md}" entityID="{entity_id}">"#,
+            r#"<md:SPSSODescriptor AuthnRequestsSigned="false" WantAssertionsSigned="true" "#,
+            r#"protocolSupportEnumeration="urn:oasis:names:tc:SAML:2.0:protocol">"#,
+            r#"<md:AssertionConsumerService Binding="{binding}" Location="{acs_url}" index="0" isDefault="true"/>"#,
+            r#"</md:SPSSODescriptor>"#,
+            r#"</md:EntityDescriptor>"#
+        ),
+        md = MD_NS,
+        entity_id = escape(entity_id),
+        binding = HTTP_POST_BINDING,
+        acs_url = escape(acs_url),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::{service_provider_metadata, IdentityProvider};
+
+    #[test]
+    fn test_identity_provider_metadata() {
+        let idp = IdentityProvider::from_metadata(include_str!(
+            "../../../tests/fixtures/saml/idp_metadata.xml"
+        ))
+        .unwrap();
+
+        assert_eq!(idp.entity_id, "https://idp.acme.test/saml");
+        assert_eq!(idp.sso_url, "https://idp.acme.test/saml/sso?tenant=acme");
+        assert_eq!(idp.signing_certs.len(), 2);
+    }
+
+    #[test]
+    fn test_service_provider_metadata() {
+        let metadata = service_provider_metadata(
+            "https://quizler.test/auth/saml/acme/metadata",
+            "https://quizler.test/auth/saml/acme/acs?a=1&b=2",
+        );
+
+        let document = roxmltree::Document::parse(&metadata).unwrap();
+        let acs = document
+            .descendants()
+            .find(|node| node.has_tag_name("AssertionConsumerService"))
+            .unwrap();
+        assert_eq!(
+            acs.attribute("Location"),
+            Some("https://quizler.test/auth/saml/acme/acs?a=1&b=2")
+        );
+    }
+}
@@ -0,0 +1,566 @@
+//! Service for single sign-on through SAML 2.0 identity providers, each
+//! organization using SAML has its own connection defined in the
+//! environment. SAML_CONNECTIONS lists the IDs of the connections and
+//! each connection is configured by variables prefixed with `{ID}_SAML`
+//!
+//! Quizler acts as the service provider, logins are started with an
+//! AuthnRequest using the HTTP-Redirect binding and the identity provider
+//! posts the signed response back to the assertion consumer service
+
+use self::{
+    metadata::{service_provider_metadata, IdentityProvider},
+    request::AuthnRequest,
+    response::{validate_response, Assertion, ExpectedResponse},
+};
+use crate::{
+    database::entities::{organization::OrganizationId, user::UserId},
+    utils::env::{env_prefixed, require_env},
+};
+use anyhow::Context;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::Utc;
+use futures::{stream::FuturesUnordered, StreamExt};
+use indexmap::IndexMap;
+use moka::future::Cache;
+use rand::{
+    distributions::{Alphanumeric, DistString},
+    rngs::StdRng,
+    SeedableRng,
+};
+use reqwest::Url;
+use std::{path::PathBuf, sync::Arc, time::Duration};
+use thiserror::Error;
+use tracing::{debug, error, warn};
+
+mod metadata;
+mod request;
+mod response;
+mod signature;
+mod xml;
+
+/// Environment variables for configuring SAML
+const SAML_CONNECTIONS: &str = "SAML_CONNECTIONS";
+const SAML_SP_BASE_URL: &str = "SAML_SP_BASE_URL";
+const HUB_BASE_URL: &str = "HUB_BASE_URL";
+
+/// NameID format used when the subject is an email address
+const EMAIL_NAME_ID_FORMAT: &str = "urn:oasis:names:tc:SAML:1.1:nameid-format:emailAddress";
+
+/// Unique ID of a configured connection
+pub type ConnectionId = String;
+
+/// Alias for an identity provider that is shared
+type SharedIdentityProvider = Arc<IdentityProvider>;
+
+pub struct SamlService {
+    /// Configured connections by ID
+    connections: IndexMap<ConnectionId, Arc<SamlConnection>>,
+    /// Identity providers for the connections, loaded from their metadata
+    providers: Cache<ConnectionId, SharedIdentityProvider>,
+    /// Logins that have been started by their random relay state, kept
+    /// until the identity provider posts back the response
+    logins: Cache<String, SamlLogin>,
+    /// Single use codes the hub exchanges for a token once a login
+    /// has completed
+    login_codes: Cache<String, SamlLoginCode>,
+    /// Base URL the service provider routes are publicly available at
+    base_url: String,
+    /// URL of the hub page that completes logins, [None] when there
+    /// aren't any connections configured
+    complete_url: Option<Url>,
+}
+
+/// Configuration for a SAML connection
+#[derive(Debug)]
+pub struct SamlConnection {
+    /// Unique ID of the connection
+    pub id: ConnectionId,
+    /// Name of the organization shown to users
+    pub name: String,
+    /// Organization that owns the connection, the identity provider is
+    /// only trusted to verify emails on the domains it has verified
+    pub organization_id: Option<OrganizationId>,
+    /// Where the identity provider metadata is loaded from
+    metadata: MetadataSource,
+    /// Attributes the account details are read from
+    attributes: AttributeMapping,
+}
+
+/// Source of the metadata for an identity provider
+#[derive(Debug)]
+enum MetadataSource {
+    /// Metadata published by the identity provider
+    Url(Url),
+    /// Metadata file downloaded from the identity provider
+    File(PathBuf),
+}
+
+/// Names of the attributes account details are read from, each
+/// identity provider names these differently
+#[derive(Debug)]
+struct AttributeMapping {
+    /// Attribute containing the email address
+    email: String,
+    /// Attribute containing the username
+    username: String,
+    /// Attribute containing the display name
+    name: String,
+}
+
+/// Login that was started and is waiting for the identity provider
+#[derive(Debug, Clone)]
+struct SamlLogin {
+    /// The connection the login was started with
+    connection: ConnectionId,
+    /// ID of the AuthnRequest the response must be in response to
+    request_id: String,
+}
+
+/// Login that has completed and is waiting for the hub to exchange its code
+#[derive(Debug, Clone)]
+struct SamlLoginCode {
+    /// The user that logged in
+    user_id: UserId,
+    /// Relay state the login was started with, only the browser that
+    /// started the login knows it
+    relay_state: String,
+}
+
+/// Identity asserted by an identity provider
+#[derive(Debug)]
+pub struct SamlIdentity {
+    /// Entity ID of the identity provider
+    pub issuer: String,
+    /// NameID identifying the account at the identity provider
+    pub subject: String,
+    /// Email address of the account
+    pub email: Option<String>,
+    /// Preferred username of the account
+    pub username: Option<String>,
+    /// Display name of the account
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Error)]
+pub enum SamlError {
+    /// Connection isn't configured
+    #[error("Unknown connection")]
+    UnknownConnection,
+    /// Identity provider metadata couldn't be loaded
+    #[error("Identity provider unavailable")]
+    ProviderUnavailable,
+    /// Login doesn't exist, has expired or was already completed
+    #[error("Unknown login")]
+    UnknownLogin,
+    /// Response failed signature, audience or other validation
+    #[error("Invalid response")]
+    InvalidResponse,
+}
+
+impl SamlService {
+    /// Logins must be completed within 10 minutes of being started
+    const LOGIN_EXPIRY: Duration = Duration::from_secs(60 * 10);
+    /// Maximum number of logins waiting to be completed
+    const MAX_LOGINS: u64 = 10_000;
+    /// Login codes must be exchanged within a minute
+    const LOGIN_CODE_EXPIRY: Duration = Duration::from_secs(60);
+    /// Length of the relay state, request IDs and login codes
+    const STATE_LENGTH: usize = 32;
+
+    /// Creates the SAML service from the connections defined in the
+    /// environment and loads the identity providers in the background
+    pub fn new() -> anyhow::Result<Arc<Self>> {
+        let connection_ids = std::env::var(SAML_CONNECTIONS).unwrap_or_default();
+
+        let mut connections = IndexMap::new();
+        for id in connection_ids
+            .split(',')
+            .map(str::trim)
+            .filter(|id| !id.is_empty())
+        {
+            let config = SamlConnection::from_env(id)
+                .with_context(|| format!("Loading SAML connection \"{id}\""))?;
+            connections.insert(config.id.clone(), Arc::new(config));
+        }
+
+        let (base_url, complete_url) = if connections.is_empty() {
+            (String::new(), None)
+        } else {
+            let base_url = require_env(SAML_SP_BASE_URL)?
+                .trim_end_matches('/')
+                .to_string();
+            let complete_url = format!(
+                "{}/auth/saml/complete",
+                require_env(HUB_BASE_URL)?.trim_end_matches('/')
+            )
+            .parse()
+            .context("Parsing HUB_BASE_URL")?;
+
+            (base_url, Some(complete_url))
+        };
+
+        let providers = Cache::builder()
+            // Reload identity provider metadata every 48 hours
+            .time_to_live(Duration::from_secs(60 * 60 * 48))
+            .initial_capacity(connections.len())
+            .build();
+
+        let logins = Cache::builder()
+            .time_to_live(Self::LOGIN_EXPIRY)
+            .max_capacity(Self::MAX_LOGINS)
+            .build();
+
+        let login_codes = Cache::builder()
+            .time_to_live(Self::LOGIN_CODE_EXPIRY)
+            .max_capacity(Self::MAX_LOGINS)
+            .build();
+
+        let service = Arc::new(Self {
+            connections,
+            providers,
+            logins,
+            login_codes,
+            base_url,
+            complete_url,
+        });
+
+        let init_service = service.clone();
+
+        // Initial load of the identity providers ahead of time
+        tokio::spawn(async move {
+            init_service
+                .connections
+                .values()
+                .map(|connection| init_service.get_provider(connection))
+                .collect::<FuturesUnordered<_>>()
+                .collect::<Vec<_>>()
+                .await;
+        });
+
+        Ok(service)
+    }
+
+    /// Finds the configured connection with the provided `id`
+    pub fn get_connection(&self, id: &str) -> Result<&Arc<SamlConnection>, SamlError> {
+        self.connections.get(id).ok_or(SamlError::UnknownConnection)
+    }
+
+    /// Creates a random value for use as a relay state, request ID or
+    /// login code
+    fn create_state() -> String {
+        Alphanumeric.sample_string(&mut StdRng::from_entropy(), Self::STATE_LENGTH)
+    }
+
+    /// Attempts to get the identity provider for the `connection` from the
+    /// cache, will load the identity provider metadata if it is expired
+    /// or not loaded
+    async fn get_provider(
+        &self,
+        connection: &SamlConnection,
+    ) -> Result<SharedIdentityProvider, SamlError> {
+        self.providers
+            .try_get_with(connection.id.clone(), connection.load_provider())
+            .await
+            .map_err(|error| {
+                error!(name: "err_initialize_saml", connection = %connection.id, %error, "Failed to initialize SAML connection");
+                SamlError::ProviderUnavailable
+            })
+    }
+
+    /// Creates the service provider metadata XML for the `connection`
+    pub fn metadata(&self, connection: &SamlConnection) -> String {
+        service_provider_metadata(
+            &connection.entity_id(&self.base_url),
+            &connection.acs_url(&self.base_url),
+        )
+    }
+
+    /// Starts a login with the identity provider of the `connection`,
+    /// responds with the URL to send the user to. The ID of the request
+    /// is stored against a random relay state until the login completes
+    pub async fn start_login(&self, connection: &SamlConnection) -> Result<Url, SamlError> {
+        let provider = self.get_provider(connection).await?;
+
+        // XML IDs can't start with a digit so the random ID is prefixed
+        let request_id = format!("id-{}", Self::create_state());
+        let relay_state = Self::create_state();
+
+        let auth_url = AuthnRequest {
+            id: &request_id,
+            issuer: &connection.entity_id(&self.base_url),
+            acs_url: &connection.acs_url(&self.base_url),
+            destination: &provider.sso_url,
+            issue_instant: Utc::now(),
+        }
+        .redirect_url(&relay_state)
+        .map_err(|error| {
+            error!(name: "err_saml_request", connection = %connection.id, %error, "Failed to create SAML request");
+            SamlError::ProviderUnavailable
+        })?;
+
+        self.logins
+            .insert(
+                relay_state,
+                SamlLogin {
+                    connection: connection.id.clone(),
+                    request_id,
+                },
+            )
+            .await;
+
+        Ok(auth_url)
+    }
+
+    /// Completes the login started with the `relay_state` by validating
+    /// the base64 encoded `response` from the identity provider of the
+    /// `connection`, responds with the identity that was asserted
+    pub async fn finish_login(
+        &self,
+        connection: &SamlConnection,
+        relay_state: &str,
+        response: &str,
+    ) -> Result<SamlIdentity, SamlError> {
+        let login = self
+            .logins
+            .remove(relay_state)
+            .await
+            // Responses must be posted to the connection the login started with
+            .filter(|login| login.connection == connection.id)
+            .ok_or(SamlError::UnknownLogin)?;
+
+        let provider = self.get_provider(connection).await?;
+
+        // Posted responses are base64 encoded and may be wrapped across lines
+        let response: String = response
+            .chars()
+            .filter(|c| !c.is_ascii_whitespace())
+            .collect();
+        let response = STANDARD
+            .decode(response)
+            .ok()
+            .and_then(|response| String::from_utf8(response).ok())
+            .ok_or(SamlError::InvalidResponse)?;
+
+        // Verifies the signature, issuer, destination, audience, validity
+        // period and that the response is for the request that was sent
+        let assertion = validate_response(
+            &response,
+            &provider,
+            &ExpectedResponse {
+                entity_id: &connection.entity_id(&self.base_url),
+                acs_url: &connection.acs_url(&self.base_url),
+                request_id: &login.request_id,
+                now: Utc::now(),
+            },
+        )
+        .map_err(|error| {
+            warn!(name: "saml_invalid_response", connection = %connection.id, %error, "SAML response failed validation");
+            SamlError::InvalidResponse
+        })?;
+
+        connection
+            .attributes
+            .identity(assertion)
+            .ok_or(SamlError::InvalidResponse)
+    }
+
+    /// Creates a single use code for completing the login started with
+    /// the `relay_state` as `user_id` from the hub
+    pub async fn create_login_code(&self, user_id: UserId, relay_state: String) -> String {
+        let code = Self::create_state();
+        self.login_codes
+            .insert(
+                code.clone(),
+                SamlLoginCode {
+                    user_id,
+                    relay_state,
+                },
+            )
+            .await;
+        code
+    }
+
+    /// Takes the user a login code was created for, each code can only
+    /// be used once. The code must be exchanged with the `relay_state`
+    /// the login was started with so that a code can't be used from a
+    /// browser that didn't start the login
+    pub async fn take_login_code(&self, code: &str, relay_state: &str) -> Option<UserId> {
+        self.login_codes
+            .remove(code)
+            .await
+            .filter(|login| login.relay_state == relay_state)
+            .map(|login| login.user_id)
+    }
+
+    /// Creates the URL of the hub page that completes a login, the page
+    /// is given either the login code or the name of the error. [None]
+    /// when there aren't any connections configured
+    pub fn complete_url(&self, result: Result<&str, &str>) -> Option<Url> {
+        let mut url = self.complete_url.clone()?;
+        match result {
+            Ok(code) => url.query_pairs_mut().append_pair("code", code),
+            Err(error) => url.query_pairs_mut().append_pair("error", error),
+        };
+        Some(url)
+    }
+}
+
+impl SamlConnection {
+    /// Loads the configuration for the connection with the provided `id`
+    /// from the environment variables prefixed with `{ID}_SAML`
+    fn from_env(id: &str) -> anyhow::Result<Self> {
+        anyhow::ensure!(
+            id.chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_'),
+            "Connection IDs must only contain lowercase letters, digits, '-' and '_'"
+        );
+
+        let prefix = format!("{}_SAML", id.to_ascii_uppercase().replace('-', "_"));
+
+        let metadata = match (
+            env_prefixed(&prefix, "IDP_METADATA_URL"),
+            env_prefixed(&prefix, "IDP_METADATA_FILE"),
+        ) {
+            (Some(url), _) => MetadataSource::Url(url.parse().context("Parsing metadata URL")?),
+            (None, Some(path)) => MetadataSource::File(PathBuf::from(path)),
+            (None, None) => {
+                anyhow::bail!("Missing {prefix}_IDP_METADATA_URL or {prefix}_IDP_METADATA_FILE")
+            }
+        };
+
+        let organization_id = env_prefixed(&prefix, "ORGANIZATION_ID")
+            .map(|value| value.parse().context("Parsing organization ID"))
+            .transpose()?;
+
+        Ok(Self {
+            id: id.to_string(),
+            name: env_prefixed(&prefix, "NAME").unwrap_or_else(|| id.to_string()),
+            organization_id,
+            metadata,
+            attributes: AttributeMapping {
+                email: env_prefixed(&prefix, "EMAIL_ATTRIBUTE")
+                    .unwrap_or_else(|| "email".to_string()),
+                username: env_prefixed(&prefix, "USERNAME_ATTRIBUTE")
+                    .unwrap_or_else(|| "username".to_string()),
+                name: env_prefixed(&prefix, "NAME_ATTRIBUTE").unwrap_or_else(|| "name".to_string()),
+            },
+        })
+    }
+
+    /// ID of the provider stored on the user links for this connection,
+    /// prefixed so that it can't collide with OpenID provider IDs
+    pub fn provider_id(&self) -> String {
+        format!("saml:{}", self.id)
+    }
+
+    /// Entity ID of the service provider, this is the URL its
+    /// metadata is available at
+    fn entity_id(&self, base_url: &str) -> String {
+        format!("{base_url}/auth/saml/{}/metadata", self.id)
+    }
+
+    /// URL of the assertion consumer service responses are posted to
+    fn acs_url(&self, base_url: &str) -> String {
+        format!("{base_url}/auth/saml/{}/acs", self.id)
+    }
+
+    /// Loads the identity provider for the connection from its metadata
+    async fn load_provider(&self) -> anyhow::Result<SharedIdentityProvider> {
+        let metadata = match &self.metadata {
+            MetadataSource::Url(url) => reqwest::get(url.clone())
+                .await
+                .and_then(|response| response.error_for_status())
+                .context("Requesting identity provider metadata")?
+                .text()
+                .await
+                .context("Reading identity provider metadata")?,
+            MetadataSource::File(path) => tokio::fs::read_to_string(path)
+                .await
+                .context("Reading identity provider metadata")?,
+        };
+
+        // Responses are only verified when the metadata contains signing
+        // certificates, connections without them are refused entirely
+        let provider = IdentityProvider::from_metadata(&metadata)
+            .context("Loading identity provider metadata")?;
+
+        debug!(name: "start_saml_connection", connection = %self.id, "Started SAML connection");
+
+        Ok(Arc::new(provider))
+    }
+}
+
+impl AttributeMapping {
+    /// Reads the identity from a validated `assertion`, the email address
+    /// falls back to the NameID when it uses the email address format
+    fn identity(&self, assertion: Assertion) -> Option<SamlIdentity> {
+        if assertion.name_id.is_empty() {
+            return None;
+        }
+
+        let mut identity = SamlIdentity {
+            issuer: assertion.issuer,
+            subject: assertion.name_id,
+            email: None,
+            username: None,
+            name: None,
+        };
+
+        for attribute in assertion.attributes {
+            // Attributes can be matched by either their name or friendly name
+            let matches = |mapped: &str| {
+                attribute.name == mapped || attribute.friendly_name.as_deref() == Some(mapped)
+            };
+
+            if matches(&self.email) {
+                identity.email = Some(attribute.value);
+            } else if matches(&self.username) {
+                identity.username = Some(attribute.value);
+            } else if matches(&self.name) {
+                identity.name = Some(attribute.value);
+            }
+        }
+
+        if identity.email.is_none()
+            && assertion.name_id_format.as_deref() == Some(EMAIL_NAME_ID_FORMAT)
+        {
+            identity.email = Some(identity.subject.clone());
+        }
+
+        Some(identity)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::SamlService;
+    use indexmap::IndexMap;
+    use moka::future::Cache;
+
+    fn test_service() -> SamlService {
+        SamlService {
+            connections: IndexMap::new(),
+            providers: Cache::builder().build(),
+            logins: Cache::builder().build(),
+            login_codes: Cache::builder().build(),
+            base_url: String::new(),
+            complete_url: None,
+        }
+    }
+
+    /// Login codes can only be exchanged once, using the relay state of
+    /// the login they were created for
+    #[tokio::test]
+    async fn test_login_code_bound_to_state() {
+        let saml = test_service();
+
+        let code = saml.create_login_code(1, "state".to_string()).await;
+        assert_eq!(saml.take_login_code(&code, "state").await, Some(1));
+        assert_eq!(saml.take_login_code(&code, "state").await, None);
+
+        // Using the code from another browser also uses up the code
+        let code = saml.create_login_code(1, "state".to_string()).await;
+        assert_eq!(saml.take_login_code(&code, "other").await, None);
+        assert_eq!(saml.take_login_code(&code, "state").await, None);
+    }
+}
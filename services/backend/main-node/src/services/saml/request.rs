@@ -0,0 +1,105 @@
+//! Authentication requests sent to identity providers using the
+//! HTTP-Redirect binding
+
+use super::{
+    metadata::HTTP_POST_BINDING,
+    xml::{escape, SAMLP_NS, SAML_NS},
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::{DateTime, SecondsFormat, Utc};
+use flate2::{write::DeflateEncoder, Compression};
+use reqwest::Url;
+use std::io::Write;
+
+/// Request for the identity provider to authenticate the user
+pub struct AuthnRequest<'a> {
+    /// Unique ID of the request, responses reference this ID
+    pub id: &'a str,
+    /// Entity ID of the service provider
+    pub issuer: &'a str,
+    /// URL the identity provider should post the response to
+    pub acs_url: &'a str,
+    /// URL of the identity provider the request is sent to
+    pub destination: &'a str,
+    /// Time the request was created
+    pub issue_instant: DateTime<Utc>,
+}
+
+impl AuthnRequest<'_> {
+    /// Creates the XML for the request
+    fn to_xml(&self) -> String {
+        format!(
+            concat!(
+                r#"<samlp:AuthnRequest xmlns:samlp="{samlp}" xmlns:saml="{saml}" "#,
+                r#"ID="{id}" Version="2.0" IssueInstant="{issue_instant}" Destination="{destination}" "#,
+                r#"ProtocolBinding="{binding}" AssertionConsumerServiceURL="{acs_url}">"#,
+                r#"<saml:Issuer>{issuer}</saml:Issuer>"#,
+                r#"</samlp:AuthnRequest>"#
+            ),
+            samlp = SAMLP_NS,
+            saml = SAML_NS,
+            id = escape(self.id),
+            issue_instant = self
+                .issue_instant
+                .to_rfc3339_opts(SecondsFormat::Secs, true),
+            destination = escape(self.destination),
+            binding = HTTP_POST_BINDING,
+            acs_url = escape(self.acs_url),
+            issuer = escape(self.issuer),
+        )
+    }
+
+    /// Creates the URL that sends the request to the identity provider
+    /// with the `relay_state`, the request is deflated and base64 encoded
+    /// as required by the HTTP-Redirect binding
+    pub fn redirect_url(&self, relay_state: &str) -> anyhow::Result<Url> {
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(self.to_xml().as_bytes())?;
+        let request = STANDARD.encode(encoder.finish()?);
+
+        let mut url: Url = self.destination.parse()?;
+        url.query_pairs_mut()
+            .append_pair("SAMLRequest", &request)
+            .append_pair("RelayState", relay_state);
+
+        Ok(url)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::AuthnRequest;
+    use base64::{engine::general_purpose::STANDARD, Engine};
+    use chrono::{TimeZone, Utc};
+    use flate2::read::DeflateDecoder;
+    use std::io::Read;
+
+    #[test]
+    fn test_redirect_url() {
+        let request = AuthnRequest {
+            id: "id-request1",
+            issuer: "https://quizler.test/auth/saml/acme/metadata",
+            acs_url: "https://quizler.test/auth/saml/acme/acs",
+            destination: "https://idp.acme.test/saml/sso?tenant=acme",
+            issue_instant: Utc.with_ymd_and_hms(2024, 4, 1, 12, 0, 0).unwrap(),
+        };
+
+        let url = request.redirect_url("state").unwrap();
+        let pairs: Vec<(String, String)> = url.query_pairs().into_owned().collect();
+
+        // Existing query parameters of the destination are preserved
+        assert_eq!(pairs[0], ("tenant".to_string(), "acme".to_string()));
+        assert_eq!(pairs[2], ("RelayState".to_string(), "state".to_string()));
+
+        let deflated = STANDARD.decode(&pairs[1].1).unwrap();
+        let mut xml = String::new();
+        DeflateDecoder::new(deflated.as_slice())
+            .read_to_string(&mut xml)
+            .unwrap();
+
+        let document = roxmltree::Document::parse(&xml).unwrap();
+        let root = document.root_element();
+        assert_eq!(root.attribute("ID"), Some("id-request1"));
+        assert_eq!(root.attribute("IssueInstant"), Some("2024-04-01T12:00:00Z"));
+    }
+}
@@ -0,0 +1,478 @@
+//! Validation of the responses identity providers post to the assertion
+//! consumer service, only a single signed assertion is accepted and the
+//! values read from it always come from the signed element
+
+use super::{
+    metadata::IdentityProvider,
+    signature::{verify_enveloped, SignatureError},
+    xml::{SAMLP_NS, SAML_NS},
+};
+use chrono::{DateTime, Duration, Utc};
+use roxmltree::{Document, Node};
+use thiserror::Error;
+
+/// Status code of successful responses
+const SUCCESS_STATUS: &str = "urn:oasis:names:tc:SAML:2.0:status:Success";
+/// Subject confirmation method used by the web browser SSO profile
+const BEARER_METHOD: &str = "urn:oasis:names:tc:SAML:2.0:cm:bearer";
+/// Allowed difference between the clocks of the identity provider and
+/// the service provider
+const CLOCK_SKEW_SECONDS: i64 = 120;
+
+/// Values a response must have been created for
+pub struct ExpectedResponse<'a> {
+    /// Entity ID of the service provider, required as the audience
+    pub entity_id: &'a str,
+    /// URL of the assertion consumer service the response was posted to
+    pub acs_url: &'a str,
+    /// ID of the authentication request that was sent
+    pub request_id: &'a str,
+    /// Time the response is being validated at
+    pub now: DateTime<Utc>,
+}
+
+/// Validated assertion about the authenticated user
+#[derive(Debug)]
+pub struct Assertion {
+    /// Entity ID of the identity provider that issued the assertion
+    pub issuer: String,
+    /// NameID identifying the user at the identity provider
+    pub name_id: String,
+    /// Format of the NameID
+    pub name_id_format: Option<String>,
+    /// Attributes describing the user
+    pub attributes: Vec<Attribute>,
+}
+
+/// Attribute from the assertion, only the first value is kept
+#[derive(Debug)]
+pub struct Attribute {
+    pub name: String,
+    pub friendly_name: Option<String>,
+    pub value: String,
+}
+
+#[derive(Debug, Error)]
+pub enum ResponseError {
+    #[error("Invalid XML: {0}")]
+    Xml(#[from] roxmltree::Error),
+    #[error("Malformed response: {0}")]
+    Malformed(&'static str),
+    #[error("Unsuccessful status {0}")]
+    Status(String),
+    #[error("Encrypted assertions aren't supported")]
+    Encrypted,
+    #[error("Invalid signature: {0}")]
+    Signature(#[from] SignatureError),
+    #[error("Unexpected issuer")]
+    Issuer,
+    #[error("Unexpected destination")]
+    Destination,
+    #[error("Not in response to the request")]
+    InResponseTo,
+    #[error("Unexpected recipient")]
+    Recipient,
+    #[error("Not intended for this service provider")]
+    Audience,
+    #[error("Assertion has expired")]
+    Expired,
+    #[error("Assertion is not yet valid")]
+    NotYetValid,
+}
+
+/// Validates the response `xml` from the identity provider `idp` was
+/// created for the `expected` request, responds with the assertion
+pub fn validate_response(
+    xml: &str,
+    idp: &IdentityProvider,
+    expected: &ExpectedResponse,
+) -> Result<Assertion, ResponseError> {
+    let document = Document::parse(xml)?;
+    let response = document.root_element();
+
+    if !response.has_tag_name((SAMLP_NS, "Response")) {
+        return Err(ResponseError::Malformed("not a response"));
+    }
+
+    if response.attribute("Version") != Some("2.0") {
+        return Err(ResponseError::Malformed("unsupported version"));
+    }
+
+    if response.attribute("InResponseTo") != Some(expected.request_id) {
+        return Err(ResponseError::InResponseTo);
+    }
+
+    // Destination is only required for signed responses but is checked
+    // whenever it's present
+    if response
+        .attribute("Destination")
+        .is_some_and(|destination| destination != expected.acs_url)
+    {
+        return Err(ResponseError::Destination);
+    }
+
+    let status = child(response, SAMLP_NS, "Status")
+        .and_then(|status| child(status, SAMLP_NS, "StatusCode"))
+        .and_then(|code| code.attribute("Value"))
+        .ok_or(ResponseError::Malformed("missing status"))?;
+    if status != SUCCESS_STATUS {
+        return Err(ResponseError::Status(status.to_string()));
+    }
+
+    if let Some(issuer) = child(response, SAML_NS, "Issuer") {
+        if text(issuer) != idp.entity_id {
+            return Err(ResponseError::Issuer);
+        }
+    }
+
+    if child(response, SAML_NS, "EncryptedAssertion").is_some() {
+        return Err(ResponseError::Encrypted);
+    }
+
+    let mut assertions = children(response, SAML_NS, "Assertion");
+    let assertion = assertions
+        .next()
+        .ok_or(ResponseError::Malformed("missing assertion"))?;
+    if assertions.next().is_some() {
+        return Err(ResponseError::Malformed("multiple assertions"));
+    }
+
+    // Either the whole response or the assertion itself must be signed
+    match verify_enveloped(response, &idp.signing_certs) {
+        Ok(()) => {}
+        Err(SignatureError::Missing) => verify_enveloped(assertion, &idp.signing_certs)?,
+        Err(error) => return Err(error.into()),
+    }
+
+    validate_assertion(assertion, idp, expected)
+}
+
+/// Validates the signed `assertion` and reads the user details from it
+fn validate_assertion(
+    assertion: Node,
+    idp: &IdentityProvider,
+    expected: &ExpectedResponse,
+) -> Result<Assertion, ResponseError> {
+    let issuer = child(assertion, SAML_NS, "Issuer")
+        .map(text)
+        .ok_or(ResponseError::Malformed("missing issuer"))?;
+    if issuer != idp.entity_id {
+        return Err(ResponseError::Issuer);
+    }
+
+    let subject =
+        child(assertion, SAML_NS, "Subject").ok_or(ResponseError::Malformed("missing subject"))?;
+    let name_id =
+        child(subject, SAML_NS, "NameID").ok_or(ResponseError::Malformed("missing name ID"))?;
+
+    // At least one bearer confirmation must be for this request and
+    // service provider, and must not have expired
+    let mut confirmed = Err(ResponseError::Malformed("missing bearer confirmation"));
+    for confirmation in children(subject, SAML_NS, "SubjectConfirmation")
+        .filter(|confirmation| confirmation.attribute("Method") == Some(BEARER_METHOD))
+    {
+        confirmed = validate_confirmation(confirmation, expected);
+        if confirmed.is_ok() {
+            break;
+        }
+    }
+    confirmed?;
+
+    let conditions = child(assertion, SAML_NS, "Conditions")
+        .ok_or(ResponseError::Malformed("missing conditions"))?;
+
+    if let Some(not_before) = conditions.attribute("NotBefore") {
+        if expected.now + Duration::seconds(CLOCK_SKEW_SECONDS) < parse_time(not_before)? {
+            return Err(ResponseError::NotYetValid);
+        }
+    }
+
+    if let Some(not_on_or_after) = conditions.attribute("NotOnOrAfter") {
+        if expected.now - Duration::seconds(CLOCK_SKEW_SECONDS) >= parse_time(not_on_or_after)? {
+            return Err(ResponseError::Expired);
+        }
+    }
+
+    // Every audience restriction must include this service provider
+    let mut restrictions = children(conditions, SAML_NS, "AudienceRestriction").peekable();
+    if restrictions.peek().is_none() {
+        return Err(ResponseError::Audience);
+    }
+    for restriction in restrictions {
+        if !children(restriction, SAML_NS, "Audience")
+            .any(|audience| text(audience) == expected.entity_id)
+        {
+            return Err(ResponseError::Audience);
+        }
+    }
+
+    let attributes = children(assertion, SAML_NS, "AttributeStatement")
+        .flat_map(|statement| children(statement, SAML_NS, "Attribute"))
+        .filter_map(|attribute| {
+            let value = children(attribute, SAML_NS, "AttributeValue")
+                .map(text)
+                .find(|value| !value.is_empty())?;
+
+            Some(Attribute {
+                name: attribute.attribute("Name")?.to_string(),
+                friendly_name: attribute.attribute("FriendlyName").map(str::to_string),
+                value,
+            })
+        })
+        .collect();
+
+    Ok(Assertion {
+        issuer,
+        name_id: text(name_id),
+        name_id_format: name_id.attribute("Format").map(str::to_string),
+        attributes,
+    })
+}
+
+/// Validates a bearer subject `confirmation` is for the expected request
+fn validate_confirmation(
+    confirmation: Node,
+    expected: &ExpectedResponse,
+) -> Result<(), ResponseError> {
+    let data = child(confirmation, SAML_NS, "SubjectConfirmationData")
+        .ok_or(ResponseError::Malformed("missing confirmation data"))?;
+
+    if data.attribute("Recipient") != Some(expected.acs_url) {
+        return Err(ResponseError::Recipient);
+    }
+
+    if data
+        .attribute("InResponseTo")
+        .is_some_and(|id| id != expected.request_id)
+    {
+        return Err(ResponseError::InResponseTo);
+    }
+
+    let not_on_or_after = data
+        .attribute("NotOnOrAfter")
+        .ok_or(ResponseError::Malformed("missing confirmation expiry"))?;
+    if expected.now - Duration::seconds(CLOCK_SKEW_SECONDS) >= parse_time(not_on_or_after)? {
+        return Err(ResponseError::Expired);
+    }
+
+    Ok(())
+}
+
+/// Finds the first child of `node` with the provided name
+fn child<'a, 'input>(
+    node: Node<'a, 'input>,
+    ns: &'a str,
+    name: &'a str,
+) -> Option<Node<'a, 'input>> {
+    children(node, ns, name).next()
+}
+
+/// Finds the children of `node` with the provided name
+fn children<'a, 'input: 'a>(
+    node: Node<'a, 'input>,
+    ns: &'a str,
+    name: &'a str,
+) -> impl Iterator<Item = Node<'a, 'input>> + 'a {
+    node.children()
+        .filter(move |child| child.has_tag_name((ns, name)))
+}
+
+/// Reads the trimmed text content of `node`
+fn text(node: Node) -> String {
+    node.descendants()
+        .filter(|node| node.is_text())
+        .filter_map(|node| node.text())
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
+
+fn parse_time(value: &str) -> Result<DateTime<Utc>, ResponseError> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|time| time.with_timezone(&Utc))
+        .map_err(|_| ResponseError::Malformed("invalid timestamp"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{validate_response, ExpectedResponse, ResponseError};
+    use crate::services::saml::{metadata::IdentityProvider, signature::SignatureError};
+    use chrono::{DateTime, TimeZone, Utc};
+
+    const IDP_METADATA: &str = include_str!("../../../tests/fixtures/saml/idp_metadata.xml");
+    const SIGNED_ASSERTION: &str =
+        include_str!("../../../tests/fixtures/saml/response_signed_assertion.xml");
+    const SIGNED_RESPONSE: &str =
+        include_str!("../../../tests/fixtures/saml/response_signed_response.xml");
+
+    fn idp() -> IdentityProvider {
+        IdentityProvider::from_metadata(IDP_METADATA).unwrap()
+    }
+
+    fn expected(request_id: &str, now: DateTime<Utc>) -> ExpectedResponse<'_> {
+        ExpectedResponse {
+            entity_id: "https://quizler.test/auth/saml/acme/metadata",
+            acs_url: "https://quizler.test/auth/saml/acme/acs",
+            request_id,
+            now,
+        }
+    }
+
+    fn now() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 4, 1, 12, 1, 0).unwrap()
+    }
+
+    /// Tests that a response with a signed assertion provides its issuer, subject
+    /// and attributes
+    #[test]
+    fn test_signed_assertion() {
+        let assertion =
+            validate_response(SIGNED_ASSERTION, &idp(), &expected("id-request1", now())).unwrap();
+
+        assert_eq!(assertion.issuer, "https://idp.acme.test/saml");
+        assert_eq!(assertion.name_id, "jane@acme.test");
+        assert_eq!(
+            assertion.name_id_format.as_deref(),
+            Some("urn:oasis:names:tc:SAML:1.1:nameid-format:emailAddress")
+        );
+
+        let values: Vec<_> = assertion
+            .attributes
+            .iter()
+            .map(|attribute| (attribute.friendly_name.as_deref(), attribute.value.as_str()))
+            .collect();
+        assert_eq!(
+            values,
+            [
+                (None, "jane.doe@acme.test"),
+                (Some("username"), "jdoe"),
+                (None, "Jane & Doe")
+            ]
+        );
+    }
+
+    /// Tests that an assertion is accepted when the whole response is signed
+    #[test]
+    fn test_signed_response() {
+        let assertion =
+            validate_response(SIGNED_RESPONSE, &idp(), &expected("id-request2", now())).unwrap();
+
+        assert_eq!(assertion.name_id, "a7c3f1e0-42");
+        assert_eq!(assertion.attributes[0].value, "sam@acme.test");
+    }
+
+    /// Tests that changing the signed assertion fails the digest check
+    #[test]
+    fn test_tampered_assertion() {
+        let xml = SIGNED_ASSERTION.replace("jane.doe@acme.test", "admin@acme.test");
+
+        let error = validate_response(&xml, &idp(), &expected("id-request1", now())).unwrap_err();
+        assert!(matches!(
+            error,
+            ResponseError::Signature(SignatureError::Digest)
+        ));
+    }
+
+    /// Tests that changing the signature value fails the signature check
+    #[test]
+    fn test_tampered_signature() {
+        let xml = SIGNED_ASSERTION.replace("Isqt/jpPkKGa", "Isqt/jpPkKGb");
+
+        let error = validate_response(&xml, &idp(), &expected("id-request1", now())).unwrap_err();
+        assert!(matches!(
+            error,
+            ResponseError::Signature(SignatureError::Invalid)
+        ));
+    }
+
+    /// Tests that responses without any signature are rejected
+    #[test]
+    fn test_unsigned_assertion() {
+        let start = SIGNED_ASSERTION.find("<ds:Signature").unwrap();
+        let end = SIGNED_ASSERTION.find("</ds:Signature>").unwrap() + "</ds:Signature>".len();
+        let xml = format!("{}{}", &SIGNED_ASSERTION[..start], &SIGNED_ASSERTION[end..]);
+
+        let error = validate_response(&xml, &idp(), &expected("id-request1", now())).unwrap_err();
+        assert!(matches!(
+            error,
+            ResponseError::Signature(SignatureError::Missing)
+        ));
+    }
+
+    /// Tests that an unsigned assertion injected next to the signed one is rejected
+    #[test]
+    fn test_injected_assertion() {
+        // A second assertion next to the signed one must not be accepted
+        let xml = SIGNED_ASSERTION.replace(
+            "</samlp:Response>",
+            r#"<saml:Assertion ID="_evil" Version="2.0"/></samlp:Response>"#,
+        );
+
+        let error = validate_response(&xml, &idp(), &expected("id-request1", now())).unwrap_err();
+        assert!(matches!(error, ResponseError::Malformed(_)));
+    }
+
+    /// Tests that assertions meant for another service provider are rejected
+    #[test]
+    fn test_wrong_audience() {
+        let error = validate_response(
+            SIGNED_ASSERTION,
+            &idp(),
+            &ExpectedResponse {
+                entity_id: "https://quizler.test/auth/saml/other/metadata",
+                ..expected("id-request1", now())
+            },
+        )
+        .unwrap_err();
+        assert!(matches!(error, ResponseError::Audience));
+    }
+
+    /// Tests that assertions sent to another assertion consumer service are rejected
+    #[test]
+    fn test_wrong_recipient() {
+        // Destination is optional so the recipient is checked without it
+        let xml = SIGNED_ASSERTION.replace(
+            r#" Destination="https://quizler.test/auth/saml/acme/acs""#,
+            "",
+        );
+
+        let error = validate_response(
+            &xml,
+            &idp(),
+            &ExpectedResponse {
+                acs_url: "https://quizler.test/auth/saml/other/acs",
+                ..expected("id-request1", now())
+            },
+        )
+        .unwrap_err();
+        assert!(matches!(error, ResponseError::Recipient));
+    }
+
+    /// Tests that responses to a different authentication request are rejected
+    #[test]
+    fn test_wrong_request() {
+        let error = validate_response(SIGNED_ASSERTION, &idp(), &expected("id-request2", now()))
+            .unwrap_err();
+        assert!(matches!(error, ResponseError::InResponseTo));
+    }
+
+    /// Tests that assertions are rejected once their validity period has ended
+    #[test]
+    fn test_expired_assertion() {
+        let now = Utc.with_ymd_and_hms(2024, 4, 1, 12, 30, 0).unwrap();
+
+        let error =
+            validate_response(SIGNED_ASSERTION, &idp(), &expected("id-request1", now)).unwrap_err();
+        assert!(matches!(error, ResponseError::Expired));
+    }
+
+    /// Tests that assertions are rejected before their validity period starts
+    #[test]
+    fn test_not_yet_valid_assertion() {
+        let now = Utc.with_ymd_and_hms(2024, 4, 1, 11, 50, 0).unwrap();
+
+        let error =
+            validate_response(SIGNED_ASSERTION, &idp(), &expected("id-request1", now)).unwrap_err();
+        assert!(matches!(error, ResponseError::NotYetValid));
+    }
+}
@@ -0,0 +1,469 @@
+//! Verification of enveloped XML signatures, the form of XML-DSig used by
+//! identity providers to sign SAML responses and assertions. Only exclusive
+//! canonicalization without comments is supported, which is the only
+//! canonicalization the SAML signature profile recommends
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use openssl::{
+    bn::BigNum,
+    ecdsa::EcdsaSig,
+    hash::{hash, MessageDigest},
+    pkey::{Id, PKey, Public},
+    sign::Verifier,
+    x509::X509,
+};
+use roxmltree::{Node, NodeId};
+use thiserror::Error;
+
+/// Namespace of the XML signature elements
+pub const DSIG_NS: &str = "http://www.w3.org/2000/09/xmldsig#";
+/// Exclusive canonicalization without comments
+const EXC_C14N: &str = "http://www.w3.org/2001/10/xml-exc-c14n#";
+/// Transform removing the signature from the signed element
+const ENVELOPED_SIGNATURE: &str = "http://www.w3.org/2000/09/xmldsig#enveloped-signature";
+
+#[derive(Debug, Error)]
+pub enum SignatureError {
+    /// Element doesn't have a signature
+    #[error("Element is not signed")]
+    Missing,
+    /// Signature is missing required elements or has unexpected content
+    #[error("Malformed signature: {0}")]
+    Malformed(&'static str),
+    /// Signature uses an algorithm that isn't accepted
+    #[error("Unsupported algorithm {0}")]
+    UnsupportedAlgorithm(String),
+    /// Signature references something other than the element it's on
+    #[error("Signature doesn't reference the signed element")]
+    Reference,
+    /// Signed element was changed after it was signed
+    #[error("Digest doesn't match the signed element")]
+    Digest,
+    /// Signature wasn't made by any of the trusted certificates
+    #[error("Signature doesn't match a trusted certificate")]
+    Invalid,
+}
+
+/// Algorithms the signature value can be created with
+#[derive(Clone, Copy)]
+enum SignatureAlgorithm {
+    Rsa(MessageDigest),
+    Ecdsa(MessageDigest),
+}
+
+impl SignatureAlgorithm {
+    fn from_uri(uri: &str) -> Option<Self> {
+        Some(match uri {
+            "http://www.w3.org/2001/04/xmldsig-more#rsa-sha256" => {
+                Self::Rsa(MessageDigest::sha256())
+            }
+            "http://www.w3.org/2001/04/xmldsig-more#rsa-sha384" => {
+                Self::Rsa(MessageDigest::sha384())
+            }
+            "http://www.w3.org/2001/04/xmldsig-more#rsa-sha512" => {
+                Self::Rsa(MessageDigest::sha512())
+            }
+            "http://www.w3.org/2001/04/xmldsig-more#ecdsa-sha256" => {
+                Self::Ecdsa(MessageDigest::sha256())
+            }
+            "http://www.w3.org/2001/04/xmldsig-more#ecdsa-sha384" => {
+                Self::Ecdsa(MessageDigest::sha384())
+            }
+            "http://www.w3.org/2001/04/xmldsig-more#ecdsa-sha512" => {
+                Self::Ecdsa(MessageDigest::sha512())
+            }
+            _ => return None,
+        })
+    }
+
+    /// Checks whether the `signature` of `data` was created by the
+    /// private key for `key`. Keys of a different type never match
+    fn verify(&self, key: &PKey<Public>, data: &[u8], signature: &[u8]) -> bool {
+        let (digest, signature) = match self {
+            Self::Rsa(digest) if key.id() == Id::RSA => (*digest, signature.to_vec()),
+            // XML signatures store the raw r and s values rather than DER
+            Self::Ecdsa(digest) if key.id() == Id::EC && signature.len().is_multiple_of(2) => {
+                let (r, s) = signature.split_at(signature.len() / 2);
+                let Some(der) = BigNum::from_slice(r)
+                    .and_then(|r| Ok((r, BigNum::from_slice(s)?)))
+                    .and_then(|(r, s)| EcdsaSig::from_private_components(r, s))
+                    .and_then(|signature| signature.to_der())
+                    .ok()
+                else {
+                    return false;
+                };
+
+                (*digest, der)
+            }
+            _ => return false,
+        };
+
+        Verifier::new(digest, key)
+            .and_then(|mut verifier| {
+                verifier.update(data)?;
+                verifier.verify(&signature)
+            })
+            .unwrap_or(false)
+    }
+}
+
+/// Finds the digest algorithm for the provided algorithm `uri`, SHA-1
+/// isn't accepted
+fn digest_algorithm(uri: &str) -> Option<MessageDigest> {
+    Some(match uri {
+        "http://www.w3.org/2001/04/xmlenc#sha256" => MessageDigest::sha256(),
+        "http://www.w3.org/2001/04/xmldsig-more#sha384" => MessageDigest::sha384(),
+        "http://www.w3.org/2001/04/xmlenc#sha512" => MessageDigest::sha512(),
+        _ => return None,
+    })
+}
+
+/// Verifies the enveloped signature of the `element` was created by one of
+/// the `certs`. The signature must be a direct child of the element and
+/// must only reference the element itself, so the verified content is
+/// always the element the caller reads from
+pub fn verify_enveloped(element: Node, certs: &[X509]) -> Result<(), SignatureError> {
+    let mut signatures = element
+        .children()
+        .filter(|node| node.has_tag_name((DSIG_NS, "Signature")));
+    let signature = signatures.next().ok_or(SignatureError::Missing)?;
+    if signatures.next().is_some() {
+        return Err(SignatureError::Malformed("multiple signatures"));
+    }
+
+    let signed_info = dsig_child(signature, "SignedInfo")?;
+
+    let canonicalization = dsig_child(signed_info, "CanonicalizationMethod")?;
+    expect_algorithm(canonicalization, EXC_C14N)?;
+
+    let signature_method = dsig_child(signed_info, "SignatureMethod")?;
+    let signature_algorithm = algorithm(signature_method).and_then(|uri| {
+        SignatureAlgorithm::from_uri(uri)
+            .ok_or_else(|| SignatureError::UnsupportedAlgorithm(uri.to_string()))
+    })?;
+
+    let mut references = dsig_children(signed_info, "Reference");
+    let reference = references
+        .next()
+        .ok_or(SignatureError::Malformed("missing reference"))?;
+    if references.next().is_some() {
+        return Err(SignatureError::Malformed("multiple references"));
+    }
+
+    // The reference must point at the element the signature is on, and the
+    // ID must be unique so it can't also resolve to another element
+    let id = element.attribute("ID").ok_or(SignatureError::Reference)?;
+    if reference
+        .attribute("URI")
+        .and_then(|uri| uri.strip_prefix('#'))
+        != Some(id)
+    {
+        return Err(SignatureError::Reference);
+    }
+    let id_count = element
+        .document()
+        .descendants()
+        .filter(|node| node.attribute("ID") == Some(id))
+        .count();
+    if id_count != 1 {
+        return Err(SignatureError::Reference);
+    }
+
+    // Only the enveloped signature transform followed by exclusive
+    // canonicalization is accepted
+    let transforms: Vec<Node> = dsig_child(reference, "Transforms")
+        .map(|transforms| dsig_children(transforms, "Transform").collect())
+        .unwrap_or_default();
+    let [enveloped, canonical] = transforms.as_slice() else {
+        return Err(SignatureError::Malformed("unexpected transforms"));
+    };
+    expect_algorithm(*enveloped, ENVELOPED_SIGNATURE)?;
+    expect_algorithm(*canonical, EXC_C14N)?;
+
+    let digest_method = dsig_child(reference, "DigestMethod")?;
+    let digest = algorithm(digest_method).and_then(|uri| {
+        digest_algorithm(uri).ok_or_else(|| SignatureError::UnsupportedAlgorithm(uri.to_string()))
+    })?;
+    let digest_value = decode_base64(dsig_child(reference, "DigestValue")?)?;
+
+    let canonical_element = canonicalize(
+        element,
+        Some(signature.id()),
+        &inclusive_prefixes(*canonical),
+    );
+    let computed = hash(digest, canonical_element.as_bytes())
+        .map_err(|_| SignatureError::Malformed("failed to digest"))?;
+    if *computed != *digest_value {
+        return Err(SignatureError::Digest);
+    }
+
+    let signature_value = decode_base64(dsig_child(signature, "SignatureValue")?)?;
+    let canonical_signed_info =
+        canonicalize(signed_info, None, &inclusive_prefixes(canonicalization));
+
+    let trusted = certs.iter().any(|cert| {
+        cert.public_key().is_ok_and(|key| {
+            signature_algorithm.verify(&key, canonical_signed_info.as_bytes(), &signature_value)
+        })
+    });
+
+    if !trusted {
+        return Err(SignatureError::Invalid);
+    }
+
+    Ok(())
+}
+
+/// Finds the only child of `node` in the signature namespace named `name`
+fn dsig_child<'a, 'input>(
+    node: Node<'a, 'input>,
+    name: &'static str,
+) -> Result<Node<'a, 'input>, SignatureError> {
+    dsig_children(node, name)
+        .next()
+        .ok_or(SignatureError::Malformed(name))
+}
+
+/// Finds the children of `node` in the signature namespace named `name`
+fn dsig_children<'a, 'input>(
+    node: Node<'a, 'input>,
+    name: &'static str,
+) -> impl Iterator<Item = Node<'a, 'input>> {
+    node.children()
+        .filter(move |child| child.has_tag_name((DSIG_NS, name)))
+}
+
+/// Reads the algorithm URI of a method or transform `node`
+fn algorithm<'a>(node: Node<'a, '_>) -> Result<&'a str, SignatureError> {
+    node.attribute("Algorithm")
+        .ok_or(SignatureError::Malformed("missing algorithm"))
+}
+
+/// Ensures the method or transform `node` uses the `expected` algorithm
+fn expect_algorithm(node: Node, expected: &str) -> Result<(), SignatureError> {
+    let uri = algorithm(node)?;
+    if uri != expected {
+        return Err(SignatureError::UnsupportedAlgorithm(uri.to_string()));
+    }
+
+    Ok(())
+}
+
+/// Decodes the base64 text content of `node`, which may be wrapped
+/// across multiple lines
+fn decode_base64(node: Node) -> Result<Vec<u8>, SignatureError> {
+    let value: String = node
+        .text()
+        .unwrap_or_default()
+        .chars()
+        .filter(|c| !c.is_ascii_whitespace())
+        .collect();
+
+    STANDARD
+        .decode(value)
+        .map_err(|_| SignatureError::Malformed("invalid base64"))
+}
+
+/// Reads the InclusiveNamespaces prefix list of an exclusive
+/// canonicalization method or transform `node`
+fn inclusive_prefixes<'a>(node: Node<'a, '_>) -> Vec<&'a str> {
+    node.children()
+        .find(|child| child.has_tag_name((EXC_C14N, "InclusiveNamespaces")))
+        .and_then(|child| child.attribute("PrefixList"))
+        .map(|list| {
+            list.split_ascii_whitespace()
+                .map(|prefix| if prefix == "#default" { "" } else { prefix })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Canonicalizes the `element` using exclusive XML canonicalization without
+/// comments, leaving out the `excluded` node. Namespaces with a prefix in
+/// `inclusive_prefixes` are rendered as they would be for inclusive
+/// canonicalization, an empty prefix is the default namespace
+pub fn canonicalize(
+    element: Node,
+    excluded: Option<NodeId>,
+    inclusive_prefixes: &[&str],
+) -> String {
+    let mut canonicalizer = Canonicalizer {
+        output: String::new(),
+        excluded,
+        inclusive_prefixes,
+        rendered: Vec::new(),
+    };
+    canonicalizer.write_element(element);
+    canonicalizer.output
+}
+
+struct Canonicalizer<'a> {
+    output: String,
+    /// Node left out of the output along with its descendants
+    excluded: Option<NodeId>,
+    /// Prefixes rendered whenever they're in scope
+    inclusive_prefixes: &'a [&'a str],
+    /// Namespaces rendered by the elements currently being written, the
+    /// last entry for a prefix is the value in scope for the output
+    rendered: Vec<(&'a str, &'a str)>,
+}
+
+impl<'a> Canonicalizer<'a> {
+    fn write_element<'input: 'a>(&mut self, element: Node<'a, 'input>) {
+        let input = element.document().input_text();
+        let name = element_qname(element);
+
+        // Prefixes that are visibly utilized by the element and its attributes
+        let mut prefixes: Vec<&'a str> = vec![qname_prefix(name)];
+        let mut attributes: Vec<(&str, &str, &str, &str)> = element
+            .attributes()
+            .map(|attribute| {
+                let qname = &input[attribute.range_qname()];
+                if qname.contains(':') {
+                    prefixes.push(qname_prefix(qname));
+                }
+
+                (
+                    attribute.namespace().unwrap_or_default(),
+                    attribute.name(),
+                    qname,
+                    attribute.value(),
+                )
+            })
+            .collect();
+        prefixes.extend(self.inclusive_prefixes.iter().copied());
+        prefixes.sort_unstable();
+        prefixes.dedup();
+
+        // Attributes are ordered by namespace URI then local name, attributes
+        // without a namespace come first
+        attributes.sort_unstable_by(|a, b| (a.0, a.1).cmp(&(b.0, b.1)));
+
+        let depth = self.rendered.len();
+
+        self.output.push('<');
+        self.output.push_str(name);
+
+        for prefix in prefixes {
+            if prefix == "xml" {
+                continue;
+            }
+
+            let uri = if prefix.is_empty() {
+                element.default_namespace().unwrap_or_default()
+            } else {
+                match element.lookup_namespace_uri(Some(prefix)) {
+                    Some(uri) => uri,
+                    // Inclusive prefixes that aren't in scope aren't rendered
+                    None => continue,
+                }
+            };
+
+            let current = self
+                .rendered
+                .iter()
+                .rev()
+                .find(|(rendered, _)| *rendered == prefix)
+                .map(|(_, uri)| *uri)
+                .unwrap_or_default();
+
+            if current == uri {
+                continue;
+            }
+
+            if prefix.is_empty() {
+                self.output.push_str(" xmlns=\"");
+            } else {
+                self.output.push_str(" xmlns:");
+                self.output.push_str(prefix);
+                self.output.push_str("=\"");
+            }
+            escape_attribute(&mut self.output, uri);
+            self.output.push('"');
+
+            self.rendered.push((prefix, uri));
+        }
+
+        for (_, _, qname, value) in attributes {
+            self.output.push(' ');
+            self.output.push_str(qname);
+            self.output.push_str("=\"");
+            escape_attribute(&mut self.output, value);
+            self.output.push('"');
+        }
+
+        self.output.push('>');
+
+        for child in element.children() {
+            if Some(child.id()) == self.excluded {
+                continue;
+            }
+
+            if child.is_element() {
+                self.write_element(child);
+            } else if child.is_text() {
+                escape_text(&mut self.output, child.text().unwrap_or_default());
+            } else if let Some(pi) = child.pi() {
+                self.output.push_str("<?");
+                self.output.push_str(pi.target);
+                if let Some(value) = pi.value {
+                    self.output.push(' ');
+                    self.output.push_str(value);
+                }
+                self.output.push_str("?>");
+            }
+        }
+
+        self.output.push_str("</");
+        self.output.push_str(name);
+        self.output.push('>');
+
+        self.rendered.truncate(depth);
+    }
+}
+
+/// Reads the qualified name of the `element` as it was written in the
+/// document, the parsed element only provides the namespace URI
+fn element_qname<'input>(element: Node<'_, 'input>) -> &'input str {
+    let input = element.document().input_text();
+    let start = element.range().start + 1;
+
+    input[start..]
+        .split(|c: char| c.is_ascii_whitespace() || c == '/' || c == '>')
+        .next()
+        .unwrap_or_default()
+}
+
+/// Provides the prefix of a qualified name, empty for unprefixed names
+fn qname_prefix(qname: &str) -> &str {
+    qname
+        .split_once(':')
+        .map(|(prefix, _)| prefix)
+        .unwrap_or_default()
+}
+
+fn escape_text(output: &mut String, value: &str) {
+    for c in value.chars() {
+        match c {
+            '&' => output.push_str("&amp;"),
+            '<' => output.push_str("&lt;"),
+            '>' => output.push_str("&gt;"),
+            '\r' => output.push_str("&#xD;"),
+            c => output.push(c),
+        }
+    }
+}
+
+fn escape_attribute(output: &mut String, value: &str) {
+    for c in value.chars() {
+        match c {
+            '&' => output.push_str("&amp;"),
+            '<' => output.push_str("&lt;"),
+            '"' => output.push_str("&quot;"),
+            '\t' => output.push_str("&#x9;"),
+            '\n' => output.push_str("&#xA;"),
+            '\r' => output.push_str("&#xD;"),
+            c => output.push(c),
+        }
+    }
+}
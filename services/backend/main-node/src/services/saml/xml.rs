@@ -0,0 +1,24 @@
+//! Namespaces and helpers shared by the SAML XML readers and writers
+
+/// SAML protocol messages
+pub const SAMLP_NS: &str = "urn:oasis:names:tc:SAML:2.0:protocol";
+/// SAML assertions
+pub const SAML_NS: &str = "urn:oasis:names:tc:SAML:2.0:assertion";
+/// SAML metadata
+pub const MD_NS: &str = "urn:oasis:names:tc:SAML:2.0:metadata";
+
+/// Escapes the `value` for use in XML text or a double quoted attribute
+pub fn escape(value: &str) -> String {
+    let mut output = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => output.push_str("&amp;"),
+            '<' => output.push_str("&lt;"),
+            '>' => output.push_str("&gt;"),
+            '"' => output.push_str("&quot;"),
+            '\'' => output.push_str("&apos;"),
+            c => output.push(c),
+        }
+    }
+    output
+}
@@ -0,0 +1,75 @@
+//! [Storage] implementation that stores content as files within a
+//! directory on the local filesystem
+
+use super::Storage;
+use async_trait::async_trait;
+use axum::body::Bytes;
+use std::{
+    io::{self, ErrorKind},
+    path::PathBuf,
+};
+use tokio::fs;
+
+pub struct LocalStorage {
+    /// Directory the files are stored in
+    root: PathBuf,
+}
+
+impl LocalStorage {
+    pub fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+
+    /// Provides the path to the file for the provided `key`, keys that
+    /// could escape the storage directory are rejected
+    fn path(&self, key: &str) -> io::Result<PathBuf> {
+        let valid = !key.is_empty()
+            && !key.starts_with('.')
+            && key
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_'));
+
+        if !valid {
+            return Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                "invalid storage key",
+            ));
+        }
+
+        Ok(self.root.join(key))
+    }
+}
+
+#[async_trait]
+impl Storage for LocalStorage {
+    async fn put(&self, key: &str, data: Bytes) -> io::Result<()> {
+        let path = self.path(key)?;
+        fs::create_dir_all(&self.root).await?;
+
+        // Write to a temporary file first so partially written files
+        // are never served
+        let temp_path = path.with_extension("tmp");
+        fs::write(&temp_path, &data).await?;
+        fs::rename(&temp_path, &path).await
+    }
+
+    async fn get(&self, key: &str) -> io::Result<Option<Bytes>> {
+        let path = self.path(key)?;
+
+        match fs::read(&path).await {
+            Ok(data) => Ok(Some(Bytes::from(data))),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    async fn delete(&self, key: &str) -> io::Result<()> {
+        let path = self.path(key)?;
+
+        match fs::remove_file(&path).await {
+            Ok(()) => Ok(()),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(err),
+        }
+    }
+}
@@ -0,0 +1,66 @@
+//! Storage for the contents of resources, content is stored and retrieved
+//! using a key through an implementation of [Storage] allowing the backing
+//! store to be swapped out
+
+use crate::utils::env::require_env;
+use async_trait::async_trait;
+use axum::body::Bytes;
+use rand::{
+    distributions::{Alphanumeric, DistString},
+    rngs::StdRng,
+    SeedableRng,
+};
+use std::{io, path::PathBuf, sync::Arc};
+
+use self::local::LocalStorage;
+
+pub mod local;
+
+/// Storage shared between requests
+pub type SharedStorage = Arc<dyn Storage>;
+
+/// Environment variable for selecting the storage backend
+const STORAGE_BACKEND: &str = "STORAGE_BACKEND";
+/// Environment variable for the root directory of local storage
+const STORAGE_LOCAL_PATH: &str = "STORAGE_LOCAL_PATH";
+
+/// Backing store for resource content
+#[async_trait]
+pub trait Storage: Send + Sync + 'static {
+    /// Stores the provided `data` under the provided `key`, replacing
+    /// any existing content
+    async fn put(&self, key: &str, data: Bytes) -> io::Result<()>;
+
+    /// Retrieves the content stored under the provided `key`, [None]
+    /// when nothing is stored under the key
+    async fn get(&self, key: &str) -> io::Result<Option<Bytes>>;
+
+    /// Removes the content stored under the provided `key`, removing
+    /// a missing key is not an error
+    async fn delete(&self, key: &str) -> io::Result<()>;
+}
+
+/// Creates the storage backend selected by the environment, local
+/// storage is used when no backend is specified
+pub fn from_env() -> anyhow::Result<SharedStorage> {
+    let backend = std::env::var(STORAGE_BACKEND).unwrap_or_else(|_| "local".to_string());
+
+    match backend.as_str() {
+        "local" => {
+            let root = require_env(STORAGE_LOCAL_PATH)?;
+            Ok(Arc::new(LocalStorage::new(PathBuf::from(root))))
+        }
+        other => Err(anyhow::anyhow!("Unknown storage backend \"{other}\"")),
+    }
+}
+
+/// Length of the random portion of storage keys
+const KEY_LENGTH: usize = 32;
+
+/// Creates a new random storage key with the provided file `extension`
+pub fn create_key(extension: &str) -> String {
+    let mut rng = StdRng::from_entropy();
+    let key = Alphanumeric.sample_string(&mut rng, KEY_LENGTH);
+
+    format!("{key}.{extension}")
+}
@@ -0,0 +1,762 @@
+//! Service for registering and logging in with WebAuthn passkeys, the
+//! state of in progress ceremonies is kept in memory until they complete
+
+use crate::{
+    database::entities::{
+        user::{User, UserId},
+        user_passkey::{encode_credential_id, UserPasskey},
+    },
+    utils::env::require_env,
+};
+use anyhow::Context;
+use moka::future::Cache;
+use rand::{
+    distributions::{Alphanumeric, DistString},
+    rngs::StdRng,
+    SeedableRng,
+};
+use sea_orm::{ConnectionTrait, DbErr};
+use std::{sync::Arc, time::Duration};
+use thiserror::Error;
+use tracing::warn;
+use webauthn_rs::prelude::{
+    CreationChallengeResponse, CredentialID, DiscoverableAuthentication, DiscoverableKey, Passkey,
+    PasskeyRegistration, PublicKeyCredential, RegisterPublicKeyCredential,
+    RequestChallengeResponse, Url, Uuid, Webauthn, WebauthnBuilder, WebauthnError,
+};
+
+/// Environment variables for configuring WebAuthn
+const WEBAUTHN_RP_ID: &str = "WEBAUTHN_RP_ID";
+const HUB_BASE_URL: &str = "HUB_BASE_URL";
+
+/// Name of the relying party shown by authenticators
+const RP_NAME: &str = "Quizler";
+
+pub struct WebauthnService {
+    /// WebAuthn relying party, [None] when passkeys are disabled
+    webauthn: Option<Webauthn>,
+    /// In progress passkey registrations by challenge ID
+    registrations: Cache<String, PendingRegistration>,
+    /// In progress passkey logins by challenge ID
+    authentications: Cache<String, DiscoverableAuthentication>,
+}
+
+/// State of a passkey registration waiting for the authenticator response
+#[derive(Clone)]
+struct PendingRegistration {
+    /// ID of the user registering the passkey
+    user_id: UserId,
+    /// WebAuthn user handle the passkey is being registered with
+    user_handle: Uuid,
+    /// State of the registration ceremony
+    state: PasskeyRegistration,
+}
+
+/// Challenge that must be completed by an authenticator
+pub struct PasskeyChallenge<O> {
+    /// ID for completing the challenge
+    pub challenge_id: String,
+    /// Options to pass to the authenticator
+    pub options: O,
+}
+
+#[derive(Debug, Error)]
+pub enum PasskeyError {
+    #[error(transparent)]
+    Database(#[from] DbErr),
+    /// Challenge doesn't exist, has expired or was already used
+    #[error("Unknown challenge")]
+    UnknownChallenge,
+    /// Credential isn't registered to any user
+    #[error("Unknown passkey")]
+    UnknownPasskey,
+    /// Authenticator response failed verification
+    #[error(transparent)]
+    Webauthn(#[from] WebauthnError),
+    /// Relying party isn't configured
+    #[error("Passkeys are disabled")]
+    Disabled,
+}
+
+impl WebauthnService {
+    /// Time a challenge can be completed within
+    const CHALLENGE_EXPIRY: Duration = Duration::from_secs(60 * 5);
+    /// Length of challenge IDs
+    const CHALLENGE_ID_LENGTH: usize = 32;
+
+    /// Creates the WebAuthn service, the relying party ID is taken from
+    /// WEBAUTHN_RP_ID or defaults to the host of HUB_BASE_URL which is
+    /// used as the expected origin. Passkeys are disabled when neither
+    /// variable is set
+    pub fn new() -> anyhow::Result<Arc<Self>> {
+        let rp_id = std::env::var(WEBAUTHN_RP_ID)
+            .ok()
+            .filter(|value| !value.is_empty());
+
+        if rp_id.is_none() && std::env::var_os(HUB_BASE_URL).is_none() {
+            warn!(name: "passkeys_disabled", "HUB_BASE_URL is not set, passkeys are disabled");
+            return Ok(Self::disabled());
+        }
+
+        let origin: Url = require_env(HUB_BASE_URL)?
+            .parse()
+            .context("Parsing HUB_BASE_URL")?;
+
+        let rp_id = match rp_id {
+            Some(value) => value,
+            None => origin
+                .host_str()
+                .context("HUB_BASE_URL is missing a host")?
+                .to_string(),
+        };
+
+        Self::with_relying_party(&rp_id, &origin).map(Arc::new)
+    }
+
+    /// Creates the WebAuthn service with passkeys disabled
+    pub fn disabled() -> Arc<Self> {
+        Arc::new(Self::with_webauthn(None))
+    }
+
+    /// Creates the WebAuthn service for the relying party `rp_id` that
+    /// expects ceremonies from the `origin`
+    fn with_relying_party(rp_id: &str, origin: &Url) -> anyhow::Result<Self> {
+        let webauthn = WebauthnBuilder::new(rp_id, origin)
+            .context("Invalid WebAuthn relying party")?
+            .rp_name(RP_NAME)
+            .build()
+            .context("Creating WebAuthn relying party")?;
+
+        Ok(Self::with_webauthn(Some(webauthn)))
+    }
+
+    fn with_webauthn(webauthn: Option<Webauthn>) -> Self {
+        let registrations = Cache::builder()
+            .time_to_live(Self::CHALLENGE_EXPIRY)
+            .build();
+        let authentications = Cache::builder()
+            .time_to_live(Self::CHALLENGE_EXPIRY)
+            .build();
+
+        Self {
+            webauthn,
+            registrations,
+            authentications,
+        }
+    }
+
+    /// Provides the relying party, failing when passkeys are disabled
+    fn relying_party(&self) -> Result<&Webauthn, PasskeyError> {
+        self.webauthn.as_ref().ok_or(PasskeyError::Disabled)
+    }
+
+    /// Creates a new random challenge ID
+    fn create_challenge_id() -> String {
+        Alphanumeric.sample_string(&mut StdRng::from_entropy(), Self::CHALLENGE_ID_LENGTH)
+    }
+
+    /// Starts registering a new passkey for the `user`, passkeys the user
+    /// already has are excluded so they aren't registered twice
+    pub async fn start_registration<C>(
+        &self,
+        db: &C,
+        user: &User,
+    ) -> Result<PasskeyChallenge<CreationChallengeResponse>, PasskeyError>
+    where
+        C: ConnectionTrait,
+    {
+        let existing = UserPasskey::find_by_user(db, user).await?;
+
+        // All passkeys of a user share the same user handle
+        let user_handle = existing
+            .first()
+            .and_then(|passkey| Uuid::parse_str(&passkey.user_handle).ok())
+            .unwrap_or_else(Uuid::new_v4);
+
+        let exclude_credentials = existing
+            .iter()
+            .map(|passkey| passkey.passkey.0.cred_id().clone())
+            .collect();
+
+        self.begin_registration(user, user_handle, exclude_credentials)
+            .await
+    }
+
+    /// Creates the registration challenge for a passkey registered to the
+    /// `user` under the `user_handle`, the authenticator must not already
+    /// have any of the `exclude_credentials`
+    async fn begin_registration(
+        &self,
+        user: &User,
+        user_handle: Uuid,
+        exclude_credentials: Vec<CredentialID>,
+    ) -> Result<PasskeyChallenge<CreationChallengeResponse>, PasskeyError> {
+        let display_name = user.name.as_deref().unwrap_or(&user.username);
+        let (options, state) = self.relying_party()?.start_passkey_registration(
+            user_handle,
+            &user.username,
+            display_name,
+            Some(exclude_credentials),
+        )?;
+
+        let challenge_id = Self::create_challenge_id();
+        self.registrations
+            .insert(
+                challenge_id.clone(),
+                PendingRegistration {
+                    user_id: user.id,
+                    user_handle,
+                    state,
+                },
+            )
+            .await;
+
+        Ok(PasskeyChallenge {
+            challenge_id,
+            options,
+        })
+    }
+
+    /// Completes registering a passkey for the `user` using the response
+    /// from their authenticator, the passkey is stored under `name`
+    pub async fn finish_registration<C>(
+        &self,
+        db: &C,
+        user: &User,
+        challenge_id: &str,
+        name: String,
+        credential: &RegisterPublicKeyCredential,
+    ) -> Result<UserPasskey, PasskeyError>
+    where
+        C: ConnectionTrait,
+    {
+        let (user_handle, passkey) = self
+            .verify_registration(user, challenge_id, credential)
+            .await?;
+
+        let passkey = UserPasskey::create(db, user, user_handle.to_string(), name, passkey).await?;
+
+        Ok(passkey)
+    }
+
+    /// Verifies the authenticator response to the registration challenge
+    /// the `user` started, responds with the user handle the passkey was
+    /// registered under and the passkey itself
+    async fn verify_registration(
+        &self,
+        user: &User,
+        challenge_id: &str,
+        credential: &RegisterPublicKeyCredential,
+    ) -> Result<(Uuid, Passkey), PasskeyError> {
+        let pending = self
+            .registrations
+            .remove(challenge_id)
+            .await
+            // Challenges can only be completed by the user that started them
+            .filter(|pending| pending.user_id == user.id)
+            .ok_or(PasskeyError::UnknownChallenge)?;
+
+        let passkey = self
+            .relying_party()?
+            .finish_passkey_registration(credential, &pending.state)?;
+
+        Ok((pending.user_handle, passkey))
+    }
+
+    /// Starts logging in with a passkey, the authenticator chooses which
+    /// of its passkeys to login with
+    pub async fn start_authentication(
+        &self,
+    ) -> Result<PasskeyChallenge<RequestChallengeResponse>, PasskeyError> {
+        let (options, state) = self.relying_party()?.start_discoverable_authentication()?;
+
+        let challenge_id = Self::create_challenge_id();
+        self.authentications
+            .insert(challenge_id.clone(), state)
+            .await;
+
+        Ok(PasskeyChallenge {
+            challenge_id,
+            options,
+        })
+    }
+
+    /// Completes logging in with a passkey using the response from the
+    /// authenticator, responds with the user the passkey belongs to
+    pub async fn finish_authentication<C>(
+        &self,
+        db: &C,
+        challenge_id: &str,
+        credential: &PublicKeyCredential,
+    ) -> Result<User, PasskeyError>
+    where
+        C: ConnectionTrait,
+    {
+        let state = self.take_authentication(challenge_id).await?;
+
+        let (_, credential_id) = self
+            .relying_party()?
+            .identify_discoverable_authentication(credential)?;
+
+        let stored = UserPasskey::find_by_credential_id(db, &encode_credential_id(credential_id))
+            .await?
+            .ok_or(PasskeyError::UnknownPasskey)?;
+
+        let updated =
+            self.verify_authentication(state, credential, &stored.user_handle, &stored.passkey.0)?;
+
+        let stored = stored.set_used(db, updated).await?;
+
+        let user = User::find_by_id(db, stored.user_id)
+            .await?
+            .ok_or(PasskeyError::UnknownPasskey)?;
+
+        Ok(user)
+    }
+
+    /// Takes the state of the login challenge with the `challenge_id`,
+    /// challenges can only be used once
+    async fn take_authentication(
+        &self,
+        challenge_id: &str,
+    ) -> Result<DiscoverableAuthentication, PasskeyError> {
+        self.authentications
+            .remove(challenge_id)
+            .await
+            .ok_or(PasskeyError::UnknownChallenge)
+    }
+
+    /// Verifies the authenticator response to a login challenge against
+    /// the stored `passkey` that was registered under the `user_handle`,
+    /// responds with the updated passkey when its counter or backup
+    /// state changed
+    fn verify_authentication(
+        &self,
+        state: DiscoverableAuthentication,
+        credential: &PublicKeyCredential,
+        user_handle: &str,
+        passkey: &Passkey,
+    ) -> Result<Option<Passkey>, PasskeyError> {
+        let webauthn = self.relying_party()?;
+        let (credential_handle, _) = webauthn.identify_discoverable_authentication(credential)?;
+
+        // The credential must have been registered for the same user handle
+        if credential_handle.to_string() != user_handle {
+            return Err(PasskeyError::UnknownPasskey);
+        }
+
+        let result = webauthn.finish_discoverable_authentication(
+            credential,
+            state,
+            &[DiscoverableKey::from(passkey)],
+        )?;
+
+        let mut passkey = passkey.clone();
+        let updated = passkey
+            .update_credential(&result)
+            .is_some_and(|changed| changed)
+            .then_some(passkey);
+
+        Ok(updated)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{PasskeyError, WebauthnService};
+    use crate::database::entities::user::User;
+    use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+    use openssl::{
+        bn::{BigNum, BigNumContext},
+        ec::{EcGroup, EcKey},
+        hash::MessageDigest,
+        nid::Nid,
+        pkey::{PKey, Private},
+        sha::sha256,
+        sign::Signer,
+    };
+    use serde_cbor_2::Value;
+    use serde_json::json;
+    use std::collections::BTreeMap;
+    use webauthn_rs::prelude::{
+        CreationChallengeResponse, Passkey, PublicKeyCredential, RegisterPublicKeyCredential,
+        RequestChallengeResponse, Url, Uuid, WebauthnError,
+    };
+
+    const RP_ID: &str = "quizler.test";
+    const ORIGIN: &str = "https://quizler.test";
+
+    /// Software authenticator holding a single P-256 passkey, responds
+    /// the same way a browser would after talking to an authenticator
+    struct SoftAuthenticator {
+        credential_id: Vec<u8>,
+        key: PKey<Private>,
+    }
+
+    impl SoftAuthenticator {
+        /// User present and user verified flags
+        const FLAGS: u8 = 0x01 | 0x04;
+        /// Attested credential data included flag
+        const FLAG_ATTESTED: u8 = 0x40;
+
+        fn new() -> Self {
+            let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+            let key = PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap();
+
+            Self {
+                credential_id: Uuid::new_v4().as_bytes().to_vec(),
+                key,
+            }
+        }
+
+        fn authenticator_data(flags: u8, counter: u32) -> Vec<u8> {
+            let mut data = sha256(RP_ID.as_bytes()).to_vec();
+            data.push(flags);
+            data.extend_from_slice(&counter.to_be_bytes());
+            data
+        }
+
+        fn client_data(kind: &str, challenge: &[u8], origin: &str) -> Vec<u8> {
+            serde_json::to_vec(&json!({
+                "type": kind,
+                "challenge": URL_SAFE_NO_PAD.encode(challenge),
+                "origin": origin,
+                "crossOrigin": false,
+            }))
+            .unwrap()
+        }
+
+        /// Public key of the passkey in COSE format
+        fn cose_key(&self) -> Vec<u8> {
+            let key = self.key.ec_key().unwrap();
+            let mut ctx = BigNumContext::new().unwrap();
+            let mut x = BigNum::new().unwrap();
+            let mut y = BigNum::new().unwrap();
+            key.public_key()
+                .affine_coordinates(key.group(), &mut x, &mut y, &mut ctx)
+                .unwrap();
+
+            let cose = BTreeMap::from([
+                // EC2 key type
+                (Value::Integer(1), Value::Integer(2)),
+                // ES256 algorithm
+                (Value::Integer(3), Value::Integer(-7)),
+                // P-256 curve
+                (Value::Integer(-1), Value::Integer(1)),
+                (
+                    Value::Integer(-2),
+                    Value::Bytes(x.to_vec_padded(32).unwrap()),
+                ),
+                (
+                    Value::Integer(-3),
+                    Value::Bytes(y.to_vec_padded(32).unwrap()),
+                ),
+            ]);
+            serde_cbor_2::to_vec(&Value::Map(cose)).unwrap()
+        }
+
+        /// Creates the passkey in response to the registration `options`
+        fn register(
+            &self,
+            options: &CreationChallengeResponse,
+            origin: &str,
+        ) -> RegisterPublicKeyCredential {
+            let mut auth_data = Self::authenticator_data(Self::FLAGS | Self::FLAG_ATTESTED, 0);
+            auth_data.extend_from_slice(&[0; 16]);
+            auth_data.extend_from_slice(&(self.credential_id.len() as u16).to_be_bytes());
+            auth_data.extend_from_slice(&self.credential_id);
+            auth_data.extend_from_slice(&self.cose_key());
+
+            let attestation = BTreeMap::from([
+                (
+                    Value::Text("fmt".to_string()),
+                    Value::Text("none".to_string()),
+                ),
+                (
+                    Value::Text("attStmt".to_string()),
+                    Value::Map(BTreeMap::new()),
+                ),
+                (Value::Text("authData".to_string()), Value::Bytes(auth_data)),
+            ]);
+            let attestation = serde_cbor_2::to_vec(&Value::Map(attestation)).unwrap();
+            let client_data = Self::client_data(
+                "webauthn.create",
+                options.public_key.challenge.as_ref(),
+                origin,
+            );
+
+            serde_json::from_value(json!({
+                "id": URL_SAFE_NO_PAD.encode(&self.credential_id),
+                "rawId": URL_SAFE_NO_PAD.encode(&self.credential_id),
+                "type": "public-key",
+                "response": {
+                    "attestationObject": URL_SAFE_NO_PAD.encode(attestation),
+                    "clientDataJSON": URL_SAFE_NO_PAD.encode(client_data),
+                },
+            }))
+            .unwrap()
+        }
+
+        /// Signs the login `options` challenge, claiming the passkey
+        /// belongs to the `user_handle` and has been used `counter` times
+        fn authenticate(
+            &self,
+            options: &RequestChallengeResponse,
+            user_handle: Uuid,
+            counter: u32,
+        ) -> PublicKeyCredential {
+            let auth_data = Self::authenticator_data(Self::FLAGS, counter);
+            let client_data = Self::client_data(
+                "webauthn.get",
+                options.public_key.challenge.as_ref(),
+                ORIGIN,
+            );
+
+            let mut signer = Signer::new(MessageDigest::sha256(), &self.key).unwrap();
+            signer.update(&auth_data).unwrap();
+            signer.update(&sha256(&client_data)).unwrap();
+            let signature = signer.sign_to_vec().unwrap();
+
+            serde_json::from_value(json!({
+                "id": URL_SAFE_NO_PAD.encode(&self.credential_id),
+                "rawId": URL_SAFE_NO_PAD.encode(&self.credential_id),
+                "type": "public-key",
+                "response": {
+                    "authenticatorData": URL_SAFE_NO_PAD.encode(auth_data),
+                    "clientDataJSON": URL_SAFE_NO_PAD.encode(client_data),
+                    "signature": URL_SAFE_NO_PAD.encode(signature),
+                    "userHandle": URL_SAFE_NO_PAD.encode(user_handle.as_bytes()),
+                },
+            }))
+            .unwrap()
+        }
+    }
+
+    fn test_service() -> WebauthnService {
+        WebauthnService::with_relying_party(RP_ID, &Url::parse(ORIGIN).unwrap()).unwrap()
+    }
+
+    /// Registers a passkey on the `authenticator` for the `user`
+    async fn register(
+        service: &WebauthnService,
+        authenticator: &SoftAuthenticator,
+        user: &User,
+        user_handle: Uuid,
+    ) -> Passkey {
+        let challenge = service
+            .begin_registration(user, user_handle, Vec::new())
+            .await
+            .unwrap();
+        let credential = authenticator.register(&challenge.options, ORIGIN);
+
+        let (registered_handle, passkey) = service
+            .verify_registration(user, &challenge.challenge_id, &credential)
+            .await
+            .unwrap();
+        assert_eq!(registered_handle, user_handle);
+
+        passkey
+    }
+
+    /// Logs in with the `authenticator` against the stored `passkey`
+    async fn login(
+        service: &WebauthnService,
+        authenticator: &SoftAuthenticator,
+        passkey: &Passkey,
+        user_handle: Uuid,
+        stored_handle: Uuid,
+        counter: u32,
+    ) -> Result<Option<Passkey>, PasskeyError> {
+        let challenge = service.start_authentication().await.unwrap();
+        let credential = authenticator.authenticate(&challenge.options, user_handle, counter);
+        let state = service.take_authentication(&challenge.challenge_id).await?;
+
+        service.verify_authentication(state, &credential, &stored_handle.to_string(), passkey)
+    }
+
+    #[tokio::test]
+    async fn test_passkey_round_trip() {
+        let service = test_service();
+        let authenticator = SoftAuthenticator::new();
+        let user = User::test(1);
+        let user_handle = Uuid::new_v4();
+
+        let passkey = register(&service, &authenticator, &user, user_handle).await;
+        assert_eq!(passkey.cred_id().as_ref(), authenticator.credential_id);
+
+        let updated = login(
+            &service,
+            &authenticator,
+            &passkey,
+            user_handle,
+            user_handle,
+            1,
+        )
+        .await
+        .unwrap()
+        .expect("Counter change should update the passkey");
+
+        // Later logins verify against the updated credential
+        login(
+            &service,
+            &authenticator,
+            &updated,
+            user_handle,
+            user_handle,
+            2,
+        )
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_passkey_counter_regression() {
+        let service = test_service();
+        let authenticator = SoftAuthenticator::new();
+        let user = User::test(1);
+        let user_handle = Uuid::new_v4();
+
+        let passkey = register(&service, &authenticator, &user, user_handle).await;
+        let updated = login(
+            &service,
+            &authenticator,
+            &passkey,
+            user_handle,
+            user_handle,
+            5,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+
+        // A counter that doesn't increase indicates a cloned authenticator
+        for counter in [3, 5] {
+            let result = login(
+                &service,
+                &authenticator,
+                &updated,
+                user_handle,
+                user_handle,
+                counter,
+            )
+            .await;
+            assert!(matches!(
+                result,
+                Err(PasskeyError::Webauthn(
+                    WebauthnError::CredentialPossibleCompromise
+                ))
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_passkey_user_handle_mismatch() {
+        let service = test_service();
+        let authenticator = SoftAuthenticator::new();
+        let user = User::test(1);
+        let user_handle = Uuid::new_v4();
+
+        let passkey = register(&service, &authenticator, &user, user_handle).await;
+
+        // Authenticator claims the passkey belongs to another user
+        let result = login(
+            &service,
+            &authenticator,
+            &passkey,
+            Uuid::new_v4(),
+            user_handle,
+            1,
+        )
+        .await;
+        assert!(matches!(result, Err(PasskeyError::UnknownPasskey)));
+    }
+
+    #[tokio::test]
+    async fn test_passkey_wrong_key() {
+        let service = test_service();
+        let user = User::test(1);
+        let user_handle = Uuid::new_v4();
+
+        let passkey = register(&service, &SoftAuthenticator::new(), &user, user_handle).await;
+
+        // Same credential ID but signed by a different key
+        let other = SoftAuthenticator {
+            credential_id: passkey.cred_id().to_vec(),
+            ..SoftAuthenticator::new()
+        };
+        let result = login(&service, &other, &passkey, user_handle, user_handle, 1).await;
+        assert!(matches!(result, Err(PasskeyError::Webauthn(_))));
+    }
+
+    #[tokio::test]
+    async fn test_passkey_challenge_single_use() {
+        let service = test_service();
+        let challenge = service.start_authentication().await.unwrap();
+
+        service
+            .take_authentication(&challenge.challenge_id)
+            .await
+            .unwrap();
+        assert!(matches!(
+            service.take_authentication(&challenge.challenge_id).await,
+            Err(PasskeyError::UnknownChallenge)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_passkey_registration_other_user() {
+        let service = test_service();
+        let authenticator = SoftAuthenticator::new();
+        let user = User::test(1);
+
+        let challenge = service
+            .begin_registration(&user, Uuid::new_v4(), Vec::new())
+            .await
+            .unwrap();
+        let credential = authenticator.register(&challenge.options, ORIGIN);
+
+        // Registrations can only be completed by the user that started them
+        let result = service
+            .verify_registration(&User::test(2), &challenge.challenge_id, &credential)
+            .await;
+        assert!(matches!(result, Err(PasskeyError::UnknownChallenge)));
+    }
+
+    #[tokio::test]
+    async fn test_passkey_registration_wrong_origin() {
+        let service = test_service();
+        let authenticator = SoftAuthenticator::new();
+        let user = User::test(1);
+
+        let challenge = service
+            .begin_registration(&user, Uuid::new_v4(), Vec::new())
+            .await
+            .unwrap();
+        let credential = authenticator.register(&challenge.options, "https://evil.test");
+
+        let result = service
+            .verify_registration(&user, &challenge.challenge_id, &credential)
+            .await;
+        assert!(matches!(
+            result,
+            Err(PasskeyError::Webauthn(WebauthnError::InvalidRPOrigin))
+        ));
+    }
+
+    /// Tests that ceremonies fail when passkeys are disabled
+    #[tokio::test]
+    async fn test_disabled() {
+        let service = WebauthnService::disabled();
+
+        assert!(matches!(
+            service.start_authentication().await,
+            Err(PasskeyError::Disabled)
+        ));
+        assert!(matches!(
+            service
+                .begin_registration(&User::test(1), Uuid::new_v4(), Vec::new())
+                .await,
+            Err(PasskeyError::Disabled)
+        ));
+    }
+}
@@ -30,3 +30,15 @@ where
         // Append the details as a context message
         .with_context(|| format!("Missing {prefixed} environment variable"))
 }
+
+/// Obtains an optional environment variable `key` prefixed by `prefix`,
+/// empty values are treated as missing
+pub fn env_prefixed<P, K>(prefix: P, key: K) -> Option<String>
+where
+    P: Display,
+    K: Display,
+{
+    std::env::var(format!("{}_{}", prefix, key))
+        .ok()
+        .filter(|value| !value.is_empty())
+}
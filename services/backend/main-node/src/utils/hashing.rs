@@ -1,9 +1,11 @@
-//! Hashing utility for hashing and verifying passwords
+//! Hashing utility for hashing and verifying passwords and tokens
 
 use argon2::{
     password_hash::{self, rand_core::OsRng, PasswordVerifier, SaltString},
     Argon2, PasswordHash, PasswordHasher,
 };
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
 
 /// Hashes the provided password using the Argon2 algorithm returning
 /// the generated hash in string form.
@@ -27,3 +29,30 @@ pub fn verify_password(password: &str, hash: &str) -> password_hash::Result<()>
     let argon2 = Argon2::default();
     argon2.verify_password(password.as_bytes(), &hash)
 }
+
+/// Creates a keyed HMAC-SHA256 hash of the provided token returning
+/// the hex encoded hash
+///
+/// `key`   The server secret key
+/// `token` The plain text token
+pub fn hash_token(key: &[u8], token: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(token.as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// Verifies that the provided token matches the hex encoded `hash`,
+/// the comparison is done in constant time
+///
+/// `key`   The server secret key
+/// `token` The plain text token
+/// `hash`  The hex encoded hash
+pub fn verify_token_hash(key: &[u8], token: &str, hash: &str) -> bool {
+    let Ok(hash) = hex::decode(hash) else {
+        return false;
+    };
+
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(token.as_bytes());
+    mac.verify_slice(&hash).is_ok()
+}
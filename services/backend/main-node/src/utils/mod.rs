@@ -1,5 +1,7 @@
 pub mod assert;
 pub mod env;
 pub mod hashing;
+pub mod pkce;
+pub mod totp;
 pub mod tracing;
 pub mod types;
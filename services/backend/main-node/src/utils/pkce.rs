@@ -0,0 +1,78 @@
+//! Proof Key for Code Exchange (RFC 7636) for OpenID logins, the OpenID
+//! client doesn't support PKCE so the challenge is added to the auth URL
+//! and the verifier is sent with the token request made here
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use openid::{Bearer, Claims, Client, CompactJson, Discovered, OAuth2Error, Provider};
+use rand::{
+    distributions::{Alphanumeric, DistString},
+    rngs::StdRng,
+    SeedableRng,
+};
+use reqwest::{header::ACCEPT, Url};
+use sha2::{Digest, Sha256};
+
+/// Length of generated code verifiers, RFC 7636 allows 43 to 128 characters
+const CODE_VERIFIER_LENGTH: usize = 64;
+
+/// Generates a new random PKCE code verifier
+pub fn generate_code_verifier() -> String {
+    Alphanumeric.sample_string(&mut StdRng::from_entropy(), CODE_VERIFIER_LENGTH)
+}
+
+/// Adds the S256 code challenge for the provided `code_verifier` to
+/// the provider `auth_url`
+pub fn append_code_challenge(auth_url: &mut Url, code_verifier: &str) {
+    let challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes()));
+
+    auth_url
+        .query_pairs_mut()
+        .append_pair("code_challenge", &challenge)
+        .append_pair("code_challenge_method", "S256");
+}
+
+/// Exchanges the OpenID `code` for a token with the provider of the
+/// `client`, including the `code_verifier` the code was requested with
+pub async fn request_token<C>(
+    client: &Client<Discovered, C>,
+    code: &str,
+    code_verifier: &str,
+) -> anyhow::Result<Bearer>
+where
+    C: CompactJson + Claims,
+{
+    let mut form = vec![
+        ("grant_type", "authorization_code"),
+        ("code", code),
+        ("code_verifier", code_verifier),
+    ];
+
+    if let Some(redirect_uri) = client.redirect_uri.as_deref() {
+        form.push(("redirect_uri", redirect_uri));
+    }
+
+    if client.provider.credentials_in_body() {
+        form.push(("client_id", &client.client_id));
+        if let Some(client_secret) = client.client_secret.as_deref() {
+            form.push(("client_secret", client_secret));
+        }
+    }
+
+    let json: serde_json::Value = client
+        .http_client
+        .post(client.provider.token_uri().clone())
+        .basic_auth(&client.client_id, client.client_secret.as_ref())
+        .header(ACCEPT, "application/json")
+        .form(&form)
+        .send()
+        .await?
+        .json()
+        .await?;
+
+    // Providers respond with an OAuth error body when the exchange fails
+    if let Ok(error) = serde_json::from_value::<OAuth2Error>(json.clone()) {
+        anyhow::bail!("Token request failed: {error}");
+    }
+
+    Ok(serde_json::from_value(json)?)
+}
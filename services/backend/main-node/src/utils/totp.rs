@@ -0,0 +1,183 @@
+//! Time-based one-time passwords (RFC 6238) used as the second factor
+//! of two-factor authentication, compatible with authenticator apps.
+//!
+//! Secrets are stored encrypted with AES-256-GCM using a server key so
+//! that they can't be used to generate codes if the database is leaked
+
+use crate::database::entities::user::UserId;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::Utc;
+use openssl::symm::{decrypt_aead, encrypt_aead, Cipher};
+use rand::{rngs::StdRng, RngCore, SeedableRng};
+use sha2::{Digest, Sha256};
+use totp_rs::{Algorithm, Secret, TOTP};
+
+/// Issuer shown alongside the account in authenticator apps
+const ISSUER: &str = "Quizler";
+/// Number of digits in each code
+const DIGITS: usize = 6;
+/// Number of seconds each code is valid for
+const STEP: u64 = 30;
+/// Number of steps either side of the current step that codes are
+/// accepted from to allow for clock drift
+const SKEW: i64 = 1;
+/// Length in bytes of generated secrets, 160 bits as recommended by RFC 4226
+const SECRET_LENGTH: usize = 20;
+/// Length in bytes of the nonce used when encrypting a secret
+const NONCE_LENGTH: usize = 12;
+/// Length in bytes of the authentication tag of an encrypted secret
+const TAG_LENGTH: usize = 16;
+
+/// Key for encrypting stored TOTP secrets
+#[derive(Clone)]
+pub struct SecretKey([u8; 32]);
+
+/// Newly generated TOTP secret
+pub struct GeneratedSecret {
+    /// Base32 encoded secret
+    pub secret: String,
+    /// otpauth:// URI for adding the secret to an authenticator app
+    pub uri: String,
+}
+
+/// Generates a new random TOTP secret for the account with the
+/// provided `account_name`
+pub fn generate_secret(account_name: &str) -> GeneratedSecret {
+    let mut bytes = vec![0u8; SECRET_LENGTH];
+    StdRng::from_entropy().fill_bytes(&mut bytes);
+
+    let secret = Secret::Raw(bytes.clone()).to_encoded().to_string();
+    let totp = TOTP::new_unchecked(
+        Algorithm::SHA1,
+        DIGITS,
+        0,
+        STEP,
+        bytes,
+        Some(ISSUER.to_string()),
+        account_name.to_string(),
+    );
+
+    GeneratedSecret {
+        secret,
+        uri: totp.get_url(),
+    }
+}
+
+/// Checks the provided `code` against the base32 encoded `secret`, codes
+/// from `last_used_step` or earlier are rejected so a code can't be
+/// replayed. Returns the time step of the code if it was valid
+pub fn verify_code(secret: &str, code: &str, last_used_step: Option<i64>) -> Option<i64> {
+    let bytes = Secret::Encoded(secret.to_string()).to_bytes().ok()?;
+    let totp = TOTP::new_unchecked(Algorithm::SHA1, DIGITS, 0, STEP, bytes, None, String::new());
+
+    // Codes are often displayed with a space in the middle
+    let code: String = code
+        .chars()
+        .filter(|value| !value.is_whitespace())
+        .collect();
+
+    let current = Utc::now().timestamp() / STEP as i64;
+
+    (current - SKEW..=current + SKEW)
+        .filter(|step| !matches!(last_used_step, Some(last) if *step <= last))
+        .find(|step| totp.check(&code, *step as u64 * STEP))
+}
+
+impl SecretKey {
+    /// Derives the encryption key from the server `secret`
+    pub fn new(secret: &[u8]) -> Self {
+        Self(Sha256::digest(secret).into())
+    }
+
+    /// Encrypts the base32 encoded `secret` of the user `user_id`, the
+    /// user ID is authenticated so the encrypted secret can't be moved
+    /// to another user. Responds with the base64 encoded nonce,
+    /// ciphertext and tag
+    pub fn encrypt(&self, user_id: UserId, secret: &str) -> String {
+        let mut nonce = [0u8; NONCE_LENGTH];
+        StdRng::from_entropy().fill_bytes(&mut nonce);
+
+        let mut tag = [0u8; TAG_LENGTH];
+        let ciphertext = encrypt_aead(
+            Cipher::aes_256_gcm(),
+            &self.0,
+            Some(&nonce),
+            &user_id.to_be_bytes(),
+            secret.as_bytes(),
+            &mut tag,
+        )
+        .expect("AES-256-GCM accepts 256-bit keys and 96-bit nonces");
+
+        let mut value = Vec::with_capacity(NONCE_LENGTH + ciphertext.len() + TAG_LENGTH);
+        value.extend_from_slice(&nonce);
+        value.extend_from_slice(&ciphertext);
+        value.extend_from_slice(&tag);
+
+        STANDARD.encode(value)
+    }
+
+    /// Decrypts a secret created by [SecretKey::encrypt] for the user
+    /// `user_id`. Responds with [None] if the secret was encrypted with
+    /// another key, for another user or was modified
+    pub fn decrypt(&self, user_id: UserId, encrypted: &str) -> Option<String> {
+        let value = STANDARD.decode(encrypted).ok()?;
+        if value.len() < NONCE_LENGTH + TAG_LENGTH {
+            return None;
+        }
+
+        let (nonce, rest) = value.split_at(NONCE_LENGTH);
+        let (ciphertext, tag) = rest.split_at(rest.len() - TAG_LENGTH);
+
+        let secret = decrypt_aead(
+            Cipher::aes_256_gcm(),
+            &self.0,
+            Some(nonce),
+            &user_id.to_be_bytes(),
+            ciphertext,
+            tag,
+        )
+        .ok()?;
+
+        String::from_utf8(secret).ok()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{generate_secret, SecretKey};
+
+    /// Encrypted secrets decrypt back to the original secret
+    #[test]
+    fn test_encrypt_secret() {
+        let key = SecretKey::new(b"test");
+        let secret = generate_secret("test@example.com").secret;
+
+        let encrypted = key.encrypt(1, &secret);
+        assert!(!encrypted.contains(&secret));
+        assert_eq!(key.decrypt(1, &encrypted), Some(secret.clone()));
+
+        // Each encryption uses a new nonce
+        assert_ne!(key.encrypt(1, &secret), encrypted);
+    }
+
+    /// Secrets can't be decrypted with another key, for another user or
+    /// once modified
+    #[test]
+    fn test_decrypt_rejected() {
+        let key = SecretKey::new(b"test");
+        let secret = generate_secret("test@example.com").secret;
+        let encrypted = key.encrypt(1, &secret);
+
+        assert_eq!(SecretKey::new(b"other").decrypt(1, &encrypted), None);
+        assert_eq!(key.decrypt(2, &encrypted), None);
+
+        let mut modified = encrypted.into_bytes();
+        modified[20] = if modified[20] == b'A' { b'B' } else { b'A' };
+        let modified = String::from_utf8(modified).unwrap();
+        assert_eq!(key.decrypt(1, &modified), None);
+
+        // Plain text secrets aren't accepted
+        assert_eq!(key.decrypt(1, &secret), None);
+        assert_eq!(key.decrypt(1, ""), None);
+    }
+}
@@ -8,6 +8,19 @@ mod m20240128_142720_create_permissions_table;
 mod m20240130_124944_create_user_links_table;
 mod m20240130_140620_create_user_refresh_tokens_table;
 mod m20240207_233443_create_resource_table;
+mod m20240215_120000_add_quiz_search;
+mod m20240220_120000_add_resource_images;
+mod m20240301_120000_create_user_sessions;
+mod m20240305_120000_create_rotated_refresh_tokens;
+mod m20240310_120000_hash_refresh_tokens;
+mod m20240315_120000_create_revoked_tokens;
+mod m20240320_120000_create_user_mfa;
+mod m20240325_120000_create_user_passkeys;
+mod m20240330_120000_add_user_link_subjects;
+mod m20240405_120000_use_provider_ids;
+mod m20240410_120000_create_organizations;
+mod m20240415_120000_create_scim;
+mod m20240420_120000_add_moderation;
 
 pub struct Migrator;
 
@@ -17,12 +30,25 @@ impl MigratorTrait for Migrator {
         vec![
             Box::new(m20240128_142246_create_users_table::Migration),
             Box::new(m20240128_142240_create_quiz_table::Migration),
-            // Box::new(m20240128_142254_create_analytics_table::Migration),
-            // Box::new(m20240128_142337_create_active_quiz_table::Migration),
-            // Box::new(m20240128_142720_create_permissions_table::Migration),
+            Box::new(m20240128_142337_create_active_quiz_table::Migration),
+            Box::new(m20240128_142254_create_analytics_table::Migration),
+            Box::new(m20240128_142720_create_permissions_table::Migration),
             Box::new(m20240130_124944_create_user_links_table::Migration),
             Box::new(m20240130_140620_create_user_refresh_tokens_table::Migration),
             Box::new(m20240207_233443_create_resource_table::Migration),
+            Box::new(m20240215_120000_add_quiz_search::Migration),
+            Box::new(m20240220_120000_add_resource_images::Migration),
+            Box::new(m20240301_120000_create_user_sessions::Migration),
+            Box::new(m20240305_120000_create_rotated_refresh_tokens::Migration),
+            Box::new(m20240310_120000_hash_refresh_tokens::Migration),
+            Box::new(m20240315_120000_create_revoked_tokens::Migration),
+            Box::new(m20240320_120000_create_user_mfa::Migration),
+            Box::new(m20240325_120000_create_user_passkeys::Migration),
+            Box::new(m20240330_120000_add_user_link_subjects::Migration),
+            Box::new(m20240405_120000_use_provider_ids::Migration),
+            Box::new(m20240410_120000_create_organizations::Migration),
+            Box::new(m20240415_120000_create_scim::Migration),
+            Box::new(m20240420_120000_add_moderation::Migration),
         ]
     }
 }
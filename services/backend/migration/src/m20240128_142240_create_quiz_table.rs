@@ -50,7 +50,7 @@ impl MigrationTrait for Migration {
 
 /// Learn more at https://docs.rs/sea-query#iden
 #[derive(Iden)]
-enum Quiz {
+pub enum Quiz {
     Table,
     Id,
     /// title
@@ -1,48 +1,107 @@
+//! Migration for creating the `analytics` table, a TimescaleDB hypertable
+//! storing a row for every answer (or missed answer) within a game
+
 use sea_orm_migration::prelude::*;
 
+use crate::{
+    m20240128_142240_create_quiz_table::Quiz, m20240128_142337_create_active_quiz_table::ActiveQuiz,
+};
+
 #[derive(DeriveMigrationName)]
 pub struct Migration;
 
 #[async_trait::async_trait]
 impl MigrationTrait for Migration {
     async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
-        // Replace the sample below with your own migration scripts
-        todo!();
+        let db = manager.get_connection();
+
+        db.execute_unprepared("CREATE EXTENSION IF NOT EXISTS timescaledb")
+            .await?;
 
         manager
             .create_table(
                 Table::create()
-                    .table(Post::Table)
+                    .table(Analytics::Table)
                     .if_not_exists()
-                    .col(
-                        ColumnDef::new(Post::Id)
-                            .integer()
-                            .not_null()
-                            .auto_increment()
-                            .primary_key(),
+                    // Hypertables require the time column within any unique keys
+                    .primary_key(
+                        Index::create()
+                            .col(Analytics::SessionId)
+                            .col(Analytics::QuestionId)
+                            .col(Analytics::PlayerId)
+                            .col(Analytics::Time),
+                    )
+                    .col(ColumnDef::new(Analytics::Time).date_time().not_null())
+                    .col(ColumnDef::new(Analytics::SessionId).integer().not_null())
+                    .col(ColumnDef::new(Analytics::QuizId).integer().not_null())
+                    .col(ColumnDef::new(Analytics::QuestionId).integer().not_null())
+                    .col(ColumnDef::new(Analytics::PlayerId).integer().not_null())
+                    .col(ColumnDef::new(Analytics::PlayerName).string().not_null())
+                    .col(ColumnDef::new(Analytics::Correct).boolean().not_null())
+                    .col(ColumnDef::new(Analytics::Points).integer().not_null())
+                    .col(ColumnDef::new(Analytics::ResponseTime).integer().null())
+                    // Cascade deletions from the active quiz table onto this table
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(Analytics::Table, Analytics::SessionId)
+                            .to(ActiveQuiz::Table, ActiveQuiz::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    // Cascade deletions from the quiz table onto this table
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(Analytics::Table, Analytics::QuizId)
+                            .to(Quiz::Table, Quiz::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
                     )
-                    .col(ColumnDef::new(Post::Title).string().not_null())
-                    .col(ColumnDef::new(Post::Text).string().not_null())
                     .to_owned(),
             )
-            .await
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-analytics-quiz_id")
+                    .table(Analytics::Table)
+                    .col(Analytics::QuizId)
+                    .to_owned(),
+            )
+            .await?;
+
+        db.execute_unprepared(
+            "SELECT create_hypertable('analytics', 'time', if_not_exists => TRUE)",
+        )
+        .await?;
+
+        Ok(())
     }
 
     async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
-        // Replace the sample below with your own migration scripts
-        todo!();
-
         manager
-            .drop_table(Table::drop().table(Post::Table).to_owned())
+            .drop_table(Table::drop().table(Analytics::Table).to_owned())
             .await
     }
 }
 
-/// Learn more at https://docs.rs/sea-query#iden
 #[derive(Iden)]
-enum Post {
+enum Analytics {
     Table,
-    Id,
-    Title,
-    Text,
+    /// When the answer was recorded
+    Time,
+    /// The game session the answer is from
+    SessionId,
+    /// The quiz that was played
+    QuizId,
+    /// ID of the question within the quiz
+    QuestionId,
+    /// ID of the player within the game
+    PlayerId,
+    /// Display name of the player
+    PlayerName,
+    /// Whether the answer was correct
+    Correct,
+    /// Points awarded for the answer
+    Points,
+    /// Time in milliseconds taken to answer, null if no answer was given
+    ResponseTime,
 }
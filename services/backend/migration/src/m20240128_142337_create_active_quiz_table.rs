@@ -1,48 +1,91 @@
+//! Migration for creating the `active_quiz` table which stores the live
+//! game sessions that have been hosted for a quiz along with their results
+
 use sea_orm_migration::prelude::*;
 
+use crate::{m20240128_142240_create_quiz_table::Quiz, m20240128_142246_create_users_table::Users};
+
 #[derive(DeriveMigrationName)]
 pub struct Migration;
 
 #[async_trait::async_trait]
 impl MigrationTrait for Migration {
     async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
-        // Replace the sample below with your own migration scripts
-        todo!();
-
         manager
             .create_table(
                 Table::create()
-                    .table(Post::Table)
+                    .table(ActiveQuiz::Table)
                     .if_not_exists()
                     .col(
-                        ColumnDef::new(Post::Id)
+                        ColumnDef::new(ActiveQuiz::Id)
                             .integer()
                             .not_null()
                             .auto_increment()
                             .primary_key(),
                     )
-                    .col(ColumnDef::new(Post::Title).string().not_null())
-                    .col(ColumnDef::new(Post::Text).string().not_null())
+                    .col(ColumnDef::new(ActiveQuiz::QuizId).integer().not_null())
+                    .col(ColumnDef::new(ActiveQuiz::HostId).integer().not_null())
+                    .col(ColumnDef::new(ActiveQuiz::Code).string().not_null())
+                    .col(ColumnDef::new(ActiveQuiz::State).integer().not_null())
+                    .col(ColumnDef::new(ActiveQuiz::Results).json().null())
+                    .col(ColumnDef::new(ActiveQuiz::CreatedAt).date_time().not_null())
+                    .col(ColumnDef::new(ActiveQuiz::StartedAt).date_time().null())
+                    .col(ColumnDef::new(ActiveQuiz::FinishedAt).date_time().null())
+                    // Cascade deletions from the quiz table onto this table
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(ActiveQuiz::Table, ActiveQuiz::QuizId)
+                            .to(Quiz::Table, Quiz::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    // Cascade deletions from the users table onto this table
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(ActiveQuiz::Table, ActiveQuiz::HostId)
+                            .to(Users::Table, Users::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-active_quiz-code")
+                    .table(ActiveQuiz::Table)
+                    .col(ActiveQuiz::Code)
                     .to_owned(),
             )
             .await
     }
 
     async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
-        // Replace the sample below with your own migration scripts
-        todo!();
-
         manager
-            .drop_table(Table::drop().table(Post::Table).to_owned())
+            .drop_table(Table::drop().table(ActiveQuiz::Table).to_owned())
             .await
     }
 }
 
-/// Learn more at https://docs.rs/sea-query#iden
 #[derive(Iden)]
-enum Post {
+pub enum ActiveQuiz {
     Table,
+    /// Unique ID for the game session
     Id,
-    Title,
-    Text,
+    /// The quiz being played
+    QuizId,
+    /// The user hosting the game
+    HostId,
+    /// Code players use to join the game
+    Code,
+    /// Lobby, Running, Finished, Cancelled
+    State,
+    /// JSON final leaderboard for the game
+    Results,
+    /// When the game was created
+    CreatedAt,
+    /// When the game was started
+    StartedAt,
+    /// When the game was finished
+    FinishedAt,
 }
@@ -1,48 +1,77 @@
+//! Migration for creating the `permissions` table which stores the roles
+//! granted to collaborators of a quiz
+
 use sea_orm_migration::prelude::*;
 
+use crate::{m20240128_142240_create_quiz_table::Quiz, m20240128_142246_create_users_table::Users};
+
 #[derive(DeriveMigrationName)]
 pub struct Migration;
 
 #[async_trait::async_trait]
 impl MigrationTrait for Migration {
     async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
-        // Replace the sample below with your own migration scripts
-        todo!();
-
         manager
             .create_table(
                 Table::create()
-                    .table(Post::Table)
+                    .table(Permissions::Table)
                     .if_not_exists()
+                    // This table uses a composite key over the quiz and user
+                    .primary_key(
+                        Index::create()
+                            .col(Permissions::QuizId)
+                            .col(Permissions::UserId),
+                    )
+                    .col(ColumnDef::new(Permissions::QuizId).integer().not_null())
+                    .col(ColumnDef::new(Permissions::UserId).integer().not_null())
+                    .col(ColumnDef::new(Permissions::Role).integer().not_null())
                     .col(
-                        ColumnDef::new(Post::Id)
-                            .integer()
-                            .not_null()
-                            .auto_increment()
-                            .primary_key(),
+                        ColumnDef::new(Permissions::CreatedAt)
+                            .date_time()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(Permissions::UpdatedAt)
+                            .date_time()
+                            .not_null(),
+                    )
+                    // Cascade deletions from the quiz table onto this table
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(Permissions::Table, Permissions::QuizId)
+                            .to(Quiz::Table, Quiz::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    // Cascade deletions from the users table onto this table
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(Permissions::Table, Permissions::UserId)
+                            .to(Users::Table, Users::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
                     )
-                    .col(ColumnDef::new(Post::Title).string().not_null())
-                    .col(ColumnDef::new(Post::Text).string().not_null())
                     .to_owned(),
             )
             .await
     }
 
     async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
-        // Replace the sample below with your own migration scripts
-        todo!();
-
         manager
-            .drop_table(Table::drop().table(Post::Table).to_owned())
+            .drop_table(Table::drop().table(Permissions::Table).to_owned())
             .await
     }
 }
 
-/// Learn more at https://docs.rs/sea-query#iden
 #[derive(Iden)]
-enum Post {
+enum Permissions {
     Table,
-    Id,
-    Title,
-    Text,
+    /// The quiz the permission is for
+    QuizId,
+    /// The user the permission is granted to
+    UserId,
+    /// Viewer, Editor, Host, Admin
+    Role,
+    /// When the permission was granted
+    CreatedAt,
+    /// When the permission was last changed
+    UpdatedAt,
 }
@@ -45,7 +45,7 @@ impl MigrationTrait for Migration {
 }
 
 #[derive(Iden)]
-enum UserLinks {
+pub enum UserLinks {
     Table,
     /// The ID of the user this link belongs to
     UserId,
@@ -52,7 +52,7 @@ impl MigrationTrait for Migration {
 }
 
 #[derive(Iden)]
-enum UserRefreshTokens {
+pub enum UserRefreshTokens {
     Table,
     /// The ID of the user the refresh token belongs to
     UserId,
@@ -50,7 +50,7 @@ impl MigrationTrait for Migration {
 
 /// Learn more at https://docs.rs/sea-query#iden
 #[derive(Iden)]
-enum Resources {
+pub enum Resources {
     Table,
     /// Unique ID for the resource
     Id,
@@ -60,7 +60,7 @@ enum Resources {
     Name,
     /// Optional description for the content
     Description,
-    /// URL path to the resource
+    /// Storage key for the resource content
     Path,
     /// The user that the resource belongs to
     Owner,
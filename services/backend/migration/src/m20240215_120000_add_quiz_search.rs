@@ -0,0 +1,103 @@
+//! Migration adding tags, play counts and the full-text search vector
+//! used for discovering public quizzes
+
+use sea_orm_migration::prelude::*;
+
+use crate::m20240128_142240_create_quiz_table::Quiz;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Quiz::Table)
+                    .add_column(
+                        ColumnDef::new(QuizSearch::Tags)
+                            .array(ColumnType::String(None))
+                            .not_null()
+                            .default(Expr::cust("'{}'")),
+                    )
+                    .add_column(
+                        ColumnDef::new(QuizSearch::PlayCount)
+                            .big_integer()
+                            .not_null()
+                            .default(0),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        let db = manager.get_connection();
+
+        // Generated search vector weighted by title, description then question text
+        db.execute_unprepared(
+            r#"ALTER TABLE "quiz" ADD COLUMN "search_vector" tsvector GENERATED ALWAYS AS (
+                setweight(to_tsvector('english'::regconfig, coalesce("title", '')), 'A') ||
+                setweight(to_tsvector('english'::regconfig, coalesce("description", '')), 'B') ||
+                setweight(jsonb_to_tsvector(
+                    'english'::regconfig,
+                    jsonb_path_query_array("data"::jsonb, '$.questions[*].text'),
+                    '["string"]'
+                ), 'C')
+            ) STORED"#,
+        )
+        .await?;
+
+        db.execute_unprepared(
+            r#"CREATE INDEX IF NOT EXISTS "idx-quiz-search_vector" ON "quiz" USING GIN ("search_vector")"#,
+        )
+        .await?;
+
+        db.execute_unprepared(
+            r#"CREATE INDEX IF NOT EXISTS "idx-quiz-tags" ON "quiz" USING GIN ("tags")"#,
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx-quiz-tags")
+                    .table(Quiz::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx-quiz-search_vector")
+                    .table(Quiz::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Quiz::Table)
+                    .drop_column(QuizSearch::SearchVector)
+                    .drop_column(QuizSearch::PlayCount)
+                    .drop_column(QuizSearch::Tags)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(Iden)]
+enum QuizSearch {
+    /// Collection of tags for the quiz
+    Tags,
+    /// Number of times the quiz has been played
+    PlayCount,
+    /// Generated full-text search vector
+    SearchVector,
+}
@@ -0,0 +1,163 @@
+//! Migration adding the dimensions and processed variants of image resources
+//! and changing quiz cover images to reference a resource
+
+use sea_orm_migration::prelude::*;
+
+use crate::{
+    m20240128_142240_create_quiz_table::Quiz, m20240207_233443_create_resource_table::Resources,
+};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Resources::Table)
+                    .add_column(
+                        ColumnDef::new(ResourceImage::Width)
+                            .integer()
+                            .not_null()
+                            .default(0),
+                    )
+                    .add_column(
+                        ColumnDef::new(ResourceImage::Height)
+                            .integer()
+                            .not_null()
+                            .default(0),
+                    )
+                    .add_column(
+                        ColumnDef::new(ResourceImage::Variants)
+                            .json()
+                            .not_null()
+                            .default(Expr::cust("'[]'")),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // The resource cover image is added alongside the existing column so
+        // the existing values can be carried over before it is removed
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Quiz::Table)
+                    .add_column(ColumnDef::new(ResourceCoverImage).integer().null())
+                    // Remove the cover image when the resource is deleted
+                    .add_foreign_key(
+                        TableForeignKey::new()
+                            .name("fk-quiz-cover_image")
+                            .from_tbl(Quiz::Table)
+                            .from_col(ResourceCoverImage)
+                            .to_tbl(Resources::Table)
+                            .to_col(Resources::Id)
+                            .on_delete(ForeignKeyAction::SetNull),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // Cover images that reference an existing resource by its ID or
+        // storage path are kept, any other value can't be mapped onto a
+        // resource and is left without a cover image
+        let db = manager.get_connection();
+        db.execute_unprepared(
+            r#"UPDATE "quiz" SET "cover_image_resource" = "resources"."id"
+            FROM "resources"
+            WHERE "quiz"."cover_image" = "resources"."id"::text
+                OR "quiz"."cover_image" = "resources"."path""#,
+        )
+        .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Quiz::Table)
+                    .drop_column(Quiz::CoverImage)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Quiz::Table)
+                    .rename_column(ResourceCoverImage, Quiz::CoverImage)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Resource cover images are kept as the text of their resource ID
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Quiz::Table)
+                    .add_column(ColumnDef::new(TextCoverImage).text().null())
+                    .to_owned(),
+            )
+            .await?;
+
+        let db = manager.get_connection();
+        db.execute_unprepared(
+            r#"UPDATE "quiz" SET "cover_image_text" = "cover_image"::text
+            WHERE "cover_image" IS NOT NULL"#,
+        )
+        .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Quiz::Table)
+                    .drop_foreign_key(Alias::new("fk-quiz-cover_image"))
+                    .drop_column(Quiz::CoverImage)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Quiz::Table)
+                    .rename_column(TextCoverImage, Quiz::CoverImage)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Resources::Table)
+                    .drop_column(ResourceImage::Variants)
+                    .drop_column(ResourceImage::Height)
+                    .drop_column(ResourceImage::Width)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(Iden)]
+enum ResourceImage {
+    /// Width of the image in pixels
+    Width,
+    /// Height of the image in pixels
+    Height,
+    /// Processed variants of the image
+    Variants,
+}
+
+/// Cover image column referencing a resource while the previous column
+/// is being replaced
+#[derive(Iden)]
+#[iden = "cover_image_resource"]
+struct ResourceCoverImage;
+
+/// Text cover image column while the resource column is being replaced
+#[derive(Iden)]
+#[iden = "cover_image_text"]
+struct TextCoverImage;
@@ -0,0 +1,107 @@
+//! Migration replacing the single refresh token per user with a refresh
+//! token per session, allowing users to be logged in on multiple devices
+
+use sea_orm_migration::prelude::*;
+
+use crate::{
+    m20240128_142246_create_users_table::Users,
+    m20240130_140620_create_user_refresh_tokens_table::{self, UserRefreshTokens},
+};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Existing tokens have no session details and are keyed by the token
+        // itself so the table is recreated, this logs out all existing users
+        manager
+            .drop_table(Table::drop().table(UserRefreshTokens::Table).to_owned())
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(UserRefreshTokens::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(UserSessions::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(UserRefreshTokens::UserId)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(UserRefreshTokens::RefreshToken)
+                            .string()
+                            .not_null()
+                            .unique_key(),
+                    )
+                    .col(ColumnDef::new(UserSessions::DeviceName).string().null())
+                    .col(ColumnDef::new(UserSessions::IpAddress).string().null())
+                    .col(ColumnDef::new(UserSessions::UserAgent).string().null())
+                    .col(
+                        ColumnDef::new(UserRefreshTokens::CreatedAt)
+                            .date_time()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(UserSessions::LastUsedAt)
+                            .date_time()
+                            .not_null(),
+                    )
+                    // Cascade deletions from the users table onto this table
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(UserRefreshTokens::Table, UserRefreshTokens::UserId)
+                            .to(Users::Table, Users::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // Sessions are listed and revoked by user
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-user_refresh_tokens-user_id")
+                    .table(UserRefreshTokens::Table)
+                    .col(UserRefreshTokens::UserId)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(UserRefreshTokens::Table).to_owned())
+            .await?;
+
+        // Restore the original single token table
+        m20240130_140620_create_user_refresh_tokens_table::Migration
+            .up(manager)
+            .await
+    }
+}
+
+/// Columns added to the `user_refresh_tokens` table for sessions
+#[derive(Iden)]
+pub enum UserSessions {
+    /// Unique ID for the session
+    Id,
+    /// Optional name of the device provided by the client
+    DeviceName,
+    /// IP address the session was last used from
+    IpAddress,
+    /// User agent of the client that created the session
+    UserAgent,
+    /// When the session was last refreshed
+    LastUsedAt,
+}
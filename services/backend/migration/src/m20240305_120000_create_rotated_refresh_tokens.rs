@@ -0,0 +1,85 @@
+//! Migration for creating the `user_rotated_refresh_tokens` table which
+//! remembers refresh tokens that have already been rotated so that
+//! replaying them can be detected
+
+use sea_orm_migration::prelude::*;
+
+use crate::{
+    m20240130_140620_create_user_refresh_tokens_table::UserRefreshTokens,
+    m20240301_120000_create_user_sessions::UserSessions,
+};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(UserRotatedRefreshTokens::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(UserRotatedRefreshTokens::RefreshToken)
+                            .string()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(UserRotatedRefreshTokens::SessionId)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(UserRotatedRefreshTokens::RotatedAt)
+                            .date_time()
+                            .not_null(),
+                    )
+                    // Cascade deletions from the sessions onto this table
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(
+                                UserRotatedRefreshTokens::Table,
+                                UserRotatedRefreshTokens::SessionId,
+                            )
+                            .to(UserRefreshTokens::Table, UserSessions::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // Rotated tokens are pruned by session
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-user_rotated_refresh_tokens-session_id")
+                    .table(UserRotatedRefreshTokens::Table)
+                    .col(UserRotatedRefreshTokens::SessionId)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(
+                Table::drop()
+                    .table(UserRotatedRefreshTokens::Table)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(Iden)]
+pub enum UserRotatedRefreshTokens {
+    Table,
+    /// The refresh token that was replaced
+    RefreshToken,
+    /// The session the token belonged to
+    SessionId,
+    /// When the token was replaced
+    RotatedAt,
+}
@@ -0,0 +1,199 @@
+//! Migration replacing the plaintext refresh tokens with a lookup identifier
+//! and a keyed hash of the token so that a database dump can't be used to
+//! take over sessions
+
+use hmac::{Hmac, Mac};
+use sea_orm_migration::{prelude::*, sea_orm::ConnectionTrait};
+use sha2::Sha256;
+
+use crate::{
+    m20240130_140620_create_user_refresh_tokens_table::UserRefreshTokens,
+    m20240301_120000_create_user_sessions::UserSessions,
+    m20240305_120000_create_rotated_refresh_tokens::UserRotatedRefreshTokens,
+};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+/// Environment variable containing the key used to hash refresh tokens
+const REFRESH_TOKEN_HASH_KEY: &str = "REFRESH_TOKEN_HASH_KEY";
+/// Length of the lookup identifier prefix of refresh tokens
+const TOKEN_ID_LENGTH: usize = 16;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(UserRefreshTokens::Table)
+                    .add_column(ColumnDef::new(RefreshTokenHash::TokenId).string().null())
+                    .add_column(ColumnDef::new(RefreshTokenHash::TokenHash).string().null())
+                    .to_owned(),
+            )
+            .await?;
+
+        let db = manager.get_connection();
+        let backend = manager.get_database_backend();
+
+        // Hash the existing tokens, existing tokens keep working as the
+        // identifier is taken from the start of the token
+        let rows = db
+            .query_all(
+                backend.build(
+                    Query::select()
+                        .column(UserSessions::Id)
+                        .column(UserRefreshTokens::RefreshToken)
+                        .from(UserRefreshTokens::Table),
+                ),
+            )
+            .await?;
+
+        if !rows.is_empty() {
+            let key = std::env::var(REFRESH_TOKEN_HASH_KEY).map_err(|_| {
+                DbErr::Migration(format!(
+                    "Missing {REFRESH_TOKEN_HASH_KEY} environment variable"
+                ))
+            })?;
+
+            for row in rows {
+                let id: i32 = row.try_get("", "id")?;
+                let token: String = row.try_get("", "refresh_token")?;
+
+                let token_id: String = token.chars().take(TOKEN_ID_LENGTH).collect();
+                let token_hash = hash_token(key.as_bytes(), &token);
+
+                db.execute(
+                    backend.build(
+                        Query::update()
+                            .table(UserRefreshTokens::Table)
+                            .value(RefreshTokenHash::TokenId, token_id)
+                            .value(RefreshTokenHash::TokenHash, token_hash)
+                            .and_where(Expr::col(UserSessions::Id).eq(id)),
+                    ),
+                )
+                .await?;
+            }
+        }
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(UserRefreshTokens::Table)
+                    .modify_column(
+                        ColumnDef::new(RefreshTokenHash::TokenId)
+                            .string()
+                            .not_null(),
+                    )
+                    .modify_column(
+                        ColumnDef::new(RefreshTokenHash::TokenHash)
+                            .string()
+                            .not_null(),
+                    )
+                    .drop_column(UserRefreshTokens::RefreshToken)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-user_refresh_tokens-token_id")
+                    .table(UserRefreshTokens::Table)
+                    .col(RefreshTokenHash::TokenId)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+
+        // Rotated tokens are only kept for detecting reuse so they are
+        // cleared rather than migrated
+        manager
+            .exec_stmt(
+                Query::delete()
+                    .from_table(UserRotatedRefreshTokens::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(UserRotatedRefreshTokens::Table)
+                    .drop_column(UserRotatedRefreshTokens::RefreshToken)
+                    .add_column(
+                        ColumnDef::new(RefreshTokenHash::TokenId)
+                            .string()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .add_column(
+                        ColumnDef::new(RefreshTokenHash::TokenHash)
+                            .string()
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Plaintext tokens can't be recovered from the hashes so all the
+        // existing sessions are removed
+        manager
+            .exec_stmt(
+                Query::delete()
+                    .from_table(UserRefreshTokens::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(UserRotatedRefreshTokens::Table)
+                    .drop_column(RefreshTokenHash::TokenId)
+                    .drop_column(RefreshTokenHash::TokenHash)
+                    .add_column(
+                        ColumnDef::new(UserRotatedRefreshTokens::RefreshToken)
+                            .string()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(UserRefreshTokens::Table)
+                    .drop_column(RefreshTokenHash::TokenId)
+                    .drop_column(RefreshTokenHash::TokenHash)
+                    .add_column(
+                        ColumnDef::new(UserRefreshTokens::RefreshToken)
+                            .string()
+                            .not_null()
+                            .unique_key(),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+/// Creates the hex encoded HMAC-SHA256 hash of the provided `token`
+fn hash_token(key: &[u8], token: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(token.as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// Columns replacing the plaintext refresh token columns
+#[derive(Iden)]
+enum RefreshTokenHash {
+    /// Identifier prefix of the token used for looking it up
+    TokenId,
+    /// Keyed hash of the token
+    TokenHash,
+}
@@ -0,0 +1,58 @@
+//! Migration for creating the `revoked_tokens` table which stores the IDs
+//! of access tokens that were revoked before they expired
+
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(RevokedTokens::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(RevokedTokens::Jti)
+                            .string()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(RevokedTokens::ExpiresAt)
+                            .date_time()
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // Expired entries are cleared by expiry time
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-revoked_tokens-expires_at")
+                    .table(RevokedTokens::Table)
+                    .col(RevokedTokens::ExpiresAt)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(RevokedTokens::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(Iden)]
+enum RevokedTokens {
+    Table,
+    /// Unique ID of the revoked token
+    Jti,
+    /// When the token expires and no longer needs to be denied
+    ExpiresAt,
+}
@@ -0,0 +1,128 @@
+//! Migration for creating the `user_totp` table which stores the TOTP
+//! secrets of users with two-factor authentication and the
+//! `user_recovery_codes` table storing their hashed recovery codes
+
+use sea_orm_migration::prelude::*;
+
+use crate::m20240128_142246_create_users_table::Users;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(UserTotp::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(UserTotp::UserId)
+                            .integer()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(UserTotp::Secret).string().not_null())
+                    .col(ColumnDef::new(UserTotp::LastUsedStep).big_integer().null())
+                    .col(ColumnDef::new(UserTotp::ConfirmedAt).date_time().null())
+                    .col(ColumnDef::new(UserTotp::CreatedAt).date_time().not_null())
+                    // Cascade deletions from the users table onto this table
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(UserTotp::Table, UserTotp::UserId)
+                            .to(Users::Table, Users::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(UserRecoveryCodes::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(UserRecoveryCodes::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(UserRecoveryCodes::UserId)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(UserRecoveryCodes::CodeHash)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(UserRecoveryCodes::CreatedAt)
+                            .date_time()
+                            .not_null(),
+                    )
+                    // Cascade deletions from the users table onto this table
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(UserRecoveryCodes::Table, UserRecoveryCodes::UserId)
+                            .to(Users::Table, Users::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // Recovery codes are looked up by user and hash
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-user_recovery_codes-user_id-code_hash")
+                    .table(UserRecoveryCodes::Table)
+                    .col(UserRecoveryCodes::UserId)
+                    .col(UserRecoveryCodes::CodeHash)
+                    .unique()
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(UserRecoveryCodes::Table).to_owned())
+            .await?;
+
+        manager
+            .drop_table(Table::drop().table(UserTotp::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(Iden)]
+enum UserTotp {
+    Table,
+    /// ID of the user the secret belongs to
+    UserId,
+    /// Base32 encoded TOTP secret
+    Secret,
+    /// Time step of the last accepted code, prevents codes being reused
+    LastUsedStep,
+    /// When the user confirmed the secret, null while enrollment is
+    /// still pending
+    ConfirmedAt,
+    CreatedAt,
+}
+
+#[derive(Iden)]
+enum UserRecoveryCodes {
+    Table,
+    Id,
+    /// ID of the user the code belongs to
+    UserId,
+    /// Keyed hash of the recovery code
+    CodeHash,
+    CreatedAt,
+}
@@ -0,0 +1,88 @@
+//! Migration for creating the `user_passkeys` table which stores the
+//! WebAuthn passkeys users can login with
+
+use sea_orm_migration::prelude::*;
+
+use crate::m20240128_142246_create_users_table::Users;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(UserPasskeys::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(UserPasskeys::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(UserPasskeys::UserId).integer().not_null())
+                    .col(
+                        ColumnDef::new(UserPasskeys::CredentialId)
+                            .string()
+                            .not_null()
+                            .unique_key(),
+                    )
+                    .col(ColumnDef::new(UserPasskeys::UserHandle).string().not_null())
+                    .col(ColumnDef::new(UserPasskeys::Name).string().not_null())
+                    .col(ColumnDef::new(UserPasskeys::Passkey).json().not_null())
+                    .col(
+                        ColumnDef::new(UserPasskeys::CreatedAt)
+                            .date_time()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(UserPasskeys::LastUsedAt).date_time().null())
+                    // Cascade deletions from the users table onto this table
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(UserPasskeys::Table, UserPasskeys::UserId)
+                            .to(Users::Table, Users::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // Passkeys are listed by user
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-user_passkeys-user_id")
+                    .table(UserPasskeys::Table)
+                    .col(UserPasskeys::UserId)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(UserPasskeys::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(Iden)]
+enum UserPasskeys {
+    Table,
+    Id,
+    /// ID of the user the passkey belongs to
+    UserId,
+    /// Base64url encoded ID of the credential
+    CredentialId,
+    /// WebAuthn user handle, shared by all the passkeys of a user
+    UserHandle,
+    /// Name given to the passkey by the user
+    Name,
+    /// The stored credential
+    Passkey,
+    CreatedAt,
+    LastUsedAt,
+}
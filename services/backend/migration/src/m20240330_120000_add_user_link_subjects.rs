@@ -0,0 +1,71 @@
+//! Migration adding the provider subject and issuer to user links so
+//! that accounts are found by the provider account rather than by the
+//! email address which can change or be reused at the provider.
+//!
+//! Existing links don't have a subject, these are bound to the provider
+//! account the next time they are used to login
+
+use sea_orm_migration::prelude::*;
+
+use crate::m20240130_124944_create_user_links_table::UserLinks;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(UserLinks::Table)
+                    .add_column(ColumnDef::new(UserLinkSubjects::Issuer).string().null())
+                    .add_column(ColumnDef::new(UserLinkSubjects::Subject).string().null())
+                    .to_owned(),
+            )
+            .await?;
+
+        // A provider account can only be linked to a single user
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-user_links-provider-subject")
+                    .table(UserLinks::Table)
+                    .col(UserLinks::Provider)
+                    .col(UserLinkSubjects::Subject)
+                    .unique()
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx-user_links-provider-subject")
+                    .table(UserLinks::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(UserLinks::Table)
+                    .drop_column(UserLinkSubjects::Issuer)
+                    .drop_column(UserLinkSubjects::Subject)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+/// Columns identifying the provider account of a link
+#[derive(Iden)]
+enum UserLinkSubjects {
+    /// Issuer of the provider ID tokens
+    Issuer,
+    /// Subject of the provider account, unique per issuer
+    Subject,
+}
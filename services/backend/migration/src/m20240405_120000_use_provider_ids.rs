@@ -0,0 +1,42 @@
+//! Migration converting the providers stored on user links to the IDs
+//! of configured providers. Links were previously stored with the
+//! uppercase name of a built in provider ("GOOGLE") which matches the
+//! lowercase ID the provider is now configured with ("google")
+
+use sea_orm_migration::prelude::*;
+
+use crate::m20240130_124944_create_user_links_table::UserLinks;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .exec_stmt(
+                Query::update()
+                    .table(UserLinks::Table)
+                    .value(
+                        UserLinks::Provider,
+                        Func::lower(Expr::col(UserLinks::Provider)),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .exec_stmt(
+                Query::update()
+                    .table(UserLinks::Table)
+                    .value(
+                        UserLinks::Provider,
+                        Func::upper(Expr::col(UserLinks::Provider)),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+}
@@ -0,0 +1,336 @@
+//! Migration for creating the `organizations` tables which store the
+//! organizations, their members and the email domains they have claimed.
+//! Quizzes and resources can optionally be owned by an organization
+
+use sea_orm_migration::prelude::*;
+
+use crate::{
+    m20240128_142240_create_quiz_table::Quiz, m20240128_142246_create_users_table::Users,
+    m20240207_233443_create_resource_table::Resources,
+};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Organizations::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Organizations::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(Organizations::Name).string().not_null())
+                    .col(
+                        ColumnDef::new(Organizations::CreatedAt)
+                            .date_time()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(Organizations::UpdatedAt)
+                            .date_time()
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(OrganizationMembers::Table)
+                    .if_not_exists()
+                    // This table uses a composite key over the organization and user
+                    .primary_key(
+                        Index::create()
+                            .col(OrganizationMembers::OrganizationId)
+                            .col(OrganizationMembers::UserId),
+                    )
+                    .col(
+                        ColumnDef::new(OrganizationMembers::OrganizationId)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(OrganizationMembers::UserId)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(OrganizationMembers::Role)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(OrganizationMembers::CreatedAt)
+                            .date_time()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(OrganizationMembers::UpdatedAt)
+                            .date_time()
+                            .not_null(),
+                    )
+                    // Cascade deletions from the organizations table onto this table
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(
+                                OrganizationMembers::Table,
+                                OrganizationMembers::OrganizationId,
+                            )
+                            .to(Organizations::Table, Organizations::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    // Cascade deletions from the users table onto this table
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(OrganizationMembers::Table, OrganizationMembers::UserId)
+                            .to(Users::Table, Users::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // Organizations are listed by member
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-organization_members-user_id")
+                    .table(OrganizationMembers::Table)
+                    .col(OrganizationMembers::UserId)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(OrganizationDomains::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(OrganizationDomains::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(OrganizationDomains::OrganizationId)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(OrganizationDomains::Domain)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(OrganizationDomains::VerificationToken)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(OrganizationDomains::VerifiedAt)
+                            .date_time()
+                            .null(),
+                    )
+                    .col(
+                        ColumnDef::new(OrganizationDomains::CreatedAt)
+                            .date_time()
+                            .not_null(),
+                    )
+                    // Cascade deletions from the organizations table onto this table
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(
+                                OrganizationDomains::Table,
+                                OrganizationDomains::OrganizationId,
+                            )
+                            .to(Organizations::Table, Organizations::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // Each organization can only claim a domain once
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-organization_domains-organization_id-domain")
+                    .table(OrganizationDomains::Table)
+                    .col(OrganizationDomains::OrganizationId)
+                    .col(OrganizationDomains::Domain)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+
+        // Multiple organizations can attempt to claim a domain but only
+        // one of them can have it verified
+        manager
+            .get_connection()
+            .execute_unprepared(
+                r#"CREATE UNIQUE INDEX IF NOT EXISTS "idx-organization_domains-verified_domain"
+                ON "organization_domains" ("domain") WHERE "verified_at" IS NOT NULL"#,
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Quiz::Table)
+                    .add_column(
+                        ColumnDef::new(OrganizationOwned::OrganizationId)
+                            .integer()
+                            .null(),
+                    )
+                    // Quizzes return to their owner when the organization is deleted
+                    .add_foreign_key(
+                        TableForeignKey::new()
+                            .name("fk-quiz-organization_id")
+                            .from_tbl(Quiz::Table)
+                            .from_col(OrganizationOwned::OrganizationId)
+                            .to_tbl(Organizations::Table)
+                            .to_col(Organizations::Id)
+                            .on_delete(ForeignKeyAction::SetNull),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Resources::Table)
+                    .add_column(
+                        ColumnDef::new(OrganizationOwned::OrganizationId)
+                            .integer()
+                            .null(),
+                    )
+                    // Resources return to their owner when the organization is deleted
+                    .add_foreign_key(
+                        TableForeignKey::new()
+                            .name("fk-resources-organization_id")
+                            .from_tbl(Resources::Table)
+                            .from_col(OrganizationOwned::OrganizationId)
+                            .to_tbl(Organizations::Table)
+                            .to_col(Organizations::Id)
+                            .on_delete(ForeignKeyAction::SetNull),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // Quizzes and resources are listed by organization
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-quiz-organization_id")
+                    .table(Quiz::Table)
+                    .col(OrganizationOwned::OrganizationId)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-resources-organization_id")
+                    .table(Resources::Table)
+                    .col(OrganizationOwned::OrganizationId)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Resources::Table)
+                    .drop_foreign_key(Alias::new("fk-resources-organization_id"))
+                    .drop_column(OrganizationOwned::OrganizationId)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Quiz::Table)
+                    .drop_foreign_key(Alias::new("fk-quiz-organization_id"))
+                    .drop_column(OrganizationOwned::OrganizationId)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .drop_table(Table::drop().table(OrganizationDomains::Table).to_owned())
+            .await?;
+
+        manager
+            .drop_table(Table::drop().table(OrganizationMembers::Table).to_owned())
+            .await?;
+
+        manager
+            .drop_table(Table::drop().table(Organizations::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(Iden)]
+pub enum Organizations {
+    Table,
+    Id,
+    /// Name of the organization
+    Name,
+    CreatedAt,
+    UpdatedAt,
+}
+
+#[derive(Iden)]
+enum OrganizationMembers {
+    Table,
+    /// The organization the user is a member of
+    OrganizationId,
+    /// The member user
+    UserId,
+    /// Member, Admin, Owner
+    Role,
+    /// When the user joined the organization
+    CreatedAt,
+    /// When the role was last changed
+    UpdatedAt,
+}
+
+#[derive(Iden)]
+enum OrganizationDomains {
+    Table,
+    Id,
+    /// The organization claiming the domain
+    OrganizationId,
+    /// The claimed email domain
+    Domain,
+    /// Token that must be published in a DNS TXT record to verify the claim
+    VerificationToken,
+    /// When the claim was verified, new users with an email address on
+    /// verified domains automatically join the organization
+    VerifiedAt,
+    CreatedAt,
+}
+
+/// Column shared by the quiz and resources tables
+#[derive(Iden)]
+enum OrganizationOwned {
+    /// The organization that owns the quiz or resource
+    OrganizationId,
+}
@@ -0,0 +1,242 @@
+//! Migration for SCIM provisioning, adds the tables storing the bearer
+//! tokens organizations provision users with and the groups provisioned
+//! from their directory. Users can be deactivated and are marked with
+//! the organization that manages them
+
+use sea_orm_migration::prelude::*;
+
+use crate::{
+    m20240128_142246_create_users_table::Users,
+    m20240410_120000_create_organizations::Organizations,
+};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Users::Table)
+                    .add_column(ColumnDef::new(ScimUsers::DeactivatedAt).date_time().null())
+                    .add_column(ColumnDef::new(ScimUsers::ManagedBy).integer().null())
+                    // Users are no longer managed once the organization is deleted
+                    .add_foreign_key(
+                        TableForeignKey::new()
+                            .name("fk-users-managed_by")
+                            .from_tbl(Users::Table)
+                            .from_col(ScimUsers::ManagedBy)
+                            .to_tbl(Organizations::Table)
+                            .to_col(Organizations::Id)
+                            .on_delete(ForeignKeyAction::SetNull),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // Managed users are listed by organization
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-users-managed_by")
+                    .table(Users::Table)
+                    .col(ScimUsers::ManagedBy)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(ScimTokens::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(ScimTokens::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(ScimTokens::OrganizationId)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(ScimTokens::Name).string().not_null())
+                    .col(
+                        ColumnDef::new(ScimTokens::TokenHash)
+                            .string()
+                            .not_null()
+                            .unique_key(),
+                    )
+                    .col(ColumnDef::new(ScimTokens::CreatedAt).date_time().not_null())
+                    .col(ColumnDef::new(ScimTokens::LastUsedAt).date_time().null())
+                    // Cascade deletions from the organizations table onto this table
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(ScimTokens::Table, ScimTokens::OrganizationId)
+                            .to(Organizations::Table, Organizations::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(ScimGroups::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(ScimGroups::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(ScimGroups::OrganizationId)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(ScimGroups::DisplayName).string().not_null())
+                    .col(ColumnDef::new(ScimGroups::ExternalId).string().null())
+                    .col(ColumnDef::new(ScimGroups::CreatedAt).date_time().not_null())
+                    .col(ColumnDef::new(ScimGroups::UpdatedAt).date_time().not_null())
+                    // Cascade deletions from the organizations table onto this table
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(ScimGroups::Table, ScimGroups::OrganizationId)
+                            .to(Organizations::Table, Organizations::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // Group names are unique within each organization
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-scim_groups-organization_id-display_name")
+                    .table(ScimGroups::Table)
+                    .col(ScimGroups::OrganizationId)
+                    .col(ScimGroups::DisplayName)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(ScimGroupMembers::Table)
+                    .if_not_exists()
+                    // This table uses a composite key over the group and user
+                    .primary_key(
+                        Index::create()
+                            .col(ScimGroupMembers::GroupId)
+                            .col(ScimGroupMembers::UserId),
+                    )
+                    .col(
+                        ColumnDef::new(ScimGroupMembers::GroupId)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(ScimGroupMembers::UserId)
+                            .integer()
+                            .not_null(),
+                    )
+                    // Cascade deletions from the groups table onto this table
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(ScimGroupMembers::Table, ScimGroupMembers::GroupId)
+                            .to(ScimGroups::Table, ScimGroups::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    // Cascade deletions from the users table onto this table
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(ScimGroupMembers::Table, ScimGroupMembers::UserId)
+                            .to(Users::Table, Users::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(ScimGroupMembers::Table).to_owned())
+            .await?;
+
+        manager
+            .drop_table(Table::drop().table(ScimGroups::Table).to_owned())
+            .await?;
+
+        manager
+            .drop_table(Table::drop().table(ScimTokens::Table).to_owned())
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Users::Table)
+                    .drop_foreign_key(Alias::new("fk-users-managed_by"))
+                    .drop_column(ScimUsers::ManagedBy)
+                    .drop_column(ScimUsers::DeactivatedAt)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+/// Columns added to the users table
+#[derive(Iden)]
+enum ScimUsers {
+    /// When the user was deactivated, deactivated users can't login
+    DeactivatedAt,
+    /// The organization that provisions and manages the user
+    ManagedBy,
+}
+
+#[derive(Iden)]
+enum ScimTokens {
+    Table,
+    Id,
+    /// The organization the token provisions users for
+    OrganizationId,
+    /// Name given to the token
+    Name,
+    /// Keyed hash of the token
+    TokenHash,
+    CreatedAt,
+    LastUsedAt,
+}
+
+#[derive(Iden)]
+enum ScimGroups {
+    Table,
+    Id,
+    /// The organization the group belongs to
+    OrganizationId,
+    /// Name of the group
+    DisplayName,
+    /// ID of the group within the directory
+    ExternalId,
+    CreatedAt,
+    UpdatedAt,
+}
+
+#[derive(Iden)]
+enum ScimGroupMembers {
+    Table,
+    /// The group the user is a member of
+    GroupId,
+    /// The member user
+    UserId,
+}
@@ -0,0 +1,80 @@
+//! Migration adding the columns used by administrators and moderators,
+//! users can be suspended or required to reset their password and public
+//! quizzes can be hidden
+
+use sea_orm_migration::prelude::*;
+
+use crate::{m20240128_142240_create_quiz_table::Quiz, m20240128_142246_create_users_table::Users};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Users::Table)
+                    .add_column(
+                        ColumnDef::new(ModerationUsers::SuspendedAt)
+                            .date_time()
+                            .null(),
+                    )
+                    .add_column(
+                        ColumnDef::new(ModerationUsers::PasswordResetRequired)
+                            .boolean()
+                            .not_null()
+                            .default(false),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Quiz::Table)
+                    .add_column(ColumnDef::new(ModerationQuiz::HiddenAt).date_time().null())
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Quiz::Table)
+                    .drop_column(ModerationQuiz::HiddenAt)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Users::Table)
+                    .drop_column(ModerationUsers::PasswordResetRequired)
+                    .drop_column(ModerationUsers::SuspendedAt)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+/// Columns added to the users table
+#[derive(Iden)]
+enum ModerationUsers {
+    /// When the user was suspended by an administrator
+    SuspendedAt,
+    /// Whether an administrator has required the user to reset their password
+    PasswordResetRequired,
+}
+
+/// Columns added to the quiz table
+#[derive(Iden)]
+enum ModerationQuiz {
+    /// When the quiz was hidden by a moderator
+    HiddenAt,
+}
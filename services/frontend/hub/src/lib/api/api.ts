@@ -9,24 +9,73 @@ export const ENDPOINTS = {
 	auth: {
 		basic: {
 			register: "/auth/basic/register",
-			login: "/auth/basic/login"
+			login: "/auth/basic/login",
+			forgot: "/auth/basic/forgot",
+			reset: "/auth/basic/reset"
 		},
 		token: {
 			refresh: "/auth/token/refresh"
 		},
+		logout: "/auth/logout",
+		mfa: {
+			status: "/auth/mfa",
+			verify: "/auth/mfa/verify",
+			totpSetup: "/auth/mfa/totp/setup",
+			totpConfirm: "/auth/mfa/totp/confirm",
+			recoveryCodes: "/auth/mfa/recovery-codes"
+		},
+		webauthn: {
+			registerStart: "/auth/webauthn/register/start",
+			registerFinish: "/auth/webauthn/register/finish",
+			loginStart: "/auth/webauthn/login/start",
+			loginFinish: "/auth/webauthn/login/finish",
+			passkeys: "/auth/webauthn/passkeys",
+			passkey: (id: number) => `/auth/webauthn/passkeys/${id}`
+		},
+		sessions: {
+			list: "/auth/sessions",
+			specific: (id: number) => `/auth/sessions/${id}`
+		},
+		verifyEmail: {
+			verify: "/auth/verify-email",
+			resend: "/auth/verify-email/resend"
+		},
 		oid: {
 			providers: "/auth/oid/providers",
 			authenticate: "/auth/oid/authenticate",
-			create: "/auth/oid/create"
+			create: "/auth/oid/create",
+			link: "/auth/oid/link",
+			linkProviders: "/auth/oid/link/providers",
+			links: "/auth/oid/links",
+			specificLink: (provider: string) => `/auth/oid/links/${provider}`
+		},
+		saml: {
+			login: (connection: string) => `/auth/saml/${connection}/login`,
+			exchange: "/auth/saml/exchange"
 		}
 	},
 	user: {
 		self: "/user/self"
 	},
 	quiz: {
+		library: "/quiz",
+		browse: "/quiz/browse",
 		create: "/quiz/create",
+		shared: "/quiz/shared",
+		specific: (id: number) => ({
+			root: `/quiz/${id}`,
+			publish: `/quiz/${id}/publish`,
+			unpublish: `/quiz/${id}/unpublish`,
+			collaborators: `/quiz/${id}/collaborators`,
+			collaborator: (userId: number) => `/quiz/${id}/collaborators/${userId}`
+		})
+	},
+	resource: {
+		list: "/resource",
+		upload: "/resource/upload",
 		specific: (id: number) => ({
-			root: `/quiz/${id}`
+			root: `/resource/${id}`,
+			content: `/resource/${id}/content`
 		})
 	}
 };
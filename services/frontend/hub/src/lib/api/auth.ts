@@ -14,31 +14,36 @@ export interface TokenResponse {
 	expiry: number;
 }
 
-// Different auth providers
-export enum AuthProvider {
-	Google = "Google",
-	Microsoft = "Microsoft"
-}
+// ID of a configured auth provider
+export type AuthProvider = string;
 
-// OpenID provider and token
-export interface OIDData {
-	token: string;
-	provider: AuthProvider;
-}
-
-export type OIDProviders = Partial<Record<AuthProvider, OIDProvider>>;
+export type OIDProviders = Record<AuthProvider, OIDProvider>;
 
 export interface OIDProvidersResponse {
 	providers: OIDProviders;
 }
 
 export interface OIDProvider {
+	name: string;
+	icon_url: string | null;
 	auth_url: string;
 }
 
 export type OIDAuthenticateResponse =
-	| { type: "CreateAccount"; token: string; default_username: string | null }
-	| ({ type: "ExistingLinked" } & TokenResponse);
+	| {
+			type: "CreateAccount";
+			provider: AuthProvider;
+			token: string;
+			default_username: string | null;
+	  }
+	| ({ type: "ExistingLinked" } & TokenResponse)
+	| { type: "MfaRequired"; mfa_token: string }
+	| { type: "MfaSetupRequired"; mfa_token: string };
+
+export interface OIDLink {
+	provider: AuthProvider;
+	created_at: string;
+}
 
 export interface BasicRegisterRequest {
 	username: string;
@@ -51,6 +56,53 @@ export interface BasicLoginRequest {
 	password: string;
 }
 
+export type LoginResponse =
+	| ({ type: "Authenticated" } & TokenResponse)
+	| { type: "MfaRequired"; mfa_token: string }
+	| { type: "MfaSetupRequired"; mfa_token: string };
+
+export interface TotpSetupResponse {
+	secret: string;
+	uri: string;
+}
+
+export type TotpConfirmResponse = { recovery_codes: string[] } & Partial<TokenResponse>;
+
+export interface MfaStatus {
+	enabled: boolean;
+	required: boolean;
+	recovery_codes_remaining: number;
+}
+
+export interface Passkey {
+	id: number;
+	name: string;
+	created_at: string;
+	last_used_at: string | null;
+}
+
+// Challenge for the authenticator from starting a passkey ceremony
+interface PasskeyChallenge {
+	challenge_id: string;
+	options: { publicKey: unknown };
+}
+
+// JSON parsing helpers for WebAuthn options, not yet in the DOM types
+type PublicKeyCredentialJSONParsers = typeof PublicKeyCredential & {
+	parseCreationOptionsFromJSON(options: unknown): PublicKeyCredentialCreationOptions;
+	parseRequestOptionsFromJSON(options: unknown): PublicKeyCredentialRequestOptions;
+};
+
+export interface Session {
+	id: number;
+	device_name: string | null;
+	ip_address: string | null;
+	user_agent: string | null;
+	created_at: string;
+	last_used_at: string;
+	current: boolean;
+}
+
 export async function registerBasic(
 	body: BasicRegisterRequest,
 	captchaToken: string
@@ -67,7 +119,7 @@ export async function registerBasic(
 export async function loginBasic(
 	body: BasicLoginRequest,
 	captchaToken: string
-): Promise<TokenResponse> {
+): Promise<LoginResponse> {
 	const res = await axiosInstance.post(ENDPOINTS.auth.basic.login, body, {
 		headers: {
 			"x-captcha-token": captchaToken
@@ -103,42 +155,405 @@ export async function openIdProviders(): Promise<OIDProvidersResponse> {
 	return res.data;
 }
 
+/**
+ * Request the available OpenID providers with auth URLs for linking
+ * a provider to the current user
+ *
+ * @returns The providers
+ */
+export async function openIdLinkProviders(): Promise<OIDProvidersResponse> {
+	const res = await axiosInstance.get(ENDPOINTS.auth.oid.linkProviders);
+
+	return res.data;
+}
+
+// Session storage key for the state of the OpenID login started from this browser
+const OPENID_STATE_STORAGE_KEY = "openid_state";
+
+/**
+ * Stores the state of an OpenID login being started from this browser,
+ * the provider must redirect back with the same state
+ *
+ * @param authUrl The provider auth URL the login is started with
+ */
+export function storeOpenIdState(authUrl: string) {
+	const state = new URL(authUrl).searchParams.get("state");
+	if (state !== null) {
+		sessionStorage.setItem(OPENID_STATE_STORAGE_KEY, state);
+	}
+}
+
+/**
+ * Takes the state of the OpenID login started from this browser, logins
+ * redirected back with any other state weren't started by this browser
+ *
+ * @returns The stored state if a login was started
+ */
+export function takeOpenIdState(): string | null {
+	const state = sessionStorage.getItem(OPENID_STATE_STORAGE_KEY);
+	sessionStorage.removeItem(OPENID_STATE_STORAGE_KEY);
+	return state;
+}
+
 /**
  * Request confirmation of a successful OpenID login
  *
- * @param data The OpenID token and provider
+ * @param code The OpenID code from the provider
+ * @param state The state the provider redirected back with
  * @returns The confirmation result
  */
 export async function openIdAuthenticate(
 	code: string,
-	provider: AuthProvider
+	state: string
 ): Promise<OIDAuthenticateResponse> {
 	const res = await axiosInstance.post(ENDPOINTS.auth.oid.authenticate, {
 		code,
-		provider
+		state
 	});
 
 	return res.data;
 }
 
 /**
- * Request to create an account using an OpenID auth token
+ * Links an OpenID provider to the current user
  *
- * @param data The OpenID token and provider
+ * @param code The OpenID code from the provider
+ * @param state The state the provider redirected back with
+ * @returns The created link
+ */
+export async function openIdLink(code: string, state: string): Promise<OIDLink> {
+	const res = await axiosInstance.post(ENDPOINTS.auth.oid.link, {
+		code,
+		state
+	});
+
+	return res.data;
+}
+
+/**
+ * Requests the OpenID providers linked to the current user
+ */
+export async function openIdLinks(): Promise<OIDLink[]> {
+	const res = await axiosInstance.get(ENDPOINTS.auth.oid.links);
+
+	return res.data;
+}
+
+/**
+ * Unlinks an OpenID provider from the current user
+ *
+ * @param provider The provider to unlink
+ */
+export async function openIdUnlink(provider: AuthProvider): Promise<void> {
+	await axiosInstance.delete(ENDPOINTS.auth.oid.specificLink(provider));
+}
+
+/**
+ * Request to create an account from a completed OpenID login
+ *
+ * @param token The signup token from authenticating with the provider
  * @param username The username to give the account
  * @param password The password to set for the account
  * @returns
  */
 export async function openIdCreate(
-	data: OIDData,
+	token: string,
 	username: string,
 	password: string
 ): Promise<TokenResponse> {
 	const res = await axiosInstance.post(ENDPOINTS.auth.oid.create, {
-		...data,
+		token,
 		username,
 		password
 	});
 
 	return res.data;
 }
+
+/**
+ * Verifies the email address of an account using the token
+ * from the verification email
+ *
+ * @param token The verification token
+ */
+export async function verifyEmail(token: string): Promise<void> {
+	await axiosInstance.post(ENDPOINTS.auth.verifyEmail.verify, { token });
+}
+
+/**
+ * Requests that the verification email be sent again
+ * for the current user
+ */
+export async function resendVerifyEmail(): Promise<void> {
+	await axiosInstance.post(ENDPOINTS.auth.verifyEmail.resend);
+}
+
+/**
+ * Requests a password reset email for the account with the
+ * provided email, succeeds even if no account exists
+ *
+ * @param email The account email
+ * @param captchaToken The reCaptcha token
+ */
+export async function forgotPassword(email: string, captchaToken: string): Promise<void> {
+	await axiosInstance.post(
+		ENDPOINTS.auth.basic.forgot,
+		{ email },
+		{
+			headers: {
+				"x-captcha-token": captchaToken
+			}
+		}
+	);
+}
+
+/**
+ * Resets the password of an account using the token from
+ * the password reset email
+ *
+ * @param token The reset token
+ * @param password The new password
+ */
+export async function resetPassword(token: string, password: string): Promise<void> {
+	await axiosInstance.post(ENDPOINTS.auth.basic.reset, { token, password });
+}
+
+/**
+ * Completes a login that requires a second factor
+ *
+ * @param mfaToken The token from the login response
+ * @param code TOTP code or recovery code
+ * @returns The token data
+ */
+export async function verifyMfa(mfaToken: string, code: string): Promise<TokenResponse> {
+	const { data } = await axiosInstance.post(ENDPOINTS.auth.mfa.verify, {
+		mfa_token: mfaToken,
+		code
+	});
+
+	return data;
+}
+
+/**
+ * Requests the two-factor authentication status of the current user
+ */
+export async function getMfaStatus(): Promise<MfaStatus> {
+	const { data } = await axiosInstance.get(ENDPOINTS.auth.mfa.status);
+
+	return data;
+}
+
+/**
+ * Starts enrolling in TOTP two-factor authentication
+ *
+ * @param mfaToken The token from the login response when enrolling
+ * while logging in
+ * @returns The new secret
+ */
+export async function setupTotp(mfaToken?: string): Promise<TotpSetupResponse> {
+	const { data } = await axiosInstance.post(ENDPOINTS.auth.mfa.totpSetup, {
+		mfa_token: mfaToken
+	});
+
+	return data;
+}
+
+/**
+ * Confirms enrollment in TOTP two-factor authentication
+ *
+ * @param code Code from the authenticator app
+ * @param mfaToken The token from the login response when enrolling
+ * while logging in
+ * @returns The recovery codes and the token data when logging in
+ */
+export async function confirmTotp(code: string, mfaToken?: string): Promise<TotpConfirmResponse> {
+	const { data } = await axiosInstance.post(ENDPOINTS.auth.mfa.totpConfirm, {
+		mfa_token: mfaToken,
+		code
+	});
+
+	return data;
+}
+
+/**
+ * Disables two-factor authentication for the current user
+ *
+ * @param code TOTP code or recovery code
+ */
+export async function disableMfa(code: string): Promise<void> {
+	await axiosInstance.delete(ENDPOINTS.auth.mfa.status, { data: { code } });
+}
+
+/**
+ * Replaces the recovery codes of the current user
+ *
+ * @param code TOTP code or recovery code
+ * @returns The new recovery codes
+ */
+export async function regenerateRecoveryCodes(code: string): Promise<string[]> {
+	const { data } = await axiosInstance.post(ENDPOINTS.auth.mfa.recoveryCodes, { code });
+
+	return data.recovery_codes;
+}
+
+/**
+ * Logs out of the current session, revoking the refresh token
+ * and the current user token
+ */
+export async function logout(): Promise<void> {
+	await axiosInstance.post(ENDPOINTS.auth.logout);
+}
+
+/**
+ * Requests the active sessions of the current user
+ */
+export async function getSessions(): Promise<Session[]> {
+	const { data } = await axiosInstance.get(ENDPOINTS.auth.sessions.list);
+
+	return data;
+}
+
+/**
+ * Revokes a session of the current user
+ *
+ * @param id The ID of the session
+ */
+export async function revokeSession(id: number): Promise<void> {
+	await axiosInstance.delete(ENDPOINTS.auth.sessions.specific(id));
+}
+
+/**
+ * Revokes every session of the current user, logging
+ * them out on all devices
+ */
+export async function revokeAllSessions(): Promise<void> {
+	await axiosInstance.delete(ENDPOINTS.auth.sessions.list);
+}
+
+/**
+ * Logs in using a passkey chosen by the user from their authenticator
+ *
+ * @returns The login response
+ */
+export async function loginPasskey(): Promise<LoginResponse> {
+	const { data: challenge }: { data: PasskeyChallenge } = await axiosInstance.post(
+		ENDPOINTS.auth.webauthn.loginStart
+	);
+
+	const parsers = PublicKeyCredential as PublicKeyCredentialJSONParsers;
+	const credential = (await navigator.credentials.get({
+		publicKey: parsers.parseRequestOptionsFromJSON(challenge.options.publicKey)
+	})) as PublicKeyCredential | null;
+
+	if (credential === null) throw new Error("No passkey was selected");
+
+	const { data } = await axiosInstance.post(ENDPOINTS.auth.webauthn.loginFinish, {
+		challenge_id: challenge.challenge_id,
+		credential: credential.toJSON()
+	});
+
+	return data;
+}
+
+/**
+ * Registers a new passkey for the current user
+ *
+ * @param name Name to identify the passkey by
+ * @returns The registered passkey
+ */
+export async function registerPasskey(name: string): Promise<Passkey> {
+	const { data: challenge }: { data: PasskeyChallenge } = await axiosInstance.post(
+		ENDPOINTS.auth.webauthn.registerStart
+	);
+
+	const parsers = PublicKeyCredential as PublicKeyCredentialJSONParsers;
+	const credential = (await navigator.credentials.create({
+		publicKey: parsers.parseCreationOptionsFromJSON(challenge.options.publicKey)
+	})) as PublicKeyCredential | null;
+
+	if (credential === null) throw new Error("Passkey creation was cancelled");
+
+	const { data } = await axiosInstance.post(ENDPOINTS.auth.webauthn.registerFinish, {
+		challenge_id: challenge.challenge_id,
+		name,
+		credential: credential.toJSON()
+	});
+
+	return data;
+}
+
+/**
+ * Requests the passkeys registered to the current user
+ */
+export async function getPasskeys(): Promise<Passkey[]> {
+	const { data } = await axiosInstance.get(ENDPOINTS.auth.webauthn.passkeys);
+
+	return data;
+}
+
+/**
+ * Removes a passkey from the current user
+ *
+ * @param id The ID of the passkey
+ */
+export async function deletePasskey(id: number): Promise<void> {
+	await axiosInstance.delete(ENDPOINTS.auth.webauthn.passkey(id));
+}
+
+export interface SAMLLoginResponse {
+	auth_url: string;
+}
+
+/**
+ * Starts a single sign-on login with the identity provider of
+ * an organization
+ *
+ * @param connection The ID of the organization SAML connection
+ * @returns The URL to send the user to
+ */
+export async function samlLogin(connection: string): Promise<SAMLLoginResponse> {
+	const res = await axiosInstance.get(ENDPOINTS.auth.saml.login(connection));
+	return res.data;
+}
+
+// Session storage key for the relay state of the SAML login started from this browser
+const SAML_STATE_STORAGE_KEY = "saml_state";
+
+/**
+ * Stores the relay state of a single sign-on login being started from
+ * this browser, the login code can only be exchanged with the same state
+ *
+ * @param authUrl The identity provider URL the login is started with
+ */
+export function storeSamlState(authUrl: string) {
+	const state = new URL(authUrl).searchParams.get("RelayState");
+	if (state !== null) {
+		sessionStorage.setItem(SAML_STATE_STORAGE_KEY, state);
+	}
+}
+
+/**
+ * Takes the relay state of the single sign-on login started from this
+ * browser
+ *
+ * @returns The stored state if a login was started
+ */
+export function takeSamlState(): string | null {
+	const state = sessionStorage.getItem(SAML_STATE_STORAGE_KEY);
+	sessionStorage.removeItem(SAML_STATE_STORAGE_KEY);
+	return state;
+}
+
+/**
+ * Exchanges the one time code from a completed single sign-on
+ * login for the token data, or a second factor challenge when
+ * the account requires one
+ *
+ * @param code The code the server redirected back with
+ * @param state The relay state of the login started from this browser
+ * @returns The login response
+ */
+export async function samlExchange(code: string, state: string): Promise<LoginResponse> {
+	const res = await axiosInstance.post(ENDPOINTS.auth.saml.exchange, { code, state });
+	return res.data;
+}
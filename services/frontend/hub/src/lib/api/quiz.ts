@@ -7,13 +7,43 @@ export interface Quiz {
 	description: string;
 	state: QuizState;
 	visibility: QuizVisibility;
-	cover_image: string | null;
-	data: unknown;
+	cover_image: number | null;
+	data: QuizData;
+	tags: string[];
+	play_count: number;
 	owner: number;
 	create_at: string;
 	updated_at: string;
 }
 
+export interface QuizData {
+	version: number;
+	questions: Question[];
+}
+
+export interface QuestionBase {
+	id: number;
+	text: string;
+	image: number | null;
+	// Time in seconds to answer the question
+	time_limit: number;
+	points: number;
+}
+
+export type Question = QuestionBase & QuestionKind;
+
+export type QuestionKind =
+	| { type: "Single"; answers: AnswerOption[] }
+	| { type: "Multiple"; answers: AnswerOption[] }
+	| { type: "TrueFalse"; answer: boolean }
+	| { type: "Typed"; answers: string[]; ignore_case: boolean }
+	| { type: "Ordering"; items: string[] };
+
+export interface AnswerOption {
+	text: string;
+	correct: boolean;
+}
+
 export const enum QuizState {
 	Draft = 0,
 	Published = 1
@@ -24,6 +54,71 @@ export const enum QuizVisibility {
 	Public = 1
 }
 
+export interface UpdateQuizRequest {
+	title?: string;
+	description?: string;
+	cover_image?: number | null;
+	visibility?: QuizVisibility;
+	tags?: string[];
+	data?: QuizData;
+}
+
+export interface QuizSummary {
+	id: number;
+	title: string;
+	description: string;
+	cover_image: number | null;
+	tags: string[];
+	play_count: number;
+	question_count: number;
+	owner_username: string;
+	created_at: string;
+}
+
+export interface BrowseQuizzesQuery {
+	query?: string;
+	tag?: string;
+	owner?: string;
+	min_questions?: number;
+	max_questions?: number;
+	sort?: "recent" | "popular";
+	cursor?: string;
+	size?: number;
+}
+
+export interface BrowseQuizzesResponse {
+	quizzes: QuizSummary[];
+	next_cursor: string | null;
+}
+
+export interface QuizListResponse {
+	quizzes: Quiz[];
+	total_items: number;
+	total_pages: number;
+}
+
+export type QuizRole = "Viewer" | "Editor" | "Host" | "Admin";
+
+export interface Collaborator {
+	user_id: number;
+	username: string;
+	name: string | null;
+	role: QuizRole;
+	created_at: string;
+}
+
+export interface QuizPermission {
+	quiz_id: number;
+	user_id: number;
+	role: QuizRole;
+	created_at: string;
+	updated_at: string;
+}
+
+export type QuizSort = "created_at" | "updated_at" | "title";
+
+export type SortOrder = "asc" | "desc";
+
 export async function createQuiz(title: string): Promise<Quiz> {
 	const { data } = await axiosInstance.post(ENDPOINTS.quiz.create, { title }, {});
 
@@ -46,3 +141,88 @@ export function useQuiz(id: number) {
 		}
 	});
 }
+
+export async function updateQuiz(id: number, body: UpdateQuizRequest): Promise<Quiz> {
+	const { data } = await axiosInstance.patch(ENDPOINTS.quiz.specific(id).root, body);
+
+	return data;
+}
+
+export async function deleteQuiz(id: number): Promise<void> {
+	await axiosInstance.delete(ENDPOINTS.quiz.specific(id).root);
+}
+
+export async function publishQuiz(id: number): Promise<Quiz> {
+	const { data } = await axiosInstance.post(ENDPOINTS.quiz.specific(id).publish);
+
+	return data;
+}
+
+export async function unpublishQuiz(id: number): Promise<Quiz> {
+	const { data } = await axiosInstance.post(ENDPOINTS.quiz.specific(id).unpublish);
+
+	return data;
+}
+
+export function useQuizLibrary(
+	page: number,
+	sort: QuizSort = "updated_at",
+	order: SortOrder = "desc"
+) {
+	return createQuery({
+		queryKey: ["quiz-library", page, sort, order],
+		queryFn: async () => {
+			const { data } = await axiosInstance.get(ENDPOINTS.quiz.library, {
+				params: { page, sort, order }
+			});
+			return data as QuizListResponse;
+		}
+	});
+}
+
+export async function browseQuizzes(query: BrowseQuizzesQuery): Promise<BrowseQuizzesResponse> {
+	const { data } = await axiosInstance.get(ENDPOINTS.quiz.browse, { params: query });
+
+	return data;
+}
+
+export async function getSharedQuizzes(): Promise<Quiz[]> {
+	const { data } = await axiosInstance.get(ENDPOINTS.quiz.shared);
+
+	return data;
+}
+
+export async function getCollaborators(id: number): Promise<Collaborator[]> {
+	const { data } = await axiosInstance.get(ENDPOINTS.quiz.specific(id).collaborators);
+
+	return data;
+}
+
+export async function addCollaborator(
+	id: number,
+	username: string,
+	role: QuizRole
+): Promise<QuizPermission> {
+	const { data } = await axiosInstance.post(ENDPOINTS.quiz.specific(id).collaborators, {
+		username,
+		role
+	});
+
+	return data;
+}
+
+export async function updateCollaborator(
+	id: number,
+	userId: number,
+	role: QuizRole
+): Promise<QuizPermission> {
+	const { data } = await axiosInstance.put(ENDPOINTS.quiz.specific(id).collaborator(userId), {
+		role
+	});
+
+	return data;
+}
+
+export async function removeCollaborator(id: number, userId: number): Promise<void> {
+	await axiosInstance.delete(ENDPOINTS.quiz.specific(id).collaborator(userId));
+}
@@ -0,0 +1,67 @@
+import { PUBLIC_API_BASE_URL } from "$env/static/public";
+import { ENDPOINTS, axiosInstance } from "./api";
+
+/// Structure of uploaded resources
+export interface Resource {
+	id: number;
+	mime_type: string;
+	name: string;
+	description: string | null;
+	owner: number;
+	visibility: ResourceVisibility;
+	created_at: string;
+	width: number;
+	height: number;
+	variants: ResourceVariant[];
+}
+
+/// Smaller processed sizes of an image
+export type ImageVariant = "thumbnail" | "medium";
+
+export interface ResourceVariant {
+	variant: ImageVariant;
+	width: number;
+	height: number;
+}
+
+export const enum ResourceVisibility {
+	Private = "Private",
+	Public = "Public"
+}
+
+/// File types accepted by the server
+export const ACCEPTED_TYPES = ["image/png", "image/jpeg", "image/webp", "image/gif"];
+
+/// Maximum upload size in bytes (10MiB)
+export const MAX_UPLOAD_SIZE = 10 * 1024 * 1024;
+
+export async function uploadResource(
+	file: File,
+	visibility: ResourceVisibility = ResourceVisibility.Public
+): Promise<Resource> {
+	const body = new FormData();
+	body.append("file", file);
+	body.append("visibility", visibility);
+
+	const { data } = await axiosInstance.post(ENDPOINTS.resource.upload, body);
+
+	return data;
+}
+
+export async function getResources(): Promise<Resource[]> {
+	const { data } = await axiosInstance.get(ENDPOINTS.resource.list);
+
+	return data;
+}
+
+export async function deleteResource(id: number): Promise<void> {
+	await axiosInstance.delete(ENDPOINTS.resource.specific(id).root);
+}
+
+/// Provides the URL for the content of a resource, optionally for one of
+/// its smaller variants
+export function getResourceURL(id: number, variant?: ImageVariant): string {
+	const url = new URL(ENDPOINTS.resource.specific(id).content, PUBLIC_API_BASE_URL);
+	if (variant !== undefined) url.searchParams.set("variant", variant);
+	return url.toString();
+}
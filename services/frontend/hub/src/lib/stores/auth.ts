@@ -94,7 +94,11 @@ export function setTokenData(value: TokenResponse) {
 	tokenRefreshTask = setTimeout(() => {
 		// Can't refresh without a refresh token
 		if (tokenData === null) return;
-		doTokenRefresh(tokenData.refresh_token);
+
+		// Refresh tokens can only be used once, another tab may have already
+		// replaced the token so prefer the latest stored token
+		const storedToken: Token = localStorage.getItem(REFRESH_TOKEN_STORAGE_KEY);
+		doTokenRefresh(storedToken ?? tokenData.refresh_token);
 	}, refreshDelay * 1000);
 
 	localStorage.setItem(REFRESH_TOKEN_STORAGE_KEY, value.refresh_token);